@@ -0,0 +1,89 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use core_affinity::CoreId;
+
+/// A set of CPU cores, parsed from a list of cores and inclusive ranges such
+/// as `0-7` or `0,2,4-6`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreList(Vec<usize>);
+
+impl FromStr for CoreList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cores = Vec::new();
+        for part in s.split(',').map(str::trim) {
+            let parse = |core: &str| {
+                core.trim()
+                    .parse::<usize>()
+                    .map_err(|e| format!("invalid core '{core}': {e}"))
+            };
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse(start)?, parse(end)?);
+                    if start > end {
+                        return Err(format!("invalid core range '{part}'"));
+                    }
+                    cores.extend(start..=end);
+                }
+                None => cores.push(parse(part)?),
+            }
+        }
+        cores.sort_unstable();
+        cores.dedup();
+        Ok(Self(cores))
+    }
+}
+
+impl Display for CoreList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cores: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", cores.join(","))
+    }
+}
+
+impl CoreList {
+    pub fn cores(&self) -> &[usize] {
+        &self.0
+    }
+
+    /// Run one worker thread per core of the list, pinning each thread the
+    /// runtime starts to the cores in turn.
+    ///
+    /// Threads of the blocking pool are also pinned, sharing the same cores.
+    pub fn pin_runtime(&self, builder: &mut tokio::runtime::Builder) {
+        let cores = self.0.clone();
+        let next = AtomicUsize::new(0);
+        builder
+            .worker_threads(cores.len())
+            .on_thread_start(move || {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let id = cores[idx % cores.len()];
+                if !core_affinity::set_for_current(CoreId { id }) {
+                    eprintln!("Unable to pin thread to core {id}");
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CoreList;
+
+    #[test]
+    fn parse() {
+        assert_eq!("0-3".parse::<CoreList>().unwrap().cores(), &[0, 1, 2, 3]);
+        assert_eq!(
+            "0,2,4-6".parse::<CoreList>().unwrap().cores(),
+            &[0, 2, 4, 5, 6]
+        );
+        assert_eq!("1".parse::<CoreList>().unwrap().cores(), &[1]);
+        assert!("3-1".parse::<CoreList>().is_err());
+        assert!("a".parse::<CoreList>().is_err());
+        assert!("".parse::<CoreList>().is_err());
+    }
+}
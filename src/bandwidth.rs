@@ -0,0 +1,107 @@
+use std::{fmt::Display, str::FromStr, sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::size::ByteSize;
+
+/// Portion of a second of bandwidth which can be written in a burst.
+const BURST: f64 = 0.1;
+
+/// A rate of bytes per second, parsed from a size with an optional `/s`
+/// suffix such as `10MB/s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bandwidth(pub u64);
+
+impl FromStr for Bandwidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = s.strip_suffix("/s").unwrap_or(s).parse::<ByteSize>()?;
+        if size.0 == 0 {
+            return Err("the bandwidth must be at least 1 byte per second".to_string());
+        }
+        Ok(Self(size.0))
+    }
+}
+
+impl Display for Bandwidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}B/s", self.0)
+    }
+}
+
+/// Token bucket which limits the rate of bytes written across every task of a
+/// run.
+///
+/// Tokens are added at the configured rate, up to a small burst. A write may
+/// take more tokens than are available, leaving a debt which following writes
+/// wait to be repaid, so payloads larger than the burst are still paced.
+pub(crate) struct TokenBucket {
+    bytes_per_second: f64,
+    capacity: f64,
+    /// Available tokens, which are negative while in debt, and when they
+    /// were last refilled.
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(bandwidth: Bandwidth) -> Self {
+        let bytes_per_second = bandwidth.0 as f64;
+        let capacity = bytes_per_second * BURST;
+        Self {
+            bytes_per_second,
+            capacity,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    /// Take `bytes` tokens, waiting until the bucket is out of debt.
+    pub(crate) async fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let (tokens, refilled) = &mut *state;
+            let now = Instant::now();
+            let refill = now.duration_since(*refilled).as_secs_f64() * self.bytes_per_second;
+            *tokens = (*tokens + refill).min(self.capacity) - bytes as f64;
+            *refilled = now;
+            if *tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-*tokens / self.bytes_per_second)
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{Bandwidth, TokenBucket};
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "10MB/s".parse::<Bandwidth>().unwrap(),
+            Bandwidth(10_000_000)
+        );
+        assert_eq!("1KiB".parse::<Bandwidth>().unwrap(), Bandwidth(1024));
+        assert!("0/s".parse::<Bandwidth>().is_err());
+        assert!("fast/s".parse::<Bandwidth>().is_err());
+    }
+
+    #[tokio::test]
+    async fn acquire() {
+        let bucket = TokenBucket::new(Bandwidth(10_000));
+        let start = Instant::now();
+        // The burst of 1000 bytes is available straight away, after which
+        // every 1000 bytes takes 100ms.
+        bucket.acquire(1000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+        bucket.acquire(1000).await;
+        bucket.acquire(1000).await;
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+}
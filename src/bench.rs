@@ -0,0 +1,140 @@
+//! Adapters for tracking the socket-level performance of a service inside a
+//! project's benchmark suite, such as with criterion.
+//!
+//! A [`Bench`] writes a fixed number of requests rather than writing for a
+//! duration, so every run performs the same work and the summaries are
+//! comparable between runs. The elapsed time of the summary fits criterion's
+//! `iter_custom`:
+//!
+//! ```rust,ignore
+//! let bench = gn::bench::Bench::new("127.0.0.1:5000".parse()?).with_payload(vec![0; 512]);
+//! c.bench_function("ingest", |b| {
+//!     b.iter_custom(|iters| bench.clone().with_requests(iters).run_blocking().unwrap().elapsed)
+//! });
+//! ```
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{statistics::Statistics, Protocol, SocketManager, WriteOptions};
+
+/// A short, fixed workload against a single address.
+#[derive(Debug, Clone)]
+pub struct Bench {
+    addr: SocketAddr,
+    protocol: Protocol,
+    payload: Vec<u8>,
+    requests: u64,
+    concurrency: u64,
+}
+
+/// Summary metrics of a single [`Bench`] run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchSummary {
+    pub requests: u64,
+    pub successful_requests: u64,
+    pub bytes: u64,
+    /// Time taken to write every request.
+    pub elapsed: Duration,
+    pub requests_per_second: f64,
+    pub bytes_per_second: f64,
+}
+
+impl Bench {
+    /// Write 1000 requests of 64 bytes over TCP, one at a time, by default.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            protocol: Protocol::Tcp,
+            payload: vec![b'g'; 64],
+            requests: 1000,
+            concurrency: 1,
+        }
+    }
+
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Data written by every request.
+    pub fn with_payload(mut self, payload: Vec<u8>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Total number of requests to write, which must be at least 1.
+    pub fn with_requests(mut self, requests: u64) -> Self {
+        self.requests = requests.max(1);
+        self
+    }
+
+    /// Number of requests in flight at once. The requests are split evenly
+    /// between them, so the total is rounded down to a multiple of this.
+    pub fn with_concurrency(mut self, concurrency: u64) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Run the workload to completion.
+    pub async fn run(&self) -> crate::Result<BenchSummary> {
+        let options = match self.concurrency {
+            1 => WriteOptions::Count(self.requests),
+            c => WriteOptions::ConcurrencyWithCount(c, self.requests),
+        };
+        let manager = SocketManager::new(
+            self.addr,
+            &self.payload,
+            self.protocol.clone(),
+            options,
+            Statistics::new(),
+        );
+        let start = Instant::now();
+        manager.write().await?;
+        let elapsed = start.elapsed();
+
+        let report = manager.report();
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        Ok(BenchSummary {
+            requests: report.requests,
+            successful_requests: report.successful_requests,
+            bytes: report.total_bytes,
+            elapsed,
+            requests_per_second: report.requests as f64 / secs,
+            bytes_per_second: report.total_bytes as f64 / secs,
+        })
+    }
+
+    /// Run the workload to completion on a new runtime, for use from
+    /// synchronous benchmark functions.
+    pub fn run_blocking(&self) -> crate::Result<BenchSummary> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(self.run())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Bench;
+    use crate::Protocol;
+
+    #[tokio::test]
+    async fn udp() {
+        let receiver = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let summary = Bench::new(receiver.local_addr().unwrap())
+            .with_protocol(Protocol::Udp)
+            .with_payload(vec![0; 16])
+            .with_requests(100)
+            .with_concurrency(4)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(summary.requests, 100);
+        assert_eq!(summary.successful_requests, 100);
+        assert_eq!(summary.bytes, 1600);
+        assert!(summary.requests_per_second > 0.0);
+    }
+}
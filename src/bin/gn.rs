@@ -1,35 +1,99 @@
-use std::io::Write;
-use std::net::SocketAddr;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
 
-use clap::{Parser, Subcommand};
-use clap_stdin::MaybeStdin;
-use gn::{statistics::Statistics, Protocol, Server, SocketManager, WriteOptions};
+use clap::{
+    builder::{ArgPredicate, ValueParser},
+    parser::ValueSource,
+    Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches, Parser, Subcommand,
+    ValueEnum,
+};
+use gn::{
+    statistics::Statistics, Annotation, Bandwidth, ByteSize, CaptureReader, CaptureWriter, Chaos,
+    CircuitBreaker, ConfigWatcher, ConnectionOverflow, CoreList, Dashboard, Delay, Digest,
+    Endpoint, Engine, FamilySplit, HookScript, MessageMatcher, MixWeight, Padding, PayloadMix,
+    PayloadOrder, PayloadSpec, Protocol, Pushgateway, RandomPayloads, Regeneration, Render,
+    ReplyFraming, Report, ReportFormat, ResponseScript, RetryPolicy, RotatingFile, Server,
+    SniRoute, SocketManager, StopReason, TlsConfig, TlsServerConfig, Transcript, WebSocketConfig,
+    WebSocketMessage, WriteOptions,
+};
+use tokio::io::AsyncReadExt;
 
 #[derive(Parser)]
 struct App {
     #[clap(subcommand)]
     cmds: Commands,
+
+    /// Pin the runtime's threads to the given cores, e.g. 0-7 or 0,2,4
+    ///
+    /// One worker thread is started per core, which avoids cross-NUMA
+    /// scheduling capping the achievable rate on large machines.
+    #[clap(long, global = true)]
+    pin_cores: Option<CoreList>,
+
+    /// TOML file of flags to use when they are not given on the command line
+    /// or through their GN_* environment variable, with a table per command,
+    /// e.g. `[write]` followed by `host = "127.0.0.1:5000"`.
+    #[clap(long, global = true, env = CONFIG_ENV)]
+    config: Option<PathBuf>,
 }
 
+/// Prefix of the environment variable of every flag, such as GN_WRITE_HOST
+/// for `gn write --host`.
+const ENV_PREFIX: &str = "GN";
+
+/// Environment variable which points to the config file.
+const CONFIG_ENV: &str = "GN_CONFIG";
+
+/// Share of the requested `--rate` below which the achieved rate is warned
+/// about.
+const RATE_TOLERANCE: f64 = 0.95;
+
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Write data over a socket.
     Write {
-        #[arg(long)]
-        host: SocketAddr,
+        /// Address to write to, or the path of a Unix socket, e.g.
+        /// /tmp/app.sock, when the protocol is unix or unix-datagram. On
+        /// Linux, @name is a socket in the abstract namespace.
+        ///
+        /// Repeat this or give a comma-separated list to distribute requests
+        /// round-robin across several hosts, rather than every host receiving
+        /// the full workload.
+        #[arg(long, required = true, value_delimiter = ',')]
+        host: Vec<Endpoint>,
 
         #[arg(long, short, default_value = "tcp")]
         protocol: Protocol,
 
-        /// Input data to be written to the socket.
+        /// Input data to be written to the socket, where `-` reads the raw
+        /// bytes of stdin, which may be binary.
         ///
-        /// Defaults to reading from stdin when unspecified.
-        #[clap(default_value = "-")]
-        input: MaybeStdin<String>,
+        /// Defaults to reading from stdin when unspecified, unless a payload
+        /// mix, input files or a payload size are used.
+        #[clap(
+            default_value = "-",
+            default_value_ifs([
+                ("mix", ArgPredicate::IsPresent, ""),
+                ("input_file", ArgPredicate::IsPresent, ""),
+                ("payload_size", ArgPredicate::IsPresent, ""),
+            ])
+        )]
+        input: String,
 
+        /// Number of requests to send, where 0 writes until interrupted.
         #[clap(short, long, default_value = "1")]
         count: u64,
 
+        /// Write until interrupted, equivalent to `--count 0`.
+        #[clap(long, conflicts_with = "count")]
+        forever: bool,
+
         /// The duration of time to write for, e.g. 30s
         ///
         /// When provided alongside `count`, whichever comes first will then halt
@@ -42,40 +106,1238 @@ enum Commands {
         #[clap(long)]
         concurrency: Option<u64>,
 
+        /// Send this many requests per second, rather than as fast as possible.
+        ///
+        /// Requests are started on a fixed schedule even when earlier ones are
+        /// still in flight, so latency is measured at a constant offered load.
+        #[clap(long, conflicts_with = "concurrency")]
+        rate: Option<NonZeroU64>,
+
+        /// Increase the concurrency or rate gradually from one to the target
+        /// over this long, e.g. 30s, before the measured run begins. Requests
+        /// written while ramping up are not counted, nor are the connections
+        /// and TLS handshakes they make.
+        #[clap(long)]
+        ramp_up: Option<humantime::Duration>,
+
+        /// Reuse a TCP connection per concurrent request for many requests,
+        /// rather than opening a connection for every request.
+        #[clap(
+            long,
+            conflicts_with_all = ["reflect_timing", "expect_response", "expect_reply", "verify_digest"]
+        )]
+        keepalive: bool,
+
+        /// Close each kept alive connection once it has been open this long,
+        /// e.g. 30s, and re-establish it for the next request, as with
+        /// clients behind load balancers which recycle connections.
+        #[clap(long, requires = "keepalive")]
+        connection_lifetime: Option<humantime::Duration>,
+
+        /// Read whatever the host pushes down each kept alive connection
+        /// while writing to it, as with chat and streaming protocols, and
+        /// report the throughput in each direction.
+        #[clap(long, requires = "keepalive")]
+        duplex: bool,
+
+        /// Wait up to this long for the host to accept a connection before
+        /// starting the run, e.g. 60s, for when it starts alongside gn.
+        #[clap(long)]
+        wait_for_target: Option<humantime::Duration>,
+
+        /// Print the bytes sent, request rate and errors of each interval of
+        /// this length while writing, e.g. 10s.
+        #[clap(long)]
+        report_interval: Option<humantime::Duration>,
+
+        /// Engine which writes the requests, where blocking-threads writes from
+        /// one OS thread per concurrent request to compare against the
+        /// overhead of the async runtime.
+        #[clap(long, default_value = "tokio")]
+        engine: Engine,
+
+        /// Limit the bytes written per second across all requests, e.g.
+        /// 10MB/s, to simulate a constrained link.
+        #[clap(long)]
+        bandwidth: Option<Bandwidth>,
+
         /// Display statistics about writes
         #[clap(long)]
         stats: bool,
+
+        /// Only print a one-line summary of the run, or the report with
+        /// `--output json`.
+        #[clap(short, long, conflicts_with_all = ["stats", "verbose", "tui", "report_interval"])]
+        quiet: bool,
+
+        /// Print the statistics of each host, address family and payload
+        /// class and the causes of failures, implying --stats. Given twice,
+        /// as -vv, also print the latency and failures of each.
+        #[clap(short, long, action = ArgAction::Count)]
+        verbose: u8,
+
+        /// Show a dashboard of the throughput, latency percentiles, success
+        /// rate and requests in flight, updated every second while writing.
+        #[clap(long, conflicts_with = "report_interval")]
+        tui: bool,
+
+        /// Push the throughput, error rate, latency percentiles and open
+        /// connections of the run, overall and to each target, to a
+        /// Prometheus Pushgateway while writing and once it ends, e.g.
+        /// http://localhost:9091/metrics/job/gn
+        #[clap(long)]
+        pushgateway_url: Option<Pushgateway>,
+
+        /// How often metrics are pushed to the --pushgateway-url.
+        #[clap(long, requires = "pushgateway_url", default_value = "10s")]
+        push_interval: humantime::Duration,
+
+        /// Sample the CPU and memory used by gn while writing, to tell when
+        /// gn rather than the target is the bottleneck. Only supported on
+        /// Linux.
+        #[clap(long)]
+        sample_resources: bool,
+
+        /// Format of the final statistics, where json always prints the
+        /// report, including latency percentiles, to stdout for scripts and
+        /// html prints a page charting it over time.
+        #[clap(long, default_value = "text")]
+        output: Output,
+
+        /// Write the json or html report to this file rather than stdout,
+        /// e.g. --output html --report-file report.html
+        #[clap(long)]
+        report_file: Option<PathBuf>,
+
+        /// Store every latency, rather than only a histogram of them, so that
+        /// short runs report exact percentiles.
+        #[clap(long)]
+        record_all_latencies: bool,
+
+        /// Most latencies which --record-all-latencies stores, beyond which
+        /// histogram percentiles are reported instead.
+        #[clap(long, default_value = "1000000", requires = "record_all_latencies")]
+        latency_cap: NonZeroUsize,
+
+        /// Record the start time and a digest of the payload of requests which
+        /// take at least this long, e.g. 50ms, in the report's outliers.
+        #[clap(long)]
+        outlier_threshold: Option<humantime::Duration>,
+
+        /// Mark a point of the run with a message, e.g. 60s=deployed v2, in
+        /// the time series of the report, the HTML output and the dashboard,
+        /// to correlate changes in throughput with outside events. Repeat
+        /// for each annotation.
+        #[clap(long)]
+        annotate_at: Vec<Annotation>,
+
+        /// Accept commands from `gn ctl` on the Unix socket at this path
+        /// while writing, such as `set-weight 127.0.0.1:5000 0` to drain an
+        /// address of the host. Each command is annotated in the time series.
+        #[clap(long)]
+        control_socket: Option<PathBuf>,
+
+        /// Distribute requests between resolved IPv4 and IPv6 addresses using
+        /// the given ratio, e.g. 70:30.
+        ///
+        /// Without this, the full workload is written to every resolved address.
+        #[clap(long)]
+        family_split: Option<FamilySplit>,
+
+        /// Weighted classes of payloads to sample per request, e.g. small=80,large=20
+        ///
+        /// Each class requires a payload provided through `--mix-class`.
+        #[clap(long, value_delimiter = ',', requires = "mix_class")]
+        mix: Vec<MixWeight>,
+
+        /// Payload of a class within the mix, e.g. small=hello or large=@large.bin
+        ///
+        /// Payloads prefixed with `@` are read from the given file.
+        #[clap(long, requires = "mix")]
+        mix_class: Vec<PayloadSpec>,
+
+        /// File to read the payload of requests from, which can be repeated
+        /// to cycle through the files in order.
+        #[clap(long, conflicts_with = "mix")]
+        input_file: Vec<PathBuf>,
+
+        /// Order to choose the payload of each request from the input files
+        /// or mix. Defaults to sequential for input files and random for a mix.
+        #[clap(long)]
+        payload_order: Option<PayloadOrder>,
+
+        /// Embed the send time in each message and estimate the one-way delay
+        /// from the timestamps reflected by a `serve --reflect-timing` server.
+        #[clap(long)]
+        reflect_timing: bool,
+
+        /// Before writing, estimate how far the clock of each server is from
+        /// the writer's from this many probes, as NTP does, and correct the
+        /// one-way delays by it. Uses the probe with the shortest round trip.
+        #[clap(long, value_name = "PROBES", requires = "reflect_timing")]
+        calibrate_clock: Option<NonZeroU32>,
+
+        /// Start a new W3C trace for every request, sending its header ahead
+        /// of the input as a line of `traceparent: 00-<trace>-<span>-01`.
+        #[clap(long, conflicts_with = "streaming_generate")]
+        traceparent: bool,
+
+        /// Log every request as a line of JSON to this file, with its start
+        /// time, target, latency, outcome and, with --traceparent, trace ID.
+        #[clap(long)]
+        event_log: Option<PathBuf>,
+
+        /// Save requests and their replies to this directory, as
+        /// <n>.request and <n>.response files listed in index.jsonl with the
+        /// start time and latency of their --event-log lines. Replies must be
+        /// read, such as with --expect-reply.
+        #[clap(long, value_name = "DIR")]
+        transcript: Option<PathBuf>,
+
+        /// Save only one in every this many requests to the --transcript.
+        #[clap(long, requires = "transcript", default_value = "1")]
+        transcript_every: NonZeroU64,
+
+        /// Send through this network interface or VRF, e.g. eth1, on
+        /// multi-homed hosts. Only supported on Linux, usually as root.
+        #[clap(long)]
+        interface: Option<String>,
+
+        /// Let UDP datagrams to a multicast group, e.g. --host 239.1.1.1:5000,
+        /// cross this many routers. Defaults to 1, the local network.
+        #[clap(long, value_name = "HOPS")]
+        multicast_ttl: Option<u32>,
+
+        /// Fail requests whose connection is not established within this
+        /// long, e.g. 2s, including any TLS handshake, rather than waiting on
+        /// a target which never answers.
+        #[clap(long)]
+        connect_timeout: Option<humantime::Duration>,
+
+        /// Fail requests whose input is not written within this long once
+        /// connected, e.g. 5s, such as when the target stops reading.
+        #[clap(long)]
+        write_timeout: Option<humantime::Duration>,
+
+        /// After writing, half-close each TCP or TLS connection and only count
+        /// the request as successful once the server closes it too, catching
+        /// servers which accept the input but crash while processing it.
+        #[clap(long, conflicts_with = "keepalive")]
+        wait_peer_close: bool,
+
+        /// Fail requests whose connection the server has not closed within
+        /// this long, e.g. 5s, with --wait-peer-close.
+        #[clap(long, requires = "wait_peer_close")]
+        close_timeout: Option<humantime::Duration>,
+
+        /// Retry each failed request up to this many times before counting it
+        /// as a failure, such as while the target restarts. Resolving the host
+        /// is retried in the same way.
+        #[clap(long)]
+        retries: Option<u32>,
+
+        /// Wait before the first retry of a request, which doubles for each
+        /// retry after it, up to 30s.
+        #[clap(long, requires = "retries", default_value = "100ms")]
+        retry_backoff: humantime::Duration,
+
+        /// Only retry requests which failed to connect, never those which
+        /// failed once connected, so no request is delivered more than once.
+        #[clap(long, requires = "retries")]
+        retry_connect_only: bool,
+
+        /// Maximum number of TCP connections which may be established at once,
+        /// independent of the number of concurrent requests.
+        #[clap(long)]
+        connect_concurrency: Option<NonZeroUsize>,
+
+        /// Wait for a response to each request, which only succeeds if the
+        /// response matches, e.g. OK, prefix:HTTP/1.1 2 or regex:^OK$
+        #[clap(long, conflicts_with = "reflect_timing")]
+        expect_response: Option<MessageMatcher>,
+
+        /// Read the reply to each request and record its round trip and time
+        /// to first byte, where it ends at eof, after a number of bytes, e.g.
+        /// 128, or at delimiter:TEXT, e.g. delimiter:\r\n
+        #[clap(
+            long,
+            value_name = "UNTIL",
+            conflicts_with_all = ["reflect_timing", "streaming_generate"]
+        )]
+        expect_reply: Option<ReplyFraming>,
+
+        /// Wait for the digest of each request from a `serve --reply-digest`
+        /// server, which only succeeds if it matches the digest of what was
+        /// written, verifying it arrived intact without echoing it back.
+        #[clap(
+            long,
+            conflicts_with_all = ["reflect_timing", "expect_response", "streaming_generate"]
+        )]
+        verify_digest: Option<Digest>,
+
+        /// Customise each request with the hooks of a Rhai script, which may
+        /// generate its payload with `fn payload(index)`, pick its target
+        /// with `fn target(index, targets)` and judge its reply with
+        /// `fn judge(request, reply)`, for bespoke protocols and validation.
+        #[clap(long, conflicts_with = "streaming_generate")]
+        script: Option<PathBuf>,
+
+        /// Stop writing once this many requests have failed.
+        #[clap(long)]
+        max_failures: Option<NonZeroU64>,
+
+        /// Pause requests to an address after this many consecutive failures,
+        /// for an optional cool-down which defaults to 5s, e.g. 5 or 5:10s.
+        #[clap(long, value_name = "FAILURES[:COOLDOWN]")]
+        circuit_breaker: Option<CircuitBreaker>,
+
+        /// With --protocol tls or wss, trust the certificates of this PEM bundle
+        /// rather than the web PKI roots.
+        #[clap(long)]
+        ca_file: Option<PathBuf>,
+
+        /// With --protocol tls or wss, accept any certificate the server presents.
+        #[clap(long, conflicts_with = "ca_file")]
+        insecure: bool,
+
+        /// With --protocol tls or wss, perform a full handshake for every
+        /// connection rather than resuming earlier sessions.
+        #[clap(long)]
+        no_session_resumption: bool,
+
+        /// With --protocol ws or wss, the path and query requested when
+        /// opening each WebSocket, e.g. /chat?room=1.
+        #[clap(long, default_value = "/")]
+        ws_path: String,
+
+        /// With --protocol ws or wss, the kind of message the input is sent
+        /// as. Replies are awaited with --expect-reply or --expect-response,
+        /// where the first message received back is the whole reply.
+        #[clap(long, default_value = "binary")]
+        ws_message: WebSocketMessage,
+
+        /// Write this many bytes of random data with each request instead of
+        /// the input, e.g. 512, 64KB or 1GiB.
+        #[clap(
+            long,
+            visible_alias = "random-bytes",
+            group = "sized",
+            conflicts_with_all = ["mix", "input_file", "reflect_timing", "expect_response"]
+        )]
+        payload_size: Option<ByteSize>,
+
+        /// Pad the input, input files or mix payloads up to this many bytes,
+        /// e.g. 512, so every message is the same size on the wire while
+        /// keeping its real content at the start.
+        #[clap(long, group = "sized", conflicts_with = "reflect_timing")]
+        pad_to: Option<ByteSize>,
+
+        /// Bytes to pad messages with up to --pad-to.
+        #[clap(long, requires = "pad_to", default_value = "zeros")]
+        padding: Padding,
+
+        /// Seed the random data of --payload-size or --padding random, so
+        /// that every run writes the same bytes.
+        #[clap(long, requires = "sized", conflicts_with = "streaming_generate")]
+        seed: Option<u64>,
+
+        /// Generate the --payload-size data in chunks as it is written, rather
+        /// than holding it in memory, to measure sustained bulk-transfer
+        /// throughput. Only supported by stream protocols.
+        #[clap(long, requires = "payload_size")]
+        streaming_generate: bool,
+
+        /// Generate distinct --payload-size data for every request from this
+        /// many threads, which run ahead of sending so that generating does
+        /// not hold it up. Requests which still wait for their payload are
+        /// reported as generator-starved.
+        #[clap(
+            long,
+            requires = "payload_size",
+            conflicts_with_all = ["streaming_generate", "pad_to"]
+        )]
+        generator_threads: Option<NonZeroUsize>,
+
+        /// Generate new --payload-size data for every request, for every
+        /// connection or once for the whole run. Generating once is fastest,
+        /// whereas every request stresses generating and defeats deduplication
+        /// by the target. Defaults to once, unless --generator-threads is given.
+        #[clap(
+            long,
+            value_enum,
+            requires = "payload_size",
+            conflicts_with_all = ["streaming_generate", "pad_to"]
+        )]
+        payload_regenerate: Option<Regeneration>,
     },
     /// Start a server, listening for a specified protocol.
     Serve {
+        /// Address to listen on, or the path of a Unix socket when the
+        /// protocol is unix or unix-datagram. On Linux, @name is a socket in
+        /// the abstract namespace. A socket left at the path by an earlier
+        /// server is replaced, and the socket is removed once serving stops.
+        ///
+        /// With port 0, the system chooses a port and every listener is
+        /// announced on stdout as a line of JSON, such as
+        /// {"service":"serve","protocol":"tcp","address":"127.0.0.1:40123"}.
         #[arg(long, default_value = "127.0.0.1:5000")]
-        address: SocketAddr,
+        address: Endpoint,
 
         #[arg(long, short, default_value = "tcp")]
         protocol: Protocol,
+
+        /// Discard received data and only report the rate at which messages
+        /// and bytes arrive, for validating the throughput of writers.
+        #[arg(long)]
+        measure_only: bool,
+
+        /// How each received message is written to stderr. Raw passes the
+        /// bytes through unchanged, for piping binary payloads elsewhere.
+        #[arg(long, default_value = "utf8-lossy", conflicts_with = "measure_only")]
+        render: Render,
+
+        /// Print each received message as a dump of its offsets, hex and
+        /// ASCII, for debugging binary protocols. This is --render hexdump.
+        #[arg(long, conflicts_with_all = ["render", "measure_only"])]
+        dump: Option<Dump>,
+
+        /// Write received messages to this file rather than stderr, appending
+        /// to it if it exists. Log lines are still printed to stderr.
+        #[arg(long, conflicts_with = "measure_only")]
+        out_file: Option<PathBuf>,
+
+        /// Rotate the --out-file to <path>.1, <path>.2 and so on before it
+        /// grows beyond this size, e.g. 100MB.
+        #[arg(long, requires = "out_file")]
+        rotate_size: Option<ByteSize>,
+
+        /// Rotate the --out-file once it has been written to for this long,
+        /// e.g. 1h.
+        #[arg(long, requires = "out_file")]
+        rotate_interval: Option<humantime::Duration>,
+
+        /// Keep only this many rotated --out-file files, removing the oldest
+        /// whenever it is rotated.
+        #[arg(long, requires = "out_file")]
+        rotate_keep: Option<usize>,
+
+        /// Reply to each message with the sender's address, its embedded send
+        /// timestamp and the time it was received.
+        #[arg(long)]
+        reflect_timing: bool,
+
+        /// Record every received message, with its peer and the time it was
+        /// received, to a capture file for use with `cat` and `replay`.
+        #[arg(long, conflicts_with = "measure_only")]
+        capture: Option<PathBuf>,
+
+        /// Reply to messages with canned responses and delays from a TOML
+        /// script of rules, matched by payload prefix or regex.
+        #[arg(long, conflicts_with_all = ["measure_only", "reflect_timing"])]
+        respond_script: Option<PathBuf>,
+
+        /// Reply to each message with its digest as a line of hex, for a
+        /// writer to verify with --verify-digest.
+        #[arg(long, conflicts_with_all = ["measure_only", "reflect_timing", "respond_script"])]
+        reply_digest: Option<Digest>,
+
+        /// Wait before replying to each message, or closing its connection,
+        /// to act as a slow backend, e.g. 50ms or a range such as 10ms..100ms
+        /// which each delay is drawn from.
+        #[arg(long, conflicts_with = "measure_only")]
+        delay: Option<Delay>,
+
+        /// Inject faults into accepted connections, to test how writers
+        /// handle an unreliable backend, e.g. drop=10%,reset=5%,close-after=512B.
+        /// Dropped connections are held open unread, reset ones are aborted
+        /// with a RST and the rest are closed without a reply after reading
+        /// the given bytes. Over UDP, only drop is supported.
+        #[arg(long, conflicts_with = "measure_only")]
+        chaos: Option<Chaos>,
+
+        /// PEM certificate chain to present with --protocol tls.
+        #[arg(long, requires = "key")]
+        cert: Option<PathBuf>,
+
+        /// PEM private key of the certificate.
+        #[arg(long, requires = "cert")]
+        key: Option<PathBuf>,
+
+        /// Write the data of TLS connections whose clients ask for a server
+        /// name through SNI to its own file rather than the output, e.g.
+        /// api.example.com=api.log, repeated for each name.
+        #[arg(long, requires = "cert", conflicts_with = "measure_only")]
+        sni_route: Vec<SniRoute>,
+
+        /// Abort TCP and TLS connections with a reset (RST) rather than
+        /// closing them gracefully, to test how clients handle resets.
+        #[arg(long)]
+        close_with_rst: bool,
+
+        /// Bind with SO_REUSEPORT, so several servers can share the port with
+        /// the kernel balancing between them.
+        #[arg(long)]
+        reuseport: bool,
+
+        /// Receive buffer size to request for the socket, e.g. 8MiB, to avoid
+        /// dropping bursts of UDP datagrams.
+        #[arg(long)]
+        recv_buffer_size: Option<ByteSize>,
+
+        /// Number of TCP connections which may wait to be accepted.
+        #[arg(long)]
+        backlog: Option<u32>,
+
+        /// Most TCP or TLS connections to keep open at once, including those
+        /// waiting while another is read, to simulate a backend at capacity.
+        #[arg(long, conflicts_with = "measure_only")]
+        max_connections: Option<NonZeroUsize>,
+
+        /// What happens to connections beyond --max-connections, which are
+        /// either closed as soon as they are accepted or left in the backlog.
+        #[arg(long, default_value = "reject", requires = "max_connections")]
+        connection_overflow: ConnectionOverflow,
+
+        /// Re-emit every UDP datagram received to a multicast group, e.g.
+        /// 239.2.2.2:6000, so several observers can consume it live.
+        #[arg(long)]
+        mirror_multicast: Option<std::net::SocketAddr>,
+
+        /// Join this multicast group, e.g. 239.1.1.1, to receive the UDP
+        /// datagrams sent to it. Usually used with an --address of 0.0.0.0.
+        #[arg(long)]
+        multicast_group: Option<std::net::IpAddr>,
+
+        /// Network interface to join the --multicast-group on, e.g. eth1,
+        /// rather than the one chosen by the system.
+        #[arg(long, requires = "multicast_group")]
+        interface: Option<String>,
+
+        /// Hold back the replies to UDP and Unix datagrams until this many
+        /// messages have been replied to, then send them together.
+        #[arg(long)]
+        ack_every: Option<NonZeroUsize>,
+
+        /// Send held back replies to datagrams once the oldest has waited this
+        /// long, e.g. 5ms, even if fewer than --ack-every are pending.
+        #[arg(long)]
+        flush_interval: Option<humantime::Duration>,
+
+        /// Serve the bytes and messages received, message sizes, active
+        /// connections, connection durations and receive rates for
+        /// Prometheus to scrape, e.g. 127.0.0.1:9090.
+        #[arg(long)]
+        metrics_addr: Option<std::net::SocketAddr>,
+
+        /// Answer Kubernetes style readiness and liveness probes at /ready
+        /// and /live on this address, e.g. 0.0.0.0:8081. The server is ready
+        /// once it is listening.
+        #[arg(long)]
+        health_addr: Option<std::net::SocketAddr>,
+
+        /// Accept commands from `gn ctl` on the Unix socket at this path
+        /// while serving, such as `connections` to list the peer, age and
+        /// bytes read so far of each open connection.
+        #[arg(long, conflicts_with = "measure_only")]
+        control_socket: Option<PathBuf>,
+
+        /// Watch the --config file while serving and apply changes to
+        /// respond-script and capture in its [serve] table without
+        /// restarting, logging each change. Flags given on the command line
+        /// or through the environment are left as they are.
+        #[arg(long, conflicts_with = "measure_only")]
+        watch_config: bool,
+    },
+    /// Print the messages within a capture file.
+    Cat { path: PathBuf },
+    /// Replay the messages within a capture file to a host, in the order they
+    /// were received.
+    Replay {
+        path: PathBuf,
+
+        #[arg(long)]
+        host: Endpoint,
+
+        /// Protocol to replay with, defaulting to the protocol each message
+        /// was captured with.
+        #[arg(long, short)]
+        protocol: Option<Protocol>,
+
+        /// Keep the gaps between messages as they were captured, rather than
+        /// replaying them as fast as possible.
+        #[arg(long)]
+        preserve_timing: bool,
+
+        /// Display statistics about writes
+        #[arg(long)]
+        stats: bool,
+    },
+    /// Render a stored JSON report in another format.
+    Convert {
+        /// Path to the JSON report.
+        path: PathBuf,
+
+        /// Format to render the report in.
+        #[arg(long)]
+        to: ReportFormat,
+    },
+    /// Benchmark a writer and server paired over loopback, giving a baseline
+    /// of what this machine is capable of.
+    Selftest {
+        /// The duration to run each preset for.
+        #[arg(long, short, default_value = "3s")]
+        duration: humantime::Duration,
+    },
+    /// Send a command to the control socket of a running `write` or `serve`,
+    /// e.g. `gn ctl --socket /tmp/gn.sock set-weight 127.0.0.1:5000 0`, and
+    /// print its reply.
+    #[cfg(unix)]
+    Ctl {
+        /// Path of the control socket.
+        #[arg(long)]
+        socket: PathBuf,
+
+        /// The command and its arguments.
+        #[arg(trailing_var_arg = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Inspect how flags are resolved from the command line, their GN_*
+    /// environment variables and the config file, in that order.
+    Config {
+        #[clap(subcommand)]
+        cmd: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the value every flag of a command resolves to and its source,
+    /// e.g. `gn config dump write --host 127.0.0.1:5000`.
+    Dump {
+        /// The command and flags to resolve.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        args: Vec<String>,
     },
 }
 
-#[tokio::main]
-async fn main() -> gn::Result<()> {
+/// Dumps of received messages, given to `serve --dump`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Dump {
+    /// Offsets, hex and ASCII, as with `hexdump -C`.
+    Hex,
+}
+
+/// How the final statistics of a write are printed.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    /// Human-readable lines on stderr, when `--stats` is given.
+    Text,
+    /// The full report as a single JSON document on stdout.
+    Json,
+    /// The full report, with the throughput and latency of every second,
+    /// as a self-contained HTML page of charts on stdout.
+    Html,
+}
+
+/// Flags of the config file which were exported to their environment
+/// variables, so that clap resolves them beneath the command line and the
+/// variables which were already set.
+#[derive(Default)]
+struct ConfigFile {
+    exported: HashSet<String>,
+    /// Flags of the command which were given on the command line or through
+    /// an environment variable the file did not set.
+    given: Vec<String>,
+}
+
+impl ConfigFile {
+    fn load(path: &PathBuf, cmd: &Command) -> gn::Result<Self> {
+        let invalid = |e: &dyn std::fmt::Display| format!("{}: {e}", path.display());
+        let table: toml::Table =
+            toml::from_str(&std::fs::read_to_string(path).map_err(|e| invalid(&e))?)
+                .map_err(|e| invalid(&e))?;
+
+        let mut config = Self::default();
+        for (key, value) in table {
+            match value {
+                toml::Value::Table(flags) => {
+                    let sub = cmd
+                        .find_subcommand(&key)
+                        .ok_or_else(|| invalid(&format!("unknown command [{key}]")))?;
+                    for (flag, value) in flags {
+                        config.export(sub, &flag, value).map_err(|e| invalid(&e))?;
+                    }
+                }
+                value => config.export(cmd, &key, value).map_err(|e| invalid(&e))?,
+            }
+        }
+        Ok(config)
+    }
+
+    /// Note the flags of the matched command which were not taken from the
+    /// file.
+    fn note_given(&mut self, cmd: &Command, matches: &ArgMatches) {
+        let Some((name, matches)) = matches.subcommand() else {
+            return;
+        };
+        let sub = cmd.find_subcommand(name).expect("matched a subcommand");
+        for arg in sub.get_arguments() {
+            let given = match matches.value_source(arg.get_id().as_str()) {
+                Some(ValueSource::CommandLine) => true,
+                Some(ValueSource::EnvVariable) => arg
+                    .get_env()
+                    .is_some_and(|var| !self.exported.contains(&*var.to_string_lossy())),
+                _ => false,
+            };
+            if let (true, Some(long)) = (given, arg.get_long()) {
+                self.given.push(long.to_string());
+            }
+        }
+    }
+
+    /// Set the environment variable of a flag to its value in the file,
+    /// unless the variable is already set.
+    fn export(&mut self, cmd: &Command, flag: &str, value: toml::Value) -> gn::Result<()> {
+        let arg = cmd
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(flag))
+            .ok_or_else(|| format!("unknown flag '{flag}' for {}", cmd.get_name()))?;
+        let var = arg
+            .get_env()
+            .expect("every flag has an environment variable")
+            .to_string_lossy()
+            .into_owned();
+        let value = match value {
+            toml::Value::String(s) => s,
+            toml::Value::Array(values) => match arg.get_value_delimiter() {
+                Some(delimiter) => values
+                    .into_iter()
+                    .map(config_value)
+                    .collect::<gn::Result<Vec<_>>>()?
+                    .join(&delimiter.to_string()),
+                None if values.len() == 1 => config_value(values[0].clone())?,
+                None => {
+                    return Err(format!("'{flag}' can only be given once in a config file").into())
+                }
+            },
+            value => config_value(value)?,
+        };
+        if std::env::var_os(&var).is_none() {
+            std::env::set_var(&var, value);
+            self.exported.insert(var);
+        }
+        Ok(())
+    }
+}
+
+fn config_value(value: toml::Value) -> gn::Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        value => Err(format!("unsupported value {value}").into()),
+    }
+}
+
+/// Wait for the next tick of an optional interval, which never completes
+/// when there is no interval.
+async fn next_tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for a request to shut down, which is Ctrl-C (SIGINT) or, on Unix,
+/// SIGTERM.
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            interrupted = tokio::signal::ctrl_c() => interrupted,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+/// Path of the config file, which is found ahead of parsing the command line
+/// so its flags can be layered beneath it.
+fn config_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1).take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(path.into());
+        }
+    }
+    std::env::var_os(CONFIG_ENV).map(PathBuf::from)
+}
+
+/// Failed requests of the report by cause, e.g. `timeouts 2, other 1`,
+/// leaving out causes which no request failed with.
+fn failure_causes(report: &Report) -> String {
+    report
+        .failure_causes()
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(cause, count)| format!("{cause} {count}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Write the report in the json or html output to the file, or stdout when
+/// there is none.
+fn write_report(report: &Report, output: Output, path: Option<&Path>) -> gn::Result<()> {
+    let format = match output {
+        Output::Html => ReportFormat::Html,
+        _ => ReportFormat::Json,
+    };
+    let mut rendered = report.render(format)?;
+    if !rendered.ends_with('\n') {
+        rendered.push('\n');
+    }
+    match path {
+        Some(path) => std::fs::write(path, rendered)?,
+        None => std::io::stdout().lock().write_all(rendered.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// The command line of [`App`], where every flag can also be given through an
+/// environment variable.
+fn command() -> Command {
+    map_args(App::command(), |command, arg| match arg.get_long() {
+        Some(long) if arg.get_env().is_none() => {
+            let var = [ENV_PREFIX, command.unwrap_or_default(), long]
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join("_")
+                .replace('-', "_")
+                .to_uppercase();
+            arg.env(var)
+        }
+        _ => arg,
+    })
+}
+
+/// Apply `f` to the arguments of the command and each of its subcommands,
+/// along with the name of the subcommand.
+fn map_args(cmd: Command, f: impl Fn(Option<&str>, Arg) -> Arg) -> Command {
+    let names: Vec<String> = cmd
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    let mut cmd = cmd.mut_args(|arg| f(None, arg));
+    for name in names {
+        cmd = cmd.mut_subcommand(&name, |sub| sub.mut_args(|arg| f(Some(&name), arg)));
+    }
+    cmd
+}
+
+fn main() -> gn::Result<()> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let mut config = match config_path(&args) {
+        Some(path) => ConfigFile::load(&path, &command())?,
+        None => ConfigFile::default(),
+    };
+    // Clap reads the environment variables as the command is built, so this
+    // happens after the config file is exported.
+    let mut cmd = command();
+    let matches = cmd
+        .try_get_matches_from_mut(args)
+        .unwrap_or_else(|e| e.exit());
+    let app = App::from_arg_matches(&matches).unwrap_or_else(|e| e.format(&mut cmd).exit());
+    config.note_given(&cmd, &matches);
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    if let Some(cores) = &app.pin_cores {
+        cores.pin_runtime(&mut runtime);
+    }
+    runtime.build()?.block_on(run(app, config))
+}
+
+async fn run(app: App, config: ConfigFile) -> gn::Result<()> {
     let mut out = std::io::stderr().lock();
 
-    match App::parse().cmds {
+    match app.cmds {
         Commands::Write {
             input,
             host,
             count,
+            forever,
             duration,
             concurrency,
+            rate,
+            ramp_up,
+            bandwidth,
+            engine,
+            report_interval,
+            wait_for_target,
+            keepalive,
+            connection_lifetime,
+            duplex,
             protocol,
             stats,
+            quiet,
+            verbose,
+            tui,
+            pushgateway_url,
+            push_interval,
+            sample_resources,
+            output,
+            report_file,
+            record_all_latencies,
+            latency_cap,
+            outlier_threshold,
+            annotate_at,
+            control_socket,
+            family_split,
+            mix,
+            mix_class,
+            input_file,
+            payload_order,
+            reflect_timing,
+            calibrate_clock,
+            traceparent,
+            event_log,
+            transcript,
+            transcript_every,
+            interface,
+            multicast_ttl,
+            connect_timeout,
+            write_timeout,
+            wait_peer_close,
+            close_timeout,
+            retries,
+            retry_backoff,
+            retry_connect_only,
+            connect_concurrency,
+            expect_response,
+            expect_reply,
+            verify_digest,
+            script,
+            max_failures,
+            circuit_breaker,
+            ca_file,
+            insecure,
+            no_session_resumption,
+            ws_path,
+            ws_message,
+            payload_size,
+            pad_to,
+            padding,
+            seed,
+            streaming_generate,
+            generator_threads,
+            payload_regenerate,
         } => {
-            let opts = WriteOptions::from_flags(count, duration, concurrency);
-            let statistics = Statistics::new();
-            let manager = SocketManager::new(host, input.as_bytes(), protocol, opts, statistics);
-            manager.write().await?;
+            let count = if forever { 0 } else { count };
+            let opts = match rate {
+                Some(rate) => WriteOptions::from_rate(rate.get(), count, duration),
+                None => WriteOptions::from_flags(count, duration, concurrency),
+            };
+            let configured = opts.count();
+            // The statistics start timing once they are created, so the wait
+            // is not measured.
+            if let Some(timeout) = wait_for_target {
+                for host in &host {
+                    gn::wait_for_target(host, &protocol, *timeout).await?;
+                }
+            }
+            let mut statistics = Statistics::new();
+            if record_all_latencies {
+                statistics = statistics.with_exact_latencies(latency_cap.get());
+            }
+            let mut tls = TlsConfig::new();
+            if let Some(name) = host[0].host_name() {
+                if host.iter().any(|h| h.host_name() != Some(name))
+                    && matches!(protocol, Protocol::Tls | Protocol::Wss)
+                {
+                    return Err(format!(
+                        "every --host must have the same name with --protocol {protocol}"
+                    )
+                    .into());
+                }
+                tls = tls.with_server_name(name);
+            }
+            if let Some(path) = ca_file {
+                tls = tls.with_ca_file(path);
+            }
+            if insecure {
+                tls = tls.with_insecure();
+            }
+            if no_session_resumption {
+                tls = tls.without_session_resumption();
+            }
+            if streaming_generate && !protocol.is_stream() {
+                return Err(
+                    format!("--streaming-generate is not supported over {protocol}").into(),
+                );
+            }
+            // Generating once is done up front, so no generator threads run.
+            let regenerate = match (payload_regenerate, generator_threads) {
+                (Some(Regeneration::Once), Some(_)) => {
+                    return Err(
+                        "--generator-threads cannot be used with --payload-regenerate once".into(),
+                    )
+                }
+                (None, Some(_)) => Some(Regeneration::PerRequest),
+                (regenerate, _) => regenerate.filter(|r| *r != Regeneration::Once),
+            };
+            let payload = match payload_size {
+                Some(size) if !streaming_generate && regenerate.is_none() => {
+                    gn::random_payload(usize::try_from(size.0)?, seed)
+                }
+                Some(_) => Vec::new(),
+                None if input == "-" => {
+                    let mut payload = Vec::new();
+                    tokio::io::stdin().read_to_end(&mut payload).await?;
+                    payload
+                }
+                None => input.into_bytes(),
+            };
+            let pad_to = pad_to.map(|len| usize::try_from(len.0)).transpose()?;
+            let payload = match pad_to {
+                Some(len) if mix.is_empty() && input_file.is_empty() => {
+                    gn::pad_payload(payload, len, padding, seed)?
+                }
+                _ => payload,
+            };
+            let mut hosts = host.into_iter();
+            let host = hosts.next().expect("--host is required");
+            let other_hosts: Vec<_> = hosts.collect();
+            let mut manager = match host {
+                Endpoint::Unix(_) if !other_hosts.is_empty() => {
+                    return Err("a Unix socket cannot be written to alongside other hosts".into());
+                }
+                Endpoint::Unix(path) => {
+                    SocketManager::unix(path, &payload, protocol, opts, statistics)
+                }
+                host => SocketManager::new(host, &payload, protocol, opts, statistics),
+            }
+            .with_tls(tls)
+            .with_websocket(
+                WebSocketConfig::new()
+                    .with_path(ws_path)
+                    .with_message(ws_message),
+            )
+            .with_engine(engine);
+            if !other_hosts.is_empty() {
+                manager = manager.with_hosts(other_hosts);
+            }
+            if let (Some(size), true) = (payload_size, streaming_generate) {
+                manager = manager.with_streamed_payload(size.0);
+            }
+            if let (Some(size), Some(regenerate)) = (payload_size, regenerate) {
+                let payloads = RandomPayloads::new(usize::try_from(size.0)?, seed);
+                let threads = generator_threads.map_or(1, NonZeroUsize::get);
+                manager = manager
+                    .with_payload_generator(payloads, threads)
+                    .with_payload_regeneration(regenerate);
+            }
+            if let Some(split) = family_split {
+                manager = manager.with_family_split(split);
+            }
+            if let Some(window) = ramp_up {
+                if concurrency.is_none() && rate.is_none() {
+                    return Err("--ramp-up requires --concurrency or --rate".into());
+                }
+                manager = manager.with_ramp_up(*window);
+            }
+            if keepalive {
+                manager = manager.with_keepalive();
+            }
+            if let Some(lifetime) = connection_lifetime {
+                manager = manager.with_connection_lifetime(*lifetime);
+            }
+            if duplex {
+                manager = manager.with_duplex();
+            }
+            if sample_resources {
+                manager = manager.with_resource_sampling();
+            }
+            if reflect_timing {
+                manager = manager.with_reflect_timing();
+            }
+            if let Some(probes) = calibrate_clock {
+                manager = manager.with_clock_calibration(probes.get());
+            }
+            if traceparent {
+                manager = manager.with_traceparent();
+            }
+            if let Some(path) = event_log {
+                manager = manager.with_event_log(std::fs::File::create(path)?);
+            }
+            if let Some(dir) = transcript {
+                manager = manager.with_transcript(Transcript::create(dir, transcript_every)?);
+            }
+            if let Some(interface) = interface {
+                manager = manager.with_interface(interface);
+            }
+            if let Some(ttl) = multicast_ttl {
+                manager = manager.with_multicast_ttl(ttl);
+            }
+            if let Some(timeout) = connect_timeout {
+                manager = manager.with_connect_timeout(*timeout);
+            }
+            if let Some(timeout) = write_timeout {
+                manager = manager.with_write_timeout(*timeout);
+            }
+            if wait_peer_close {
+                manager = manager.with_wait_peer_close(close_timeout.map(Into::into));
+            }
+            if let Some(retries) = retries {
+                manager = manager.with_retries(RetryPolicy {
+                    retries,
+                    backoff: *retry_backoff,
+                    writes: !retry_connect_only,
+                });
+            }
+            if let Some(limit) = connect_concurrency {
+                manager = manager.with_connect_concurrency(limit.get());
+            }
+            if let Some(matcher) = expect_response {
+                manager = manager.with_expect_response(matcher);
+            }
+            if let Some(framing) = expect_reply {
+                manager = manager.with_expect_reply(framing);
+            }
+            if let Some(digest) = verify_digest {
+                manager = manager.with_verify_digest(digest);
+            }
+            if let Some(path) = script {
+                manager = manager.with_hooks(HookScript::load(path)?);
+            }
+            if let Some(max) = max_failures {
+                manager = manager.with_max_failures(max.get());
+            }
+            if let Some(bandwidth) = bandwidth {
+                manager = manager.with_bandwidth(bandwidth);
+            }
+            if let Some(breaker) = circuit_breaker {
+                manager = manager.with_circuit_breaker(breaker);
+            }
+            if let Some(threshold) = outlier_threshold {
+                manager = manager.with_latency_outliers(*threshold);
+            }
+            for annotation in annotate_at {
+                manager = manager.with_annotation(annotation);
+            }
+            if let Some(path) = control_socket {
+                manager = manager.with_control_socket(path);
+            }
+            if output == Output::Html {
+                manager = manager.with_timeline();
+            }
+            if let Some(gateway) = pushgateway_url.clone() {
+                manager = manager.with_observer(gateway.observer(*push_interval));
+            }
+            let payload_mix = if !mix.is_empty() {
+                Some(PayloadMix::from_specs(&mix, &mix_class)?)
+            } else if !input_file.is_empty() {
+                Some(PayloadMix::from_files(&input_file)?)
+            } else {
+                None
+            };
+            if let Some(mut payload_mix) = payload_mix {
+                if let Some(len) = pad_to {
+                    payload_mix = payload_mix.with_padding(len, padding, seed)?;
+                }
+                if let Some(order) = payload_order {
+                    payload_mix = payload_mix.with_order(order);
+                }
+                manager = manager.with_payload_mix(payload_mix);
+            }
+            // The dashboard is closed before the final statistics are printed.
+            let dashboard = tui.then(Dashboard::start).transpose()?;
+            if let Some(dashboard) = &dashboard {
+                manager = manager.with_observer(dashboard.observer());
+            }
+            // Stop gracefully on Ctrl-C or SIGTERM, so the statistics of what
+            // was written are still reported. A second signal abandons the
+            // requests which are still in flight.
+            let write = manager.write();
+            tokio::pin!(write);
+            let shutdown = shutdown_signal();
+            tokio::pin!(shutdown);
+            let mut report_tick = report_interval.map(|interval| {
+                tokio::time::interval_at(tokio::time::Instant::now() + *interval, *interval)
+            });
+            loop {
+                tokio::select! {
+                    written = &mut write => {
+                        written?;
+                        break;
+                    }
+                    signalled = &mut shutdown => {
+                        signalled?;
+                        manager.stop();
+                        tokio::select! {
+                            written = &mut write => {
+                                written?;
+                            }
+                            signalled = shutdown_signal() => signalled?,
+                        }
+                        break;
+                    }
+                    _ = next_tick(&mut report_tick) => {
+                        let interval = manager.take_interval();
+                        write!(
+                            out,
+                            "[{}s] Sent: {} bytes, {:.1} requests/s, {} errors",
+                            manager.elapsed() / 1000,
+                            interval.bytes,
+                            interval.request_rate(),
+                            interval.failures
+                        )?;
+                        match interval.generator_waits {
+                            0 => writeln!(out)?,
+                            waits => writeln!(out, ", generator-starved ({waits} requests waited)")?,
+                        }
+                    }
+                }
+            }
 
-            if stats {
+            drop(dashboard);
+            let report = manager.report();
+            if let Some(gateway) = pushgateway_url {
+                if let Err(e) = gateway.push(&report).await {
+                    writeln!(out, "Unable to push metrics: {e}")?;
+                }
+            }
+            if quiet {
+                let mut stdout = std::io::stdout().lock();
+                match output {
+                    Output::Text => writeln!(stdout, "{}", report.summary())?,
+                    _ => write_report(&report, output, report_file.as_deref())?,
+                }
+                return Ok(());
+            }
+            if let (Some(requested), Some(achieved)) = (report.requested_rate, report.achieved_rate)
+            {
+                if achieved < requested as f64 * RATE_TOLERANCE {
+                    writeln!(
+                        out,
+                        "Warning: only achieved {achieved:.1} of the {requested} requests/s which were requested, as the machine could not sustain the rate"
+                    )?;
+                }
+            }
+
+            if output != Output::Text {
+                write_report(&report, output, report_file.as_deref())?;
+            } else if stats || verbose > 0 {
                 match manager.elapsed() {
                     0..1000 => writeln!(
                         out,
@@ -91,18 +1353,511 @@ async fn main() -> gn::Result<()> {
                     )?,
                 }
                 writeln!(out, "Throughput: {} bytes per second", manager.throughput())?;
-                writeln!(
+                if duplex {
+                    writeln!(
+                        out,
+                        "Inbound: {} bytes received, {} bytes per second",
+                        report.inbound_bytes, report.inbound_throughput
+                    )?;
+                }
+                write!(
                     out,
                     "Requests: {}/{} ({:.2}%) successful",
                     manager.successful_requests(),
-                    count,
+                    manager.request_count(),
                     manager.successful_requests_percentage()
                 )?;
+                match configured {
+                    Some(configured) => writeln!(out, ", {configured} configured")?,
+                    None => writeln!(out)?,
+                }
+                if keepalive {
+                    write!(
+                        out,
+                        "Connections: {} opened for {} requests",
+                        report.connections_opened, report.requests
+                    )?;
+                    match connection_lifetime {
+                        Some(_) => writeln!(out, ", {} recycled", report.connections_recycled)?,
+                        None => writeln!(out)?,
+                    }
+                }
+                if report.stop_reason == StopReason::MaxFailures {
+                    writeln!(
+                        out,
+                        "Stopped early: reached {} failed requests",
+                        report.failed_requests
+                    )?;
+                }
+                if report.partial_writes > 0 {
+                    writeln!(
+                        out,
+                        "Partial writes: {} failed requests were partially written",
+                        report.partial_writes
+                    )?;
+                }
+                if report.mismatched_responses > 0 {
+                    writeln!(
+                        out,
+                        "Failures: {} transport errors, {} mismatched responses",
+                        report.failed_requests - report.mismatched_responses,
+                        report.mismatched_responses
+                    )?;
+                }
+                if report.retries > 0 {
+                    writeln!(
+                        out,
+                        "Retries: {} attempts were retried ({} failed to connect, {} after connecting)",
+                        report.retries, report.connect_retries, report.write_retries
+                    )?;
+                }
+                if report.resolution_failures > 0 {
+                    writeln!(
+                        out,
+                        "Resolution: {} attempts to resolve the host failed",
+                        report.resolution_failures
+                    )?;
+                }
+                if report.generator_waits > 0 {
+                    writeln!(
+                        out,
+                        "Generator: {} requests waited {:.1}ms in total for their payload",
+                        report.generator_waits, report.generator_wait_ms
+                    )?;
+                }
+                if report.timeouts > 0 {
+                    writeln!(out, "Timeouts: {} requests timed out", report.timeouts)?;
+                }
+                if report.unacknowledged_requests > 0 {
+                    writeln!(
+                        out,
+                        "Unacknowledged: {} requests written but never acknowledged, {} failed to send",
+                        report.unacknowledged_requests, report.send_failures
+                    )?;
+                }
+                if report.connection_resets + report.premature_closes > 0 {
+                    writeln!(
+                        out,
+                        "Closed by peer: {} connections reset, {} closed before the reply was complete",
+                        report.connection_resets, report.premature_closes
+                    )?;
+                }
+                if report.would_block + report.no_buffer_space > 0 {
+                    writeln!(
+                        out,
+                        "Dropped at source: {} would block, {} no buffer space (send queue peaked at {} bytes)",
+                        report.would_block, report.no_buffer_space, report.send_queue_peak_bytes
+                    )?;
+                }
+                for offset in &report.clock_offsets {
+                    writeln!(
+                        out,
+                        "Clock offset: {} is {:+.1}us ahead (probe round trip {:.1}us)",
+                        offset.addr, offset.offset_us, offset.round_trip_us
+                    )?;
+                }
+                for event in &report.circuit_events {
+                    writeln!(
+                        out,
+                        "Circuit {}: {} after {}ms",
+                        event.state, event.addr, event.at_ms
+                    )?;
+                }
+                if let (Some(min), Some(mean), Some(max)) = (
+                    report.one_way_delay_min_us,
+                    report.one_way_delay_mean_us,
+                    report.one_way_delay_max_us,
+                ) {
+                    writeln!(
+                        out,
+                        "One-way delay: min {min:.1}us, mean {mean:.1}us, max {max:.1}us"
+                    )?;
+                }
+                if let (Some(requested), Some(p50), Some(p99), Some(max)) = (
+                    report.requested_rate,
+                    report.send_lag_p50_us,
+                    report.send_lag_p99_us,
+                    report.send_lag_max_us,
+                ) {
+                    writeln!(
+                        out,
+                        "Rate: {:.1}/{requested} requests/s achieved, send lag p50 {p50:.1}us, p99 {p99:.1}us, max {max:.1}us",
+                        report.achieved_rate.unwrap_or_default()
+                    )?;
+                }
+                if let (Some(avg), Some(peak), Some(thread), Some(rss)) = (
+                    report.cpu_avg_percent,
+                    report.cpu_peak_percent,
+                    report.thread_cpu_peak_percent,
+                    report.rss_peak_bytes,
+                ) {
+                    writeln!(
+                        out,
+                        "Resources: CPU avg {avg:.1}%, peak {peak:.1}% (busiest thread {thread:.1}%), peak memory {:.1}MiB",
+                        rss as f64 / (1 << 20) as f64
+                    )?;
+                }
+                if let (Some(p50), Some(p90), Some(p99), Some(max)) = (
+                    report.latency_p50_us,
+                    report.latency_p90_us,
+                    report.latency_p99_us,
+                    report.latency_max_us,
+                ) {
+                    writeln!(
+                        out,
+                        "Latency ({}): p50 {p50:.1}us, p90 {p90:.1}us, p99 {p99:.1}us, max {max:.1}us",
+                        report.latency_percentiles
+                    )?;
+                }
+                for annotation in &report.annotations {
+                    writeln!(
+                        out,
+                        "Annotation: {} at {:.1}s",
+                        annotation.message,
+                        annotation.at_ms as f64 / 1000.0
+                    )?;
+                }
+                for outlier in &report.latency_outliers {
+                    let at = UNIX_EPOCH + Duration::from_nanos(outlier.started_at_ns);
+                    writeln!(
+                        out,
+                        "Outlier: {}us to {} at {}, {} bytes with hash {} starting \"{}\"",
+                        outlier.latency_us,
+                        outlier.addr,
+                        humantime::format_rfc3339_nanos(at),
+                        outlier.payload_len,
+                        outlier.payload_hash,
+                        outlier.payload_prefix
+                    )?;
+                }
+                if let (Some(p50), Some(p99), Some(max)) =
+                    (report.rtt_p50_us, report.rtt_p99_us, report.rtt_max_us)
+                {
+                    writeln!(
+                        out,
+                        "Replies: {} bytes received, round trip p50 {p50:.1}us, p99 {p99:.1}us, max {max:.1}us",
+                        report.reply_bytes
+                    )?;
+                }
+                if let (Some(p50), Some(p99), Some(max)) =
+                    (report.ttfb_p50_us, report.ttfb_p99_us, report.ttfb_max_us)
+                {
+                    writeln!(
+                        out,
+                        "Time to first byte: p50 {p50:.1}us, p99 {p99:.1}us, max {max:.1}us"
+                    )?;
+                }
+                if let (Some(p50), Some(p99), Some(max)) = (
+                    report.tls_handshake_p50_us,
+                    report.tls_handshake_p99_us,
+                    report.tls_handshake_max_us,
+                ) {
+                    writeln!(
+                        out,
+                        "TLS handshakes: {} full, {} resumed, p50 {p50:.1}us, p99 {p99:.1}us, max {max:.1}us",
+                        report.tls_full_handshakes, report.tls_resumed_handshakes
+                    )?;
+                }
+                if verbose > 0 {
+                    if report.failed_requests > 0 {
+                        writeln!(out, "Failure causes: {}", failure_causes(&report))?;
+                    }
+                    let breakdown = manager
+                        .family_statistics()
+                        .into_iter()
+                        .chain(manager.host_statistics())
+                        .chain(manager.payload_statistics());
+                    for (name, stats) in breakdown {
+                        writeln!(
+                            out,
+                            "[{name}] Sent: {} bytes, Requests: {}/{} ({:.2}%) successful",
+                            stats.total_bytes(),
+                            stats.successful_requests(),
+                            stats.request_count(),
+                            stats.success_percentage()
+                        )?;
+                        if verbose < 2 {
+                            continue;
+                        }
+                        let report = Report::from(&*stats);
+                        if let (Some(p50), Some(p90), Some(p99), Some(max)) = (
+                            report.latency_p50_us,
+                            report.latency_p90_us,
+                            report.latency_p99_us,
+                            report.latency_max_us,
+                        ) {
+                            writeln!(
+                                out,
+                                "[{name}] Latency: p50 {p50:.1}us, p90 {p90:.1}us, p99 {p99:.1}us, max {max:.1}us"
+                            )?;
+                        }
+                        if report.failed_requests > 0 {
+                            writeln!(out, "[{name}] Failure causes: {}", failure_causes(&report))?;
+                        }
+                    }
+                }
             }
         }
-        Commands::Serve { address, protocol } => {
-            let mut server = Server::new(address, protocol, out);
-            server.serve().await?;
+        Commands::Serve {
+            address,
+            protocol,
+            measure_only,
+            render,
+            dump,
+            out_file,
+            rotate_size,
+            rotate_interval,
+            rotate_keep,
+            reflect_timing,
+            capture,
+            respond_script,
+            reply_digest,
+            delay,
+            chaos,
+            cert,
+            key,
+            sni_route,
+            close_with_rst,
+            reuseport,
+            recv_buffer_size,
+            backlog,
+            max_connections,
+            connection_overflow,
+            mirror_multicast,
+            multicast_group,
+            interface,
+            metrics_addr,
+            health_addr,
+            control_socket,
+            ack_every,
+            flush_interval,
+            watch_config,
+        } => {
+            // Ports chosen by the system are announced for scripts to find.
+            let any_port = match &address {
+                Endpoint::Inet(addr) => addr.port() == 0,
+                Endpoint::Host(host) => host.ends_with(":0"),
+                Endpoint::Unix(_) => false,
+            } || [metrics_addr, health_addr]
+                .iter()
+                .flatten()
+                .any(|addr| addr.port() == 0);
+            let sink: Box<dyn Write> = match out_file {
+                Some(path) => {
+                    let mut file = RotatingFile::create(path)?;
+                    if let Some(size) = rotate_size {
+                        file = file.with_max_size(size.0);
+                    }
+                    if let Some(interval) = rotate_interval {
+                        file = file.with_interval(*interval);
+                    }
+                    if let Some(keep) = rotate_keep {
+                        file = file.with_keep(keep);
+                    }
+                    Box::new(file)
+                }
+                None => Box::new(out),
+            };
+            let render = match dump {
+                Some(Dump::Hex) => Render::Hexdump,
+                None => render,
+            };
+            let mut server = Server::new(address, protocol, sink).render(render);
+            if any_port {
+                server = server.announce_bind();
+            }
+            if reuseport {
+                server = server.reuseport();
+            }
+            if let Some(size) = recv_buffer_size {
+                server = server.recv_buffer_size(usize::try_from(size.0)?);
+            }
+            if let Some(len) = backlog {
+                server = server.backlog(len);
+            }
+            if let Some(limit) = max_connections {
+                server = server.max_connections(limit.get(), connection_overflow);
+            }
+            if let Some(group) = mirror_multicast {
+                server = server.mirror_multicast(group);
+            }
+            if let Some(group) = multicast_group {
+                server = server.multicast_group(group);
+            }
+            if let Some(interface) = interface {
+                server = server.multicast_interface(interface);
+            }
+            if let Some(addr) = metrics_addr {
+                server = server.metrics_addr(addr);
+            }
+            if let Some(addr) = health_addr {
+                server = server.health_addr(addr);
+            }
+            if let Some(path) = control_socket {
+                server = server.control_socket(path);
+            }
+            if let Some(messages) = ack_every {
+                server = server.ack_every(messages.get());
+            }
+            if let Some(interval) = flush_interval {
+                server = server.flush_interval(*interval);
+            }
+            if close_with_rst {
+                server = server.close_with_rst();
+            }
+            if measure_only {
+                server = server.measure_only();
+            }
+            if reflect_timing {
+                server = server.reflect_timing();
+            }
+            if let Some(digest) = reply_digest {
+                server = server.reply_digest(digest);
+            }
+            if let Some(delay) = delay {
+                server = server.delay(delay);
+            }
+            if let Some(chaos) = chaos {
+                server = server.chaos(chaos);
+            }
+            if let Some(path) = respond_script {
+                server = server.respond_script(ResponseScript::load(path)?);
+            }
+            if let Some(path) = capture {
+                server = server.capture(CaptureWriter::create(path)?);
+            }
+            if let (Some(cert), Some(key)) = (cert, key) {
+                server = server.tls(TlsServerConfig::load(cert, key)?);
+            }
+            for route in sni_route {
+                let file = BufWriter::new(File::create(&route.path)?);
+                server = server.sni_route(&route.name, file);
+            }
+            if watch_config {
+                let path = app
+                    .config
+                    .as_ref()
+                    .ok_or("--watch-config requires a --config file")?;
+                let watcher = config
+                    .given
+                    .iter()
+                    .fold(ConfigWatcher::new(path)?, |watcher, flag| watcher.pin(flag));
+                server = server.watch_config(watcher);
+            }
+            // Stop on Ctrl-C or SIGTERM, dropping any connection which is
+            // being handled, and log what was received before exiting.
+            tokio::select! {
+                served = server.serve() => served?,
+                signalled = shutdown_signal() => {
+                    signalled?;
+                    server.log_summary();
+                }
+            }
+        }
+        Commands::Cat { path } => {
+            for record in CaptureReader::open(path)? {
+                let record = record?;
+                let at = UNIX_EPOCH + Duration::from_nanos(record.timestamp);
+                writeln!(
+                    out,
+                    "{} {}://{} {} bytes",
+                    humantime::format_rfc3339_nanos(at),
+                    record.protocol,
+                    record.peer,
+                    record.data.len()
+                )?;
+                writeln!(out, "{}", String::from_utf8_lossy(&record.data))?;
+            }
+        }
+        Commands::Replay {
+            path,
+            host,
+            protocol,
+            preserve_timing,
+            stats,
+        } => {
+            let statistics = Statistics::new();
+            let reader = CaptureReader::open(path)?;
+            gn::replay(reader, host, protocol, preserve_timing, &statistics).await?;
+            if stats {
+                writeln!(
+                    out,
+                    "Sent: {} bytes, Requests: {}/{} ({:.2}%) successful",
+                    statistics.total_bytes(),
+                    statistics.successful_requests(),
+                    statistics.request_count(),
+                    statistics.success_percentage()
+                )?;
+            }
+        }
+        Commands::Convert { path, to } => {
+            let report = Report::from_json(&std::fs::read_to_string(path)?)?;
+            write!(out, "{}", report.render(to)?)?;
+        }
+        Commands::Selftest { duration } => {
+            for result in gn::selftest(duration).await? {
+                writeln!(
+                    out,
+                    "{}: {:.0} requests/s, {:.0} bytes/s sent, {:.0} bytes/s received ({:.2}% successful)",
+                    result.name,
+                    result.requests_per_second,
+                    result.bytes_per_second,
+                    result.received_bytes_per_second,
+                    result.success_percentage
+                )?;
+            }
+        }
+        #[cfg(unix)]
+        Commands::Ctl { socket, command } => {
+            write!(out, "{}", gn::send_command(&socket, &command).await?)?;
+        }
+        Commands::Config {
+            cmd: ConfigCommand::Dump { args },
+        } => {
+            // Keep every value as it was given, without parsing it, so that
+            // values are shown as they were written, e.g. `1GiB`.
+            let mut cmd = map_args(command(), |_, arg| {
+                if arg.get_action().takes_values() {
+                    arg.value_parser(ValueParser::os_string())
+                } else {
+                    arg
+                }
+            });
+            let matches = cmd
+                .try_get_matches_from_mut(std::iter::once("gn".to_string()).chain(args))
+                .unwrap_or_else(|e| e.exit());
+            let (name, matches) = matches
+                .subcommand()
+                .ok_or("expected a command to resolve, e.g. write")?;
+            let sub = cmd.find_subcommand(name).expect("matched a subcommand");
+
+            if let Some(path) = &app.config {
+                writeln!(out, "# Config file: {}", path.display())?;
+            }
+            writeln!(out, "[{name}]")?;
+            for arg in sub.get_arguments() {
+                let id = arg.get_id().as_str();
+                let (Some(source), Some(values)) = (matches.value_source(id), matches.get_raw(id))
+                else {
+                    continue;
+                };
+                let var = arg.get_env().map(|var| var.to_string_lossy().into_owned());
+                let source = match (source, var) {
+                    (ValueSource::CommandLine, _) => "command line".to_string(),
+                    (ValueSource::EnvVariable, Some(var)) if config.exported.contains(&var) => {
+                        "config file".to_string()
+                    }
+                    (ValueSource::EnvVariable, Some(var)) => format!("environment {var}"),
+                    _ => "default".to_string(),
+                };
+                let values: Vec<_> = values.map(|value| value.to_string_lossy()).collect();
+                writeln!(
+                    out,
+                    "{} = {:?}  # {source}",
+                    arg.get_long().unwrap_or(id),
+                    values.join(",")
+                )?;
+            }
         }
     };
     Ok(())
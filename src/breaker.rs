@@ -0,0 +1,155 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::report::CircuitState;
+
+/// Cool-down used when a [`CircuitBreaker`] is given without one.
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(5);
+
+/// Stop sending to a target after a number of consecutive failures, for a
+/// cool-down period, parsed from `FAILURES[:COOLDOWN]` such as `5:10s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    pub failures: u32,
+    pub cooldown: Duration,
+}
+
+impl FromStr for CircuitBreaker {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (failures, cooldown) = match s.split_once(':') {
+            Some((failures, cooldown)) => {
+                let cooldown = cooldown
+                    .trim()
+                    .parse::<humantime::Duration>()
+                    .map_err(|e| format!("invalid cool-down '{cooldown}': {e}"))?;
+                (failures, *cooldown)
+            }
+            None => (s, DEFAULT_COOLDOWN),
+        };
+        let failures = failures
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid failure count '{failures}': {e}"))?;
+        if failures == 0 {
+            return Err("the failure count must be at least 1".to_string());
+        }
+        Ok(Self { failures, cooldown })
+    }
+}
+
+impl Display for CircuitBreaker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.failures,
+            humantime::format_duration(self.cooldown)
+        )
+    }
+}
+
+/// State of the [`CircuitBreaker`] of a single address.
+///
+/// Once the cool-down has passed, requests are sent to the address again. A
+/// success closes the circuit, whereas a failure opens it straight away.
+#[derive(Default)]
+pub(crate) struct Breaker {
+    consecutive_failures: AtomicU32,
+    open_until: Mutex<Option<Instant>>,
+}
+
+impl Breaker {
+    pub(crate) fn is_open(&self, now: Instant) -> bool {
+        self.open_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| until > now)
+    }
+
+    /// Time at which an open circuit allows requests again.
+    pub(crate) fn open_until(&self) -> Option<Instant> {
+        *self.open_until.lock().unwrap()
+    }
+
+    /// Record the outcome of a request, returning the new state if the
+    /// circuit opened or closed.
+    pub(crate) fn record(&self, config: &CircuitBreaker, success: bool) -> Option<CircuitState> {
+        if success {
+            let failures = self.consecutive_failures.swap(0, Ordering::Relaxed);
+            if failures >= config.failures {
+                *self.open_until.lock().unwrap() = None;
+                return Some(CircuitState::Closed);
+            }
+            return None;
+        }
+
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        let mut open_until = self.open_until.lock().unwrap();
+        if failures >= config.failures && !open_until.is_some_and(|until| until > now) {
+            *open_until = Some(now + config.cooldown);
+            return Some(CircuitState::Open);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{Breaker, CircuitBreaker};
+    use crate::report::CircuitState;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "5:10s".parse::<CircuitBreaker>().unwrap(),
+            CircuitBreaker {
+                failures: 5,
+                cooldown: Duration::from_secs(10)
+            }
+        );
+        assert_eq!(
+            "3".parse::<CircuitBreaker>().unwrap().cooldown,
+            Duration::from_secs(5)
+        );
+        assert!("0".parse::<CircuitBreaker>().is_err());
+        assert!("5:soon".parse::<CircuitBreaker>().is_err());
+    }
+
+    #[tokio::test]
+    async fn transitions() {
+        let config = CircuitBreaker {
+            failures: 2,
+            cooldown: Duration::from_millis(50),
+        };
+        let breaker = Breaker::default();
+        assert_eq!(breaker.record(&config, false), None);
+        assert_eq!(breaker.record(&config, false), Some(CircuitState::Open));
+        assert!(breaker.is_open(Instant::now()));
+
+        // A failure after the cool-down opens the circuit again.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!breaker.is_open(Instant::now()));
+        assert_eq!(breaker.record(&config, false), Some(CircuitState::Open));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(breaker.record(&config, true), Some(CircuitState::Closed));
+        assert_eq!(breaker.record(&config, true), None);
+        assert!(!breaker.is_open(Instant::now()));
+    }
+}
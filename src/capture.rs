@@ -0,0 +1,313 @@
+//! A simple binary container for messages received by the server.
+//!
+//! A capture starts with the magic bytes `GNCAP` and a version byte, followed
+//! by one record per message. All integers are big-endian:
+//!
+//! | Field     | Size          | Description                                   |
+//! |-----------|---------------|-----------------------------------------------|
+//! | timestamp | 8             | Nanoseconds since the UNIX epoch              |
+//! | protocol  | 1             | `0` TCP, `1` UDP, `2` Unix, `3` Unix datagram, `4` TLS |
+//! | family    | 1             | `4` or `6`                                    |
+//! | address   | 4 or 16       | IP address of the peer, unspecified for Unix  |
+//! | port      | 2             | Port of the peer                              |
+//! | length    | 4             | Length of the message                         |
+//! | data      | length        | The message itself                            |
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::{
+    endpoint::{Endpoint, UNIX_PEER},
+    statistics::Statistics,
+    timing,
+    transport::{self, PartialWrite},
+    Protocol,
+};
+
+const MAGIC: &[u8] = b"GNCAP";
+const VERSION: u8 = 1;
+
+/// A single captured message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    /// Nanoseconds since the UNIX epoch at which the message was received.
+    pub timestamp: u64,
+    pub protocol: Protocol,
+    pub peer: SocketAddr,
+    pub data: Vec<u8>,
+}
+
+/// Appends [`CaptureRecord`]s to a capture.
+pub struct CaptureWriter<W: Write> {
+    inner: W,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    /// Create a capture file at the given path, truncating any existing file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a new capture, writing the header.
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION])?;
+        Ok(Self { inner })
+    }
+
+    /// Record a message which was received now.
+    pub fn record(&mut self, protocol: &Protocol, peer: SocketAddr, data: &[u8]) -> io::Result<()> {
+        self.write_record(&CaptureRecord {
+            timestamp: timing::now(),
+            protocol: protocol.clone(),
+            peer,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Append a record, flushing it so the capture is readable while the
+    /// server is still running.
+    pub fn write_record(&mut self, record: &CaptureRecord) -> io::Result<()> {
+        let len = u32::try_from(record.data.len())
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "message is too large"))?;
+        self.inner.write_all(&record.timestamp.to_be_bytes())?;
+        self.inner.write_all(&[match record.protocol {
+            Protocol::Tcp => 0,
+            Protocol::Udp => 1,
+            Protocol::Unix => 2,
+            Protocol::UnixDatagram => 3,
+            Protocol::Tls => 4,
+            Protocol::Ws => 5,
+            Protocol::Wss => 6,
+        }])?;
+        match record.peer.ip() {
+            IpAddr::V4(ip) => {
+                self.inner.write_all(&[4])?;
+                self.inner.write_all(&ip.octets())?;
+            }
+            IpAddr::V6(ip) => {
+                self.inner.write_all(&[6])?;
+                self.inner.write_all(&ip.octets())?;
+            }
+        }
+        self.inner.write_all(&record.peer.port().to_be_bytes())?;
+        self.inner.write_all(&len.to_be_bytes())?;
+        self.inner.write_all(&record.data)?;
+        self.inner.flush()
+    }
+}
+
+/// Reads the [`CaptureRecord`]s of a capture in order.
+pub struct CaptureReader<R: Read> {
+    inner: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+    /// Open the capture file at the given path.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Open a capture, validating its header.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut header = [0; MAGIC.len() + 1];
+        inner.read_exact(&mut header)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a gn capture"));
+        }
+        if header[MAGIC.len()] != VERSION {
+            return Err(invalid_data("unsupported capture version"));
+        }
+        Ok(Self { inner })
+    }
+
+    /// Read the next record, which is `None` at the end of the capture.
+    pub fn next_record(&mut self) -> io::Result<Option<CaptureRecord>> {
+        let mut timestamp = [0; 8];
+        match self.inner.read_exact(&mut timestamp) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let protocol = match self.read_u8()? {
+            0 => Protocol::Tcp,
+            1 => Protocol::Udp,
+            2 => Protocol::Unix,
+            3 => Protocol::UnixDatagram,
+            4 => Protocol::Tls,
+            5 => Protocol::Ws,
+            6 => Protocol::Wss,
+            _ => return Err(invalid_data("unknown protocol")),
+        };
+        let ip = match self.read_u8()? {
+            4 => {
+                let mut octets = [0; 4];
+                self.inner.read_exact(&mut octets)?;
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+            6 => {
+                let mut octets = [0; 16];
+                self.inner.read_exact(&mut octets)?;
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return Err(invalid_data("unknown address family")),
+        };
+        let mut port = [0; 2];
+        self.inner.read_exact(&mut port)?;
+        let mut len = [0; 4];
+        self.inner.read_exact(&mut len)?;
+        let mut data = vec![0; u32::from_be_bytes(len) as usize];
+        self.inner.read_exact(&mut data)?;
+
+        Ok(Some(CaptureRecord {
+            timestamp: u64::from_be_bytes(timestamp),
+            protocol,
+            peer: SocketAddr::new(ip, u16::from_be_bytes(port)),
+            data,
+        }))
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut byte = [0; 1];
+        self.inner.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Replay every message of a capture to `endpoint`, using the protocol each
+/// message was captured with unless one is given. When `preserve_timing` is
+/// set, the gaps between messages are kept as they were captured.
+pub async fn replay<R: Read>(
+    reader: CaptureReader<R>,
+    endpoint: impl Into<Endpoint>,
+    protocol: Option<Protocol>,
+    preserve_timing: bool,
+    stats: &Statistics,
+) -> crate::Result<()> {
+    let endpoint = endpoint.into().resolved()?;
+    let addr = match &endpoint {
+        Endpoint::Inet(addr) => *addr,
+        Endpoint::Host(_) | Endpoint::Unix(_) => UNIX_PEER,
+    };
+    let config = transport::TransportConfig::default();
+    let start = Instant::now();
+    let mut first = None;
+    for record in reader {
+        let record = record?;
+        if preserve_timing {
+            let first = *first.get_or_insert(record.timestamp);
+            let offset = Duration::from_nanos(record.timestamp.saturating_sub(first));
+            tokio::time::sleep_until(start + offset).await;
+        }
+        let protocol = protocol.as_ref().unwrap_or(&record.protocol);
+        let transport = transport::for_endpoint(protocol, &endpoint, &config)?;
+        match transport.write(addr, &record.data).await {
+            Ok(b) => {
+                stats.increment_total(b);
+                stats.record_success();
+            }
+            Err(e) => {
+                stats.record_failure();
+                if let Some(partial) = e.downcast_ref::<PartialWrite>() {
+                    stats.record_partial_write(partial.written);
+                }
+            }
+        }
+    }
+    stats.record_throughput();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::{replay, CaptureReader, CaptureRecord, CaptureWriter};
+    use crate::{statistics::Statistics, Protocol};
+
+    #[test]
+    fn round_trip() {
+        let records = vec![
+            CaptureRecord {
+                timestamp: 1,
+                protocol: Protocol::Tcp,
+                peer: "127.0.0.1:5000".parse().unwrap(),
+                data: b"hello".to_vec(),
+            },
+            CaptureRecord {
+                timestamp: 2,
+                protocol: Protocol::Udp,
+                peer: "[::1]:6000".parse().unwrap(),
+                data: vec![0, 159, 146, 150],
+            },
+        ];
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        for record in &records {
+            writer.write_record(record).unwrap();
+        }
+
+        let reader = CaptureReader::new(Cursor::new(writer.inner)).unwrap();
+        let read: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(read, records);
+    }
+
+    #[test]
+    fn invalid_header() {
+        assert!(CaptureReader::new(Cursor::new(b"NOTCAP".to_vec())).is_err());
+        assert!(CaptureReader::new(Cursor::new(b"GNCAP\x09".to_vec())).is_err());
+    }
+
+    #[tokio::test]
+    async fn replay_tcp() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let peer = "127.0.0.1:6000".parse().unwrap();
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer.record(&Protocol::Tcp, peer, b"first").unwrap();
+        writer.record(&Protocol::Tcp, peer, b"second").unwrap();
+        let reader = CaptureReader::new(Cursor::new(writer.inner)).unwrap();
+
+        let received = tokio::spawn(async move {
+            let mut messages = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut message = String::new();
+                stream.read_to_string(&mut message).await.unwrap();
+                messages.push(message);
+            }
+            messages
+        });
+
+        let stats = Statistics::new();
+        replay(reader, addr, None, true, &stats).await.unwrap();
+        assert_eq!(stats.successful_requests(), 2);
+        assert_eq!(stats.total_bytes(), 11);
+        assert_eq!(received.await.unwrap(), vec!["first", "second"]);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::ByteSize;
+
+/// Faults a [`crate::Server`] injects into the connections it accepts,
+/// parsed from a list such as `drop=10%,reset=5%,close-after=512B`.
+///
+/// Each connection is dropped or reset with the given chance, and any other
+/// is closed once the given number of bytes has been read from it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Chaos {
+    /// Chance of holding a connection open without reading from it, or of
+    /// discarding a datagram, so the writer times out waiting for a reply.
+    drop: f64,
+    /// Chance of aborting a connection with a reset (RST) once accepted.
+    reset: f64,
+    /// Bytes read from a connection before it is closed without a reply.
+    close_after: Option<u64>,
+}
+
+/// Fault injected into a single connection or datagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fault {
+    Drop,
+    Reset,
+    CloseAfter(u64),
+}
+
+impl Chaos {
+    /// Draw the fault to inject into the next connection or datagram, if any.
+    pub(crate) fn draw(&self) -> Option<Fault> {
+        let roll: f64 = rand::random();
+        if roll < self.drop {
+            Some(Fault::Drop)
+        } else if roll < self.drop + self.reset {
+            Some(Fault::Reset)
+        } else {
+            self.close_after.map(Fault::CloseAfter)
+        }
+    }
+
+    /// Whether only datagrams are dropped, as they cannot be reset or closed.
+    pub(crate) fn is_drop_only(&self) -> bool {
+        self.reset == 0.0 && self.close_after.is_none()
+    }
+}
+
+/// Parse a percentage such as `10%` into a fraction.
+fn percentage(key: &str, value: &str) -> Result<f64, String> {
+    let percent: f64 = value
+        .trim()
+        .strip_suffix('%')
+        .ok_or_else(|| format!("the chance to {key} must be a percentage, such as 10%"))?
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid chance to {key} '{value}': {e}"))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!(
+            "the chance to {key} must be between 0% and 100%, not {value}"
+        ));
+    }
+    Ok(percent / 100.0)
+}
+
+impl FromStr for Chaos {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chaos = Self::default();
+        for fault in s.split(',') {
+            let (key, value) = fault
+                .split_once('=')
+                .ok_or_else(|| format!("expected a fault such as drop=10%, found '{fault}'"))?;
+            match key.trim() {
+                "drop" => chaos.drop = percentage("drop", value)?,
+                "reset" => chaos.reset = percentage("reset", value)?,
+                "close-after" => chaos.close_after = Some(value.parse::<ByteSize>()?.0),
+                key => {
+                    return Err(format!(
+                        "unknown fault '{key}', expected drop, reset or close-after"
+                    ))
+                }
+            }
+        }
+        if chaos.drop + chaos.reset > 1.0 {
+            return Err("the chances to drop and reset add up to more than 100%".to_string());
+        }
+        Ok(chaos)
+    }
+}
+
+impl Display for Chaos {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut faults = Vec::new();
+        if self.drop > 0.0 {
+            faults.push(format!("drop={}%", self.drop * 100.0));
+        }
+        if self.reset > 0.0 {
+            faults.push(format!("reset={}%", self.reset * 100.0));
+        }
+        if let Some(bytes) = self.close_after {
+            faults.push(format!("close-after={}", ByteSize(bytes)));
+        }
+        write!(f, "{}", faults.join(","))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Chaos, Fault};
+
+    #[test]
+    fn parse() {
+        let chaos = "drop=10%,reset=5%,close-after=512B"
+            .parse::<Chaos>()
+            .unwrap();
+        assert_eq!(chaos.to_string(), "drop=10%,reset=5%,close-after=512B");
+        assert!(!chaos.is_drop_only());
+
+        let reset = "reset=100%".parse::<Chaos>().unwrap();
+        for _ in 0..100 {
+            assert_eq!(reset.draw(), Some(Fault::Reset));
+        }
+        let close = "close-after=1KiB".parse::<Chaos>().unwrap();
+        assert_eq!(close.draw(), Some(Fault::CloseAfter(1024)));
+        assert!("drop=0%".parse::<Chaos>().unwrap().draw().is_none());
+        assert!("drop=50%".parse::<Chaos>().unwrap().is_drop_only());
+
+        assert!("drop=10".parse::<Chaos>().is_err());
+        assert!("drop=120%".parse::<Chaos>().is_err());
+        assert!("drop=60%,reset=60%".parse::<Chaos>().is_err());
+        assert!("stall=10%".parse::<Chaos>().is_err());
+        assert!("close-after=lots".parse::<Chaos>().is_err());
+    }
+}
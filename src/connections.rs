@@ -0,0 +1,122 @@
+//! Connections being handled by a [`crate::Server`], with the bytes read
+//! from each so far, as listed on its control socket.
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Instant,
+};
+
+/// Connections which are open, by the order they were accepted in.
+#[derive(Default)]
+pub(crate) struct ConnectionTable {
+    next_id: AtomicU64,
+    open: Mutex<BTreeMap<u64, OpenConnection>>,
+}
+
+struct OpenConnection {
+    peer: SocketAddr,
+    opened: Instant,
+    bytes: Arc<AtomicU64>,
+}
+
+impl ConnectionTable {
+    /// Add the stream of a connection accepted from `peer`, counting the
+    /// bytes read from it. It is removed once the returned stream is dropped.
+    pub(crate) fn track<S>(self: &Arc<Self>, peer: SocketAddr, stream: S) -> Tracked<S> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let bytes = Arc::new(AtomicU64::new(0));
+        self.open.lock().unwrap().insert(
+            id,
+            OpenConnection {
+                peer,
+                opened: Instant::now(),
+                bytes: Arc::clone(&bytes),
+            },
+        );
+        Tracked {
+            stream,
+            bytes,
+            id,
+            table: Arc::clone(self),
+        }
+    }
+
+    /// Peer, age and bytes read so far of each open connection, one per line
+    /// under a header.
+    pub(crate) fn render(&self) -> String {
+        let open = self.open.lock().unwrap();
+        if open.is_empty() {
+            return "no open connections\n".to_string();
+        }
+        let mut table = format!("{:<40} {:>10} {:>14}\n", "PEER", "AGE", "BYTES");
+        for connection in open.values() {
+            let _ = writeln!(
+                table,
+                "{:<40} {:>9.1}s {:>14}",
+                connection.peer.to_string(),
+                connection.opened.elapsed().as_secs_f64(),
+                connection.bytes.load(Ordering::Relaxed)
+            );
+        }
+        table
+    }
+}
+
+/// Stream of a connection in a [`ConnectionTable`], which counts the bytes
+/// read from it.
+pub(crate) struct Tracked<S> {
+    stream: S,
+    bytes: Arc<AtomicU64>,
+    id: u64,
+    table: Arc<ConnectionTable>,
+}
+
+impl<S> Drop for Tracked<S> {
+    fn drop(&mut self) {
+        self.table.open.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Tracked<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let polled = Pin::new(&mut self.stream).poll_read(cx, buf);
+        let read = (buf.filled().len() - before) as u64;
+        self.bytes.fetch_add(read, Ordering::Relaxed);
+        polled
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Tracked<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
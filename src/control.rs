@@ -0,0 +1,221 @@
+//! Control socket of a running writer or server, on which `gn ctl` sends
+//! commands that change or inspect the run while it continues.
+//!
+//! Each connection carries a single command, as a line of words such as
+//! `set-weight 127.0.0.1:5000 0`. It is answered with the result of the
+//! command, or with a line starting with `error: ` if it failed, and closed.
+use std::{
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    task::JoinHandle,
+};
+
+use crate::{
+    connections::ConnectionTable,
+    report::Annotation,
+    statistics::Statistics,
+    target::AddressWeights,
+    unix::{self, SocketFile},
+};
+
+/// Longest command which is read from a connection.
+const MAX_COMMAND_LEN: u64 = 4096;
+
+/// Prefix of the reply to a command which failed.
+const ERROR_PREFIX: &str = "error: ";
+
+/// Runs the commands received on a [`ControlSocket`].
+pub(crate) trait ControlHandler: Send + Sync + 'static {
+    /// Run the command, given as its words, returning what to reply with.
+    fn handle(&self, command: &[&str]) -> Result<String, String>;
+}
+
+/// Control socket accepting commands in the background, which stops and
+/// removes its file when dropped.
+pub(crate) struct ControlSocket {
+    _file: SocketFile,
+    handle: JoinHandle<()>,
+}
+
+impl ControlSocket {
+    /// Listen for commands on the socket at `path`, running them with the
+    /// handler.
+    pub(crate) fn start(path: &Path, handler: impl ControlHandler) -> std::io::Result<Self> {
+        let file = SocketFile::claim(path)?;
+        let listener = unix::bind_listener(path)?;
+        Ok(Self {
+            _file: file,
+            handle: tokio::spawn(serve(listener, Arc::new(handler))),
+        })
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Answer commands on the listener until the task is aborted.
+async fn serve(listener: UnixListener, handler: Arc<dyn ControlHandler>) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        // A client which never sends its command must not hold up the others.
+        let handler = Arc::clone(&handler);
+        tokio::spawn(async move {
+            let _ = respond(stream, handler.as_ref()).await;
+        });
+    }
+}
+
+async fn respond(stream: UnixStream, handler: &dyn ControlHandler) -> std::io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(read.take(MAX_COMMAND_LEN))
+        .read_line(&mut line)
+        .await?;
+    let command: Vec<_> = line.split_whitespace().collect();
+    let reply = match handler.handle(&command) {
+        Ok(reply) => reply,
+        Err(e) => format!("{ERROR_PREFIX}{e}"),
+    };
+    write.write_all(reply.as_bytes()).await?;
+    if !reply.ends_with('\n') {
+        write.write_all(b"\n").await?;
+    }
+    write.shutdown().await
+}
+
+/// Send the command to the control socket at `path`, returning its reply.
+pub async fn send_command(path: &Path, command: &[String]) -> crate::Result<String> {
+    let mut stream = unix::connect(path)
+        .await
+        .map_err(|e| format!("cannot connect to the control socket: {e}"))?;
+    stream
+        .write_all(format!("{}\n", command.join(" ")).as_bytes())
+        .await?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).await?;
+    match reply.strip_prefix(ERROR_PREFIX) {
+        Some(e) => Err(e.trim_end().into()),
+        None => Ok(reply),
+    }
+}
+
+/// Changes a [`crate::SocketManager`] while it writes, as commanded on its
+/// control socket.
+pub(crate) struct WriteControl {
+    pub(crate) stats: Arc<Statistics>,
+    pub(crate) weights: Arc<AddressWeights>,
+    pub(crate) annotations: Arc<Mutex<Vec<Annotation>>>,
+}
+
+impl WriteControl {
+    /// Place an annotation at the current time of the run.
+    fn annotate(&self, message: String) {
+        let at_ms = u64::try_from(self.stats.elapsed()).unwrap_or(u64::MAX);
+        let mut annotations = self.annotations.lock().unwrap();
+        annotations.push(Annotation { at_ms, message });
+        annotations.sort_by_key(|a| a.at_ms);
+    }
+}
+
+impl ControlHandler for WriteControl {
+    fn handle(&self, command: &[&str]) -> Result<String, String> {
+        match command {
+            ["set-weight", addr, weight] => {
+                let addr: SocketAddr = addr
+                    .parse()
+                    .map_err(|e| format!("invalid address '{addr}': {e}"))?;
+                let weight: u32 = weight
+                    .parse()
+                    .map_err(|e| format!("invalid weight '{weight}': {e}"))?;
+                self.weights.set(addr, weight);
+                let message = format!("weight of {addr} set to {weight}");
+                self.annotate(message.clone());
+                Ok(message)
+            }
+            ["annotate", message @ ..] if !message.is_empty() => {
+                self.annotate(message.join(" "));
+                Ok("annotated".to_string())
+            }
+            _ => Err(format!(
+                "unknown command '{}', expected set-weight ADDR WEIGHT or annotate MESSAGE",
+                command.join(" ")
+            )),
+        }
+    }
+}
+
+/// Inspects a [`crate::Server`] while it serves, as commanded on its control
+/// socket.
+pub(crate) struct ServeControl(pub(crate) Arc<ConnectionTable>);
+
+impl ControlHandler for ServeControl {
+    fn handle(&self, command: &[&str]) -> Result<String, String> {
+        match command {
+            ["connections"] => Ok(self.0.render()),
+            _ => Err(format!(
+                "unknown command '{}', expected connections",
+                command.join(" ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{ControlSocket, WriteControl};
+    use crate::{statistics::Statistics, transport::TemporarySocketPath};
+
+    #[tokio::test]
+    async fn write_control() {
+        let path = TemporarySocketPath::new();
+        let control = WriteControl {
+            stats: Arc::new(Statistics::new()),
+            weights: Arc::default(),
+            annotations: Arc::default(),
+        };
+        let weights = Arc::clone(&control.weights);
+        let annotations = Arc::clone(&control.annotations);
+        let socket = ControlSocket::start(&path.0, control).unwrap();
+
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        let command = ["set-weight", "127.0.0.1:5000", "0"].map(String::from);
+        assert_eq!(
+            super::send_command(&path.0, &command).await.unwrap(),
+            "weight of 127.0.0.1:5000 set to 0\n"
+        );
+        assert_eq!(weights.get(addr), 0);
+
+        let command = ["annotate", "paused", "cache"].map(String::from);
+        super::send_command(&path.0, &command).await.unwrap();
+        let messages: Vec<_> = annotations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|a| a.message.clone())
+            .collect();
+        assert_eq!(
+            messages,
+            ["weight of 127.0.0.1:5000 set to 0", "paused cache"]
+        );
+
+        let command = ["set-weight", "localhost", "1"].map(String::from);
+        let e = super::send_command(&path.0, &command).await.unwrap_err();
+        assert!(e.to_string().starts_with("invalid address 'localhost'"));
+
+        drop(socket);
+        assert!(!path.0.exists());
+    }
+}
@@ -0,0 +1,242 @@
+//! Terminal dashboard of a write run, redrawn every time a
+//! [`WriteObserver`] is ticked.
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io::{self, Stderr},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    crossterm::{
+        cursor, execute,
+        terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    widgets::{Block, Paragraph, Sparkline},
+    Frame, Terminal,
+};
+
+use crate::{Report, WriteObserver};
+
+/// Number of throughput samples kept for the graph, one per tick.
+const HISTORY_LEN: usize = 120;
+
+/// Dashboard drawn on the alternate screen of the terminal, through stderr so
+/// stdout remains free for reports. The terminal is restored when this is
+/// dropped.
+///
+/// Raw mode is not enabled, so Ctrl-C still stops the run.
+pub struct Dashboard {
+    screen: Arc<Mutex<Screen<CrosstermBackend<Stderr>>>>,
+    in_flight: Arc<AtomicU64>,
+}
+
+/// [`WriteObserver`] which updates a [`Dashboard`].
+pub struct DashboardObserver<B: Backend> {
+    screen: Arc<Mutex<Screen<B>>>,
+    in_flight: Arc<AtomicU64>,
+}
+
+/// Terminal and the history of the run which is drawn to it.
+struct Screen<B: Backend> {
+    /// Terminal which is drawn to, until the dashboard is stopped.
+    terminal: Option<Terminal<B>>,
+    /// Bytes written per second of each tick, oldest first.
+    throughput: VecDeque<u64>,
+    /// Total bytes and elapsed milliseconds at the last tick.
+    last: (u64, u128),
+}
+
+impl Dashboard {
+    /// Switch the terminal to the alternate screen to draw the dashboard.
+    pub fn start() -> io::Result<Self> {
+        let mut stderr = io::stderr();
+        execute!(stderr, EnterAlternateScreen, cursor::Hide)?;
+        let terminal = Terminal::new(CrosstermBackend::new(stderr))?;
+        Ok(Self {
+            screen: Arc::new(Mutex::new(Screen::new(terminal))),
+            in_flight: Arc::default(),
+        })
+    }
+
+    /// Observer to add to the [`crate::SocketManager`] whose run is shown.
+    pub fn observer(&self) -> DashboardObserver<CrosstermBackend<Stderr>> {
+        DashboardObserver {
+            screen: Arc::clone(&self.screen),
+            in_flight: Arc::clone(&self.in_flight),
+        }
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        // Ticks which race with the run ending are no longer drawn.
+        self.screen.lock().unwrap().terminal = None;
+        let _ = execute!(io::stderr(), LeaveAlternateScreen, cursor::Show);
+    }
+}
+
+impl<B: Backend> Screen<B> {
+    fn new(terminal: Terminal<B>) -> Self {
+        Self {
+            terminal: Some(terminal),
+            throughput: VecDeque::with_capacity(HISTORY_LEN),
+            last: (0, 0),
+        }
+    }
+
+    /// Record the throughput since the last tick and redraw.
+    fn update(&mut self, report: &Report, in_flight: u64) {
+        let (bytes, elapsed_ms) = self.last;
+        let interval_ms = report.elapsed_ms.saturating_sub(elapsed_ms);
+        let written = report.total_bytes.saturating_sub(bytes) as u128 * 1000;
+        if let Some(rate) = written.checked_div(interval_ms) {
+            if self.throughput.len() == HISTORY_LEN {
+                self.throughput.pop_front();
+            }
+            self.throughput
+                .push_back(u64::try_from(rate).unwrap_or(u64::MAX));
+        }
+        self.last = (report.total_bytes, report.elapsed_ms);
+
+        let Some(terminal) = self.terminal.as_mut() else {
+            return;
+        };
+        let throughput: Vec<_> = self.throughput.iter().copied().collect();
+        let _ = terminal.draw(|frame| draw(frame, report, &throughput, in_flight));
+    }
+}
+
+impl<B: Backend + Send> WriteObserver for DashboardObserver<B> {
+    fn on_request_start(&self, _addr: SocketAddr) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_success(&self, _addr: SocketAddr, _bytes: u64, _elapsed: Duration) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn on_failure(&self, _addr: SocketAddr, _error: &(dyn Error + 'static), _elapsed: Duration) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn on_tick(&self, report: &Report) {
+        let in_flight = self.in_flight.load(Ordering::Relaxed);
+        self.screen.lock().unwrap().update(report, in_flight);
+    }
+}
+
+/// Draw the totals, throughput graph and latency percentiles of the run.
+fn draw(frame: &mut Frame, report: &Report, throughput: &[u64], in_flight: u64) {
+    let [totals, graph, latency] = Layout::vertical([
+        Constraint::Length(5),
+        Constraint::Min(5),
+        Constraint::Length(3),
+    ])
+    .areas(frame.area());
+
+    // The latest annotation marks what the throughput since then follows.
+    let annotation = match report.annotations.last() {
+        Some(a) => format!(
+            "\nAnnotation at {:.1}s: {}",
+            a.at_ms as f64 / 1000.0,
+            a.message
+        ),
+        None => String::new(),
+    };
+    frame.render_widget(
+        Paragraph::new(format!(
+            "Elapsed: {:.1}s, {} bytes sent, {} requests in flight\nRequests: {}/{} ({:.2}%) successful{annotation}",
+            report.elapsed_ms as f64 / 1000.0,
+            report.total_bytes,
+            in_flight,
+            report.successful_requests,
+            report.requests,
+            report.success_percentage
+        ))
+        .block(Block::bordered().title(" gn write ")),
+        totals,
+    );
+
+    let current = throughput.last().copied().unwrap_or_default();
+    // Only the latest samples which fit within the borders are shown.
+    let width = usize::from(graph.width.saturating_sub(2));
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(format!(" Throughput: {current} bytes/s ")))
+            .data(&throughput[throughput.len().saturating_sub(width)..]),
+        graph,
+    );
+
+    let percentiles = match (
+        report.latency_p50_us,
+        report.latency_p90_us,
+        report.latency_p99_us,
+        report.latency_max_us,
+    ) {
+        (Some(p50), Some(p90), Some(p99), Some(max)) => {
+            format!("p50 {p50:.1}us, p90 {p90:.1}us, p99 {p99:.1}us, max {max:.1}us")
+        }
+        _ => "No successful requests yet".to_string(),
+    };
+    frame.render_widget(
+        Paragraph::new(percentiles).block(Block::bordered().title(" Latency ")),
+        latency,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use ratatui::{backend::TestBackend, Terminal};
+
+    use super::{DashboardObserver, Screen};
+    use crate::{statistics::Statistics, Report, WriteObserver};
+
+    #[test]
+    fn draw() {
+        let terminal = Terminal::new(TestBackend::new(80, 16)).unwrap();
+        let screen = Arc::new(Mutex::new(Screen::new(terminal)));
+        let observer = DashboardObserver {
+            screen: Arc::clone(&screen),
+            in_flight: Arc::default(),
+        };
+        let addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        observer.on_request_start(addr);
+        observer.on_request_start(addr);
+        observer.on_success(addr, 10, Duration::from_micros(120));
+
+        let stats = Statistics::new().with_exact_latencies(10);
+        stats.increment_total(10);
+        stats.record_success();
+        stats.record_latency(Duration::from_micros(120));
+        let report = Report {
+            elapsed_ms: 1000,
+            annotations: vec!["500ms=deployed v2".parse().unwrap()],
+            ..Report::from(&stats)
+        };
+        observer.on_tick(&report);
+
+        let screen = screen.lock().unwrap();
+        assert_eq!(screen.throughput, [10]);
+        let buffer = screen.terminal.as_ref().unwrap().backend().buffer();
+        let text: String = buffer.content().iter().map(|cell| cell.symbol()).collect();
+        assert!(text.contains("1 requests in flight"));
+        assert!(text.contains("Requests: 1/1 (100.00%) successful"));
+        assert!(text.contains("Throughput: 10 bytes/s"));
+        assert!(text.contains("p50 120.0us"));
+        assert!(text.contains("Annotation at 0.5s: deployed v2"));
+    }
+}
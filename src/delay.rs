@@ -0,0 +1,94 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+/// Time a [`crate::Server`] waits before replying to each message, parsed
+/// from a duration such as `50ms` or a range such as `10ms..100ms`, which
+/// each delay is drawn from uniformly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delay {
+    min: Duration,
+    max: Duration,
+}
+
+impl Delay {
+    /// Wait exactly this long.
+    pub fn fixed(delay: Duration) -> Self {
+        Self {
+            min: delay,
+            max: delay,
+        }
+    }
+
+    /// Wait between `min` and `max`, inclusive, which must not be reversed.
+    pub fn range(min: Duration, max: Duration) -> Result<Self, String> {
+        if min > max {
+            return Err(format!(
+                "the delay range starts after it ends ({} > {})",
+                humantime::format_duration(min),
+                humantime::format_duration(max)
+            ));
+        }
+        Ok(Self { min, max })
+    }
+
+    /// Draw the time to wait before the next reply.
+    pub fn sample(&self) -> Duration {
+        if self.min == self.max {
+            return self.min;
+        }
+        let nanos = |d: Duration| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX);
+        Duration::from_nanos(rand::random_range(nanos(self.min)..=nanos(self.max)))
+    }
+}
+
+impl FromStr for Delay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |d: &str| {
+            d.trim()
+                .parse::<humantime::Duration>()
+                .map(|d| *d)
+                .map_err(|e| format!("invalid delay '{d}': {e}"))
+        };
+        match s.split_once("..") {
+            Some((min, max)) => Self::range(parse(min)?, parse(max)?),
+            None => Ok(Self::fixed(parse(s)?)),
+        }
+    }
+}
+
+impl Display for Delay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", humantime::format_duration(self.min))?;
+        if self.min != self.max {
+            write!(f, "..{}", humantime::format_duration(self.max))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Delay;
+
+    #[test]
+    fn parse() {
+        let fixed = "50ms".parse::<Delay>().unwrap();
+        assert_eq!(fixed, Delay::fixed(Duration::from_millis(50)));
+        assert_eq!(fixed.sample(), Duration::from_millis(50));
+        assert_eq!(fixed.to_string(), "50ms");
+
+        let range = "10ms..100ms".parse::<Delay>().unwrap();
+        assert_eq!(range.to_string(), "10ms..100ms");
+        for _ in 0..100 {
+            let delay = range.sample();
+            assert!(delay >= Duration::from_millis(10) && delay <= Duration::from_millis(100));
+        }
+
+        assert!("100ms..10ms".parse::<Delay>().is_err());
+        assert!("slow".parse::<Delay>().is_err());
+        assert!("10ms..".parse::<Delay>().is_err());
+    }
+}
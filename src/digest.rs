@@ -0,0 +1,37 @@
+//! Digests which a [`crate::Server`] replies to each message with, so that
+//! the writer can verify the message arrived intact without it being echoed.
+use clap::ValueEnum;
+
+/// Hash a server replies with, as a line of lowercase hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Digest {
+    Sha256,
+}
+
+impl Digest {
+    /// Reply to the message, which is its hash in hex followed by a newline.
+    pub fn reply(&self, message: &[u8]) -> Vec<u8> {
+        let hash = match self {
+            Self::Sha256 => ring::digest::digest(&ring::digest::SHA256, message),
+        };
+        let mut reply = String::with_capacity(hash.as_ref().len() * 2 + 1);
+        for byte in hash.as_ref() {
+            reply.push_str(&format!("{byte:02x}"));
+        }
+        reply.push('\n');
+        reply.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Digest;
+
+    #[test]
+    fn reply() {
+        assert_eq!(
+            Digest::Sha256.reply(b"abc"),
+            b"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\n"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    fmt::Display,
+    io::{self, ErrorKind},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs},
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// Address recorded for peers of a Unix socket, which have no IP address.
+pub(crate) const UNIX_PEER: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
+
+/// Where a socket is written to or served from, either an IP address and
+/// port, a host name and port, or the path of a Unix socket.
+///
+/// Parsed from an address such as `127.0.0.1:5000`, a host such as
+/// `example.com:443`, or a path such as `/tmp/app.sock`, which must contain a
+/// `/` or be given as `unix:PATH`. On Linux, a name starting with `@`, such
+/// as `@app`, is a Unix socket in the abstract namespace, which has no file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Inet(SocketAddr),
+    /// Host name and port, which is resolved when it is used.
+    Host(String),
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// The host name, without its port, when this is an [`Endpoint::Host`].
+    pub fn host_name(&self) -> Option<&str> {
+        match self {
+            Self::Host(host) => host.rsplit_once(':').map(|(name, _)| name),
+            _ => None,
+        }
+    }
+
+    /// Resolve a host name to the first of its addresses.
+    pub(crate) fn resolved(&self) -> io::Result<Self> {
+        match self {
+            Self::Host(_) => self
+                .to_socket_addrs()?
+                .next()
+                .map(Self::Inet)
+                .ok_or_else(|| {
+                    io::Error::new(ErrorKind::NotFound, format!("{self} has no addresses"))
+                }),
+            endpoint => Ok(endpoint.clone()),
+        }
+    }
+}
+
+impl ToSocketAddrs for Endpoint {
+    type Iter = std::vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+        match self {
+            Self::Inet(addr) => Ok(vec![*addr].into_iter()),
+            Self::Host(host) => Ok(host.to_socket_addrs()?.collect::<Vec<_>>().into_iter()),
+            Self::Unix(path) => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} is a Unix socket, which has no IP address",
+                    path.display()
+                ),
+            )),
+        }
+    }
+}
+
+impl From<SocketAddr> for Endpoint {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Inet(addr)
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("expected a socket path after unix:".to_string());
+            }
+            return Ok(Self::Unix(path.into()));
+        }
+        if s.contains('/') || s.starts_with('@') {
+            return Ok(Self::Unix(s.into()));
+        }
+        if let Ok(addr) = s.parse() {
+            return Ok(Self::Inet(addr));
+        }
+        match s.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                Ok(Self::Host(s.to_string()))
+            }
+            _ => Err(format!(
+                "invalid address '{s}': expected HOST:PORT or the path of a Unix socket"
+            )),
+        }
+    }
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Inet(addr) => write!(f, "{addr}"),
+            Self::Host(host) => write!(f, "{host}"),
+            Self::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::Endpoint;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "127.0.0.1:5000".parse::<Endpoint>().unwrap(),
+            Endpoint::Inet("127.0.0.1:5000".parse().unwrap())
+        );
+        assert_eq!(
+            "/tmp/app.sock".parse::<Endpoint>().unwrap(),
+            Endpoint::Unix(PathBuf::from("/tmp/app.sock"))
+        );
+        assert_eq!(
+            "unix:app.sock".parse::<Endpoint>().unwrap(),
+            Endpoint::Unix(PathBuf::from("app.sock"))
+        );
+        assert_eq!(
+            "@app".parse::<Endpoint>().unwrap(),
+            Endpoint::Unix(PathBuf::from("@app"))
+        );
+        let host = "example.com:443".parse::<Endpoint>().unwrap();
+        assert_eq!(host, Endpoint::Host("example.com:443".to_string()));
+        assert_eq!(host.host_name(), Some("example.com"));
+        assert!("unix:".parse::<Endpoint>().is_err());
+        assert!("localhost".parse::<Endpoint>().is_err());
+        assert!("localhost:http".parse::<Endpoint>().is_err());
+    }
+}
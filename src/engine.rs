@@ -0,0 +1,236 @@
+//! Engines which drive the requests of a [`crate::SocketManager`].
+//!
+//! The tokio engine is the default. The blocking engine writes from dedicated
+//! OS threads with std sockets instead, as a baseline for ruling out the
+//! overhead of the async runtime, recording into the same [`Statistics`].
+use std::{
+    io::{self, ErrorKind, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    endpoint::Endpoint, manager::Stop, report::StopReason, statistics::Statistics,
+    transport::PartialWrite, Protocol, WriteOptions,
+};
+
+/// How requests are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Engine {
+    /// Asynchronous tasks on the tokio runtime.
+    #[default]
+    Tokio,
+    /// One OS thread per concurrent request, writing with blocking sockets.
+    BlockingThreads,
+}
+
+/// Requests which each thread of the blocking engine writes.
+pub(crate) struct BlockingPlan {
+    threads: u64,
+    count: Option<u64>,
+    duration: Option<Duration>,
+}
+
+impl BlockingPlan {
+    /// Split the [`WriteOptions`] across threads in the same way as the
+    /// concurrent tasks of the tokio engine.
+    pub(crate) fn new(opts: &WriteOptions) -> crate::Result<Self> {
+        let (threads, count, duration) = match *opts {
+            WriteOptions::Count(count) => (1, Some(count), None),
+            WriteOptions::Duration(duration) => (1, None, Some(*duration)),
+            WriteOptions::CountOrDuration(count, duration) => (1, Some(count), Some(*duration)),
+            WriteOptions::ConcurrencyWithCount(concurrency, count) => {
+                (concurrency, Some(count / concurrency), None)
+            }
+            WriteOptions::ConcurrencyWithDuration(concurrency, duration) => {
+                (concurrency, None, Some(*duration))
+            }
+            WriteOptions::Unlimited => (1, None, None),
+            WriteOptions::ConcurrencyUnlimited(concurrency) => (concurrency, None, None),
+            WriteOptions::Rate(_)
+            | WriteOptions::RateWithCount(..)
+            | WriteOptions::RateWithDuration(..)
+            | WriteOptions::RateWithCountOrDuration(..) => {
+                return Err("the blocking-threads engine does not support a request rate".into())
+            }
+        };
+        Ok(Self {
+            threads,
+            count,
+            duration,
+        })
+    }
+
+    /// Write the input to the endpoint from every thread, returning once they
+    /// have all finished.
+    pub(crate) fn run(
+        &self,
+        protocol: &Protocol,
+        endpoint: &Endpoint,
+        input: &[u8],
+        stats: &Statistics,
+        stop: &Stop,
+        max_failures: Option<u64>,
+    ) {
+        std::thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| {
+                    let start = Instant::now();
+                    let mut sent = 0;
+                    while !stop.is_stopped()
+                        && self.count.is_none_or(|count| sent < count)
+                        && self.duration.is_none_or(|d| start.elapsed() < d)
+                    {
+                        sent += 1;
+                        let started = Instant::now();
+                        match write_once(protocol, endpoint, input) {
+                            Ok(written) => {
+                                stats.increment_total(written);
+                                stats.record_success();
+                                stats.record_latency(started.elapsed());
+                            }
+                            Err(e) => {
+                                stats.record_failure();
+                                if let Some(partial) = e.downcast_ref::<PartialWrite>() {
+                                    stats.record_partial_write(partial.written);
+                                }
+                                if max_failures.is_some_and(|max| stats.failed_requests() >= max) {
+                                    stop.stop(StopReason::MaxFailures);
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// Whether the blocking engine can write with the protocol.
+pub(crate) fn supports(protocol: &Protocol) -> bool {
+    match protocol {
+        Protocol::Tcp | Protocol::Udp => true,
+        Protocol::Unix | Protocol::UnixDatagram => cfg!(unix),
+        Protocol::Tls | Protocol::Ws | Protocol::Wss => false,
+    }
+}
+
+/// Write the input as a single request, over a new connection for stream
+/// protocols.
+fn write_once(
+    protocol: &Protocol,
+    endpoint: &Endpoint,
+    input: &[u8],
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    match (protocol, endpoint) {
+        (Protocol::Tcp, Endpoint::Inet(addr)) => {
+            let mut stream = TcpStream::connect(addr)?;
+            Ok(write_counted(&mut stream, input)?)
+        }
+        (Protocol::Udp, Endpoint::Inet(addr)) => {
+            let local: SocketAddr = if addr.is_ipv4() {
+                (Ipv4Addr::UNSPECIFIED, 0).into()
+            } else {
+                (Ipv6Addr::UNSPECIFIED, 0).into()
+            };
+            let socket = UdpSocket::bind(local)?;
+            Ok(socket.send_to(input, addr)? as u64)
+        }
+        #[cfg(unix)]
+        (Protocol::Unix, Endpoint::Unix(path)) => {
+            let addr = crate::unix::socket_addr(path)?;
+            let mut stream = std::os::unix::net::UnixStream::connect_addr(&addr)?;
+            Ok(write_counted(&mut stream, input)?)
+        }
+        #[cfg(unix)]
+        (Protocol::UnixDatagram, Endpoint::Unix(path)) => {
+            let addr = crate::unix::socket_addr(path)?;
+            let socket = std::os::unix::net::UnixDatagram::unbound()?;
+            Ok(socket.send_to_addr(input, &addr)? as u64)
+        }
+        (protocol, endpoint) => Err(io::Error::new(
+            ErrorKind::Unsupported,
+            format!("cannot write {protocol} to {endpoint} with the blocking-threads engine"),
+        )
+        .into()),
+    }
+}
+
+/// Blocking counterpart of [`crate::transport::write_counted`].
+fn write_counted<W: Write>(writer: &mut W, input: &[u8]) -> Result<u64, PartialWrite> {
+    let mut written = 0;
+    while written < input.len() {
+        match writer.write(&input[written..]) {
+            Ok(0) => {
+                return Err(PartialWrite {
+                    written: written as u64,
+                    source: ErrorKind::WriteZero.into(),
+                })
+            }
+            Ok(n) => written += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(source) => {
+                return Err(PartialWrite {
+                    written: written as u64,
+                    source,
+                })
+            }
+        }
+    }
+    Ok(written as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Read, net::TcpListener, time::Duration};
+
+    use super::BlockingPlan;
+    use crate::{
+        endpoint::Endpoint, manager::Stop, statistics::Statistics, Protocol, WriteOptions,
+    };
+
+    #[test]
+    fn plan() {
+        let plan = BlockingPlan::new(&WriteOptions::ConcurrencyWithCount(4, 100)).unwrap();
+        assert_eq!(
+            (plan.threads, plan.count, plan.duration),
+            (4, Some(25), None)
+        );
+        let duration = humantime::Duration::from(Duration::from_secs(1));
+        let plan = BlockingPlan::new(&WriteOptions::Duration(duration)).unwrap();
+        assert_eq!((plan.threads, plan.count), (1, None));
+        assert!(BlockingPlan::new(&WriteOptions::Rate(10)).is_err());
+    }
+
+    #[test]
+    fn run() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = std::thread::spawn(move || {
+            let mut total = 0;
+            for _ in 0..20 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = Vec::new();
+                total += stream.read_to_end(&mut buf).unwrap();
+            }
+            total
+        });
+
+        let stats = Statistics::new();
+        BlockingPlan::new(&WriteOptions::ConcurrencyWithCount(4, 20))
+            .unwrap()
+            .run(
+                &Protocol::Tcp,
+                &Endpoint::Inet(addr),
+                b"blocking",
+                &stats,
+                &Stop::default(),
+                None,
+            );
+        assert_eq!(stats.successful_requests(), 20);
+        assert_eq!(stats.total_bytes(), 160);
+        assert_eq!(received.join().unwrap(), 160);
+    }
+}
@@ -0,0 +1,135 @@
+//! Log of every request made by a writer, as one JSON object per line.
+use std::{
+    io::{BufWriter, Write},
+    net::SocketAddr,
+    sync::Mutex,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::trace::TraceContext;
+
+/// Outcome of a single request, as it is logged.
+#[derive(Debug, Serialize)]
+pub(crate) struct RequestEvent {
+    /// Time the request started, in nanoseconds since the UNIX epoch.
+    pub(crate) started_at_ns: u64,
+    pub(crate) addr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) trace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) span_id: Option<String>,
+    pub(crate) latency_us: u64,
+    /// Bytes written, when the request succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+impl RequestEvent {
+    pub(crate) fn new(
+        addr: SocketAddr,
+        started_at_ns: u64,
+        latency: Duration,
+        trace: Option<&TraceContext>,
+        result: &crate::Result<u64>,
+    ) -> Self {
+        Self {
+            started_at_ns,
+            addr: addr.to_string(),
+            trace_id: trace.map(TraceContext::trace_id),
+            span_id: trace.map(TraceContext::span_id),
+            latency_us: u64::try_from(latency.as_micros()).unwrap_or(u64::MAX),
+            bytes: result.as_ref().ok().copied(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// Buffered log of request events, which is flushed when dropped.
+pub(crate) struct EventLog {
+    out: Mutex<BufWriter<Box<dyn Write + Send>>>,
+}
+
+impl EventLog {
+    pub(crate) fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Mutex::new(BufWriter::new(Box::new(out))),
+        }
+    }
+
+    /// Append the event, logging to stderr if it cannot be written.
+    pub(crate) fn record(&self, event: &RequestEvent) {
+        let mut out = self.out.lock().unwrap();
+        let written = serde_json::to_writer(&mut *out, event)
+            .map_err(std::io::Error::from)
+            .and_then(|_| out.write_all(b"\n"));
+        if let Err(e) = written {
+            eprintln!("Unable to write to the event log: {e}");
+        }
+    }
+
+    pub(crate) fn flush(&self) -> std::io::Result<()> {
+        self.out.lock().unwrap().flush()
+    }
+}
+
+/// Writer whose output can be read back once it is written.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(pub(crate) std::sync::Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{EventLog, RequestEvent, SharedBuffer};
+    use crate::trace::TraceContext;
+
+    #[test]
+    fn record() {
+        let buffer = SharedBuffer::default();
+        let log = EventLog::new(buffer.clone());
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        let trace = TraceContext::random();
+        let latency = Duration::from_micros(120);
+        log.record(&RequestEvent::new(addr, 10, latency, Some(&trace), &Ok(5)));
+        log.record(&RequestEvent::new(
+            addr,
+            20,
+            latency,
+            None,
+            &Err("refused".into()),
+        ));
+        assert!(buffer.0.lock().unwrap().is_empty());
+        log.flush().unwrap();
+
+        let out = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!(
+                r#"{{"started_at_ns":10,"addr":"127.0.0.1:5000","trace_id":"{}","span_id":"{}","latency_us":120,"bytes":5}}"#,
+                trace.trace_id(),
+                trace.span_id()
+            )
+        );
+        assert_eq!(
+            lines[1],
+            r#"{"started_at_ns":20,"addr":"127.0.0.1:5000","latency_us":120,"error":"refused"}"#
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! C ABI for embedding the writer in harnesses which are not written in Rust.
+//!
+//! A run is described by a JSON configuration and produces a JSON [`Report`],
+//! for example:
+//!
+//! ```json
+//! {"host": "127.0.0.1:5000", "protocol": "udp", "input": "hello", "count": 100}
+//! ```
+//!
+//! Strings returned to the caller must be released with [`gn_string_free`].
+//!
+//! The crate is only built as an `rlib` by default, so the shared library is
+//! built with `cargo rustc --release --lib --features ffi --crate-type cdylib`.
+use std::{
+    any::Any,
+    ffi::{c_char, CStr, CString},
+    panic,
+};
+
+use serde::Deserialize;
+
+use crate::{statistics::Statistics, Endpoint, Protocol, Report, SocketManager, WriteOptions};
+
+/// Configuration of a single run, mirroring the flags of `gn write`.
+#[derive(Deserialize)]
+struct RunConfig {
+    /// Address, `name:port` or Unix socket path, as accepted by `--host`.
+    host: String,
+    #[serde(default)]
+    protocol: Protocol,
+    input: String,
+    #[serde(default = "default_count")]
+    count: u64,
+    /// Duration in a human readable form, e.g. `30s`.
+    duration: Option<String>,
+    concurrency: Option<u64>,
+    /// Requests per second, instead of sending as fast as possible.
+    rate: Option<u64>,
+}
+
+fn default_count() -> u64 {
+    1
+}
+
+fn run(config: &str) -> crate::Result<Report> {
+    let config: RunConfig = serde_json::from_str(config)?;
+    let host: Endpoint = config.host.parse()?;
+    let duration = config
+        .duration
+        .map(|d| d.parse::<humantime::Duration>())
+        .transpose()?;
+    let opts = match config.rate {
+        Some(0) => return Err("the rate must be at least 1".into()),
+        Some(rate) => WriteOptions::from_rate(rate, config.count, duration),
+        None => WriteOptions::from_flags(config.count, duration, config.concurrency),
+    };
+    if opts.is_unlimited() {
+        return Err("an unlimited run cannot be stopped, provide a count or duration".into());
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let manager = SocketManager::new(
+            host,
+            config.input.as_bytes(),
+            config.protocol,
+            opts,
+            Statistics::new(),
+        );
+        manager.write().await?;
+        Ok(manager.report())
+    })
+}
+
+/// Message of a panic which was caught, for the error returned in its place.
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    format!("the run panicked: {message}")
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // Serialized JSON never contains interior nul bytes.
+    CString::new(s).expect("JSON is nul free").into_raw()
+}
+
+/// Run the writer with the given JSON configuration, returning a JSON report.
+///
+/// When the run fails or panics, an object with a single `error` field is
+/// returned instead, as unwinding across the C ABI would abort the caller.
+///
+/// # Safety
+///
+/// `config_json` must be a valid pointer to a nul terminated string. The
+/// returned string must be released with [`gn_string_free`].
+#[no_mangle]
+pub unsafe extern "C" fn gn_run(config_json: *const c_char) -> *mut c_char {
+    let result = panic::catch_unwind(|| {
+        CStr::from_ptr(config_json)
+            .to_str()
+            .map_err(Into::into)
+            .and_then(run)
+            .map_err(|e| e.to_string())
+    })
+    .unwrap_or_else(|panic| Err(panic_message(panic)));
+    let json = match result {
+        Ok(report) => serde_json::to_string(&report),
+        Err(e) => serde_json::to_string(&serde_json::json!({ "error": e })),
+    };
+    into_c_string(json.expect("Report is serializable"))
+}
+
+/// Release a string which was returned by this library.
+///
+/// # Safety
+///
+/// `s` must have been returned by [`gn_run`] and not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn gn_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::{CStr, CString};
+
+    use super::{gn_run, gn_string_free, panic_message};
+    use crate::Report;
+
+    fn call(config: &str) -> String {
+        let config = CString::new(config).unwrap();
+        unsafe {
+            let out = gn_run(config.as_ptr());
+            let json = CStr::from_ptr(out).to_str().unwrap().to_string();
+            gn_string_free(out);
+            json
+        }
+    }
+
+    #[test]
+    fn run_udp() {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let config = format!(
+            r#"{{"host": "{}", "protocol": "udp", "input": "hello", "count": 3}}"#,
+            socket.local_addr().unwrap()
+        );
+        let report: Report = serde_json::from_str(&call(&config)).unwrap();
+        assert_eq!(report.total_bytes, 15);
+        assert_eq!(report.successful_requests, 3);
+    }
+
+    #[test]
+    fn invalid_config() {
+        let out: serde_json::Value = serde_json::from_str(&call("{}")).unwrap();
+        assert!(out["error"].is_string());
+
+        let unlimited = r#"{"host": "127.0.0.1:5000", "input": "hi", "count": 0}"#;
+        let out: serde_json::Value = serde_json::from_str(&call(unlimited)).unwrap();
+        assert!(out["error"].is_string());
+
+        let no_port = r#"{"host": "localhost", "input": "hi"}"#;
+        let out: serde_json::Value = serde_json::from_str(&call(no_port)).unwrap();
+        assert!(out["error"].as_str().unwrap().contains("localhost"));
+    }
+
+    #[test]
+    fn caught_panic() {
+        let panic = std::panic::catch_unwind(|| panic!("at {}", 1)).unwrap_err();
+        assert_eq!(panic_message(panic), "the run panicked: at 1");
+    }
+}
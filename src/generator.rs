@@ -0,0 +1,151 @@
+//! Payloads which are generated for every request off the send path, by
+//! threads which fill a bounded queue ahead of the requests taking from it.
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::payload::random_payload;
+
+/// Payloads queued ahead of requests for each generator thread.
+const QUEUED_PER_THREAD: usize = 64;
+
+/// Source of a distinct payload for every request, such as templated or
+/// fuzzed messages which are too expensive to build on the send path.
+///
+/// Payloads are generated from threads of their own, so this may block.
+pub trait PayloadGenerator: Send + Sync {
+    /// Generate the payload of the `index`th request, counting from 0.
+    fn generate(&self, index: u64) -> Vec<u8>;
+}
+
+/// How often a [`PayloadGenerator`] generates a new payload during a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Regeneration {
+    /// Generate a distinct payload for every request, which stresses the
+    /// generator and defeats deduplication by the target.
+    #[default]
+    PerRequest,
+    /// Generate a payload for each connection, which every request it
+    /// carries reuses while connections are kept alive.
+    PerConnection,
+    /// Generate a single payload for the whole run, for the fastest path to
+    /// sending.
+    Once,
+}
+
+/// [`PayloadGenerator`] of random payloads of a fixed size, where a seed
+/// makes the payload of each index the same for every run.
+pub struct RandomPayloads {
+    len: usize,
+    seed: Option<u64>,
+}
+
+impl RandomPayloads {
+    pub fn new(len: usize, seed: Option<u64>) -> Self {
+        Self { len, seed }
+    }
+}
+
+impl PayloadGenerator for RandomPayloads {
+    fn generate(&self, index: u64) -> Vec<u8> {
+        random_payload(self.len, self.seed.map(|seed| seed.wrapping_add(index)))
+    }
+}
+
+/// Threads running a [`PayloadGenerator`] for a single run, which stop once
+/// this is dropped.
+pub(crate) struct GeneratorPool {
+    receiver: Mutex<mpsc::Receiver<Vec<u8>>>,
+}
+
+impl GeneratorPool {
+    /// Start `threads` threads which generate payloads, in order of their
+    /// index, until the queue is full.
+    pub(crate) fn start(generator: Arc<dyn PayloadGenerator>, threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(threads * QUEUED_PER_THREAD);
+        let next = Arc::new(AtomicU64::new(0));
+        for _ in 0..threads {
+            let generator = Arc::clone(&generator);
+            let sender = sender.clone();
+            let next = Arc::clone(&next);
+            std::thread::spawn(move || loop {
+                let payload = generator.generate(next.fetch_add(1, Ordering::Relaxed));
+                // The receiver is dropped once the run has finished.
+                if sender.blocking_send(payload).is_err() {
+                    break;
+                }
+            });
+        }
+        Self {
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// Take the next payload, alongside how long it was waited for when none
+    /// had been generated ahead of it.
+    pub(crate) async fn next(&self) -> (Vec<u8>, Option<Duration>) {
+        // Another request holding the queue is waiting on the generator.
+        if let Ok(mut receiver) = self.receiver.try_lock() {
+            if let Ok(payload) = receiver.try_recv() {
+                return (payload, None);
+            }
+        }
+        let start = Instant::now();
+        let payload = self
+            .receiver
+            .lock()
+            .await
+            .recv()
+            .await
+            .expect("payload generator threads panicked");
+        (payload, Some(start.elapsed()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, thread, time::Duration};
+
+    use super::{GeneratorPool, PayloadGenerator, RandomPayloads};
+
+    struct Slow;
+
+    impl PayloadGenerator for Slow {
+        fn generate(&self, index: u64) -> Vec<u8> {
+            thread::sleep(Duration::from_millis(20));
+            index.to_be_bytes().to_vec()
+        }
+    }
+
+    #[test]
+    fn random() {
+        let seeded = RandomPayloads::new(32, Some(7));
+        assert_eq!(seeded.generate(0).len(), 32);
+        assert_eq!(
+            seeded.generate(3),
+            RandomPayloads::new(32, Some(7)).generate(3)
+        );
+        assert_ne!(seeded.generate(0), seeded.generate(1));
+    }
+
+    #[tokio::test]
+    async fn pool() {
+        let pool = GeneratorPool::start(Arc::new(Slow), 1);
+        let (payload, waited) = pool.next().await;
+        assert_eq!(payload, 0u64.to_be_bytes());
+        assert!(waited.is_some());
+
+        // The generator runs ahead while the payload is being sent.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let (payload, waited) = pool.next().await;
+        assert_eq!(payload, 1u64.to_be_bytes());
+        assert_eq!(waited, None);
+    }
+}
@@ -0,0 +1,171 @@
+use std::net::ToSocketAddrs;
+
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::{statistics::Statistics, Report, SocketManager, StopReason};
+
+/// Several [`SocketManager`]s, each with their own targets, protocol and
+/// options, which are written with at the same time and reported on
+/// together.
+pub struct RunGroup<'a, S: ToSocketAddrs> {
+    managers: Vec<(String, SocketManager<'a, S>)>,
+}
+
+/// [`Report`] of every writer of a [`RunGroup`], alongside one which merges
+/// them as though they were a single writer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupReport {
+    pub merged: Report,
+    /// Report of each writer, in the order they were added to the group.
+    pub sections: Vec<GroupSection>,
+}
+
+/// [`Report`] of a single writer within a [`RunGroup`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupSection {
+    pub name: String,
+    pub report: Report,
+}
+
+impl<S: ToSocketAddrs> Default for RunGroup<'_, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, S: ToSocketAddrs> RunGroup<'a, S> {
+    pub fn new() -> Self {
+        Self {
+            managers: Vec::new(),
+        }
+    }
+
+    /// Add a writer to the group, whose section of the [`GroupReport`] is
+    /// given the name.
+    pub fn with_manager(mut self, name: impl Into<String>, manager: SocketManager<'a, S>) -> Self {
+        self.managers.push((name.into(), manager));
+        self
+    }
+
+    /// Write with every manager at the same time, returning the total number
+    /// of bytes written once they have all finished. If any of them fail, the
+    /// others still run to completion and the first error is returned.
+    pub async fn write(&self) -> crate::Result<u64> {
+        let written = join_all(self.managers.iter().map(|(_, m)| m.write())).await;
+        let mut total = 0;
+        for (written, (name, _)) in written.into_iter().zip(&self.managers) {
+            total += written.map_err(|e| format!("{name}: {e}"))?;
+        }
+        Ok(total)
+    }
+
+    /// Stop every manager of an ongoing [`RunGroup::write`], as with
+    /// [`SocketManager::stop`].
+    pub fn stop(&self) {
+        for (_, manager) in &self.managers {
+            manager.stop();
+        }
+    }
+
+    /// Produce a [`GroupReport`] of every manager.
+    pub fn report(&self) -> GroupReport {
+        let sections: Vec<_> = self
+            .managers
+            .iter()
+            .map(|(name, manager)| GroupSection {
+                name: name.clone(),
+                report: manager.report(),
+            })
+            .collect();
+
+        let stats = Statistics::merged(self.managers.iter().map(|(_, m)| m.statistics()));
+        stats.record_throughput();
+        let reports = || sections.iter().map(|s| &s.report);
+        let rates: Vec<_> = reports().filter_map(|r| r.requested_rate).collect();
+        let merged = Report {
+            requested_rate: (!rates.is_empty()).then(|| rates.iter().sum()),
+            stop_reason: reports()
+                .map(|r| r.stop_reason)
+                .find(|reason| *reason != StopReason::Completed)
+                .unwrap_or_default(),
+            circuit_events: reports()
+                .flat_map(|r| r.circuit_events.iter().cloned())
+                .collect(),
+            latency_outliers: reports()
+                .flat_map(|r| r.latency_outliers.iter().cloned())
+                .collect(),
+            clock_offsets: reports()
+                .flat_map(|r| r.clock_offsets.iter().cloned())
+                .collect(),
+            ..Report::from(&stats)
+        };
+        GroupReport { merged, sections }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use crate::{
+        statistics::Statistics, MemoryTransport, Protocol, RunGroup, SocketManager, WriteOptions,
+    };
+
+    #[tokio::test]
+    async fn write() {
+        let first: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let second: SocketAddr = "127.0.0.1:6000".parse().unwrap();
+        let (memory, mut listener) = MemoryTransport::new();
+        let (refused, _) = MemoryTransport::new();
+        let group = RunGroup::new()
+            .with_manager(
+                "small",
+                SocketManager::new(
+                    first,
+                    b"small",
+                    Protocol::Tcp,
+                    WriteOptions::Count(10),
+                    Statistics::new().with_exact_latencies(100),
+                )
+                .with_transport(memory),
+            )
+            .with_manager(
+                "large",
+                SocketManager::new(
+                    second,
+                    b"large payload",
+                    Protocol::Tcp,
+                    WriteOptions::ConcurrencyWithCount(2, 4),
+                    Statistics::new(),
+                )
+                .with_transport(refused),
+            );
+        assert_eq!(group.write().await.unwrap(), 50);
+
+        let report = group.report();
+        let names: Vec<_> = report.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["small", "large"]);
+        assert_eq!(report.sections[0].report.successful_requests, 10);
+        assert_eq!(report.sections[1].report.failed_requests, 4);
+
+        let merged = &report.merged;
+        assert_eq!(merged.total_bytes, 50);
+        assert_eq!(merged.requests, 14);
+        assert_eq!(merged.successful_requests, 10);
+        assert_eq!(merged.failed_requests, 4);
+        assert!(merged.latency_p50_us.is_some());
+        // Only one of the writers kept every latency.
+        assert_eq!(
+            merged.latency_percentiles,
+            crate::LatencyPercentiles::Histogram
+        );
+
+        drop(group);
+        let mut accepted = 0;
+        while listener.accept().await.is_some() {
+            accepted += 1;
+        }
+        assert_eq!(accepted, 10);
+    }
+}
@@ -0,0 +1,133 @@
+//! Health endpoint of a [`crate::Server`], for the readiness and liveness
+//! probes of an orchestrator such as Kubernetes.
+//!
+//! `GET /live` succeeds whenever the endpoint is being served, while
+//! `GET /ready` only succeeds once the server's listener is bound, failing
+//! with `503 Service Unavailable` until then. Other paths are not found.
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::metrics::read_request;
+
+/// Endpoint serving health checks in the background, which stops when
+/// dropped.
+pub(crate) struct HealthEndpoint {
+    addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl HealthEndpoint {
+    /// Bind to the address and start serving health checks, where the server
+    /// is ready once `ready` is set.
+    pub(crate) async fn start(addr: SocketAddr, ready: Arc<AtomicBool>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self {
+            addr: listener.local_addr()?,
+            handle: tokio::spawn(serve(listener, ready)),
+        })
+    }
+
+    /// Address the endpoint is bound to.
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for HealthEndpoint {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Answer health checks on the listener until the task is aborted.
+async fn serve(listener: TcpListener, ready: Arc<AtomicBool>) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        // A probe which never sends its request must not hold up the others.
+        let ready = Arc::clone(&ready);
+        tokio::spawn(async move {
+            let _ = respond(stream, &ready).await;
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, ready: &AtomicBool) -> std::io::Result<()> {
+    let request = read_request(&mut stream).await?;
+    let path = std::str::from_utf8(&request)
+        .ok()
+        .and_then(|head| head.split_whitespace().nth(1))
+        .unwrap_or_default();
+    let (status, body) = match path {
+        "/live" => ("200 OK", "live"),
+        "/ready" if ready.load(Ordering::Relaxed) => ("200 OK", "ready"),
+        "/ready" => ("503 Service Unavailable", "not ready"),
+        _ => ("404 Not Found", "not found"),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}\n",
+        body.len() + 1
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use super::HealthEndpoint;
+
+    async fn probe(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn probes() {
+        let ready = Arc::new(AtomicBool::new(false));
+        let health = HealthEndpoint::start("127.0.0.1:0".parse().unwrap(), Arc::clone(&ready))
+            .await
+            .unwrap();
+        let addr = health.addr();
+
+        assert!(probe(addr, "/live")
+            .await
+            .starts_with("HTTP/1.1 200 OK\r\n"));
+        let response = probe(addr, "/ready").await;
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.ends_with("\r\n\r\nnot ready\n"));
+
+        ready.store(true, Ordering::Relaxed);
+        assert!(probe(addr, "/ready")
+            .await
+            .starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(probe(addr, "/metrics")
+            .await
+            .starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
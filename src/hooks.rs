@@ -0,0 +1,286 @@
+//! Hooks which customise every request from a [Rhai] script, covering
+//! bespoke protocols and validation logic which the options of the writer
+//! cannot express.
+//!
+//! Each hook is an optional function of the script:
+//!
+//! ```rhai
+//! // The payload of the `index`th request, counting from 0, as a string or blob.
+//! fn payload(index) {
+//!     `GET /item/${index % 100}\n`
+//! }
+//!
+//! // The position in `targets`, an array of addresses, to send the request to.
+//! fn target(index, targets) {
+//!     if index % 10 == 0 { 0 } else { targets.len() - 1 }
+//! }
+//!
+//! // Whether the reply, a blob, is acceptable for the request, also a blob.
+//! fn judge(request, reply) {
+//!     reply.as_string().starts_with("OK")
+//! }
+//! ```
+//!
+//! The top level of the script runs once as it is loaded, so the variables
+//! it defines are available to every hook. Recorded traffic which was sent to
+//! several endpoints is replayed by keeping its records there, each with the
+//! target it was sent to, and picking both from them:
+//!
+//! ```rhai
+//! let records = [
+//!     #{ target: "10.0.0.1:5000", payload: "GET /a\n" },
+//!     #{ target: "10.0.0.2:5000", payload: "GET /b\n" },
+//! ];
+//!
+//! fn payload(index) { records[index % records.len()].payload }
+//!
+//! fn target(index, targets) {
+//!     targets.index_of(records[index % records.len()].target)
+//! }
+//! ```
+//!
+//! [Rhai]: https://rhai.rs
+use std::{fmt::Display, net::SocketAddr, path::Path};
+
+use rhai::{Array, Blob, CallFnOptions, Dynamic, Engine, FuncArgs, Scope, AST};
+
+/// Script of hooks which are called for every request, as described in the
+/// [module](self) documentation.
+pub struct HookScript {
+    engine: Engine,
+    ast: AST,
+    /// Variables defined by the top level of the script.
+    scope: Scope<'static>,
+    payload: bool,
+    target: bool,
+    judge: bool,
+}
+
+/// Failure of a hook, which fails the request it was called for.
+#[derive(Debug)]
+pub struct HookError {
+    hook: &'static str,
+    message: String,
+}
+
+impl Display for HookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the {} hook failed: {}", self.hook, self.message)
+    }
+}
+
+impl std::error::Error for HookError {}
+
+impl HookScript {
+    /// Read a script from a Rhai file.
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+        Self::from_source(&std::fs::read_to_string(path)?)
+    }
+
+    /// Compile a script, running its top level, which must define at least
+    /// one hook, each taking the arguments described in the
+    /// [module](self) documentation.
+    pub fn from_source(source: &str) -> crate::Result<Self> {
+        let engine = Engine::new();
+        let ast = engine.compile(source)?;
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast)?;
+
+        let mut script = Self {
+            engine,
+            ast,
+            scope,
+            payload: false,
+            target: false,
+            judge: false,
+        };
+        for f in script.ast.iter_functions() {
+            let (defined, params) = match f.name {
+                "payload" => (&mut script.payload, 1),
+                "target" => (&mut script.target, 2),
+                "judge" => (&mut script.judge, 2),
+                _ => continue,
+            };
+            if f.params.len() != params {
+                return Err(format!(
+                    "the {} hook takes {params} arguments, not {}",
+                    f.name,
+                    f.params.len()
+                )
+                .into());
+            }
+            *defined = true;
+        }
+        if !(script.payload || script.target || script.judge) {
+            return Err("the script defines none of the payload, target or judge hooks".into());
+        }
+        Ok(script)
+    }
+
+    /// Whether the script decides the payload of each request.
+    pub fn has_payload(&self) -> bool {
+        self.payload
+    }
+
+    /// Whether the script decides the target of each request.
+    pub fn has_target(&self) -> bool {
+        self.target
+    }
+
+    /// Whether the script judges the reply to each request, which must then
+    /// be read.
+    pub fn has_judge(&self) -> bool {
+        self.judge
+    }
+
+    fn call<T: Clone + Send + Sync + 'static>(
+        &self,
+        hook: &'static str,
+        args: impl FuncArgs,
+    ) -> Result<T, HookError> {
+        let error = |message: String| HookError { hook, message };
+        // Hooks see the variables of the top level, but cannot change them
+        // for later requests.
+        let mut scope = self.scope.clone();
+        let returned: Dynamic = self
+            .engine
+            .call_fn_with_options(
+                CallFnOptions::new().eval_ast(false),
+                &mut scope,
+                &self.ast,
+                hook,
+                args,
+            )
+            .map_err(|e| error(e.to_string()))?;
+        let type_name = returned.type_name();
+        returned
+            .try_cast::<T>()
+            .ok_or_else(|| error(format!("returned a {type_name}")))
+    }
+
+    /// Payload of the `index`th request.
+    pub(crate) fn payload(&self, index: u64) -> Result<Vec<u8>, HookError> {
+        let payload: Dynamic = self.call("payload", (index as i64,))?;
+        if payload.is_blob() {
+            return Ok(payload.cast::<Blob>());
+        }
+        match payload.into_immutable_string() {
+            Ok(s) => Ok(s.as_bytes().to_vec()),
+            Err(type_name) => Err(HookError {
+                hook: "payload",
+                message: format!("returned a {type_name} rather than a string or blob"),
+            }),
+        }
+    }
+
+    /// Position in `targets` which the `index`th request is sent to.
+    pub(crate) fn target(&self, index: u64, targets: &[SocketAddr]) -> Result<usize, HookError> {
+        let array: Array = targets.iter().map(|t| t.to_string().into()).collect();
+        let picked: i64 = self.call("target", (index as i64, array))?;
+        usize::try_from(picked)
+            .ok()
+            .filter(|picked| *picked < targets.len())
+            .ok_or_else(|| HookError {
+                hook: "target",
+                message: format!(
+                    "returned {picked}, which is not one of the {} targets",
+                    targets.len()
+                ),
+            })
+    }
+
+    /// Whether the reply to the request is acceptable.
+    pub(crate) fn judge(&self, request: &[u8], reply: &[u8]) -> Result<bool, HookError> {
+        self.call("judge", (request.to_vec(), reply.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use super::HookScript;
+
+    #[test]
+    fn hooks() {
+        let script = HookScript::from_source(
+            r#"
+            let prefix = "item";
+
+            fn payload(index) {
+                if index == 3 { blob(2, 0x41) } else { `${prefix}-${index}` }
+            }
+
+            fn target(index, targets) {
+                if index > 9 { targets.len() } else { index % targets.len() }
+            }
+
+            fn judge(request, reply) {
+                reply.as_string() == "OK " + request.as_string()
+            }
+            "#,
+        )
+        .unwrap();
+        assert!(script.has_payload() && script.has_target() && script.has_judge());
+
+        assert_eq!(script.payload(1).unwrap(), b"item-1");
+        assert_eq!(script.payload(3).unwrap(), b"AA");
+
+        let targets: Vec<SocketAddr> = vec![
+            "127.0.0.1:5000".parse().unwrap(),
+            "127.0.0.1:5001".parse().unwrap(),
+        ];
+        assert_eq!(script.target(3, &targets).unwrap(), 1);
+        let out_of_range = script.target(10, &targets).unwrap_err();
+        assert!(out_of_range
+            .to_string()
+            .contains("not one of the 2 targets"));
+
+        assert!(script.judge(b"a", b"OK a").unwrap());
+        assert!(!script.judge(b"a", b"ERR").unwrap());
+    }
+
+    #[test]
+    fn records() {
+        let script = HookScript::from_source(
+            r#"
+            let records = [
+                #{ target: "127.0.0.1:5001", payload: "GET /a" },
+                #{ target: "127.0.0.1:5000", payload: "GET /b" },
+                #{ target: "127.0.0.1:5002", payload: "GET /c" },
+            ];
+
+            fn payload(index) { records[index % records.len()].payload }
+
+            fn target(index, targets) {
+                targets.index_of(records[index % records.len()].target)
+            }
+            "#,
+        )
+        .unwrap();
+        let targets: Vec<SocketAddr> = vec![
+            "127.0.0.1:5000".parse().unwrap(),
+            "127.0.0.1:5001".parse().unwrap(),
+        ];
+        assert_eq!(script.payload(0).unwrap(), b"GET /a");
+        assert_eq!(script.target(0, &targets).unwrap(), 1);
+        assert_eq!(script.payload(4).unwrap(), b"GET /b");
+        assert_eq!(script.target(4, &targets).unwrap(), 0);
+        // A record sent to an endpoint which is not a target fails.
+        assert!(script.target(2, &targets).is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(HookScript::from_source("let x = 1;").is_err());
+        assert!(HookScript::from_source("fn payload() { 1 }").is_err());
+        assert!(HookScript::from_source("fn payload(index) {").is_err());
+
+        let script = HookScript::from_source("fn payload(index) { index }").unwrap();
+        assert!(!script.has_target());
+        let wrong_type = script.payload(1).unwrap_err();
+        assert!(wrong_type
+            .to_string()
+            .contains("rather than a string or blob"));
+    }
+}
@@ -0,0 +1,297 @@
+//! Rendering of a [`Report`] as a single HTML page, with its charts drawn as
+//! inline SVG so the page can be shared without any other files.
+use std::fmt::Write;
+
+use crate::report::{Annotation, Report};
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 220.0;
+/// Space left of and below the plot for the axis labels.
+const MARGIN_LEFT: f64 = 70.0;
+const MARGIN_BOTTOM: f64 = 30.0;
+const MARGIN_TOP: f64 = 10.0;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:720px;color:#222}\
+h2{margin-top:1.5em;font-size:1.1em}\
+table{border-collapse:collapse}\
+td{padding:2px 12px 2px 0;border-bottom:1px solid #eee}\
+svg text{font-size:11px;fill:#555}\
+.legend span{margin-right:1em}";
+
+/// Line of a chart, drawn in the colour through the points.
+struct Series<'a> {
+    name: &'a str,
+    colour: &'a str,
+    points: Vec<(f64, f64)>,
+}
+
+/// Render the report as a self-contained HTML page.
+pub(crate) fn render(report: &Report) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>gn report</title>\n");
+    let _ = writeln!(out, "<style>{STYLE}</style>\n</head>\n<body>");
+    out.push_str("<h1>gn report</h1>\n");
+    let _ = writeln!(out, "<p>{}</p>", escape(&report.summary()));
+
+    out.push_str("<h2>Throughput</h2>\n");
+    out.push_str(&throughput(report));
+    out.push_str("<h2>Latency over time</h2>\n");
+    out.push_str(&latency_timeline(report));
+    out.push_str("<h2>Latency percentiles</h2>\n");
+    out.push_str(&latency_percentiles(report));
+    out.push_str("<h2>Failures</h2>\n");
+    out.push_str(&bar_chart(
+        &report
+            .failure_causes()
+            .map(|(cause, count)| (cause, count as f64)),
+        "requests",
+    ));
+
+    out.push_str("<h2>Metrics</h2>\n<table>\n");
+    for (name, value) in report.fields() {
+        if let Some(value) = value {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape(name),
+                escape(&value)
+            );
+        }
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+/// Requests and failures per second over each interval of the timeline.
+fn throughput(report: &Report) -> String {
+    let mut requests = Vec::with_capacity(report.timeline.len());
+    let mut failures = Vec::with_capacity(report.timeline.len());
+    let mut last = (0, 0, 0);
+    for point in &report.timeline {
+        let (elapsed, total, failed) = last;
+        let secs = point.elapsed_ms.saturating_sub(elapsed) as f64 / 1000.0;
+        if secs > 0.0 {
+            let at = point.elapsed_ms as f64 / 1000.0;
+            requests.push((at, (point.requests - total) as f64 / secs));
+            failures.push((at, (point.failed_requests - failed) as f64 / secs));
+        }
+        last = (point.elapsed_ms, point.requests, point.failed_requests);
+    }
+    line_chart(
+        &report.annotations,
+        &[
+            Series {
+                name: "requests/s",
+                colour: "#1f77b4",
+                points: requests,
+            },
+            Series {
+                name: "failures/s",
+                colour: "#d62728",
+                points: failures,
+            },
+        ],
+        "requests/s",
+    )
+}
+
+fn latency_timeline(report: &Report) -> String {
+    let series = |name, colour, percentile: fn(&crate::TimelinePoint) -> Option<f64>| Series {
+        name,
+        colour,
+        points: report
+            .timeline
+            .iter()
+            .filter_map(|p| Some((p.elapsed_ms as f64 / 1000.0, percentile(p)?)))
+            .collect(),
+    };
+    line_chart(
+        &report.annotations,
+        &[
+            series("p50", "#2ca02c", |p| p.latency_p50_us),
+            series("p99", "#ff7f0e", |p| p.latency_p99_us),
+        ],
+        "µs",
+    )
+}
+
+fn latency_percentiles(report: &Report) -> String {
+    let bars: Vec<_> = [
+        ("min", report.latency_min_us),
+        ("p50", report.latency_p50_us),
+        ("p90", report.latency_p90_us),
+        ("p99", report.latency_p99_us),
+        ("p99.9", report.latency_p999_us),
+        ("max", report.latency_max_us),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, value?)))
+    .collect();
+    match bars.is_empty() {
+        true => "<p>No latencies were recorded.</p>\n".to_string(),
+        false => bar_chart(&bars, "µs"),
+    }
+}
+
+/// Chart of each series against the seconds since the run started, with a
+/// dashed marker at each annotation.
+fn line_chart(annotations: &[Annotation], series: &[Series], unit: &str) -> String {
+    if series.iter().all(|s| s.points.is_empty()) {
+        return "<p>No time series was recorded.</p>\n".to_string();
+    }
+    let points = || series.iter().flat_map(|s| s.points.iter());
+    let max_x = points().map(|(x, _)| *x).fold(0.0, f64::max);
+    let max_y = points().map(|(_, y)| *y).fold(0.0, f64::max);
+    let (plot_width, plot_height) = plot_size();
+    let x = |v: f64| MARGIN_LEFT + v / nonzero(max_x) * plot_width;
+    let y = |v: f64| MARGIN_TOP + plot_height - v / nonzero(max_y) * plot_height;
+
+    let mut out = svg_start();
+    axes(&mut out, max_y, unit);
+    let _ = writeln!(
+        out,
+        "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}s</text>",
+        WIDTH,
+        HEIGHT - 8.0,
+        number(max_x)
+    );
+    for annotation in annotations {
+        let at = x((annotation.at_ms as f64 / 1000.0).min(max_x));
+        let _ = writeln!(
+            out,
+            "<line x1=\"{at:.1}\" y1=\"{MARGIN_TOP}\" x2=\"{at:.1}\" y2=\"{:.1}\" stroke=\"#7f7f7f\" stroke-dasharray=\"4 3\"><title>{}</title></line>",
+            MARGIN_TOP + plot_height,
+            escape(&annotation.message)
+        );
+        let _ = writeln!(
+            out,
+            "<text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+            at + 3.0,
+            MARGIN_TOP + 10.0,
+            escape(&annotation.message)
+        );
+    }
+    for s in series {
+        let path: Vec<_> = s
+            .points
+            .iter()
+            .map(|&(px, py)| format!("{:.1},{:.1}", x(px), y(py)))
+            .collect();
+        let _ = writeln!(
+            out,
+            "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"/>",
+            s.colour,
+            path.join(" ")
+        );
+    }
+    out.push_str("</svg>\n<div class=\"legend\">");
+    for s in series {
+        let _ = write!(
+            out,
+            "<span style=\"color:{}\">&#9632; {}</span>",
+            s.colour,
+            escape(s.name)
+        );
+    }
+    out.push_str("</div>\n");
+    out
+}
+
+/// Chart of a labelled bar for each value.
+fn bar_chart(bars: &[(&str, f64)], unit: &str) -> String {
+    let max = bars.iter().map(|(_, v)| *v).fold(0.0, f64::max);
+    let (plot_width, plot_height) = plot_size();
+    let slot = plot_width / bars.len().max(1) as f64;
+
+    let mut out = svg_start();
+    axes(&mut out, max, unit);
+    for (i, (name, value)) in bars.iter().enumerate() {
+        let height = value / nonzero(max) * plot_height;
+        let left = MARGIN_LEFT + slot * i as f64;
+        let _ = writeln!(
+            out,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#1f77b4\"><title>{}</title></rect>",
+            left + slot * 0.15,
+            MARGIN_TOP + plot_height - height,
+            slot * 0.7,
+            height,
+            number(*value)
+        );
+        let _ = writeln!(
+            out,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+            left + slot / 2.0,
+            HEIGHT - 8.0,
+            escape(name)
+        );
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+fn plot_size() -> (f64, f64) {
+    (
+        WIDTH - MARGIN_LEFT - 10.0,
+        HEIGHT - MARGIN_TOP - MARGIN_BOTTOM,
+    )
+}
+
+fn svg_start() -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" viewBox=\"0 0 {WIDTH} {HEIGHT}\">\n"
+    )
+}
+
+/// Draw the axes, labelling the vertical one with zero and its maximum.
+fn axes(out: &mut String, max: f64, unit: &str) {
+    let bottom = HEIGHT - MARGIN_BOTTOM;
+    let _ = writeln!(
+        out,
+        "<path d=\"M{MARGIN_LEFT},{MARGIN_TOP} V{bottom} H{}\" stroke=\"#999\" fill=\"none\"/>",
+        WIDTH - 10.0
+    );
+    let _ = writeln!(
+        out,
+        "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{} {}</text>",
+        MARGIN_LEFT - 4.0,
+        MARGIN_TOP + 10.0,
+        number(max),
+        escape(unit)
+    );
+    let _ = writeln!(
+        out,
+        "<text x=\"{:.1}\" y=\"{bottom}\" text-anchor=\"end\">0</text>",
+        MARGIN_LEFT - 4.0
+    );
+}
+
+/// Divisor for scaling to a maximum, so that all zero values are drawn at
+/// zero rather than dividing by it.
+fn nonzero(max: f64) -> f64 {
+    match max > 0.0 {
+        true => max,
+        false => 1.0,
+    }
+}
+
+fn number(value: f64) -> String {
+    match value.fract() == 0.0 {
+        true => format!("{value:.0}"),
+        false => format!("{value:.1}"),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
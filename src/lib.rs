@@ -1,10 +1,110 @@
+mod affinity;
+mod bandwidth;
+pub mod bench;
+mod breaker;
+mod cancel;
+mod capture;
+mod chaos;
+mod connections;
+#[cfg(unix)]
+mod control;
+mod dashboard;
+mod delay;
+mod digest;
+mod endpoint;
+mod engine;
+mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod generator;
+mod group;
+mod health;
+mod hooks;
+mod html;
 mod manager;
+mod matcher;
+mod metrics;
+mod multicast;
+mod observer;
+mod payload;
+mod peers;
 mod protocol;
+mod push;
+#[cfg(feature = "python")]
+mod python;
+mod readiness;
+mod reload;
+mod render;
+mod reply;
+mod report;
+mod resources;
+mod respond;
+mod retry;
+mod rotate;
+mod selftest;
 mod server;
+mod size;
 pub mod statistics;
+mod target;
+mod timing;
+mod tls;
+mod trace;
+mod transcript;
+mod transport;
+#[cfg(unix)]
+mod unix;
+mod websocket;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-pub use manager::{SocketManager, WriteOptions};
+pub use affinity::CoreList;
+pub use bandwidth::Bandwidth;
+pub use breaker::CircuitBreaker;
+pub use cancel::CancellationToken;
+pub use capture::{replay, CaptureReader, CaptureRecord, CaptureWriter};
+pub use chaos::Chaos;
+#[cfg(unix)]
+pub use control::send_command;
+pub use dashboard::{Dashboard, DashboardObserver};
+pub use delay::Delay;
+pub use digest::Digest;
+pub use endpoint::Endpoint;
+pub use engine::Engine;
+pub use generator::{PayloadGenerator, RandomPayloads, Regeneration};
+pub use group::{GroupReport, GroupSection, RunGroup};
+pub use hooks::{HookError, HookScript};
+pub use manager::{ResponseMismatch, SocketManager, WriteOptions};
+pub use matcher::MessageMatcher;
+pub use observer::WriteObserver;
+pub use payload::{
+    pad_payload, random_payload, MixWeight, Padding, PayloadClass, PayloadMix, PayloadOrder,
+    PayloadSpec,
+};
+pub use peers::PeerCount;
 pub use protocol::Protocol;
-pub use server::Server;
+pub use push::{PushObserver, Pushgateway};
+pub use readiness::wait_for_target;
+pub use reload::ConfigWatcher;
+pub use render::Render;
+pub use reply::{Reply, ReplyFraming};
+pub use report::{
+    Annotation, CircuitEvent, CircuitState, ClockOffset, LatencyOutlier, LatencyPercentiles,
+    Report, ReportFormat, StopReason, TimelinePoint,
+};
+pub use resources::ResourceUsage;
+pub use respond::{ResponseScript, Rule};
+pub use retry::RetryPolicy;
+pub use rotate::RotatingFile;
+pub use selftest::{selftest, SelftestResult};
+pub use server::{ConnectionOverflow, Server};
+pub use size::ByteSize;
+pub use target::FamilySplit;
+pub use tls::{SniRoute, TlsConfig, TlsServerConfig};
+pub use transcript::Transcript;
+pub use transport::{
+    ConnectError, MemoryListener, MemoryTransport, PartialWrite, TcpTransport, TlsTransport,
+    Transport, UdpTransport, Unacknowledged,
+};
+#[cfg(unix)]
+pub use transport::{UnixDatagramTransport, UnixTransport};
+pub use websocket::{WebSocketConfig, WebSocketMessage, WebSocketTransport};
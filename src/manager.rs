@@ -1,20 +1,71 @@
 use std::{
+    collections::HashMap,
+    fmt::Display,
     net::{SocketAddr, ToSocketAddrs},
-    sync::Arc,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::Duration,
 };
 
 use futures::{stream::FuturesUnordered, StreamExt};
 use tokio::{
-    io::AsyncWriteExt,
-    net::{TcpStream, UdpSocket},
-    task::JoinHandle,
-    time::Instant,
+    runtime::RuntimeFlavor,
+    sync::watch,
+    task::{JoinHandle, JoinSet},
+    time::{Instant, MissedTickBehavior},
 };
 
-use crate::{statistics::Statistics, Protocol};
+#[cfg(unix)]
+use crate::control::{ControlSocket, WriteControl};
+use crate::{
+    bandwidth::{Bandwidth, TokenBucket},
+    breaker::CircuitBreaker,
+    cancel::CancellationToken,
+    digest::Digest,
+    endpoint::{Endpoint, UNIX_PEER},
+    engine::{self, BlockingPlan, Engine},
+    events::{EventLog, RequestEvent},
+    generator::{GeneratorPool, PayloadGenerator, Regeneration},
+    hooks::{HookError, HookScript},
+    matcher::MessageMatcher,
+    observer::WriteObserver,
+    payload::PayloadMix,
+    reply::{Reply, ReplyFraming},
+    report::{
+        Annotation, CircuitEvent, ClockOffset, LatencyOutlier, Report, StopReason, TimelinePoint,
+    },
+    resources::{ResourceSampler, ResourceUsage},
+    retry::RetryPolicy,
+    statistics::{Statistics, StatisticsSnapshot, WriteInterval},
+    target::{AddressWeights, FamilySplit, Targets},
+    timing,
+    tls::TlsConfig,
+    trace::{self, TraceContext},
+    transcript::Transcript,
+    transport::{
+        self, ConnectError, ConnectionPool, PartialWrite, PeerClose, SourceDrop, Transport,
+        TransportConfig, Unacknowledged,
+    },
+    websocket::WebSocketConfig,
+    Protocol,
+};
+
+/// Interval at which [`WriteObserver::on_tick`] is called.
+const OBSERVER_TICK: Duration = Duration::from_secs(1);
+
+/// Interval at which targets are checked again while every address has been
+/// drained through the control socket.
+const DRAINED_RETRY: Duration = Duration::from_millis(10);
+
+/// Most latency outliers which are kept for the [`Report`], so a degraded
+/// target cannot grow them without bound.
+const MAX_LATENCY_OUTLIERS: usize = 1000;
 
 /// Desired behaviour for how a socket should be written to.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum WriteOptions {
     /// Write a `u64` number of streams.
     Count(u64),
@@ -27,11 +78,32 @@ pub enum WriteOptions {
     ConcurrencyWithCount(u64, u64),
     /// Write a concurrent number of streams for a set duration.
     ConcurrencyWithDuration(u64, humantime::Duration),
+    /// Write streams until stopped through [`SocketManager::stop`].
+    Unlimited,
+    /// Write a concurrent number of streams until stopped through
+    /// [`SocketManager::stop`].
+    ConcurrencyUnlimited(u64),
+    /// Write a `u64` number of streams per second until stopped through
+    /// [`SocketManager::stop`].
+    ///
+    /// Streams are started on a fixed schedule, regardless of whether earlier
+    /// streams have completed, so the offered load stays constant when the
+    /// target slows down.
+    Rate(u64),
+    /// Write a number of streams per second up to a particular count.
+    RateWithCount(u64, u64),
+    /// Write a number of streams per second for a set duration.
+    RateWithDuration(u64, humantime::Duration),
+    /// Write a number of streams per second up to a particular count or for a
+    /// set duration, whichever comes first.
+    RateWithCountOrDuration(u64, u64, humantime::Duration),
 }
 
 impl WriteOptions {
     /// Create [`WriteOptions`] from the known flags of the application which
     /// influence the behaviour of writes.
+    ///
+    /// A `count` of 0 means there is no limit on the number of streams.
     pub fn from_flags(
         count: u64,
         duration: Option<humantime::Duration>,
@@ -40,11 +112,67 @@ impl WriteOptions {
         match (duration, concurrency) {
             (Some(d), None) if count > 1 => WriteOptions::CountOrDuration(count, d),
             (Some(d), None) => WriteOptions::Duration(d),
+            (None, Some(c)) if count == 0 => WriteOptions::ConcurrencyUnlimited(c),
             (None, Some(c)) => WriteOptions::ConcurrencyWithCount(c, count),
             (Some(d), Some(c)) => WriteOptions::ConcurrencyWithDuration(c, d),
+            (None, None) if count == 0 => WriteOptions::Unlimited,
             (None, None) => WriteOptions::Count(count),
         }
     }
+
+    /// Create [`WriteOptions`] which start `rate` streams per second, from
+    /// the same `count` and `duration` flags as [`WriteOptions::from_flags`].
+    pub fn from_rate(rate: u64, count: u64, duration: Option<humantime::Duration>) -> Self {
+        match duration {
+            Some(d) if count > 1 => WriteOptions::RateWithCountOrDuration(rate, count, d),
+            Some(d) => WriteOptions::RateWithDuration(rate, d),
+            None if count == 0 => WriteOptions::Rate(rate),
+            None => WriteOptions::RateWithCount(rate, count),
+        }
+    }
+
+    /// Number of requests which are configured to be written to each
+    /// address, if the count is limited.
+    pub fn count(&self) -> Option<u64> {
+        match self {
+            WriteOptions::Count(count)
+            | WriteOptions::CountOrDuration(count, _)
+            | WriteOptions::ConcurrencyWithCount(_, count)
+            | WriteOptions::RateWithCount(_, count)
+            | WriteOptions::RateWithCountOrDuration(_, count, _) => Some(*count),
+            _ => None,
+        }
+    }
+
+    /// Requests per second which are configured to be sent, if writes are
+    /// paced at a fixed rate.
+    pub fn rate(&self) -> Option<u64> {
+        match self {
+            WriteOptions::Rate(rate)
+            | WriteOptions::RateWithCount(rate, _)
+            | WriteOptions::RateWithDuration(rate, _)
+            | WriteOptions::RateWithCountOrDuration(rate, _, _) => Some(*rate),
+            _ => None,
+        }
+    }
+
+    /// Number of concurrent writers which are configured, if more than one.
+    pub fn concurrency(&self) -> Option<u64> {
+        match self {
+            WriteOptions::ConcurrencyWithCount(concurrency, _)
+            | WriteOptions::ConcurrencyWithDuration(concurrency, _)
+            | WriteOptions::ConcurrencyUnlimited(concurrency) => Some(*concurrency),
+            _ => None,
+        }
+    }
+
+    /// Whether writes only end once the [`SocketManager`] is stopped.
+    pub fn is_unlimited(&self) -> bool {
+        matches!(
+            self,
+            WriteOptions::Unlimited | WriteOptions::ConcurrencyUnlimited(_) | WriteOptions::Rate(_)
+        )
+    }
 }
 
 pub struct SocketManager<'a, S: ToSocketAddrs> {
@@ -53,6 +181,80 @@ pub struct SocketManager<'a, S: ToSocketAddrs> {
     protocol: Protocol,
     write_options: WriteOptions,
     stats: Arc<Statistics>,
+    family_split: Option<FamilySplit>,
+    group_stats: Mutex<Vec<(String, Arc<Statistics>)>>,
+    /// Every host which requests are distributed across, by name, when more
+    /// than one is written to.
+    hosts: Vec<(String, S)>,
+    host_stats: Mutex<Vec<(String, Arc<Statistics>)>>,
+    payload_mix: Option<Arc<PayloadMix>>,
+    /// Generator of the payload of each request and how many threads run it.
+    generator: Option<(Arc<dyn PayloadGenerator>, usize)>,
+    regeneration: Regeneration,
+    transport: Option<Arc<dyn Transport>>,
+    transport_config: TransportConfig,
+    reflect_timing: bool,
+    /// Number of probes which estimate the clock offset of each server.
+    clock_probes: Option<u32>,
+    clock_offsets: Arc<Mutex<Vec<ClockOffset>>>,
+    traceparent: bool,
+    event_log: Option<Arc<EventLog>>,
+    transcript: Option<Arc<Transcript>>,
+    expect_response: Option<Arc<MessageMatcher>>,
+    expect_reply: Option<ReplyFraming>,
+    verify_digest: Option<Digest>,
+    /// Script whose hooks decide the payload and target of each request and
+    /// judge its reply.
+    hooks: Option<Arc<HookScript>>,
+    max_failures: Option<u64>,
+    circuit_breaker: Option<CircuitBreaker>,
+    circuit_events: Arc<Mutex<Vec<CircuitEvent>>>,
+    outlier_threshold: Option<Duration>,
+    latency_outliers: Arc<Mutex<Vec<LatencyOutlier>>>,
+    observers: Vec<Arc<dyn WriteObserver>>,
+    /// Totals which are published to subscribers while the run is written.
+    snapshots: watch::Sender<StatisticsSnapshot>,
+    /// Totals recorded every second for the [`Report`], when requested.
+    timeline: Option<Arc<Mutex<Vec<TimelinePoint>>>>,
+    /// Markers placed in the timeline once the run reaches them.
+    annotations: Arc<Mutex<Vec<Annotation>>>,
+    /// Path of the socket on which commands change the run while it writes.
+    control_socket: Option<PathBuf>,
+    /// Weights of the addresses, as changed through the control socket.
+    addr_weights: Arc<AddressWeights>,
+    stop: Arc<Stop>,
+    /// Token which stops the run once it is cancelled.
+    cancellation: Option<CancellationToken>,
+    /// Path of the Unix socket which is written to instead of the host.
+    unix_path: Option<PathBuf>,
+    streamed_payload: Option<u64>,
+    bandwidth: Option<Arc<TokenBucket>>,
+    engine: Engine,
+    sample_resources: bool,
+    resources: Mutex<Option<ResourceUsage>>,
+    ramp_up: Option<Duration>,
+    retry: Option<RetryPolicy>,
+}
+
+impl<'a> SocketManager<'a, Endpoint> {
+    /// Create a new [`SocketManager`] which writes to the Unix socket at
+    /// `path`, using either [`Protocol::Unix`] or [`Protocol::UnixDatagram`].
+    ///
+    /// Unix sockets have no address, so [`WriteObserver`]s and the [`Report`]
+    /// see requests as being made to the unspecified address `0.0.0.0:0`.
+    pub fn unix(
+        path: impl Into<PathBuf>,
+        input: &'a [u8],
+        protocol: Protocol,
+        write_options: WriteOptions,
+        stats: Statistics,
+    ) -> Self {
+        let path = path.into();
+        Self {
+            unix_path: Some(path.clone()),
+            ..Self::new(Endpoint::Unix(path), input, protocol, write_options, stats)
+        }
+    }
 }
 
 impl<'a, S> SocketManager<'a, S>
@@ -73,112 +275,1006 @@ where
             write_options,
             protocol,
             stats: Arc::new(stats),
+            family_split: None,
+            group_stats: Mutex::new(Vec::new()),
+            hosts: Vec::new(),
+            host_stats: Mutex::new(Vec::new()),
+            payload_mix: None,
+            generator: None,
+            regeneration: Regeneration::default(),
+            transport: None,
+            transport_config: TransportConfig::default(),
+            reflect_timing: false,
+            clock_probes: None,
+            clock_offsets: Arc::default(),
+            traceparent: false,
+            event_log: None,
+            transcript: None,
+            expect_response: None,
+            expect_reply: None,
+            verify_digest: None,
+            hooks: None,
+            max_failures: None,
+            circuit_breaker: None,
+            circuit_events: Arc::default(),
+            outlier_threshold: None,
+            latency_outliers: Arc::default(),
+            observers: Vec::new(),
+            snapshots: watch::Sender::new(StatisticsSnapshot::default()),
+            timeline: None,
+            annotations: Arc::default(),
+            control_socket: None,
+            addr_weights: Arc::default(),
+            stop: Arc::default(),
+            cancellation: None,
+            unix_path: None,
+            streamed_payload: None,
+            bandwidth: None,
+            engine: Engine::default(),
+            sample_resources: false,
+            resources: Mutex::new(None),
+            ramp_up: None,
+            retry: None,
         }
     }
 
+    /// Give every request a new W3C trace, sending its `traceparent` header
+    /// on a line ahead of the input so the request can be found in the
+    /// target's distributed traces. The trace is included in the event log.
+    pub fn with_traceparent(mut self) -> Self {
+        self.traceparent = true;
+        self
+    }
+
+    /// Log the outcome of every request to `out`, as a line of JSON holding
+    /// its start time, address, trace, latency and bytes written or error.
+    pub fn with_event_log(mut self, out: impl std::io::Write + Send + 'static) -> Self {
+        self.event_log = Some(Arc::new(EventLog::new(out)));
+        self
+    }
+
+    /// Save a sample of requests and their replies to a [`Transcript`], which
+    /// requires replies to be read.
+    pub fn with_transcript(mut self, transcript: Transcript) -> Self {
+        self.transcript = Some(Arc::new(transcript));
+        self
+    }
+
+    /// Prefix each message with its send time and wait for the server to
+    /// reflect it back alongside the time it was received, recording the
+    /// estimated one-way delay. This requires a server which reflects timing.
+    pub fn with_reflect_timing(mut self) -> Self {
+        self.reflect_timing = true;
+        self
+    }
+
+    /// Before writing, send this many probes to each server to estimate how
+    /// far its clock is from the writer's, in the manner of NTP, and correct
+    /// the one-way delays to it by that offset. This requires reflected
+    /// timing.
+    pub fn with_clock_calibration(mut self, probes: u32) -> Self {
+        self.clock_probes = Some(probes);
+        self
+    }
+
+    /// Wait for the response to each request, which only counts as
+    /// successful when the response matches. Mismatched responses fail with
+    /// a [`ResponseMismatch`] and are counted separately from other failures.
+    pub fn with_expect_response(mut self, matcher: MessageMatcher) -> Self {
+        self.expect_response = Some(Arc::new(matcher));
+        self
+    }
+
+    /// Wait for the reply to each request, which ends as given by the
+    /// framing, recording its size and round trip. Expected responses are
+    /// matched against this reply, and read until EOF when it is not set.
+    pub fn with_expect_reply(mut self, framing: ReplyFraming) -> Self {
+        self.expect_reply = Some(framing);
+        self
+    }
+
+    /// Wait for the reply to each request, which only counts as successful
+    /// when it is the digest of the request, as replied by a server with
+    /// [`crate::Server::reply_digest`]. Requests whose digest differs fail
+    /// with a [`ResponseMismatch`].
+    pub fn with_verify_digest(mut self, digest: Digest) -> Self {
+        self.verify_digest = Some(digest);
+        self
+    }
+
+    /// Customise every request with the hooks of the script, which decide
+    /// its payload and target and judge its reply. Judged replies are waited
+    /// for, read until EOF unless framed otherwise, and requests whose reply
+    /// is judged unacceptable fail with a [`ResponseMismatch`]. A hook which
+    /// fails, fails its request with a [`HookError`].
+    pub fn with_hooks(mut self, script: HookScript) -> Self {
+        self.hooks = Some(Arc::new(script));
+        self
+    }
+
+    /// Write using a custom [`Transport`] rather than the one chosen by the
+    /// [`Protocol`].
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Distribute requests between the resolved IPv4 and IPv6 addresses of
+    /// the host using the given ratio, rather than writing the full workload
+    /// to every resolved address.
+    pub fn with_family_split(mut self, split: FamilySplit) -> Self {
+        self.family_split = Some(split);
+        self
+    }
+
+    /// Distribute requests round-robin across these hosts as well as the one
+    /// the manager was created with, rather than writing the full workload to
+    /// each of them. Each host is named by how it displays in
+    /// [`SocketManager::host_statistics`].
+    pub fn with_hosts(mut self, hosts: impl IntoIterator<Item = S>) -> Self
+    where
+        S: Clone + Display,
+    {
+        if self.hosts.is_empty() {
+            self.hosts.push((self.host.to_string(), self.host.clone()));
+        }
+        self.hosts
+            .extend(hosts.into_iter().map(|host| (host.to_string(), host)));
+        self
+    }
+
+    /// Sample the payload of each request from a weighted [`PayloadMix`]
+    /// instead of always sending the input.
+    pub fn with_payload_mix(mut self, mix: PayloadMix) -> Self {
+        self.payload_mix = Some(Arc::new(mix));
+        self
+    }
+
+    /// Generate the payload of each request instead of sending the input,
+    /// from `threads` threads which run ahead of the requests, so expensive
+    /// payloads do not hold up sending. Requests which have to wait for their
+    /// payload are counted in the [`Report`].
+    pub fn with_payload_generator(
+        mut self,
+        generator: impl PayloadGenerator + 'static,
+        threads: usize,
+    ) -> Self {
+        self.generator = Some((Arc::new(generator), threads.max(1)));
+        self
+    }
+
+    /// Generate a new payload from the [`PayloadGenerator`] for every request,
+    /// for every connection or once for the whole run, rather than for every
+    /// request.
+    ///
+    /// Without [`SocketManager::with_keepalive`] every request opens its own
+    /// connection, so generating for every connection is the same as for
+    /// every request.
+    pub fn with_payload_regeneration(mut self, regeneration: Regeneration) -> Self {
+        self.regeneration = regeneration;
+        self
+    }
+
+    /// Limit how many connections may be in the process of being established
+    /// at once, independently of the write concurrency. This has no effect
+    /// on connectionless protocols or a custom [`Transport`].
+    pub fn with_connect_concurrency(mut self, limit: usize) -> Self {
+        self.transport_config.connect_concurrency = Some(limit);
+        self
+    }
+
+    /// Write `len` bytes of generated data with every request instead of the
+    /// input. The data is written to the socket in chunks as it is generated,
+    /// so the payload is never held in memory, which suits measuring the
+    /// sustained throughput of bulk transfers. This takes precedence over the
+    /// input, a [`PayloadMix`] and waiting for replies, and is only supported
+    /// by stream oriented protocols.
+    pub fn with_streamed_payload(mut self, len: u64) -> Self {
+        self.streamed_payload = Some(len);
+        self
+    }
+
+    /// Increase the concurrency or rate of the [`WriteOptions`] gradually from
+    /// one to their target over `window` before writing them, so the target
+    /// is not hit with the full load from cold. Requests written while
+    /// ramping up are not recorded, nor are the connections they open or
+    /// their TLS handshakes, and the window is left out of the elapsed time.
+    pub fn with_ramp_up(mut self, window: Duration) -> Self {
+        self.ramp_up = Some(window);
+        self
+    }
+
+    /// Sample the CPU and memory used by this process while writing, which
+    /// is included in the [`Report`] to show when the writer rather than the
+    /// target is the bottleneck. This is only supported on Linux.
+    pub fn with_resource_sampling(mut self) -> Self {
+        self.sample_resources = true;
+        self
+    }
+
+    /// Settings for the TLS handshake when writing with [`Protocol::Tls`].
+    pub fn with_tls(mut self, config: TlsConfig) -> Self {
+        self.transport_config.tls = config;
+        self
+    }
+
+    /// Settings for the WebSocket which is opened for every request when
+    /// writing with [`Protocol::Ws`] or [`Protocol::Wss`].
+    pub fn with_websocket(mut self, config: WebSocketConfig) -> Self {
+        self.transport_config.websocket = config;
+        self
+    }
+
+    /// Reuse TCP connections across requests, with one connection for each
+    /// concurrent request, rather than opening a connection for every
+    /// request. The [`Report`] counts the connections which were opened.
+    ///
+    /// Requests which wait for a reply still open a connection each, as the
+    /// reply is read until the server closes the connection.
+    pub fn with_keepalive(mut self) -> Self {
+        self.transport_config.keepalive = true;
+        self
+    }
+
+    /// Read whatever the server pushes down each kept alive connection while
+    /// requests are written to it, simulating chat and streaming protocols
+    /// with traffic in both directions. The [`Report`] counts the inbound
+    /// bytes and their throughput alongside those written. This requires
+    /// [`SocketManager::with_keepalive`] and is only supported over TCP.
+    pub fn with_duplex(mut self) -> Self {
+        self.transport_config.duplex = true;
+        self
+    }
+
+    /// Close kept alive connections once they have been open for the
+    /// lifetime, re-establishing them for the next request, to simulate
+    /// clients behind load balancers which recycle connections. The [`Report`]
+    /// counts the connections which were recycled. This requires
+    /// [`SocketManager::with_keepalive`].
+    pub fn with_connection_lifetime(mut self, lifetime: Duration) -> Self {
+        self.transport_config.connection_lifetime = Some(lifetime);
+        self
+    }
+
+    /// Bind every socket to a network interface or VRF, such as `eth1`, so
+    /// traffic only leaves through it on multi-homed hosts. This is only
+    /// supported over TCP, TLS and UDP on Linux.
+    pub fn with_interface(mut self, interface: impl Into<String>) -> Self {
+        self.transport_config.interface = Some(interface.into());
+        self
+    }
+
+    /// Let UDP datagrams which are written to a multicast group cross this
+    /// many routers, where the default of 1 keeps them on the local network.
+    /// Datagrams leave through the interface given to
+    /// [`SocketManager::with_interface`], if any.
+    pub fn with_multicast_ttl(mut self, ttl: u32) -> Self {
+        self.transport_config.multicast_ttl = Some(ttl);
+        self
+    }
+
+    /// Fail requests whose TCP connection, including any TLS handshake, is
+    /// not established within the timeout. The [`Report`] counts requests
+    /// which timed out. This is only supported over TCP and TLS.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.transport_config.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Once the input of a request is written, half-close the connection and
+    /// only count the request as successful once the server closes it too,
+    /// within the timeout if one is given. This catches servers which accept
+    /// the input but crash while processing it. This is only supported over
+    /// TCP and TLS, without keepalive.
+    pub fn with_wait_peer_close(mut self, timeout: Option<Duration>) -> Self {
+        self.transport_config.wait_peer_close = true;
+        self.transport_config.close_timeout = timeout;
+        self
+    }
+
+    /// Fail requests whose input is not written within the timeout once
+    /// connected, such as when the target stops reading. This is only
+    /// supported over TCP and TLS.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.transport_config.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Retry failed requests to the same address as given by the policy,
+    /// before counting them as failures. Requests whose response did not
+    /// match are not retried, and the [`Report`] counts the retries made,
+    /// by whether the attempt failed to connect or once connected.
+    ///
+    /// Resolving the host is retried in the same way, where each failed
+    /// attempt is counted as a resolution failure rather than a request.
+    pub fn with_retries(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Write with the given [`Engine`] rather than tokio tasks.
+    ///
+    /// The [`Engine::BlockingThreads`] engine only writes the input with the
+    /// configured [`WriteOptions`], so it cannot be combined with options
+    /// which shape individual requests, such as a [`PayloadMix`].
+    pub fn with_engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Limit the rate of bytes written across every concurrent request. Each
+    /// request waits for its payload to fit within the bandwidth before it is
+    /// written, which is not counted towards its latency.
+    pub fn with_bandwidth(mut self, bandwidth: Bandwidth) -> Self {
+        self.bandwidth = Some(Arc::new(TokenBucket::new(bandwidth)));
+        self
+    }
+
+    /// Stop writing once `max` requests have failed, so a broken target does
+    /// not use up the whole run. The [`Report`] records this as the reason
+    /// the run stopped.
+    pub fn with_max_failures(mut self, max: u64) -> Self {
+        self.max_failures = Some(max);
+        self
+    }
+
+    /// Stop sending to an address for a cool-down period once it has failed
+    /// a number of requests in a row. Within a [`FamilySplit`], its share of
+    /// requests moves to the remaining addresses in the meantime, otherwise
+    /// writing pauses until the cool-down ends. The [`Report`] records each
+    /// time a circuit opens or closes.
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Record the address, start time and a digest of the payload of every
+    /// successful request which takes at least `threshold`, so slowness can
+    /// be correlated with message contents. The [`Report`] keeps the first
+    /// 1000 outliers.
+    pub fn with_latency_outliers(mut self, threshold: Duration) -> Self {
+        self.outlier_threshold = Some(threshold);
+        self
+    }
+
+    /// Notify the [`WriteObserver`] of every request, and periodically of
+    /// the progress of the run. This can be called multiple times to add more
+    /// observers.
+    pub fn with_observer(mut self, observer: impl WriteObserver + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Receive a [`StatisticsSnapshot`] of the run every second while
+    /// [`SocketManager::write`] runs, and the final totals once it completes.
+    pub fn subscribe(&self) -> watch::Receiver<StatisticsSnapshot> {
+        self.snapshots.subscribe()
+    }
+
+    /// Record the totals of the run every second, and once it completes, as
+    /// the [`Report`]'s timeline.
+    pub fn with_timeline(mut self) -> Self {
+        self.timeline = Some(Arc::default());
+        self
+    }
+
+    /// Mark the point of the run at the annotation, such as when a deployment
+    /// is due, so changes in throughput can be correlated with it. The
+    /// annotation is placed in the first point of the timeline, which this
+    /// records, at or after it, and in the [`Report`] once it is reached.
+    pub fn with_annotation(mut self, annotation: Annotation) -> Self {
+        self.timeline.get_or_insert_with(Arc::default);
+        let mut annotations = self.annotations.lock().unwrap();
+        annotations.push(annotation);
+        annotations.sort_by_key(|a| a.at_ms);
+        drop(annotations);
+        self
+    }
+
+    /// Accept commands on the Unix socket at `path` while writing, which are
+    /// sent with `gn ctl`. `set-weight ADDR WEIGHT` changes the share of
+    /// requests sent to an address among those of its host, where a weight of
+    /// zero drains it, and `annotate MESSAGE` marks the current time. Both are
+    /// recorded as annotations of the timeline, which this records.
+    pub fn with_control_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.timeline.get_or_insert_with(Arc::default);
+        self.control_socket = Some(path.into());
+        self
+    }
+
+    /// Stop the run once the token is cancelled, as with
+    /// [`SocketManager::stop`], so that an application embedding the writer
+    /// can end it early from elsewhere and still obtain the [`Report`].
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
     /// Write to the provided host(s), returning the total number of bytes written.
     /// At the same time, this also calculates the throughput for total number
     /// of bytes sent per second.
-    ///
-    /// NOTE: Owing to truncation from nanosecond precision to seconds, the
-    /// produced throughput may not be accurate for low write counts.
     pub async fn write(&self) -> crate::Result<u64> {
-        let addrs = self
-            .host
-            .to_socket_addrs()
-            .expect("Valid socket addresses are provided");
-        for addr in addrs {
-            match self.write_options {
-                WriteOptions::Count(count) => {
-                    for _ in 0..count {
-                        match write_stream(addr, &self.protocol, self.input).await {
-                            Ok(b) => {
-                                self.stats.increment_total(b);
-                                self.stats.record_success();
-                            }
-                            Err(_) => self.stats.record_failure(),
-                        }
+        if self.write_options.rate() == Some(0) {
+            return Err("the rate must be at least 1 request per second".into());
+        }
+        let _control = self.start_control()?;
+        let Some(token) = &self.cancellation else {
+            return self.write_sampled().await;
+        };
+        let write = self.write_sampled();
+        tokio::pin!(write);
+        tokio::select! {
+            written = &mut write => return written,
+            _ = token.cancelled() => self.stop(),
+        }
+        // In-flight requests complete and are recorded before returning.
+        write.await
+    }
+
+    async fn write_sampled(&self) -> crate::Result<u64> {
+        let Some(mut sampler) = self.sample_resources.then(ResourceSampler::new).flatten() else {
+            return self.write_observed().await;
+        };
+        let written = tokio::select! {
+            written = self.write_observed() => written,
+            never = sampler.run() => match never {},
+        };
+        *self.resources.lock().unwrap() = sampler.usage();
+        written
+    }
+
+    async fn write_observed(&self) -> crate::Result<u64> {
+        if self.observers.is_empty() && self.snapshots.is_closed() && self.timeline.is_none() {
+            return self.write_targets().await;
+        }
+
+        let write = self.write_targets();
+        tokio::pin!(write);
+        let mut tick = tokio::time::interval(OBSERVER_TICK);
+        // The first tick completes immediately, before anything is written.
+        tick.tick().await;
+        loop {
+            tokio::select! {
+                written = &mut write => {
+                    self.publish_snapshot();
+                    return written;
+                }
+                _ = tick.tick() => {
+                    self.publish_snapshot();
+                    if self.observers.is_empty() {
+                        continue;
+                    }
+                    self.stats.record_throughput();
+                    let report = self.report();
+                    for observer in &self.observers {
+                        observer.on_tick(&report);
                     }
                 }
-                WriteOptions::Duration(duration) => {
-                    let for_duration = Instant::now();
-
-                    let predicate = || for_duration.elapsed() >= *duration;
-                    write_stream_with_predicate(
-                        predicate,
-                        addr,
-                        &self.protocol,
-                        self.input,
-                        &self.stats,
-                    )
-                    .await?;
+            }
+        }
+    }
+
+    /// Start accepting commands on the control socket, if a path was given
+    /// for it. They are accepted until the returned socket is dropped.
+    #[cfg(unix)]
+    fn start_control(&self) -> crate::Result<Option<ControlSocket>> {
+        let Some(path) = &self.control_socket else {
+            return Ok(None);
+        };
+        let control = WriteControl {
+            stats: Arc::clone(&self.stats),
+            weights: Arc::clone(&self.addr_weights),
+            annotations: Arc::clone(&self.annotations),
+        };
+        ControlSocket::start(path, control)
+            .map(Some)
+            .map_err(|e| format!("cannot listen on the control socket: {e}").into())
+    }
+
+    #[cfg(not(unix))]
+    fn start_control(&self) -> crate::Result<Option<()>> {
+        match self.control_socket {
+            Some(_) => Err("a control socket is only supported on Unix".into()),
+            None => Ok(None),
+        }
+    }
+
+    fn publish_snapshot(&self) {
+        let snapshot = self.stats.snapshot();
+        if let Some(timeline) = &self.timeline {
+            let mut timeline = timeline.lock().unwrap();
+            let mut point = TimelinePoint::from(&snapshot);
+            let since = timeline.last().map(|last| last.elapsed_ms);
+            point.annotations = self
+                .annotations
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|a| {
+                    since.is_none_or(|since| a.at_ms > since) && a.at_ms <= point.elapsed_ms
+                })
+                .map(|a| a.message.clone())
+                .collect();
+            timeline.push(point);
+        }
+        self.snapshots.send_replace(snapshot);
+    }
+
+    async fn write_targets(&self) -> crate::Result<u64> {
+        if self.engine == Engine::BlockingThreads {
+            self.write_blocking().await?;
+            return Ok(self.finish());
+        }
+        if let Some(path) = &self.unix_path {
+            let endpoint = Endpoint::Unix(path.clone());
+            let ctx = Arc::new(self.context(Targets::single(UNIX_PEER), &endpoint)?);
+            self.write_with_context(&ctx).await?;
+            return Ok(self.finish());
+        }
+
+        if !self.hosts.is_empty() {
+            return self.write_round_robin().await;
+        }
+
+        let addrs = self
+            .resolve(&self.host)
+            .await
+            .map_err(|e| format!("unable to resolve the host: {e}"))?;
+
+        match self.family_split {
+            Some(split) => {
+                let targets = Targets::family_split(&addrs, split)?;
+                let endpoint = Endpoint::Inet(addrs[0]);
+                let ctx = Arc::new(self.context(targets, &endpoint)?);
+                self.write_with_context(&ctx).await?;
+                let mut group_stats = self.group_stats.lock().unwrap();
+                for group in ctx.targets.groups() {
+                    group.stats().record_throughput();
+                    group_stats.push((group.name().to_string(), Arc::clone(group.stats())));
                 }
-                WriteOptions::CountOrDuration(count, duration) => {
-                    let for_duration = Instant::now();
-                    let mut sent = 0;
-                    let predicate = || {
-                        if sent == count || for_duration.elapsed() >= *duration {
-                            return true;
-                        }
-                        sent += 1;
-                        false
-                    };
-                    write_stream_with_predicate(
-                        predicate,
-                        addr,
-                        &self.protocol,
-                        self.input,
-                        &self.stats,
-                    )
-                    .await?;
+            }
+            None => {
+                for addr in addrs {
+                    let ctx = Arc::new(self.context(Targets::single(addr), &addr.into())?);
+                    self.write_with_context(&ctx).await?;
                 }
-                WriteOptions::ConcurrencyWithCount(concurrency, count) => {
-                    let futs = FuturesUnordered::new();
-                    let requests_per_task = count / concurrency;
-                    for _ in 0..concurrency {
-                        let input = self.input.to_owned();
-                        let protocol = self.protocol.clone();
-                        let task = tokio::spawn(async move {
-                            let mut task_bytes = 0;
-                            let mut success: u64 = 0;
-                            let mut failure: u64 = 0;
-                            for _ in 0..requests_per_task {
-                                match write_stream(addr, &protocol, &input).await {
-                                    Ok(b) => {
-                                        task_bytes += b;
-                                        success += 1;
-                                    }
-                                    Err(_) => failure += 1,
-                                }
-                            }
-                            (task_bytes, success, failure)
-                        });
-                        futs.push(task);
+            }
+        }
+        Ok(self.finish())
+    }
+
+    /// Write to each of the hosts in turn, recording their own statistics.
+    async fn write_round_robin(&self) -> crate::Result<u64> {
+        if self.family_split.is_some() {
+            return Err("a family split cannot be used with multiple hosts".into());
+        }
+        let mut hosts = Vec::new();
+        for (name, host) in &self.hosts {
+            let addrs = self
+                .resolve(host)
+                .await
+                .map_err(|e| format!("cannot resolve {name}: {e}"))?;
+            hosts.push((name.clone(), addrs));
+        }
+        let endpoint = Endpoint::Inet(hosts[0].1[0]);
+        let ctx = Arc::new(self.context(Targets::round_robin(hosts), &endpoint)?);
+        self.write_with_context(&ctx).await?;
+        let mut host_stats = self.host_stats.lock().unwrap();
+        for group in ctx.targets.groups() {
+            group.stats().record_throughput();
+            host_stats.push((group.name().to_string(), Arc::clone(group.stats())));
+        }
+        Ok(self.finish())
+    }
+
+    /// Write to every address from the OS threads of the blocking engine.
+    async fn write_blocking(&self) -> crate::Result<()> {
+        // Every field is named, so that an option which is added does not
+        // compile until it is either listed as unsupported or known to apply.
+        let Self {
+            host: _,
+            input: _,
+            protocol: _,
+            write_options: _,
+            stats: _,
+            family_split,
+            group_stats: _,
+            hosts,
+            host_stats: _,
+            payload_mix,
+            generator,
+            // Only applies to a payload generator.
+            regeneration: _,
+            transport,
+            transport_config,
+            reflect_timing,
+            clock_probes,
+            clock_offsets: _,
+            traceparent,
+            event_log,
+            transcript,
+            expect_response,
+            expect_reply,
+            verify_digest,
+            hooks,
+            max_failures: _,
+            circuit_breaker,
+            circuit_events: _,
+            outlier_threshold,
+            latency_outliers: _,
+            observers,
+            snapshots: _,
+            timeline: _,
+            annotations: _,
+            control_socket,
+            addr_weights: _,
+            stop: _,
+            cancellation: _,
+            unix_path: _,
+            streamed_payload,
+            bandwidth,
+            engine: _,
+            sample_resources: _,
+            resources: _,
+            ramp_up,
+            retry,
+        } = self;
+        let TransportConfig {
+            connect_concurrency,
+            stats: _,
+            tls: _,
+            websocket: _,
+            keepalive,
+            duplex,
+            connection_lifetime,
+            interface,
+            multicast_ttl,
+            connect_timeout,
+            write_timeout,
+            wait_peer_close,
+            // Only applies when waiting for the peer to close.
+            close_timeout: _,
+        } = transport_config;
+        let unsupported = [
+            (family_split.is_some(), "a family split"),
+            (!hosts.is_empty(), "multiple hosts"),
+            (payload_mix.is_some(), "a payload mix"),
+            (generator.is_some(), "a payload generator"),
+            (transport.is_some(), "a custom transport"),
+            (connect_concurrency.is_some(), "a connect concurrency"),
+            (*keepalive, "keepalive"),
+            (*duplex, "duplex"),
+            (control_socket.is_some(), "a control socket"),
+            (connection_lifetime.is_some(), "a connection lifetime"),
+            (interface.is_some(), "an interface"),
+            (multicast_ttl.is_some(), "a multicast TTL"),
+            (
+                connect_timeout.is_some() || write_timeout.is_some(),
+                "timeouts",
+            ),
+            (*wait_peer_close, "waiting for the peer to close"),
+            (*reflect_timing, "reflected timing"),
+            (clock_probes.is_some(), "clock calibration"),
+            (*traceparent, "trace context"),
+            (event_log.is_some(), "an event log"),
+            (transcript.is_some(), "a transcript"),
+            (expect_response.is_some(), "expected responses"),
+            (expect_reply.is_some(), "expected replies"),
+            (verify_digest.is_some(), "verifying digests"),
+            (hooks.is_some(), "a hook script"),
+            (circuit_breaker.is_some(), "a circuit breaker"),
+            (outlier_threshold.is_some(), "latency outliers"),
+            (!observers.is_empty(), "observers"),
+            (streamed_payload.is_some(), "streamed payloads"),
+            (bandwidth.is_some(), "a bandwidth limit"),
+            (ramp_up.is_some(), "a ramp-up"),
+            (retry.is_some(), "retries"),
+        ];
+        if let Some((_, option)) = unsupported.iter().find(|(set, _)| *set) {
+            return Err(format!("the blocking-threads engine does not support {option}").into());
+        }
+        if !engine::supports(&self.protocol) {
+            return Err(format!(
+                "the blocking-threads engine does not support {}",
+                self.protocol
+            )
+            .into());
+        }
+
+        let plan = Arc::new(BlockingPlan::new(&self.write_options)?);
+        let endpoints = match &self.unix_path {
+            Some(path) => vec![Endpoint::Unix(path.clone())],
+            None => self
+                .resolve(&self.host)
+                .await
+                .map_err(|e| format!("unable to resolve the host: {e}"))?
+                .into_iter()
+                .map(Endpoint::Inet)
+                .collect(),
+        };
+        for endpoint in endpoints {
+            let plan = Arc::clone(&plan);
+            let protocol = self.protocol.clone();
+            let input = self.input.to_owned();
+            let stats = Arc::clone(&self.stats);
+            let stop = Arc::clone(&self.stop);
+            let max_failures = self.max_failures;
+            tokio::task::spawn_blocking(move || {
+                plan.run(&protocol, &endpoint, &input, &stats, &stop, max_failures)
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Resolve the addresses of a host, retrying with the backoff of the
+    /// [`RetryPolicy`] if one is given. Failed attempts are counted as
+    /// resolution failures, never as failed requests.
+    async fn resolve(&self, host: &S) -> Result<Vec<SocketAddr>, String> {
+        let mut attempts = 0;
+        loop {
+            let error = match lookup(host) {
+                Ok(addrs) if !addrs.is_empty() => return Ok(addrs),
+                Ok(_) => "no addresses were found".to_string(),
+                Err(e) => e.to_string(),
+            };
+            self.stats.record_resolution_failure();
+            attempts += 1;
+            match self.retry {
+                Some(policy) if attempts <= policy.retries => {
+                    tokio::time::sleep(policy.backoff(attempts)).await;
+                }
+                _ if attempts > 1 => return Err(format!("{error}, after {attempts} attempts")),
+                _ => return Err(error),
+            }
+        }
+    }
+
+    /// Record the final throughput of the run, returning the total number of
+    /// bytes written.
+    fn finish(&self) -> u64 {
+        for class in self.payload_mix.iter().flat_map(|mix| mix.classes()) {
+            class.stats().record_throughput();
+        }
+        self.stats.record_throughput();
+        if let Some(Err(e)) = self.event_log.as_ref().map(|log| log.flush()) {
+            eprintln!("Unable to write to the event log: {e}");
+        }
+        if let Some(Err(e)) = self.transcript.as_ref().map(|t| t.flush()) {
+            eprintln!("Unable to write to the transcript: {e}");
+        }
+        self.stats.total_bytes()
+    }
+
+    fn context(&self, targets: Targets, endpoint: &Endpoint) -> crate::Result<WriteContext> {
+        if self.clock_probes.is_some() && !self.reflect_timing {
+            return Err("clock calibration requires reflected timing".into());
+        }
+        if self.traceparent && self.streamed_payload.is_some() {
+            return Err("trace context cannot be sent with streamed payloads".into());
+        }
+        if self.generator.is_some()
+            && (self.payload_mix.is_some() || self.streamed_payload.is_some())
+        {
+            return Err(
+                "a payload generator cannot be used with a payload mix or streamed payloads".into(),
+            );
+        }
+        if self.hooks.as_ref().is_some_and(|hooks| hooks.has_payload())
+            && (self.generator.is_some()
+                || self.payload_mix.is_some()
+                || self.streamed_payload.is_some())
+        {
+            return Err("the payload hook cannot be used with a payload generator, a payload mix or streamed payloads".into());
+        }
+        let targets = match self.circuit_breaker {
+            Some(breaker) => targets.with_circuit_breaker(breaker),
+            None => targets,
+        }
+        .with_address_weights(&self.addr_weights);
+        let transport = match &self.transport {
+            Some(transport) => Arc::clone(transport),
+            None => {
+                let config = TransportConfig {
+                    stats: Some(Arc::clone(&self.stats)),
+                    ..self.transport_config.clone()
+                };
+                transport::for_endpoint(&self.protocol, endpoint, &config)?
+            }
+        };
+        let expect_reply = self.expect_reply.clone().or_else(|| {
+            (self.expect_response.is_some()
+                || self.verify_digest.is_some()
+                || self.hooks.as_ref().is_some_and(|hooks| hooks.has_judge()))
+            .then(ReplyFraming::default)
+        });
+        if self.transcript.is_some() && expect_reply.is_none() {
+            return Err("a transcript requires replies to be read".into());
+        }
+        let (input, generator) = match &self.generator {
+            Some((generator, _)) if self.regeneration == Regeneration::Once => {
+                (generator.generate(0), None)
+            }
+            Some((generator, threads)) => (
+                self.input.to_owned(),
+                Some(GeneratorPool::start(Arc::clone(generator), *threads)),
+            ),
+            None => (self.input.to_owned(), None),
+        };
+        Ok(WriteContext {
+            targets,
+            transport,
+            input,
+            payload_mix: self.payload_mix.clone(),
+            generator,
+            connection_payloads: (self.regeneration == Regeneration::PerConnection
+                && self.transport_config.keepalive)
+                .then(ConnectionPool::default),
+            reflect_timing: self.reflect_timing,
+            clock_offsets: Mutex::default(),
+            traceparent: self.traceparent,
+            event_log: self.event_log.clone(),
+            transcript: self.transcript.clone(),
+            streamed_payload: self.streamed_payload,
+            bandwidth: self.bandwidth.clone(),
+            expect_response: self.expect_response.clone(),
+            expect_reply,
+            verify_digest: self.verify_digest,
+            hooks: self.hooks.clone(),
+            next_index: AtomicU64::new(0),
+            stats: Arc::clone(&self.stats),
+            max_failures: self.max_failures,
+            circuit_events: Arc::clone(&self.circuit_events),
+            outlier_threshold: self.outlier_threshold,
+            latency_outliers: Arc::clone(&self.latency_outliers),
+            observers: self.observers.clone(),
+            stop: Arc::clone(&self.stop),
+            retry: self.retry,
+            warming_up: AtomicBool::new(false),
+        })
+    }
+
+    /// Run the configured [`WriteOptions`] against the targets of the context.
+    async fn write_with_context(&self, ctx: &Arc<WriteContext>) -> crate::Result<()> {
+        if let Some(probes) = self.clock_probes {
+            let mut offsets = Vec::new();
+            for addr in ctx.targets.addrs() {
+                offsets.push(ctx.calibrate_clock(addr, probes).await?);
+            }
+            self.clock_offsets.lock().unwrap().extend(offsets);
+        }
+        if let Some(window) = self.ramp_up {
+            let start = Instant::now();
+            ctx.warming_up.store(true, Ordering::Relaxed);
+            self.stats.set_warming_up(true);
+            let ramped = ramp_up(&self.write_options, window, ctx).await;
+            self.stats.set_warming_up(false);
+            ctx.warming_up.store(false, Ordering::Relaxed);
+            ramped?;
+            let stats = ctx
+                .targets
+                .groups()
+                .iter()
+                .map(|group| group.stats())
+                .chain(
+                    self.payload_mix
+                        .iter()
+                        .flat_map(|mix| mix.classes())
+                        .map(|c| c.stats()),
+                )
+                .chain([&ctx.stats]);
+            for stats in stats {
+                stats.exclude(start.elapsed());
+            }
+        }
+        match self.write_options {
+            WriteOptions::Count(count) => {
+                for _ in 0..count {
+                    if ctx.is_stopped() {
+                        break;
                     }
-                    self.handle_futures(futs).await?;
+                    ctx.write_next().await;
                 }
-                WriteOptions::ConcurrencyWithDuration(concurrency, duration) => {
-                    let futs = FuturesUnordered::new();
-                    for _ in 0..concurrency {
-                        let input = self.input.to_owned();
-                        let protocol = self.protocol.clone();
-                        let stats = Arc::clone(&self.stats);
-                        let task = tokio::spawn(async move {
-                            let for_duration = Instant::now();
-                            let predicate = || for_duration.elapsed() >= *duration;
-                            write_stream_with_predicate(predicate, addr, &protocol, &input, &stats)
-                                .await
-                                .unwrap()
-                        });
-                        futs.push(task);
+            }
+            WriteOptions::Unlimited => write_stream_with_predicate(|| false, ctx).await,
+            WriteOptions::Duration(duration) => {
+                let for_duration = Instant::now();
+
+                let predicate = || for_duration.elapsed() >= *duration;
+                write_stream_with_predicate(predicate, ctx).await;
+            }
+            WriteOptions::CountOrDuration(count, duration) => {
+                let for_duration = Instant::now();
+                let mut sent = 0;
+                let predicate = || {
+                    if sent == count || for_duration.elapsed() >= *duration {
+                        return true;
                     }
-                    self.handle_futures(futs).await?;
+                    sent += 1;
+                    false
+                };
+                write_stream_with_predicate(predicate, ctx).await;
+            }
+            WriteOptions::ConcurrencyWithCount(concurrency, count) => {
+                let futs = FuturesUnordered::new();
+                let requests_per_task = count / concurrency;
+                for _ in 0..concurrency {
+                    let ctx = Arc::clone(ctx);
+                    let task = tokio::spawn(async move {
+                        for _ in 0..requests_per_task {
+                            if ctx.is_stopped() {
+                                break;
+                            }
+                            ctx.write_next().await;
+                        }
+                    });
+                    futs.push(task);
+                }
+                handle_futures(futs).await?;
+            }
+            WriteOptions::ConcurrencyWithDuration(concurrency, duration) => {
+                let futs = FuturesUnordered::new();
+                for _ in 0..concurrency {
+                    let ctx = Arc::clone(ctx);
+                    let task = tokio::spawn(async move {
+                        let for_duration = Instant::now();
+                        let predicate = || for_duration.elapsed() >= *duration;
+                        write_stream_with_predicate(predicate, &ctx).await
+                    });
+                    futs.push(task);
+                }
+                handle_futures(futs).await?;
+            }
+            WriteOptions::ConcurrencyUnlimited(concurrency) => {
+                let futs = FuturesUnordered::new();
+                for _ in 0..concurrency {
+                    let ctx = Arc::clone(ctx);
+                    let task =
+                        tokio::spawn(
+                            async move { write_stream_with_predicate(|| false, &ctx).await },
+                        );
+                    futs.push(task);
                 }
+                handle_futures(futs).await?;
+            }
+            WriteOptions::Rate(rate) => write_at_rate(rate, || false, ctx).await?,
+            WriteOptions::RateWithCount(rate, count) => {
+                let mut sent = 0;
+                let predicate = || {
+                    if sent == count {
+                        return true;
+                    }
+                    sent += 1;
+                    false
+                };
+                write_at_rate(rate, predicate, ctx).await?;
+            }
+            WriteOptions::RateWithDuration(rate, duration) => {
+                let for_duration = Instant::now();
+                let predicate = || for_duration.elapsed() >= *duration;
+                write_at_rate(rate, predicate, ctx).await?;
+            }
+            WriteOptions::RateWithCountOrDuration(rate, count, duration) => {
+                let for_duration = Instant::now();
+                let mut sent = 0;
+                let predicate = || {
+                    if sent == count || for_duration.elapsed() >= *duration {
+                        return true;
+                    }
+                    sent += 1;
+                    false
+                };
+                write_at_rate(rate, predicate, ctx).await?;
             }
         }
+        Ok(())
+    }
 
-        self.stats.record_throughput();
-        Ok(self.stats.total_bytes())
+    /// Stop an ongoing [`SocketManager::write`] once in-flight requests have
+    /// completed, regardless of the [`WriteOptions`]. Statistics are still
+    /// recorded for everything that was written.
+    pub fn stop(&self) {
+        self.stop.stop(StopReason::Stopped);
     }
 
     /// Get the recorded throughput from the internal [`Statistics`].
@@ -196,6 +1292,11 @@ where
         self.stats.successful_requests()
     }
 
+    /// The number of attempted requests from the internal [`Statistics`].
+    pub fn request_count(&self) -> u64 {
+        self.stats.request_count()
+    }
+
     /// Percentage of requests that were successful from the internal [`Statistics`].
     pub fn successful_requests_percentage(&self) -> f64 {
         self.stats.success_percentage()
@@ -205,84 +1306,600 @@ where
         self.stats.elapsed()
     }
 
-    /// Helper to handle a number of futures within a [`FuturesUnordered`]
-    /// structure
-    async fn handle_futures(
-        &self,
-        mut futs: FuturesUnordered<JoinHandle<(u64, u64, u64)>>,
-    ) -> crate::Result<()> {
-        while let Some(task) = futs.next().await {
-            let (written, success_count, failure_count) = task?;
-            self.stats.increment_total(written);
-            for _ in 0..success_count {
-                self.stats.record_success();
-            }
-            for _ in 0..failure_count {
-                self.stats.record_failure();
-            }
-        }
-        Ok(())
+    /// Summarise what was written since the last call from the internal
+    /// [`Statistics`], for reporting on the progress of a run.
+    pub fn take_interval(&self) -> WriteInterval {
+        self.stats.take_interval()
     }
-}
 
-/// Utility function for writing to a stream continously through the result of
-/// a given predicate. The predicate is the break condition for the continuous
-/// writes.
-///
-/// For example, passing a predicate of `|| true` means that the loop instantly
-/// breaks and no writes occur.
-async fn write_stream_with_predicate<P>(
-    mut predicate: P,
-    addr: SocketAddr,
-    protocol: &Protocol,
-    input: &[u8],
-    stats: &Statistics,
-) -> crate::Result<(u64, u64, u64)>
-where
-    P: FnMut() -> bool,
-{
-    let mut task_bytes: u64 = 0;
-    let mut task_success: u64 = 0;
-    let mut task_failed: u64 = 0;
-    loop {
-        if predicate() {
-            break;
-        } else {
-            match write_stream(addr, protocol, input).await {
-                Ok(b) => {
-                    task_bytes += b;
-                    task_success += 1;
-                    stats.increment_total(b);
-                    stats.record_success();
-                }
-                Err(_) => {
-                    stats.record_failure();
-                    task_failed += 1;
-                }
-            }
-        }
+    /// The internal [`Statistics`] of the run.
+    pub(crate) fn statistics(&self) -> &Statistics {
+        &self.stats
     }
-    Ok((task_bytes, task_success, task_failed))
-}
 
-/// Write the provided input data to a [`SocketAddr`] using the chosen [`Protocol`].
-async fn write_stream(addr: SocketAddr, protocol: &Protocol, input: &[u8]) -> crate::Result<u64> {
-    let out: u64;
-    match protocol {
-        Protocol::Tcp => {
-            let mut stream = TcpStream::connect(addr).await?;
-            stream.write_all(input).await?;
-            out = input.len() as u64;
+    /// Produce a [`Report`] from the internal [`Statistics`].
+    pub fn report(&self) -> Report {
+        let report = Report {
+            requested_rate: self.write_options.rate(),
+            stop_reason: self.stop.reason(),
+            circuit_events: self.circuit_events.lock().unwrap().clone(),
+            latency_outliers: self.latency_outliers.lock().unwrap().clone(),
+            clock_offsets: self.clock_offsets.lock().unwrap().clone(),
+            timeline: self
+                .timeline
+                .as_ref()
+                .map(|timeline| timeline.lock().unwrap().clone())
+                .unwrap_or_default(),
+            annotations: self
+                .annotations
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|a| u128::from(a.at_ms) <= self.stats.elapsed())
+                .cloned()
+                .collect(),
+            ..Report::from(self.stats.as_ref())
+        };
+        match *self.resources.lock().unwrap() {
+            Some(usage) => report.with_resources(usage),
+            None => report,
+        }
+    }
+
+    /// [`Statistics`] for each address family when a [`FamilySplit`] is used,
+    /// keyed by the family name.
+    pub fn family_statistics(&self) -> Vec<(String, Arc<Statistics>)> {
+        self.group_stats.lock().unwrap().clone()
+    }
+
+    /// [`Statistics`] for each host when more than one is written to, keyed by
+    /// the host name.
+    pub fn host_statistics(&self) -> Vec<(String, Arc<Statistics>)> {
+        self.host_stats.lock().unwrap().clone()
+    }
+
+    /// [`Statistics`] for each class of the [`PayloadMix`], keyed by the
+    /// class name.
+    pub fn payload_statistics(&self) -> Vec<(String, Arc<Statistics>)> {
+        self.payload_mix
+            .iter()
+            .flat_map(|mix| mix.classes())
+            .map(|c| (c.name().to_string(), Arc::clone(c.stats())))
+            .collect()
+    }
+}
+
+/// Look up the addresses of a host. As this blocks, other tasks are moved
+/// off the worker thread meanwhile when the runtime has more than one.
+fn lookup(host: &impl ToSocketAddrs) -> std::io::Result<Vec<SocketAddr>> {
+    let lookup = || host.to_socket_addrs().map(Iterator::collect);
+    match tokio::runtime::Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => tokio::task::block_in_place(lookup),
+        _ => lookup(),
+    }
+}
+
+/// State shared by every write of a single [`SocketManager::write`] run.
+struct WriteContext {
+    targets: Targets,
+    transport: Arc<dyn Transport>,
+    input: Vec<u8>,
+    payload_mix: Option<Arc<PayloadMix>>,
+    generator: Option<GeneratorPool>,
+    /// Generated payloads of the idle connections to each address, which are
+    /// reused by the next request on them.
+    connection_payloads: Option<ConnectionPool<Vec<u8>>>,
+    reflect_timing: bool,
+    /// Nanoseconds which the clock of each calibrated server is ahead.
+    clock_offsets: Mutex<HashMap<SocketAddr, i64>>,
+    traceparent: bool,
+    event_log: Option<Arc<EventLog>>,
+    transcript: Option<Arc<Transcript>>,
+    streamed_payload: Option<u64>,
+    bandwidth: Option<Arc<TokenBucket>>,
+    expect_response: Option<Arc<MessageMatcher>>,
+    /// How replies end, which is set whenever replies are read.
+    expect_reply: Option<ReplyFraming>,
+    verify_digest: Option<Digest>,
+    hooks: Option<Arc<HookScript>>,
+    /// Index of the next request, which is passed to the hooks.
+    next_index: AtomicU64,
+    stats: Arc<Statistics>,
+    max_failures: Option<u64>,
+    circuit_events: Arc<Mutex<Vec<CircuitEvent>>>,
+    /// Latency at or beyond which successful requests are kept as outliers.
+    outlier_threshold: Option<Duration>,
+    latency_outliers: Arc<Mutex<Vec<LatencyOutlier>>>,
+    observers: Vec<Arc<dyn WriteObserver>>,
+    stop: Arc<Stop>,
+    retry: Option<RetryPolicy>,
+    /// Whether the run is ramping up, when requests are not recorded.
+    warming_up: AtomicBool,
+}
+
+/// Signal for a run to stop early, along with the first reason it was given.
+#[derive(Default)]
+pub(crate) struct Stop {
+    stopped: AtomicBool,
+    reason: OnceLock<StopReason>,
+}
+
+impl Stop {
+    pub(crate) fn stop(&self, reason: StopReason) {
+        let _ = self.reason.set(reason);
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    fn reason(&self) -> StopReason {
+        self.reason.get().copied().unwrap_or_default()
+    }
+}
+
+impl WriteContext {
+    fn is_stopped(&self) -> bool {
+        self.stop.is_stopped()
+    }
+
+    /// Estimate the clock offset of the server at `addr` from the probe with
+    /// the shortest round trip, which is then subtracted from one-way delays.
+    async fn calibrate_clock(&self, addr: SocketAddr, probes: u32) -> crate::Result<ClockOffset> {
+        let mut samples = Vec::new();
+        for _ in 0..probes {
+            let (_, reply) = self
+                .transport
+                .exchange(addr, &timing::with_timestamp(&[]))
+                .await
+                .map_err(|e| format!("unable to calibrate the clock of {addr}: {e}"))?;
+            let returned = timing::now();
+            let Some(reflection) = timing::parse_reply(&reply) else {
+                return Err(format!("{addr} did not reflect the timing of a probe").into());
+            };
+            samples.push(timing::Probe {
+                reflection,
+                returned,
+            });
+        }
+        let Some(probe) = timing::best_probe(samples) else {
+            return Err("clock calibration requires at least one probe".into());
+        };
+        self.clock_offsets
+            .lock()
+            .unwrap()
+            .insert(addr, probe.offset());
+        Ok(ClockOffset {
+            addr: addr.to_string(),
+            offset_us: probe.offset() as f64 / 1000.0,
+            round_trip_us: probe.round_trip() as f64 / 1000.0,
+        })
+    }
+
+    /// Nanoseconds which the clock of the server at `addr` is ahead, which is
+    /// zero unless it was calibrated.
+    fn clock_offset(&self, addr: SocketAddr) -> i64 {
+        self.clock_offsets
+            .lock()
+            .unwrap()
+            .get(&addr)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Write the input to the next target, recording the outcome in the
+    /// overall, per-target and per-payload [`Statistics`].
+    async fn write_next(&self) {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        let (mut addr, mut group) = loop {
+            // A run which is stopped while nothing can be sent ends without
+            // sending this request.
+            if self.is_stopped() {
+                return;
+            }
+            match self.targets.next() {
+                Some(target) => break target,
+                // Every circuit is open, so wait for the first to allow
+                // requests again, or every address was drained through the
+                // control socket, so wait for one to be given a weight.
+                None => match self.targets.next_reopening() {
+                    Some(at) => tokio::time::sleep_until(at).await,
+                    None => tokio::time::sleep(DRAINED_RETRY).await,
+                },
+            }
+        };
+        // A request whose hook fails is not attempted.
+        let mut hook_failure = None;
+        if let Some(hooks) = self.hooks.as_ref().filter(|hooks| hooks.has_target()) {
+            let addrs: Vec<_> = self.targets.addrs().collect();
+            match hooks.target(index, &addrs) {
+                Ok(picked) => {
+                    (addr, group) = self
+                        .targets
+                        .nth(picked)
+                        .expect("the target hook picks one of the addresses");
+                }
+                Err(e) => hook_failure = Some(e),
+            }
+        }
+        let scripted = match self.hooks.as_ref().filter(|hooks| hooks.has_payload()) {
+            Some(hooks) => match hooks.payload(index) {
+                Ok(payload) => Some(payload),
+                Err(e) => {
+                    hook_failure.get_or_insert(e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let class = self.payload_mix.as_ref().map(|mix| mix.sample());
+        let reused = self
+            .connection_payloads
+            .as_ref()
+            .and_then(|payloads| payloads.take(addr));
+        let generated = match &self.generator {
+            Some(_) if reused.is_some() => reused,
+            Some(generator) => {
+                let (payload, waited) = generator.next().await;
+                if let Some(waited) = waited.filter(|_| !self.warming_up.load(Ordering::Relaxed)) {
+                    self.stats.record_generator_wait(waited);
+                }
+                Some(payload)
+            }
+            None => None,
+        };
+        let input = match (&scripted, &generated, class) {
+            (Some(payload), _, _) | (None, Some(payload), _) => payload.as_slice(),
+            (None, None, Some(class)) => class.data(),
+            (None, None, None) => self.input.as_slice(),
+        };
+        let trace = self.traceparent.then(TraceContext::random);
+        let traced = trace.map(|trace| trace::with_traceparent(&trace, input));
+        let input = traced.as_deref().unwrap_or(input);
+        if let Some(bandwidth) = &self.bandwidth {
+            let len = self.streamed_payload.unwrap_or(input.len() as u64);
+            bandwidth.acquire(len).await;
+        }
+
+        for observer in &self.observers {
+            observer.on_request_start(addr);
+        }
+        // Only the latency of the last attempt is recorded, as the retries
+        // are counted on their own.
+        let (mut retries, mut connect_retries) = (0, 0);
+        let (result, elapsed, delay, reply_received) = loop {
+            // The error of a failed attempt is dropped before waiting, as it
+            // is not `Send`.
+            let backoff = {
+                let start = Instant::now();
+                let (result, delay, reply_received) = match hook_failure.take() {
+                    Some(e) => (Err(e.into()), None, None),
+                    None => self.attempt(addr, input).await,
+                };
+                match (&result, &self.retry) {
+                    (Err(e), Some(policy))
+                        if retries < policy.retries
+                            && !e.is::<ResponseMismatch>()
+                            && !e.is::<HookError>()
+                            && policy.allows(ConnectError::is_cause(e.as_ref()))
+                            && !self.is_stopped() =>
+                    {
+                        retries += 1;
+                        if ConnectError::is_cause(e.as_ref()) {
+                            connect_retries += 1;
+                        }
+                        policy.backoff(retries)
+                    }
+                    _ => break (result, start.elapsed(), delay, reply_received),
+                }
+            };
+            tokio::time::sleep(backoff).await;
+        };
+
+        if self.warming_up.load(Ordering::Relaxed) {
+            self.keep_payload(addr, generated, result.is_ok());
+            return;
+        }
+        let transcript = self
+            .transcript
+            .as_ref()
+            .filter(|transcript| transcript.samples(index));
+        if self.event_log.is_some() || transcript.is_some() {
+            let elapsed_ns = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+            let started_at = timing::now().saturating_sub(elapsed_ns);
+            let event = RequestEvent::new(addr, started_at, elapsed, trace.as_ref(), &result);
+            if let Some(log) = &self.event_log {
+                log.record(&event);
+            }
+            if let Some(transcript) = transcript {
+                let reply = reply_received.as_ref().map(|reply| reply.data.as_slice());
+                transcript.record(index, input, reply, &event);
+            }
+        }
+        let stats = [&self.stats, group.stats()]
+            .into_iter()
+            .chain(class.map(|c| c.stats()));
+        for stats in stats {
+            if retries > 0 {
+                stats.record_retries(retries, connect_retries);
+            }
+            match &result {
+                Ok(b) => {
+                    stats.increment_total(*b);
+                    stats.record_success();
+                    stats.record_latency(elapsed);
+                }
+                Err(e) => {
+                    stats.record_failure();
+                    if let Some(partial) = e.downcast_ref::<PartialWrite>() {
+                        stats.record_partial_write(partial.written);
+                    }
+                    if let Some(mismatch) = e.downcast_ref::<ResponseMismatch>() {
+                        stats.record_mismatched_response(mismatch.written);
+                    }
+                    match SourceDrop::from_error(e.as_ref()) {
+                        Some(SourceDrop::WouldBlock) => stats.record_would_block(),
+                        Some(SourceDrop::NoBufferSpace) => stats.record_no_buffer_space(),
+                        None => {}
+                    }
+                    if transport::is_timeout(e.as_ref()) {
+                        stats.record_timeout();
+                    }
+                    if Unacknowledged::is_cause(e.as_ref()) {
+                        stats.record_unacknowledged();
+                    } else if !(e.is::<ResponseMismatch>() || e.is::<HookError>()) {
+                        stats.record_send_failure();
+                    }
+                    match PeerClose::from_error(e.as_ref()) {
+                        Some(PeerClose::Reset) => stats.record_connection_reset(),
+                        Some(PeerClose::Early) => stats.record_premature_close(),
+                        None => {}
+                    }
+                }
+            }
+            if let Some(delay) = delay {
+                stats.record_one_way_delay(delay);
+            }
+            if let Some(reply) = &reply_received {
+                stats.record_reply(reply.data.len() as u64, reply.round_trip, reply.first_byte);
+            }
+        }
+
+        match (&result, self.outlier_threshold) {
+            (Ok(_), Some(threshold)) if elapsed >= threshold => {
+                let mut outliers = self.latency_outliers.lock().unwrap();
+                if outliers.len() < MAX_LATENCY_OUTLIERS {
+                    let elapsed_ns = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+                    let started_at = timing::now().saturating_sub(elapsed_ns);
+                    outliers.push(LatencyOutlier::new(addr, started_at, elapsed, input));
+                }
+            }
+            _ => {}
+        }
+
+        for observer in &self.observers {
+            match &result {
+                Ok(b) => observer.on_success(addr, *b, elapsed),
+                Err(e) => observer.on_failure(addr, e.as_ref(), elapsed),
+            }
+        }
+
+        if let Some(state) = self.targets.record_outcome(group, addr, result.is_ok()) {
+            self.circuit_events.lock().unwrap().push(CircuitEvent {
+                addr: addr.to_string(),
+                state,
+                at_ms: self.stats.elapsed(),
+            });
+        }
+
+        if let (Err(_), Some(max)) = (&result, self.max_failures) {
+            if self.stats.failed_requests() >= max {
+                self.stop.stop(StopReason::MaxFailures);
+            }
+        }
+        self.keep_payload(addr, generated, result.is_ok());
+    }
+
+    /// Keep the generated payload of a request to reuse for the next request
+    /// on its connection, when payloads are generated for every connection.
+    /// A failed request does not return its connection to be reused.
+    fn keep_payload(&self, addr: SocketAddr, payload: Option<Vec<u8>>, succeeded: bool) {
+        if let (Some(payloads), Some(payload), true) =
+            (&self.connection_payloads, payload, succeeded)
+        {
+            payloads.put(addr, payload);
+        }
+    }
+
+    /// Make a single attempt at a request, returning its outcome alongside
+    /// the one-way delay in nanoseconds and the reply, when they were
+    /// measured.
+    async fn attempt(
+        &self,
+        addr: SocketAddr,
+        input: &[u8],
+    ) -> (crate::Result<u64>, Option<i64>, Option<Reply>) {
+        let mut delay = None;
+        let mut reply_received = None;
+        let result = if let Some(len) = self.streamed_payload {
+            self.transport.write_generated(addr, len).await
+        } else if self.reflect_timing {
+            let message = timing::with_timestamp(input);
+            self.transport
+                .exchange(addr, &message)
+                .await
+                .map(|(written, reply)| {
+                    delay = timing::parse_reply(&reply)
+                        .map(|r| r.one_way_delay() - self.clock_offset(addr));
+                    written
+                })
+        } else if let Some(framing) = &self.expect_reply {
+            self.transport
+                .exchange_until(addr, input, framing)
+                .await
+                .and_then(|reply| {
+                    let matched = self
+                        .expect_response
+                        .as_ref()
+                        .is_none_or(|expected| expected.matches(&reply.data));
+                    // Trailing whitespace is ignored, so the digest can also
+                    // be framed by a delimiter.
+                    let verified = self.verify_digest.is_none_or(|digest| {
+                        digest.reply(input).trim_ascii_end() == reply.data.trim_ascii_end()
+                    });
+                    let judged = match self.hooks.as_ref().filter(|hooks| hooks.has_judge()) {
+                        Some(hooks) => hooks.judge(input, &reply.data),
+                        None => Ok(true),
+                    };
+                    let mismatched = judged
+                        .as_ref()
+                        .is_ok_and(|judged| !(matched && verified && *judged));
+                    let mismatch = mismatched.then(|| reply.data.clone());
+                    let written = reply.written;
+                    reply_received = Some(reply);
+                    judged?;
+                    match mismatch {
+                        None => Ok(written),
+                        Some(reply) => Err(ResponseMismatch { written, reply }.into()),
+                    }
+                })
+        } else {
+            self.transport.write(addr, input).await
+        };
+
+        (result, delay, reply_received)
+    }
+}
+
+/// A request which was written, but whose response did not match what was
+/// expected.
+#[derive(Debug)]
+pub struct ResponseMismatch {
+    /// Number of bytes which were written.
+    pub written: u64,
+    pub reply: Vec<u8>,
+}
+
+impl Display for ResponseMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unexpected response: {}",
+            String::from_utf8_lossy(&self.reply)
+        )
+    }
+}
+
+impl std::error::Error for ResponseMismatch {}
+
+/// Helper to handle a number of futures within a [`FuturesUnordered`]
+/// structure
+async fn handle_futures(mut futs: FuturesUnordered<JoinHandle<()>>) -> crate::Result<()> {
+    while let Some(task) = futs.next().await {
+        task?;
+    }
+    Ok(())
+}
+
+/// Utility function for writing to a stream continously through the result of
+/// a given predicate. The predicate is the break condition for the continuous
+/// writes, alongside the manager being stopped.
+///
+/// For example, passing a predicate of `|| true` means that the loop instantly
+/// breaks and no writes occur.
+async fn write_stream_with_predicate<P>(mut predicate: P, ctx: &WriteContext)
+where
+    P: FnMut() -> bool,
+{
+    while !ctx.is_stopped() && !predicate() {
+        ctx.write_next().await;
+    }
+}
+
+/// Start a write `rate` times per second until the predicate or the manager
+/// being stopped ends the run, then wait for the writes which are in flight.
+///
+/// Each write is spawned on its own task, so a slow target does not delay the
+/// writes which follow it. Ticks which are missed, such as when the runtime is
+/// busy, are caught up on straight away to keep the average rate.
+async fn write_at_rate<P>(rate: u64, mut predicate: P, ctx: &Arc<WriteContext>) -> crate::Result<()>
+where
+    P: FnMut() -> bool,
+{
+    let mut tick = tokio::time::interval(Duration::from_secs_f64(1.0 / rate as f64));
+    tick.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    let mut in_flight = JoinSet::new();
+    while !predicate() {
+        let intended = tick.tick().await;
+        if ctx.is_stopped() {
+            break;
+        }
+        let ctx = Arc::clone(ctx);
+        in_flight.spawn(async move {
+            // Sends lag behind their schedule when the runtime is too busy to
+            // start them on time.
+            ctx.stats
+                .record_send(intended.into_std(), Instant::now().into_std());
+            ctx.write_next().await
+        });
+        while let Some(task) = in_flight.try_join_next() {
+            task?;
+        }
+    }
+    while let Some(task) = in_flight.join_next().await {
+        task?;
+    }
+    Ok(())
+}
+
+/// Increase the concurrency or rate of the [`WriteOptions`] linearly from one
+/// to their target over the window, then wait for the writes in flight.
+async fn ramp_up(
+    opts: &WriteOptions,
+    window: Duration,
+    ctx: &Arc<WriteContext>,
+) -> crate::Result<()> {
+    let start = Instant::now();
+    let deadline = start + window;
+    if let Some(concurrency) = opts.concurrency() {
+        let futs = FuturesUnordered::new();
+        for n in 0..concurrency {
+            let ctx = Arc::clone(ctx);
+            let at = start + window.mul_f64(n as f64 / concurrency as f64);
+            let task = tokio::spawn(async move {
+                tokio::time::sleep_until(at).await;
+                write_stream_with_predicate(|| Instant::now() >= deadline, &ctx).await
+            });
+            futs.push(task);
+        }
+        return handle_futures(futs).await;
+    }
+    let Some(rate) = opts.rate() else {
+        return Err("a ramp-up requires a concurrency or rate to ramp up to".into());
+    };
+    let mut in_flight = JoinSet::new();
+    let mut next = start;
+    loop {
+        // The ramp-up lasts the whole window, even when the next request
+        // would be sent after it.
+        tokio::time::sleep_until(next.min(deadline)).await;
+        if next >= deadline || ctx.is_stopped() {
+            break;
         }
-        Protocol::Udp => {
-            // Binding to 0 mimics the functionality of an unspecified socket.
-            // It simply assigns a random port for the UDP socket to begin writing.
-            // Ref: https://man7.org/linux/man-pages/man7/udp.7.html
-            let stream = UdpSocket::bind("127.0.0.1:0").await?;
-            out = stream.send_to(input, addr).await? as u64;
+        let ctx = Arc::clone(ctx);
+        in_flight.spawn(async move { ctx.write_next().await });
+        let progress = (next - start).as_secs_f64() / window.as_secs_f64();
+        let current = 1.0 + (rate - 1) as f64 * progress;
+        next += Duration::from_secs_f64(1.0 / current);
+        while let Some(task) = in_flight.try_join_next() {
+            task?;
         }
     }
-    Ok(out)
+    while let Some(task) = in_flight.join_next().await {
+        task?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -290,14 +1907,28 @@ mod test {
     use std::{
         net::{SocketAddr, TcpListener},
         str::FromStr,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
         time::Instant,
     };
 
     use humantime::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     use crate::{
+        bandwidth::Bandwidth,
+        engine::Engine,
+        generator::{PayloadGenerator, Regeneration},
         manager::{write_stream_with_predicate, WriteOptions},
+        observer::WriteObserver,
+        payload::{PayloadClass, PayloadMix},
+        report::{CircuitState, StopReason},
         statistics::Statistics,
+        target::{FamilySplit, Targets},
+        timing,
+        transport::MemoryTransport,
         Protocol, SocketManager,
     };
 
@@ -346,6 +1977,55 @@ mod test {
         ),
         expected = WriteOptions::ConcurrencyWithDuration(10, _)
     );
+    write_options!(
+        from_flags_unlimited,
+        opts = WriteOptions::from_flags(0, None, None),
+        expected = WriteOptions::Unlimited
+    );
+    write_options!(
+        from_flags_concurrency_unlimited,
+        opts = WriteOptions::from_flags(0, None, Some(10)),
+        expected = WriteOptions::ConcurrencyUnlimited(10)
+    );
+
+    write_options!(
+        from_rate_count,
+        opts = WriteOptions::from_rate(50, 100, None),
+        expected = WriteOptions::RateWithCount(50, 100)
+    );
+    write_options!(
+        from_rate_duration,
+        opts = WriteOptions::from_rate(50, 1, Some(humantime::Duration::from_str("10s").unwrap())),
+        expected = WriteOptions::RateWithDuration(50, _)
+    );
+    write_options!(
+        from_rate_count_or_duration,
+        opts = WriteOptions::from_rate(50, 3, Some(humantime::Duration::from_str("10s").unwrap())),
+        expected = WriteOptions::RateWithCountOrDuration(50, 3, _)
+    );
+    write_options!(
+        from_rate_unlimited,
+        opts = WriteOptions::from_rate(50, 0, None),
+        expected = WriteOptions::Rate(50)
+    );
+
+    #[test]
+    fn configured_count() {
+        let duration = humantime::Duration::from_str("10s").unwrap();
+        assert_eq!(WriteOptions::from_flags(5, None, None).count(), Some(5));
+        assert_eq!(WriteOptions::from_flags(5, None, Some(2)).count(), Some(5));
+        assert_eq!(
+            WriteOptions::from_flags(5, Some(duration), None).count(),
+            Some(5)
+        );
+        assert_eq!(
+            WriteOptions::from_flags(1, Some(duration), None).count(),
+            None
+        );
+        assert_eq!(WriteOptions::from_flags(0, None, None).count(), None);
+        assert_eq!(WriteOptions::from_rate(10, 5, None).count(), Some(5));
+        assert!(WriteOptions::from_rate(10, 0, None).is_unlimited());
+    }
 
     /// Encompass the count variant of the write options into a macro for ease of
     /// use of testing various scenarios
@@ -459,6 +2139,7 @@ mod test {
                 let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
                 socket.local_addr().unwrap()
             }
+            protocol => unreachable!("{protocol} is not bound by the tests"),
         }
     }
 
@@ -538,22 +2219,1842 @@ mod test {
         let addr = bind_socket(&protocol).await;
         let duration = humantime::Duration::from_str("1s").unwrap();
 
-        let stats = Statistics::default();
-        write_stream_with_predicate(|| true, addr, &protocol, b"test", &stats)
-            .await
-            .unwrap();
-        assert_eq!(stats.successful_requests(), 0);
-        assert_eq!(stats.total_bytes(), 0);
+        let context = || {
+            SocketManager::new(
+                addr,
+                b"test",
+                protocol.clone(),
+                WriteOptions::Duration(duration),
+                Statistics::default(),
+            )
+            .context(Targets::single(addr), &addr.into())
+            .unwrap()
+        };
+
+        let ctx = context();
+        write_stream_with_predicate(|| true, &ctx).await;
+        assert_eq!(ctx.stats.successful_requests(), 0);
+        assert_eq!(ctx.stats.total_bytes(), 0);
 
         let start = Instant::now();
-        let stats = Statistics::default();
+        let ctx = context();
         let predicate = || start.elapsed() > *duration;
-        write_stream_with_predicate(predicate, addr, &protocol, b"test", &stats)
+        write_stream_with_predicate(predicate, &ctx).await;
+        assert_eq!(start.elapsed().as_secs(), 1);
+        assert!(ctx.stats.total_bytes() > 0);
+        assert!(ctx.stats.successful_requests() > 0);
+    }
+
+    #[tokio::test]
+    async fn write_family_split() {
+        let v4 = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let v6 = tokio::net::UdpSocket::bind("[::1]:0").await.unwrap();
+        let addrs = [v4.local_addr().unwrap(), v6.local_addr().unwrap()];
+
+        let s = SocketManager::new(
+            &addrs[..],
+            b"split",
+            Protocol::Udp,
+            WriteOptions::Count(10),
+            Statistics::new(),
+        )
+        .with_family_split(FamilySplit { ipv4: 70, ipv6: 30 });
+        assert_eq!(s.write().await.unwrap(), 50);
+
+        let families = s.family_statistics();
+        assert_eq!(families.len(), 2);
+        assert_eq!(families[0].0, "ipv4");
+        assert_eq!(families[0].1.successful_requests(), 7);
+        assert_eq!(families[1].0, "ipv6");
+        assert_eq!(families[1].1.successful_requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn write_hosts() {
+        let a = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let a = a.local_addr().unwrap().to_string();
+        let b = b.local_addr().unwrap().to_string();
+
+        let s = SocketManager::new(
+            a.as_str(),
+            b"hosts",
+            Protocol::Udp,
+            WriteOptions::Count(10),
+            Statistics::new(),
+        )
+        .with_hosts([b.as_str()]);
+        // The count is shared between the hosts, rather than written to each.
+        assert_eq!(s.write().await.unwrap(), 50);
+
+        let hosts = s.host_statistics();
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0].0, a);
+        assert_eq!(hosts[0].1.successful_requests(), 5);
+        assert_eq!(hosts[1].0, b);
+        assert_eq!(hosts[1].1.successful_requests(), 5);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn write_control_socket() {
+        use crate::transport::TemporarySocketPath;
+
+        let a = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let a = a.local_addr().unwrap().to_string();
+        let b = b.local_addr().unwrap().to_string();
+        let path = TemporarySocketPath::new();
+
+        let s = Arc::new(
+            SocketManager::new(
+                a.clone(),
+                b"drain",
+                Protocol::Udp,
+                WriteOptions::RateWithCount(40, 40),
+                Statistics::new(),
+            )
+            .with_hosts([b.clone()])
+            .with_control_socket(path.0.clone()),
+        );
+        let write = tokio::spawn({
+            let s = Arc::clone(&s);
+            async move { s.write().await.unwrap() }
+        });
+        while !path.0.exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let command = ["set-weight".to_string(), a.clone(), "0".to_string()];
+        crate::send_command(&path.0, &command).await.unwrap();
+        assert_eq!(write.await.unwrap(), 40 * 5);
+
+        // Once drained, the rest of the requests are sent to the other host.
+        let hosts = s.host_statistics();
+        let drained = hosts[0].1.successful_requests();
+        assert!((1..20).contains(&drained), "{drained}");
+        assert_eq!(hosts[1].1.successful_requests(), 40 - drained);
+        let annotations = s.report().annotations;
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].message, format!("weight of {a} set to 0"));
+        // The socket is removed once the run stops.
+        assert!(!path.0.exists());
+
+        // A run with every address drained still ends once it is cancelled.
+        let token = crate::CancellationToken::new();
+        let s = Arc::new(
+            SocketManager::new(
+                a.clone(),
+                b"drain",
+                Protocol::Udp,
+                WriteOptions::Unlimited,
+                Statistics::new(),
+            )
+            .with_hosts([b.clone()])
+            .with_control_socket(path.0.clone())
+            .with_cancellation(token.clone()),
+        );
+        let write = tokio::spawn({
+            let s = Arc::clone(&s);
+            async move { s.write().await.unwrap() }
+        });
+        while !path.0.exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        for addr in [&a, &b] {
+            let command = ["set-weight".to_string(), addr.clone(), "0".to_string()];
+            crate::send_command(&path.0, &command).await.unwrap();
+        }
+        let drained = s.request_count();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        token.cancel();
+        tokio::time::timeout(std::time::Duration::from_secs(5), write)
             .await
+            .expect("the drained run ends once cancelled")
             .unwrap();
-        assert_eq!(start.elapsed().as_secs(), 1);
-        assert!(stats.total_bytes() > 0);
-        assert!(stats.successful_requests() > 0);
+        // At most the requests which were in flight as the last address was
+        // drained are sent after it.
+        assert!(s.request_count() <= drained + 1);
+    }
+
+    #[tokio::test]
+    async fn write_hooks() {
+        use crate::{HookScript, ResponseScript, Server};
+
+        let a = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let a = a.local_addr().unwrap().to_string();
+        let b = b.local_addr().unwrap().to_string();
+        let s = SocketManager::new(
+            a.as_str(),
+            b"",
+            Protocol::Udp,
+            WriteOptions::Count(10),
+            Statistics::new(),
+        )
+        .with_hosts([b.as_str()])
+        .with_hooks(
+            HookScript::from_source(
+                r#"
+                fn payload(index) { `msg-${index}` }
+                fn target(index, targets) { if index < 3 { 1 } else { 0 } }
+                "#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(s.write().await.unwrap(), 50);
+        let hosts = s.host_statistics();
+        assert_eq!(hosts[0].1.successful_requests(), 7);
+        assert_eq!(hosts[1].1.successful_requests(), 3);
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .respond_script(
+            ResponseScript::from_toml("[[rule]]\nprefix = \"PING\"\nresponse = \"PONG\"").unwrap(),
+        )
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let judged = r#"
+            fn payload(index) { if index % 2 == 0 { "PING" } else { "PANG" } }
+            fn judge(request, reply) { reply.as_string() == "PONG" }
+        "#;
+        let failing = r#"fn payload(index) { throw "no payload" }"#;
+        for (script, successful, mismatched) in [(judged, 2, 2), (failing, 0, 0)] {
+            let s = SocketManager::new(
+                addr,
+                b"",
+                Protocol::Tcp,
+                WriteOptions::Count(4),
+                Statistics::new(),
+            )
+            .with_hooks(HookScript::from_source(script).unwrap());
+            s.write().await.unwrap();
+            let report = s.report();
+            assert_eq!(report.successful_requests, successful);
+            assert_eq!(report.failed_requests, 4 - successful);
+            assert_eq!(report.mismatched_responses, mismatched);
+        }
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn write_payload_mix() {
+        let addr = bind_socket(&Protocol::Udp).await;
+        let mix = PayloadMix::new(vec![
+            PayloadClass::new("small", 1, b"a".to_vec()),
+            PayloadClass::new("large", 1, b"aaaaaaaaaa".to_vec()),
+            PayloadClass::new("unused", 0, b"never".to_vec()),
+        ])
+        .unwrap();
+        let s = SocketManager::new(
+            addr,
+            b"",
+            Protocol::Udp,
+            WriteOptions::Count(100),
+            Statistics::new(),
+        )
+        .with_payload_mix(mix);
+        let written = s.write().await.unwrap();
+
+        let classes = s.payload_statistics();
+        assert_eq!(classes.len(), 3);
+        let (small, large) = (&classes[0].1, &classes[1].1);
+        assert_eq!(small.request_count() + large.request_count(), 100);
+        assert!(small.request_count() > 0 && large.request_count() > 0);
+        assert_eq!(classes[2].1.request_count(), 0);
+        assert_eq!(written, small.total_bytes() + large.total_bytes());
+        assert_eq!(large.total_bytes(), large.request_count() * 10);
+    }
+
+    #[tokio::test]
+    async fn write_payload_generator() {
+        struct Growing;
+
+        impl PayloadGenerator for Growing {
+            fn generate(&self, index: u64) -> Vec<u8> {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                vec![b'a'; index as usize + 1]
+            }
+        }
+
+        let addr = bind_socket(&Protocol::Udp).await;
+        let s = SocketManager::new(
+            addr,
+            b"unused",
+            Protocol::Udp,
+            WriteOptions::Count(3),
+            Statistics::new(),
+        )
+        .with_payload_generator(Growing, 1);
+        assert_eq!(s.write().await.unwrap(), 1 + 2 + 3);
+
+        // Sending is faster than generating, so requests wait on the generator.
+        let report = s.report();
+        assert!(report.generator_waits > 0);
+        assert!(report.generator_wait_ms > 0.0);
+
+        let s = SocketManager::new(
+            addr,
+            b"unused",
+            Protocol::Udp,
+            WriteOptions::Count(3),
+            Statistics::new(),
+        )
+        .with_payload_generator(Growing, 1)
+        .with_payload_regeneration(Regeneration::Once);
+        assert_eq!(s.write().await.unwrap(), 3);
+        assert_eq!(s.report().generator_waits, 0);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move { stream.read_to_end(&mut Vec::new()).await });
+            }
+        });
+        // Every request reuses the payload of the single kept alive
+        // connection, whereas each opens its own connection without keepalive.
+        for (keepalive, written) in [(true, 4), (false, 1 + 2 + 3 + 4)] {
+            let mut s = SocketManager::new(
+                addr,
+                b"unused",
+                Protocol::Tcp,
+                WriteOptions::Count(4),
+                Statistics::new(),
+            )
+            .with_payload_generator(Growing, 1)
+            .with_payload_regeneration(Regeneration::PerConnection);
+            if keepalive {
+                s = s.with_keepalive();
+            }
+            assert_eq!(s.write().await.unwrap(), written);
+        }
+    }
+
+    #[tokio::test]
+    async fn write_latency_outliers() {
+        let addr = bind_socket(&Protocol::Udp).await;
+        let outliers = |threshold| async move {
+            let s = SocketManager::new(
+                addr,
+                b"outlier",
+                Protocol::Udp,
+                WriteOptions::Count(5),
+                Statistics::new(),
+            )
+            .with_latency_outliers(threshold);
+            s.write().await.unwrap();
+            s.report().latency_outliers
+        };
+
+        assert!(outliers(std::time::Duration::from_secs(3600))
+            .await
+            .is_empty());
+        let all = outliers(std::time::Duration::ZERO).await;
+        assert_eq!(all.len(), 5);
+        assert!(all.iter().all(|o| o.addr == addr.to_string()
+            && o.payload_len == 7
+            && o.payload_prefix == "outlier"
+            && o.payload_hash == all[0].payload_hash));
+    }
+
+    #[tokio::test]
+    async fn write_memory_transport() {
+        let addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let (memory, mut listener) = MemoryTransport::new();
+        let s = SocketManager::new(
+            addr,
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::ConcurrencyWithCount(4, 100),
+            Statistics::new(),
+        )
+        .with_transport(memory);
+        assert_eq!(s.write().await.unwrap(), 600);
+        assert_eq!(s.successful_requests(), 100);
+
+        drop(s);
+        let mut accepted = 0;
+        while let Some((to, _stream)) = listener.accept().await {
+            assert_eq!(to, addr);
+            accepted += 1;
+        }
+        assert_eq!(accepted, 100);
+    }
+
+    #[tokio::test]
+    async fn write_at_rate() {
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move { while listener.accept().await.is_some() {} });
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::RateWithCount(100, 10),
+            Statistics::new(),
+        )
+        .with_transport(memory);
+
+        // The first request is sent straight away, then one every 10ms.
+        let start = Instant::now();
+        assert_eq!(s.write().await.unwrap(), 60);
+        assert!(start.elapsed() >= std::time::Duration::from_millis(90));
+        assert_eq!(s.successful_requests(), 10);
+
+        let report = s.report();
+        assert_eq!(report.requested_rate, Some(100));
+        let achieved = report.achieved_rate.unwrap();
+        assert!((50.0..=110.0).contains(&achieved), "achieved {achieved}");
+        assert!(report.send_lag_max_us.is_some());
+
+        for opts in [WriteOptions::Rate(0), WriteOptions::RateWithCount(0, 10)] {
+            let s = SocketManager::new(
+                "127.0.0.1:5000",
+                b"memory",
+                Protocol::Tcp,
+                opts,
+                Statistics::new(),
+            )
+            .with_ramp_up(std::time::Duration::from_millis(100));
+            let e = s.write().await.unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                "the rate must be at least 1 request per second"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn write_ramp_up() {
+        let ramp_up = std::time::Duration::from_millis(300);
+        for opts in [
+            WriteOptions::ConcurrencyWithCount(4, 8),
+            WriteOptions::RateWithCount(50, 8),
+        ] {
+            let (memory, mut listener) = MemoryTransport::new();
+            let accepted = tokio::spawn(async move {
+                let mut accepted = 0;
+                while listener.accept().await.is_some() {
+                    accepted += 1;
+                }
+                accepted
+            });
+            let s = SocketManager::new(
+                "127.0.0.1:5000",
+                b"memory",
+                Protocol::Tcp,
+                opts,
+                Statistics::new(),
+            )
+            .with_transport(memory)
+            .with_ramp_up(ramp_up);
+
+            let start = Instant::now();
+            s.write().await.unwrap();
+            assert!(start.elapsed() >= ramp_up);
+            // Only the requests after the ramp-up are recorded and timed.
+            assert_eq!(s.request_count(), 8);
+            assert!(s.elapsed() < ramp_up.as_millis());
+            drop(s);
+            assert!(accepted.await.unwrap() > 8);
+        }
+
+        // Nor are the connections and TLS handshakes of the ramp-up.
+        use crate::{
+            tls::{TestCertificate, TlsConfig, TlsServerConfig},
+            Server,
+        };
+
+        let certificate = TestCertificate::new();
+        for protocol in [Protocol::Tcp, Protocol::Tls] {
+            let mut server = Server::new(
+                "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+                protocol.clone(),
+                std::io::sink(),
+            )
+            .without_logs();
+            if protocol == Protocol::Tls {
+                server =
+                    server.tls(TlsServerConfig::load(&certificate.cert, &certificate.key).unwrap());
+            }
+            let received = server.statistics();
+            let mut bound = server.bound_addr();
+            let handle =
+                tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+            let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+            let s = SocketManager::new(
+                addr,
+                b"warm",
+                protocol.clone(),
+                WriteOptions::ConcurrencyWithCount(4, 8),
+                Statistics::new(),
+            )
+            .with_tls(
+                TlsConfig::new()
+                    .with_ca_file(&certificate.cert)
+                    .with_server_name("localhost"),
+            )
+            .with_ramp_up(ramp_up);
+            s.write().await.unwrap();
+            let report = s.report();
+            match protocol {
+                Protocol::Tls => assert_eq!(
+                    report.tls_full_handshakes + report.tls_resumed_handshakes,
+                    8
+                ),
+                _ => assert_eq!(report.connections_opened, 8),
+            }
+            while received.messages() <= 8 {
+                tokio::task::yield_now().await;
+            }
+            handle.abort();
+        }
+
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_ramp_up(ramp_up);
+        assert!(s.write().await.is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn write_resource_sampling() {
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move { while listener.accept().await.is_some() {} });
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::RateWithCount(20, 10),
+            Statistics::new(),
+        )
+        .with_transport(memory);
+        s.write().await.unwrap();
+        assert_eq!(s.report().rss_peak_bytes, None);
+
+        let s = s.with_resource_sampling();
+        s.write().await.unwrap();
+        let report = s.report();
+        assert!(report.rss_peak_bytes.unwrap() > 0);
+        assert!(report.cpu_avg_percent.is_some());
+    }
+
+    #[tokio::test]
+    async fn write_bandwidth() {
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move { while listener.accept().await.is_some() {} });
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            &[0; 1000],
+            Protocol::Tcp,
+            WriteOptions::ConcurrencyWithCount(4, 8),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_bandwidth(Bandwidth(20_000));
+
+        // A burst of 2000 bytes is allowed, then the remaining 6000 bytes are
+        // shared by the concurrent requests at 20000 bytes per second.
+        let start = Instant::now();
+        assert_eq!(s.write().await.unwrap(), 8000);
+        assert!(start.elapsed() >= std::time::Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn write_blocking_threads() {
+        let protocols = vec![Protocol::Tcp, Protocol::Udp];
+        for protocol in protocols {
+            let addr = bind_socket(&protocol).await;
+            let s = SocketManager::new(
+                addr,
+                b"blocking",
+                protocol,
+                WriteOptions::ConcurrencyWithCount(2, 10),
+                Statistics::new(),
+            )
+            .with_engine(Engine::BlockingThreads);
+            assert_eq!(s.write().await.unwrap(), 80);
+            assert_eq!(s.successful_requests(), 10);
+        }
+
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"blocking",
+            Protocol::Tcp,
+            WriteOptions::Rate(10),
+            Statistics::new(),
+        )
+        .with_engine(Engine::BlockingThreads);
+        assert!(s.write().await.is_err());
+
+        // Options which the engine would otherwise ignore are refused.
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"blocking",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_engine(Engine::BlockingThreads)
+        .with_latency_outliers(std::time::Duration::from_millis(50));
+        assert_eq!(
+            s.write().await.unwrap_err().to_string(),
+            "the blocking-threads engine does not support latency outliers"
+        );
+    }
+
+    #[tokio::test]
+    async fn write_keepalive() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = tokio::spawn(async move {
+            let mut readers = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                readers.push(tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    stream.read_to_end(&mut buf).await.unwrap();
+                    buf.len()
+                }));
+            }
+            let mut total = 0;
+            for reader in readers {
+                total += reader.await.unwrap();
+            }
+            total
+        });
+
+        let s = SocketManager::new(
+            addr,
+            b"keepalive",
+            Protocol::Tcp,
+            WriteOptions::ConcurrencyWithCount(2, 20),
+            Statistics::new(),
+        )
+        .with_keepalive();
+        assert_eq!(s.write().await.unwrap(), 180);
+        let report = s.report();
+        assert_eq!(report.successful_requests, 20);
+        assert_eq!(report.connections_opened, 2);
+
+        // The connections are closed once the manager is dropped.
+        drop(s);
+        assert_eq!(received.await.unwrap(), 180);
+    }
+
+    #[tokio::test]
+    async fn write_duplex() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            // The server pushes its own stream while the writes arrive.
+            stream.write_all(&[b'x'; 4096]).await.unwrap();
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await.unwrap();
+            buf.len()
+        });
+
+        // Writing at a rate leaves the inbound stream time to arrive.
+        let s = SocketManager::new(
+            addr,
+            b"duplex",
+            Protocol::Tcp,
+            WriteOptions::RateWithCount(50, 10),
+            Statistics::new(),
+        )
+        .with_keepalive()
+        .with_duplex();
+        assert_eq!(s.write().await.unwrap(), 60);
+        let report = s.report();
+        assert_eq!(report.connections_opened, 1);
+        assert_eq!(report.inbound_bytes, 4096);
+        assert!(report.inbound_throughput > 0.0);
+        assert!(report.throughput > 0.0);
+
+        drop(s);
+        assert_eq!(received.await.unwrap(), 60);
+
+        let s = SocketManager::new(
+            addr,
+            b"duplex",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_duplex();
+        let err = s.write().await.unwrap_err().to_string();
+        assert_eq!(err, "duplex requires keepalive");
+    }
+
+    #[tokio::test]
+    async fn write_connection_lifetime() {
+        use crate::Server;
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        // Every connection is recycled after the write which exceeds its
+        // lifetime, one request per 20ms.
+        let s = SocketManager::new(
+            addr,
+            b"recycle",
+            Protocol::Tcp,
+            WriteOptions::RateWithCount(50, 10),
+            Statistics::new(),
+        )
+        .with_keepalive()
+        .with_connection_lifetime(std::time::Duration::from_millis(50));
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!(report.successful_requests, 10);
+        assert!(report.connections_recycled >= 2, "{report:?}");
+        assert_eq!(
+            report.connections_opened,
+            report.connections_recycled + 1,
+            "{report:?}"
+        );
+
+        let s = SocketManager::new(
+            addr,
+            b"recycle",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_connection_lifetime(std::time::Duration::from_secs(1));
+        assert!(s.write().await.is_err());
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn write_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Hold accepted connections open without reading from them.
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let input = vec![0; 64 * 1024 * 1024];
+        let s = SocketManager::new(
+            addr,
+            &input,
+            Protocol::Tcp,
+            WriteOptions::Count(2),
+            Statistics::new(),
+        )
+        .with_write_timeout(std::time::Duration::from_millis(100));
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!(report.failed_requests, 2);
+        assert_eq!(report.timeouts, 2);
+
+        let s = SocketManager::new(
+            addr,
+            b"udp",
+            Protocol::Udp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_connect_timeout(std::time::Duration::from_secs(1));
+        assert!(s.write().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn write_wait_peer_close() {
+        /// Read each connection to the end, then close it or, when `hold` is
+        /// set, keep it open as if processing had stalled.
+        async fn server(hold: bool) -> SocketAddr {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut held = Vec::new();
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let mut message = Vec::new();
+                    stream.read_to_end(&mut message).await.unwrap();
+                    if hold {
+                        held.push(stream);
+                    }
+                }
+            });
+            addr
+        }
+
+        for (hold, failed) in [(false, 0), (true, 2)] {
+            let s = SocketManager::new(
+                server(hold).await,
+                b"close",
+                Protocol::Tcp,
+                WriteOptions::Count(2),
+                Statistics::new(),
+            )
+            .with_wait_peer_close(Some(std::time::Duration::from_millis(100)));
+            s.write().await.unwrap();
+            let report = s.report();
+            assert_eq!(report.failed_requests, failed);
+            assert_eq!(report.timeouts, failed);
+        }
+
+        let s = SocketManager::new(
+            server(false).await,
+            b"close",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_keepalive()
+        .with_wait_peer_close(None);
+        assert!(s.write().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn write_retries() {
+        use crate::{RetryPolicy, Transport};
+
+        /// Fails a number of writes before succeeding.
+        struct Flaky(AtomicU64);
+
+        impl Transport for Flaky {
+            fn write<'a>(
+                &'a self,
+                _addr: SocketAddr,
+                input: &'a [u8],
+            ) -> futures::future::BoxFuture<'a, crate::Result<u64>> {
+                let failed = self
+                    .0
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                    .is_ok();
+                Box::pin(async move {
+                    if failed {
+                        Err("connection refused".into())
+                    } else {
+                        Ok(input.len() as u64)
+                    }
+                })
+            }
+        }
+
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff: std::time::Duration::from_millis(1),
+            writes: true,
+        };
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"retry",
+            Protocol::Tcp,
+            WriteOptions::Count(3),
+            Statistics::new(),
+        )
+        .with_transport(Flaky(AtomicU64::new(2)))
+        .with_retries(policy);
+        assert_eq!(s.write().await.unwrap(), 15);
+        let report = s.report();
+        assert_eq!(report.successful_requests, 3);
+        assert_eq!(report.retries, 2);
+
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"retry",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_transport(Flaky(AtomicU64::new(5)))
+        .with_retries(policy);
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!(report.failed_requests, 1);
+        assert_eq!(report.retries, 2);
+        assert_eq!((report.connect_retries, report.write_retries), (0, 2));
+
+        // Only failures to connect are retried when writes are not.
+        let connect_only = RetryPolicy {
+            writes: false,
+            ..policy
+        };
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"retry",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_transport(Flaky(AtomicU64::new(1)))
+        .with_retries(connect_only);
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!((report.failed_requests, report.retries), (1, 0));
+
+        let s = SocketManager::new(
+            "127.0.0.1:1",
+            b"retry",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_retries(connect_only);
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!(report.failed_requests, 1);
+        assert_eq!((report.connect_retries, report.write_retries), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn write_unresolved_host() {
+        use crate::RetryPolicy;
+
+        let s = SocketManager::new(
+            "gn-missing.invalid:5000",
+            b"dns",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        );
+        let err = s.write().await.unwrap_err().to_string();
+        assert!(err.starts_with("unable to resolve the host: "), "{err}");
+        assert_eq!(s.report().resolution_failures, 1);
+
+        let s = SocketManager::new(
+            "gn-missing.invalid:5000",
+            b"dns",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_retries(RetryPolicy {
+            retries: 2,
+            backoff: std::time::Duration::from_millis(1),
+            writes: true,
+        });
+        let err = s.write().await.unwrap_err().to_string();
+        assert!(err.ends_with(", after 3 attempts"), "{err}");
+        let report = s.report();
+        assert_eq!(report.resolution_failures, 3);
+        assert_eq!((report.requests, report.failed_requests), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn write_until_stopped() {
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move { while listener.accept().await.is_some() {} });
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::ConcurrencyUnlimited(4),
+            Statistics::new(),
+        )
+        .with_transport(memory);
+
+        let stop = async {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            s.stop();
+        };
+        let (written, ()) = tokio::join!(s.write(), stop);
+        assert!(written.unwrap() > 0);
+        assert_eq!(s.report().failed_requests, 0);
+        assert_eq!(s.report().stop_reason, StopReason::Stopped);
+    }
+
+    #[tokio::test]
+    async fn write_cancellation() {
+        use crate::{CancellationToken, Server};
+
+        let token = CancellationToken::new();
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .cancellation(token.clone())
+        .without_logs();
+        let stats = server.statistics();
+        let mut bound = server.bound_addr();
+        let served = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        // The run would otherwise last for a minute.
+        let s = SocketManager::new(
+            addr,
+            b"cancel",
+            Protocol::Tcp,
+            WriteOptions::Duration(humantime::Duration::from_str("60s").unwrap()),
+            Statistics::new(),
+        )
+        .with_cancellation(token.clone());
+        let write = tokio::spawn(async move {
+            let written = s.write().await.map_err(|e| e.to_string());
+            (written, s.report())
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        token.cancel();
+
+        let (written, report) = tokio::time::timeout(std::time::Duration::from_secs(5), write)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(written.unwrap() > 0);
+        assert_eq!(report.stop_reason, StopReason::Stopped);
+        tokio::time::timeout(std::time::Duration::from_secs(5), served)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(stats.messages() > 0);
+    }
+
+    #[tokio::test]
+    async fn write_subscribe() {
+        use crate::Server;
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .without_logs();
+        let mut bound = server.bound_addr();
+        tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let s = Arc::new(
+            SocketManager::new(
+                addr,
+                b"live",
+                Protocol::Tcp,
+                WriteOptions::Duration(humantime::Duration::from_str("1500ms").unwrap()),
+                Statistics::new(),
+            )
+            .with_timeline()
+            .with_annotation("1200ms=rolled back".parse().unwrap())
+            .with_annotation("500ms=deployed v2".parse().unwrap())
+            .with_annotation("1h=never reached".parse().unwrap()),
+        );
+        let mut snapshots = s.subscribe();
+        let write = tokio::spawn({
+            let s = Arc::clone(&s);
+            async move { s.write().await.map_err(|e| e.to_string()) }
+        });
+
+        // A snapshot is published while the run is still being written.
+        snapshots.changed().await.unwrap();
+        let live = snapshots.borrow_and_update().clone();
+        assert!(!write.is_finished());
+        assert!(live.requests > 0);
+        assert!(live.elapsed >= std::time::Duration::from_secs(1));
+
+        let written = write.await.unwrap().unwrap();
+        let last = snapshots.borrow().clone();
+        assert_eq!(last.bytes, written);
+        assert!(last.requests >= live.requests);
+        assert_eq!(last.requests, s.statistics().request_count());
+
+        // The timeline holds the snapshot of each second and the final one.
+        let timeline = s.report().timeline;
+        assert_eq!(timeline.len(), 2);
+        assert_eq!(timeline[1].requests, last.requests);
+        assert_eq!(timeline[1].total_bytes, written);
+        // Annotations are placed in the first point at or after them.
+        assert_eq!(timeline[0].annotations, ["deployed v2"]);
+        assert_eq!(timeline[1].annotations, ["rolled back"]);
+        let annotations = s.report().annotations;
+        let reached: Vec<_> = annotations.iter().map(|a| a.at_ms).collect();
+        assert_eq!(reached, [500, 1200]);
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        started: AtomicU64,
+        succeeded: AtomicU64,
+        failed: AtomicU64,
+    }
+
+    impl WriteObserver for Arc<Counter> {
+        fn on_request_start(&self, _addr: SocketAddr) {
+            self.started.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_success(&self, _addr: SocketAddr, bytes: u64, _elapsed: std::time::Duration) {
+            assert_eq!(bytes, 6);
+            self.succeeded.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_failure(
+            &self,
+            _addr: SocketAddr,
+            _error: &(dyn std::error::Error + 'static),
+            _elapsed: std::time::Duration,
+        ) {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn write_observer() {
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move { while listener.accept().await.is_some() {} });
+        let counter = Arc::new(Counter::default());
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::ConcurrencyWithCount(4, 20),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_observer(Arc::clone(&counter));
+        s.write().await.unwrap();
+
+        assert_eq!(counter.started.load(Ordering::Relaxed), 20);
+        assert_eq!(counter.succeeded.load(Ordering::Relaxed), 20);
+        assert_eq!(counter.failed.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn write_expect_response() {
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move {
+            let mut count = 0;
+            while let Some((_, mut stream)) = listener.accept().await {
+                let mut message = Vec::new();
+                stream.read_to_end(&mut message).await.unwrap();
+                let reply: &[u8] = if count % 2 == 0 { b"OK" } else { b"ERR" };
+                stream.write_all(reply).await.unwrap();
+                count += 1;
+            }
+        });
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::Count(10),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_expect_response("OK".parse().unwrap());
+        assert_eq!(s.write().await.unwrap(), 60);
+
+        let report = s.report();
+        assert_eq!(report.successful_requests, 5);
+        assert_eq!(report.failed_requests, 5);
+        assert_eq!(report.mismatched_responses, 5);
+    }
+
+    #[tokio::test]
+    async fn write_verify_digest() {
+        use crate::{Digest, Server};
+
+        for reply_digest in [true, false] {
+            let mut server = Server::new(
+                "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+                Protocol::Tcp,
+                std::io::sink(),
+            )
+            .without_logs();
+            if reply_digest {
+                server = server.reply_digest(Digest::Sha256);
+            }
+            let mut bound = server.bound_addr();
+            let handle =
+                tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+            let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+            let s = SocketManager::new(
+                addr,
+                b"intact",
+                Protocol::Tcp,
+                WriteOptions::Count(4),
+                Statistics::new(),
+            )
+            .with_verify_digest(Digest::Sha256);
+            s.write().await.unwrap();
+
+            let report = s.report();
+            // Without a digest in reply, nothing can be verified.
+            let verified = if reply_digest { 4 } else { 0 };
+            assert_eq!(report.successful_requests, verified);
+            assert_eq!(report.mismatched_responses, 4 - verified);
+            handle.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn write_against_chaos() {
+        use crate::{Chaos, ReplyFraming, Server};
+
+        // The whole message is read before closing, so the writer sees the
+        // close rather than a reset for unread data.
+        for (chaos, resets, closes) in [("reset=100%", 3, 0), ("close-after=6B", 0, 3)] {
+            let mut server = Server::new(
+                "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+                Protocol::Tcp,
+                std::io::sink(),
+            )
+            .chaos(chaos.parse::<Chaos>().unwrap())
+            .without_logs();
+            let mut bound = server.bound_addr();
+            let handle =
+                tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+            let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+            let s = SocketManager::new(
+                addr,
+                b"intact",
+                Protocol::Tcp,
+                WriteOptions::Count(3),
+                Statistics::new(),
+            )
+            .with_expect_reply(ReplyFraming::Bytes(4));
+            s.write().await.unwrap();
+
+            let report = s.report();
+            assert_eq!(report.failed_requests, 3, "{chaos}");
+            assert_eq!(report.connection_resets, resets, "{chaos}");
+            assert_eq!(report.premature_closes, closes, "{chaos}");
+            handle.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn write_expect_reply() {
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move {
+            while let Some((_, mut stream)) = listener.accept().await {
+                let mut message = [0; 4];
+                stream.read_exact(&mut message).await.unwrap();
+                // The connection stays open, so the reply must be framed.
+                stream.write_all(b"PONG\r\n").await.unwrap();
+                stream.read_to_end(&mut Vec::new()).await.unwrap();
+            }
+        });
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"PING",
+            Protocol::Tcp,
+            WriteOptions::Count(10),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_expect_reply("delimiter:\\r\\n".parse().unwrap())
+        .with_expect_response("prefix:PONG".parse().unwrap());
+        assert_eq!(s.write().await.unwrap(), 40);
+
+        let report = s.report();
+        assert_eq!(report.successful_requests, 10);
+        assert_eq!(report.reply_bytes, 60);
+        assert!(report.rtt_p50_us.is_some());
+        assert!(report.rtt_max_us >= report.rtt_p50_us);
+        assert!(report.ttfb_p50_us.is_some());
+        assert!(report.ttfb_p50_us <= report.rtt_max_us);
+    }
+
+    #[tokio::test]
+    async fn write_unacknowledged() {
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move {
+            while let Some((_, mut stream)) = listener.accept().await {
+                // The request is read, but the connection is closed without a
+                // reply.
+                let mut message = [0; 4];
+                stream.read_exact(&mut message).await.unwrap();
+            }
+        });
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"PING",
+            Protocol::Tcp,
+            WriteOptions::Count(3),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_expect_reply("delimiter:\\r\\n".parse().unwrap());
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!(report.failed_requests, 3);
+        assert_eq!(
+            (report.unacknowledged_requests, report.send_failures),
+            (3, 0)
+        );
+
+        let (memory, listener) = MemoryTransport::new();
+        drop(listener);
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"PING",
+            Protocol::Tcp,
+            WriteOptions::Count(3),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_expect_reply("delimiter:\\r\\n".parse().unwrap());
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!(
+            (report.unacknowledged_requests, report.send_failures),
+            (0, 3)
+        );
+    }
+
+    #[tokio::test]
+    async fn write_transcript() {
+        use crate::Transcript;
+
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move {
+            while let Some((_, mut stream)) = listener.accept().await {
+                let mut message = Vec::new();
+                stream.read_to_end(&mut message).await.unwrap();
+                stream.write_all(b"OK ").await.unwrap();
+                stream.write_all(&message).await.unwrap();
+            }
+        });
+        let dir = std::env::temp_dir().join(format!("gn-transcript-{}", std::process::id()));
+        let every = std::num::NonZeroU64::new(2).unwrap();
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"PING",
+            Protocol::Tcp,
+            WriteOptions::Count(4),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_expect_reply("eof".parse().unwrap())
+        .with_event_log(std::io::sink())
+        .with_transcript(Transcript::create(&dir, every).unwrap());
+        s.write().await.unwrap();
+
+        // Only every other request is saved.
+        let index = std::fs::read_to_string(dir.join("index.jsonl")).unwrap();
+        let entries: Vec<serde_json::Value> = index
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1]["index"], 2);
+        assert_eq!(entries[1]["request"], "2.request");
+        assert_eq!(entries[1]["response"], "2.response");
+        assert_eq!(entries[1]["addr"], "127.0.0.1:5000");
+        assert!(entries[1]["latency_us"].is_u64());
+        assert_eq!(std::fs::read(dir.join("2.request")).unwrap(), b"PING");
+        assert_eq!(std::fs::read(dir.join("2.response")).unwrap(), b"OK PING");
+        assert!(!dir.join("1.request").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"PING",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_transport(MemoryTransport::new().0)
+        .with_transcript(Transcript::create(&dir, every).unwrap());
+        let err = s.write().await.unwrap_err().to_string();
+        assert_eq!(err, "a transcript requires replies to be read");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn write_max_failures() {
+        let (memory, listener) = MemoryTransport::new();
+        drop(listener);
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::ConcurrencyUnlimited(4),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_max_failures(100);
+        s.write().await.unwrap();
+
+        let report = s.report();
+        assert_eq!(report.stop_reason, StopReason::MaxFailures);
+        // In-flight requests complete after the limit is reached.
+        assert!((100..104).contains(&report.failed_requests));
+    }
+
+    #[tokio::test]
+    async fn write_circuit_breaker() {
+        let (memory, listener) = MemoryTransport::new();
+        drop(listener);
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::Count(3),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_circuit_breaker("2:50ms".parse().unwrap());
+        s.write().await.unwrap();
+
+        // The third request waits for the cool-down, then opens the circuit
+        // again as it fails.
+        let report = s.report();
+        assert_eq!(report.failed_requests, 3);
+        let states: Vec<_> = report.circuit_events.iter().map(|e| e.state).collect();
+        assert_eq!(states, [CircuitState::Open, CircuitState::Open]);
+        assert!(report.circuit_events[1].at_ms >= 50);
+        assert_eq!(report.circuit_events[0].addr, "127.0.0.1:5000");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn write_unix() {
+        use crate::{transport::TemporarySocketPath, Server};
+
+        for protocol in [Protocol::Unix, Protocol::UnixDatagram] {
+            let path = TemporarySocketPath::new();
+            let mut server = Server::new(
+                crate::Endpoint::Unix(path.0.clone()),
+                protocol.clone(),
+                std::io::sink(),
+            )
+            .without_logs();
+            let received = server.statistics();
+            let handle =
+                tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+            while !path.0.exists() {
+                tokio::task::yield_now().await;
+            }
+
+            let s = SocketManager::unix(
+                &path.0,
+                b"unix",
+                protocol.clone(),
+                WriteOptions::Count(10),
+                Statistics::new(),
+            );
+            assert_eq!(s.write().await.unwrap(), 40, "[{protocol}]");
+            assert_eq!(s.successful_requests(), 10, "[{protocol}]");
+            while received.messages() < 10 {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(received.bytes(), 40, "[{protocol}]");
+            handle.abort();
+        }
+
+        // Unix protocols require a path, and the others an IP address.
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"unix",
+            Protocol::Unix,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        );
+        assert!(s.write().await.is_err());
+        let s = SocketManager::unix(
+            "/tmp/gn.sock",
+            b"unix",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        );
+        assert!(s.write().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn write_close_with_rst() {
+        use crate::{MessageMatcher, Server};
+
+        // A graceful close gives an empty reply, whereas a reset is an error.
+        for (rst, mismatched) in [(false, 3), (true, 0)] {
+            let mut server = Server::new(
+                "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+                Protocol::Tcp,
+                std::io::sink(),
+            )
+            .without_logs();
+            if rst {
+                server = server.close_with_rst();
+            }
+            let mut bound = server.bound_addr();
+            let handle =
+                tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+            let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+            let s = SocketManager::new(
+                addr,
+                b"reset",
+                Protocol::Tcp,
+                WriteOptions::Count(3),
+                Statistics::new(),
+            )
+            .with_expect_response(MessageMatcher::Prefix(b"OK".to_vec()));
+            s.write().await.unwrap();
+            let report = s.report();
+            assert_eq!(report.failed_requests, 3, "[rst={rst}]");
+            assert_eq!(report.mismatched_responses, mismatched, "[rst={rst}]");
+            handle.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn write_websocket() {
+        use crate::{websocket::echo_server, MessageMatcher};
+
+        let addr = echo_server().await;
+        let s = SocketManager::new(
+            addr,
+            b"ws",
+            Protocol::Ws,
+            WriteOptions::ConcurrencyWithCount(2, 6),
+            Statistics::new(),
+        )
+        .with_expect_response(MessageMatcher::from_str("ws").unwrap());
+        assert_eq!(s.write().await.unwrap(), 12);
+        let report = s.report();
+        assert_eq!(report.successful_requests, 6);
+        assert_eq!(report.reply_bytes, 12);
+        assert_eq!(report.connections_opened, 6);
+    }
+
+    #[tokio::test]
+    async fn write_tls() {
+        use crate::{
+            tls::{TestCertificate, TlsConfig, TlsServerConfig},
+            Server,
+        };
+
+        let certificate = TestCertificate::new();
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tls,
+            std::io::sink(),
+        )
+        .tls(TlsServerConfig::load(&certificate.cert, &certificate.key).unwrap())
+        .without_logs();
+        let received = server.statistics();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let s = SocketManager::new(
+            addr,
+            b"tls",
+            Protocol::Tls,
+            WriteOptions::Count(5),
+            Statistics::new(),
+        )
+        .with_tls(
+            TlsConfig::new()
+                .with_ca_file(&certificate.cert)
+                .with_server_name("localhost"),
+        );
+        assert_eq!(s.write().await.unwrap(), 15);
+        while received.messages() < 5 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(received.bytes(), 15);
+        // Only the first connection has nothing to resume.
+        let report = s.report();
+        assert_eq!(report.tls_full_handshakes, 1);
+        assert_eq!(report.tls_resumed_handshakes, 4);
+        assert!(report.tls_handshake_p50_us.is_some());
+
+        let s = SocketManager::new(
+            addr,
+            b"tls",
+            Protocol::Tls,
+            WriteOptions::Count(5),
+            Statistics::new(),
+        )
+        .with_tls(
+            TlsConfig::new()
+                .with_ca_file(&certificate.cert)
+                .with_server_name("localhost")
+                .without_session_resumption(),
+        );
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!(report.tls_full_handshakes, 5);
+        assert_eq!(report.tls_resumed_handshakes, 0);
+        handle.abort();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn write_reuseport() {
+        use crate::Server;
+
+        let serve = |addr: SocketAddr, reuseport: bool| {
+            let mut server = Server::new(addr, Protocol::Udp, std::io::sink())
+                .recv_buffer_size(1 << 20)
+                .without_logs();
+            if reuseport {
+                server = server.reuseport();
+            }
+            let received = server.statistics();
+            let mut bound = server.bound_addr();
+            let handle =
+                tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+            async move {
+                // The server drops its sender of the bound address if it
+                // fails to bind.
+                let addr = bound.wait_for(Option::is_some).await.map(|addr| *addr);
+                match addr {
+                    Ok(addr) => Ok((addr.unwrap(), received, handle)),
+                    Err(_) => Err(handle.await.unwrap().unwrap_err()),
+                }
+            }
+        };
+        let (addr, first, first_handle) =
+            serve("127.0.0.1:0".parse().unwrap(), true).await.unwrap();
+        let (_, second, second_handle) = serve(addr, true).await.unwrap();
+        assert!(serve(addr, false).await.is_err());
+
+        let s = SocketManager::new(
+            addr,
+            b"reuse",
+            Protocol::Udp,
+            WriteOptions::Count(50),
+            Statistics::new(),
+        );
+        s.write().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(first.messages() + second.messages(), 50);
+        first_handle.abort();
+        second_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn write_mirror_multicast() {
+        use crate::Server;
+        use std::net::Ipv4Addr;
+
+        let group = Ipv4Addr::new(239, 2, 2, 2);
+        let observer = tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .await
+            .unwrap();
+        observer
+            .join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)
+            .unwrap();
+        let group = SocketAddr::from((group, observer.local_addr().unwrap().port()));
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Udp,
+            std::io::sink(),
+        )
+        .mirror_multicast(group)
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let s = SocketManager::new(
+            addr,
+            b"mirrored",
+            Protocol::Udp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        );
+        s.write().await.unwrap();
+        let mut buf = [0; 64];
+        let len = tokio::time::timeout(std::time::Duration::from_secs(1), observer.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], b"mirrored");
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn write_streamed_payload() {
+        let (memory, mut listener) = MemoryTransport::new();
+        let received = tokio::spawn(async move {
+            let mut total = 0;
+            while let Some((_, mut stream)) = listener.accept().await {
+                let mut buf = vec![0; 8192];
+                loop {
+                    match stream.read(&mut buf).await.unwrap() {
+                        0 => break,
+                        n => total += n,
+                    }
+                }
+            }
+            total
+        });
+        let len = 1 << 20;
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"",
+            Protocol::Tcp,
+            WriteOptions::ConcurrencyWithCount(2, 4),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_streamed_payload(len);
+        assert_eq!(s.write().await.unwrap(), 4 * len);
+        drop(s);
+        assert_eq!(received.await.unwrap() as u64, 4 * len);
+    }
+
+    #[tokio::test]
+    async fn write_memory_transport_failures() {
+        let (memory, listener) = MemoryTransport::new();
+        drop(listener);
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"memory",
+            Protocol::Tcp,
+            WriteOptions::Count(10),
+            Statistics::new(),
+        )
+        .with_transport(memory);
+        assert_eq!(s.write().await.unwrap(), 0);
+        assert_eq!(s.successful_requests(), 0);
+        assert_eq!(s.report().failed_requests, 10);
+        assert_eq!(s.successful_requests_percentage(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn write_reflect_timing() {
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move {
+            while let Some((addr, mut stream)) = listener.accept().await {
+                let mut message = Vec::new();
+                stream.read_to_end(&mut message).await.unwrap();
+                let (sent, body) = timing::split_timestamp(&message);
+                assert_eq!(body, b"timing");
+                let reply = timing::reply(addr, sent, sent.unwrap() + 1_000);
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"timing",
+            Protocol::Tcp,
+            WriteOptions::Count(5),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_reflect_timing();
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!(report.successful_requests, 5);
+        assert_eq!(report.one_way_delay_mean_us, Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn write_traceparent() {
+        use crate::events::SharedBuffer;
+
+        let (memory, mut listener) = MemoryTransport::new();
+        let received = tokio::spawn(async move {
+            let mut traces = Vec::new();
+            while let Some((_, mut stream)) = listener.accept().await {
+                let mut message = String::new();
+                stream.read_to_string(&mut message).await.unwrap();
+                let (header, body) = message.split_once('\n').unwrap();
+                assert_eq!(body, "traced");
+                let traceparent = header.strip_prefix("traceparent: 00-").unwrap();
+                traces.push(traceparent.split('-').next().unwrap().to_string());
+            }
+            traces
+        });
+
+        let events = SharedBuffer::default();
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"traced",
+            Protocol::Tcp,
+            WriteOptions::Count(3),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_traceparent()
+        .with_event_log(events.clone());
+        s.write().await.unwrap();
+        drop(s);
+        let traces = received.await.unwrap();
+        assert_eq!(traces.len(), 3);
+        assert_ne!(traces[0], traces[1]);
+
+        let events = String::from_utf8(events.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = events
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 3);
+        for (event, trace) in events.iter().zip(&traces) {
+            assert_eq!(event["trace_id"], trace.as_str());
+            assert_eq!(event["addr"], "127.0.0.1:5000");
+            assert_eq!(event["bytes"], 75);
+        }
+    }
+
+    #[tokio::test]
+    async fn write_clock_calibration() {
+        // The server's clock is 5ms ahead of the writer's.
+        const SKEW: u64 = 5_000_000;
+        let (memory, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move {
+            while let Some((addr, mut stream)) = listener.accept().await {
+                let mut message = Vec::new();
+                stream.read_to_end(&mut message).await.unwrap();
+                let (sent, _) = timing::split_timestamp(&message);
+                let received = timing::now() + SKEW;
+                let reply = format!("{addr} {} {received} {}\n", sent.unwrap(), received + 10);
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        let s = SocketManager::new(
+            "127.0.0.1:5000",
+            b"timing",
+            Protocol::Tcp,
+            WriteOptions::Count(5),
+            Statistics::new(),
+        )
+        .with_transport(memory)
+        .with_reflect_timing()
+        .with_clock_calibration(8);
+        s.write().await.unwrap();
+        let report = s.report();
+        assert_eq!(report.successful_requests, 5);
+        assert_eq!(report.clock_offsets.len(), 1);
+        let offset = &report.clock_offsets[0];
+        assert_eq!(offset.addr, "127.0.0.1:5000");
+        assert!((4_000.0..6_000.0).contains(&offset.offset_us));
+        assert!(report.one_way_delay_mean_us.unwrap().abs() < 1_000.0);
+
+        let uncalibrated = SocketManager::new(
+            "127.0.0.1:5000",
+            b"timing",
+            Protocol::Tcp,
+            WriteOptions::Count(1),
+            Statistics::new(),
+        )
+        .with_clock_calibration(1);
+        assert!(uncalibrated.write().await.is_err());
     }
 
     async fn throughput_helper(protocol: Protocol) {
@@ -0,0 +1,74 @@
+use std::{fmt::Display, str::FromStr};
+
+use regex::bytes::Regex;
+
+/// Matches the contents of a message, parsed from `exact:TEXT`,
+/// `prefix:TEXT` or `regex:PATTERN`. Text without one of these kinds is
+/// matched exactly.
+#[derive(Debug, Clone)]
+pub enum MessageMatcher {
+    Exact(Vec<u8>),
+    Prefix(Vec<u8>),
+    Regex(Regex),
+}
+
+impl MessageMatcher {
+    pub fn matches(&self, message: &[u8]) -> bool {
+        match self {
+            Self::Exact(expected) => message == expected.as_slice(),
+            Self::Prefix(prefix) => message.starts_with(prefix),
+            Self::Regex(regex) => regex.is_match(message),
+        }
+    }
+}
+
+impl FromStr for MessageMatcher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("exact", text)) => Ok(Self::Exact(text.as_bytes().to_vec())),
+            Some(("prefix", prefix)) => Ok(Self::Prefix(prefix.as_bytes().to_vec())),
+            Some(("regex", pattern)) => Regex::new(pattern)
+                .map(Self::Regex)
+                .map_err(|e| format!("invalid regex '{pattern}': {e}")),
+            _ => Ok(Self::Exact(s.as_bytes().to_vec())),
+        }
+    }
+}
+
+impl Display for MessageMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact(text) => write!(f, "exact:{}", String::from_utf8_lossy(text)),
+            Self::Prefix(prefix) => write!(f, "prefix:{}", String::from_utf8_lossy(prefix)),
+            Self::Regex(regex) => write!(f, "regex:{regex}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MessageMatcher;
+
+    #[test]
+    fn parse() {
+        let exact: MessageMatcher = "OK".parse().unwrap();
+        assert!(exact.matches(b"OK"));
+        assert!(!exact.matches(b"OK\n"));
+
+        let prefix: MessageMatcher = "prefix:HTTP/1.1 2".parse().unwrap();
+        assert!(prefix.matches(b"HTTP/1.1 200 OK"));
+        assert!(!prefix.matches(b"HTTP/1.1 500"));
+
+        let regex: MessageMatcher = "regex:^OK( \\d+)?$".parse().unwrap();
+        assert!(regex.matches(b"OK 12"));
+        assert!(!regex.matches(b"NOK"));
+
+        // Text with a colon but an unknown kind is matched exactly.
+        let colon: MessageMatcher = "status:OK".parse().unwrap();
+        assert!(colon.matches(b"status:OK"));
+
+        assert!("regex:(".parse::<MessageMatcher>().is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Prometheus endpoint exposing what a [`crate::Server`] has received.
+//!
+//! Every request to the endpoint, whatever its path, is answered with the
+//! metrics in the Prometheus text exposition format. Rates are calculated
+//! over the time since the previous scrape, or since the server started for
+//! the first one.
+use std::{fmt::Write, net::SocketAddr, sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::statistics::ServerStatistics;
+
+/// Largest request which is read before replying, as only the metrics are
+/// ever served.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Upper bounds of the buckets of the histogram of connection durations.
+const CONNECTION_DURATION_BUCKETS: [Duration; 10] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+    Duration::from_secs(30),
+    Duration::from_secs(60),
+];
+
+/// Totals at the previous scrape, which rates are calculated from.
+struct Scrape {
+    at: Instant,
+    messages: u64,
+    bytes: u64,
+}
+
+/// Endpoint serving metrics in the background, which stops when dropped.
+pub(crate) struct MetricsEndpoint {
+    addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl MetricsEndpoint {
+    /// Bind to the address and start serving the metrics of the statistics.
+    pub(crate) async fn start(
+        addr: SocketAddr,
+        stats: Arc<ServerStatistics>,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self {
+            addr: listener.local_addr()?,
+            handle: tokio::spawn(serve(listener, stats)),
+        })
+    }
+
+    /// Address the endpoint is bound to.
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsEndpoint {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Serve the metrics of the statistics on the listener until the task is
+/// aborted.
+async fn serve(listener: TcpListener, stats: Arc<ServerStatistics>) {
+    let mut last = Scrape {
+        at: Instant::now(),
+        messages: 0,
+        bytes: 0,
+    };
+    loop {
+        let Ok((mut stream, _)) = listener.accept().await else {
+            continue;
+        };
+        if read_request(&mut stream).await.is_err() {
+            continue;
+        }
+        let body = render(&stats, &mut last);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+}
+
+/// Read the head of an HTTP request, up to a limit.
+pub(crate) async fn read_request(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        match stream.read(&mut buf).await? {
+            0 => break,
+            n => request.extend_from_slice(&buf[..n]),
+        }
+    }
+    Ok(request)
+}
+
+/// Render the metrics, starting a new interval for the rates.
+fn render(stats: &ServerStatistics, last: &mut Scrape) -> String {
+    let now = Scrape {
+        at: Instant::now(),
+        messages: stats.messages(),
+        bytes: stats.bytes(),
+    };
+    let secs = now.at.duration_since(last.at).as_secs_f64();
+    let rate = |current: u64, previous: u64| {
+        if secs > 0.0 {
+            (current - previous) as f64 / secs
+        } else {
+            0.0
+        }
+    };
+
+    let mut out = String::new();
+    let mut metric =
+        |name, kind, help, value: String| write_metric(&mut out, name, kind, help, &value);
+    metric(
+        "gn_server_received_bytes_total",
+        "counter",
+        "Bytes received by the server.",
+        now.bytes.to_string(),
+    );
+    metric(
+        "gn_server_received_messages_total",
+        "counter",
+        "Messages received by the server.",
+        now.messages.to_string(),
+    );
+    metric(
+        "gn_server_active_connections",
+        "gauge",
+        "Connections which are being read from.",
+        stats.active_connections().to_string(),
+    );
+    metric(
+        "gn_server_queued_connections",
+        "gauge",
+        "Connections which are waiting to be read from.",
+        stats.queued_connections().to_string(),
+    );
+    metric(
+        "gn_server_rejected_connections_total",
+        "counter",
+        "Connections which were closed as the server was at its limit.",
+        stats.rejected_connections().to_string(),
+    );
+    if let Some(sizes) = stats.message_sizes() {
+        write_summary(
+            &mut out,
+            "gn_server_message_size_bytes",
+            "Sizes of the messages received by the server.",
+            &[(0.5, sizes.p50), (0.9, sizes.p90), (0.99, sizes.p99)],
+            sizes.mean * sizes.count as f64,
+            sizes.count,
+        );
+    }
+    let durations = stats.connection_durations(&CONNECTION_DURATION_BUCKETS);
+    write_histogram(
+        &mut out,
+        "gn_server_connection_duration_seconds",
+        "How long connections were read from before they closed.",
+        &CONNECTION_DURATION_BUCKETS
+            .iter()
+            .map(Duration::as_secs_f64)
+            .zip(durations.counts)
+            .collect::<Vec<_>>(),
+        durations.sum.as_secs_f64(),
+        durations.count,
+    );
+    let mut metric =
+        |name, kind, help, value: String| write_metric(&mut out, name, kind, help, &value);
+    metric(
+        "gn_server_received_bytes_per_second",
+        "gauge",
+        "Bytes received per second since the previous scrape.",
+        rate(now.bytes, last.bytes).to_string(),
+    );
+    metric(
+        "gn_server_received_messages_per_second",
+        "gauge",
+        "Messages received per second since the previous scrape.",
+        rate(now.messages, last.messages).to_string(),
+    );
+    *last = now;
+    out
+}
+
+/// Append a summary of the quantiles of a distribution to `out`, in the
+/// Prometheus text format.
+fn write_summary(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    quantiles: &[(f64, u64)],
+    sum: f64,
+    count: u64,
+) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} summary");
+    for (quantile, value) in quantiles {
+        let _ = writeln!(out, "{name}{{quantile=\"{quantile}\"}} {value}");
+    }
+    let _ = writeln!(out, "{name}_sum {sum}\n{name}_count {count}");
+}
+
+/// Append a histogram of the cumulative count at or below each upper bound to
+/// `out`, in the Prometheus text format.
+fn write_histogram(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    buckets: &[(f64, u64)],
+    sum: f64,
+    count: u64,
+) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+    for (bound, cumulative) in buckets {
+        let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+    }
+    let _ = writeln!(
+        out,
+        "{name}_bucket{{le=\"+Inf\"}} {count}\n{name}_sum {sum}\n{name}_count {count}"
+    );
+}
+
+/// Append a metric with a single sample to `out`, in the Prometheus text
+/// format.
+pub(crate) fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: &str) {
+    let _ = write!(
+        out,
+        "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::statistics::ServerStatistics;
+
+    async fn scrape(addr: std::net::SocketAddr) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn serve() {
+        let stats = Arc::new(ServerStatistics::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(super::serve(listener, Arc::clone(&stats)));
+
+        stats.record_message(10);
+        stats.record_message(5);
+        stats.record_connection_opened();
+        let response = scrape(addr).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\ngn_server_received_bytes_total 15\n"));
+        assert!(response.contains("\ngn_server_received_messages_total 2\n"));
+        assert!(response.contains("\ngn_server_active_connections 1\n"));
+        assert!(response.contains("# TYPE gn_server_received_bytes_per_second gauge\n"));
+        assert!(response.contains("\ngn_server_message_size_bytes{quantile=\"0.99\"} 10\n"));
+        assert!(response.contains("\ngn_server_message_size_bytes_sum 15\n"));
+        assert!(response.contains("\ngn_server_message_size_bytes_count 2\n"));
+
+        stats.record_connection_closed(std::time::Duration::from_millis(20));
+        let response = scrape(addr).await;
+        assert!(response.contains("\ngn_server_active_connections 0\n"));
+        assert!(response.contains("# TYPE gn_server_connection_duration_seconds histogram\n"));
+        assert!(
+            response.contains("\ngn_server_connection_duration_seconds_bucket{le=\"0.01\"} 0\n")
+        );
+        assert!(
+            response.contains("\ngn_server_connection_duration_seconds_bucket{le=\"0.05\"} 1\n")
+        );
+        assert!(
+            response.contains("\ngn_server_connection_duration_seconds_bucket{le=\"+Inf\"} 1\n")
+        );
+        assert!(response.contains("\ngn_server_connection_duration_seconds_count 1\n"));
+        assert!(response.contains("\ngn_server_received_messages_per_second 0\n"));
+        handle.abort();
+    }
+}
@@ -0,0 +1,96 @@
+//! Joining and sending to multicast groups over UDP.
+use std::{io, net::IpAddr};
+
+use socket2::SockRef;
+
+/// Join the multicast group on the network interface named `interface`, or
+/// on the one chosen by the system when none is given.
+pub(crate) fn join(socket: SockRef, group: IpAddr, interface: Option<&str>) -> io::Result<()> {
+    match (group, interface) {
+        (IpAddr::V4(group), None) => {
+            socket.join_multicast_v4(&group, &std::net::Ipv4Addr::UNSPECIFIED)
+        }
+        #[cfg(target_os = "linux")]
+        (IpAddr::V4(group), Some(interface)) => socket.join_multicast_v4_n(
+            &group,
+            &socket2::InterfaceIndexOrAddress::Index(interface_index(interface)?),
+        ),
+        #[cfg(not(target_os = "linux"))]
+        (IpAddr::V4(_), Some(interface)) => Err(interface_unsupported(interface)),
+        (IpAddr::V6(group), None) => socket.join_multicast_v6(&group, 0),
+        (IpAddr::V6(group), Some(interface)) => {
+            socket.join_multicast_v6(&group, interface_index(interface)?)
+        }
+    }
+}
+
+/// Limit datagrams which are sent to a multicast group to crossing this many
+/// routers, where the system default of 1 keeps them on the local network.
+pub(crate) fn set_ttl(socket: SockRef, group: IpAddr, ttl: u32) -> io::Result<()> {
+    match group {
+        IpAddr::V4(_) => socket.set_multicast_ttl_v4(ttl),
+        IpAddr::V6(_) => socket.set_multicast_hops_v6(ttl),
+    }
+}
+
+/// Index of the network interface named `name`, such as `eth1`.
+#[cfg(target_os = "linux")]
+fn interface_index(name: &str) -> io::Result<u32> {
+    let missing = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no interface named {name}"),
+        )
+    };
+    let name = std::ffi::CString::new(name).map_err(|_| missing())?;
+    // SAFETY: the name is a valid NUL terminated string for the whole call.
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(missing()),
+        index => Ok(index),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn interface_index(name: &str) -> io::Result<u32> {
+    Err(interface_unsupported(name))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn interface_unsupported(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("cannot join a multicast group on interface {name}: only supported on Linux"),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use socket2::SockRef;
+    use tokio::net::UdpSocket;
+
+    #[tokio::test]
+    async fn join_and_send() {
+        let group = Ipv4Addr::new(239, 3, 3, 3);
+        let receiver = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.unwrap();
+        super::join(SockRef::from(&receiver), group.into(), None).unwrap();
+        let port = receiver.local_addr().unwrap().port();
+
+        let sender = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.unwrap();
+        super::set_ttl(SockRef::from(&sender), IpAddr::V4(group), 4).unwrap();
+        assert_eq!(sender.multicast_ttl_v4().unwrap(), 4);
+        sender.send_to(b"group", (group, port)).await.unwrap();
+
+        let mut buf = [0; 16];
+        let len = tokio::time::timeout(std::time::Duration::from_secs(1), receiver.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], b"group");
+
+        let err =
+            super::join(SockRef::from(&receiver), group.into(), Some("gn-missing0")).unwrap_err();
+        assert!(err.to_string().contains("gn-missing0"), "{err}");
+    }
+}
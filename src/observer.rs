@@ -0,0 +1,24 @@
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use crate::Report;
+
+/// Hooks into the write path of a [`crate::SocketManager`], allowing custom
+/// metrics or logging to be integrated without relying on the built-in
+/// [`crate::statistics::Statistics`].
+///
+/// Every method has a default implementation which does nothing, so only the
+/// hooks of interest need to be implemented. Hooks are called from the tasks
+/// performing writes, so they should return quickly.
+pub trait WriteObserver: Send + Sync {
+    /// A request to `addr` is about to be written.
+    fn on_request_start(&self, _addr: SocketAddr) {}
+
+    /// A request to `addr` wrote `bytes`, taking `elapsed` in total.
+    fn on_success(&self, _addr: SocketAddr, _bytes: u64, _elapsed: Duration) {}
+
+    /// A request to `addr` failed with `error` after `elapsed`.
+    fn on_failure(&self, _addr: SocketAddr, _error: &(dyn Error + 'static), _elapsed: Duration) {}
+
+    /// Called periodically while writing, with a snapshot of the run so far.
+    fn on_tick(&self, _report: &Report) {}
+}
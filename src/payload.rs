@@ -0,0 +1,369 @@
+use std::{
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use clap::ValueEnum;
+use rand::{
+    distr::{weighted::WeightedIndex, Distribution},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
+
+use crate::{statistics::Statistics, target::gcd};
+
+/// Generate `len` bytes of random data, which is the same for every run with
+/// the same `seed`.
+pub fn random_payload(len: usize, seed: Option<u64>) -> Vec<u8> {
+    let mut payload = vec![0; len];
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed).fill_bytes(&mut payload),
+        None => rand::fill(payload.as_mut_slice()),
+    }
+    payload
+}
+
+/// Bytes which a payload is padded with up to a fixed size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Padding {
+    #[default]
+    Zeros,
+    /// Random bytes, which are the same for every run with the same seed.
+    Random,
+}
+
+/// Pad the payload with bytes up to `len`, so every message is the same size
+/// on the wire while keeping its real content at the start.
+pub fn pad_payload(
+    mut payload: Vec<u8>,
+    len: usize,
+    padding: Padding,
+    seed: Option<u64>,
+) -> crate::Result<Vec<u8>> {
+    if payload.len() > len {
+        return Err(format!(
+            "payload of {} bytes is larger than the padded size of {len} bytes",
+            payload.len()
+        )
+        .into());
+    }
+    match padding {
+        Padding::Zeros => payload.resize(len, 0),
+        Padding::Random => payload.extend(random_payload(len - payload.len(), seed)),
+    }
+    Ok(payload)
+}
+
+/// How the payload of each request is chosen from a [`PayloadMix`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PayloadOrder {
+    /// Sample a class at random, by its weight.
+    #[default]
+    Random,
+    /// Cycle through the classes in order, sending as many consecutive
+    /// requests of each class as its weight, reduced by their common divisor.
+    Sequential,
+}
+
+/// A named payload which is chosen for a share of requests proportional to
+/// its weight.
+pub struct PayloadClass {
+    name: String,
+    weight: u32,
+    data: Vec<u8>,
+    stats: Arc<Statistics>,
+}
+
+impl PayloadClass {
+    pub fn new(name: impl Into<String>, weight: u32, data: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            weight,
+            data,
+            stats: Arc::new(Statistics::new()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// [`Statistics`] of the requests which were sent with this payload.
+    pub fn stats(&self) -> &Arc<Statistics> {
+        &self.stats
+    }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// A weighted mix of [`PayloadClass`]es, where the payload of each request is
+/// chosen from the classes by their weight in the [`PayloadOrder`].
+pub struct PayloadMix {
+    classes: Vec<PayloadClass>,
+    index: WeightedIndex<u32>,
+    order: PayloadOrder,
+    /// Indexes of the classes in the order they are sent when sequential.
+    schedule: Vec<usize>,
+    counter: AtomicUsize,
+}
+
+impl PayloadMix {
+    pub fn new(classes: Vec<PayloadClass>) -> crate::Result<Self> {
+        let index = WeightedIndex::new(classes.iter().map(|c| c.weight))
+            .map_err(|e| format!("invalid payload mix weights: {e}"))?;
+        let divisor = classes.iter().fold(0, |acc, c| gcd(acc, c.weight)).max(1);
+        let schedule = classes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, c)| std::iter::repeat_n(i, (c.weight / divisor) as usize))
+            .collect();
+        Ok(Self {
+            classes,
+            index,
+            order: PayloadOrder::default(),
+            schedule,
+            counter: AtomicUsize::new(0),
+        })
+    }
+
+    /// Choose the payload of each request in the given order, rather than
+    /// sampling at random.
+    pub fn with_order(mut self, order: PayloadOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Pad the payload of every class up to `len` bytes, as with
+    /// [`pad_payload`].
+    pub fn with_padding(
+        mut self,
+        len: usize,
+        padding: Padding,
+        seed: Option<u64>,
+    ) -> crate::Result<Self> {
+        for class in &mut self.classes {
+            let data = std::mem::take(&mut class.data);
+            class.data = pad_payload(data, len, padding, seed)
+                .map_err(|e| format!("unable to pad '{}': {e}", class.name))?;
+        }
+        Ok(self)
+    }
+
+    /// Build a mix with one equally weighted class per file, named by its
+    /// path, which are cycled through in order.
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> crate::Result<Self> {
+        let classes = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                let data = std::fs::read(path)
+                    .map_err(|e| format!("unable to read input file '{}': {e}", path.display()))?;
+                Ok(PayloadClass::new(path.display().to_string(), 1, data))
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(Self::new(classes)?.with_order(PayloadOrder::Sequential))
+    }
+
+    /// Build a mix from the weights of each class and their payload specs,
+    /// every weighted class must have a matching payload.
+    pub fn from_specs(weights: &[MixWeight], payloads: &[PayloadSpec]) -> crate::Result<Self> {
+        let classes = weights
+            .iter()
+            .map(|w| {
+                let payload = payloads
+                    .iter()
+                    .find(|p| p.name == w.name)
+                    .ok_or_else(|| format!("no payload provided for mix class '{}'", w.name))?;
+                Ok(PayloadClass::new(&w.name, w.weight, payload.load()?))
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        Self::new(classes)
+    }
+
+    /// Choose the payload class for the next request.
+    pub(crate) fn sample(&self) -> &PayloadClass {
+        let idx = match self.order {
+            PayloadOrder::Random => self.index.sample(&mut rand::rng()),
+            PayloadOrder::Sequential => {
+                let next = self.counter.fetch_add(1, Ordering::Relaxed);
+                self.schedule[next % self.schedule.len()]
+            }
+        };
+        &self.classes[idx]
+    }
+
+    pub fn classes(&self) -> &[PayloadClass] {
+        &self.classes
+    }
+}
+
+/// Weight of a named class within a mix, parsed from `name=weight`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MixWeight {
+    pub name: String,
+    pub weight: u32,
+}
+
+impl FromStr for MixWeight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, weight) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected a class in the form NAME=WEIGHT, got '{s}'"))?;
+        let weight = weight
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid weight for class '{name}': {e}"))?;
+        Ok(Self {
+            name: name.trim().to_string(),
+            weight,
+        })
+    }
+}
+
+/// Payload of a named class, parsed from `name=spec`.
+///
+/// The spec is either literal text or, when prefixed with `@`, the path to a
+/// file whose contents are used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadSpec {
+    pub name: String,
+    pub spec: String,
+}
+
+impl FromStr for PayloadSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, spec) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected a payload in the form NAME=PAYLOAD, got '{s}'"))?;
+        Ok(Self {
+            name: name.trim().to_string(),
+            spec: spec.to_string(),
+        })
+    }
+}
+
+impl PayloadSpec {
+    /// Load the bytes described by the spec.
+    pub fn load(&self) -> crate::Result<Vec<u8>> {
+        match self.spec.strip_prefix('@') {
+            Some(path) => std::fs::read(path)
+                .map_err(|e| format!("unable to read payload file '{path}': {e}").into()),
+            None => Ok(self.spec.as_bytes().to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        pad_payload, random_payload, MixWeight, Padding, PayloadClass, PayloadMix, PayloadOrder,
+        PayloadSpec,
+    };
+
+    #[test]
+    fn random() {
+        assert_eq!(random_payload(4096, None).len(), 4096);
+        assert_eq!(random_payload(64, Some(7)), random_payload(64, Some(7)));
+        assert_ne!(random_payload(64, Some(7)), random_payload(64, Some(8)));
+    }
+
+    #[test]
+    fn pad() {
+        let padded = pad_payload(b"HDR".to_vec(), 8, Padding::Zeros, None).unwrap();
+        assert_eq!(padded, b"HDR\0\0\0\0\0");
+        let padded = pad_payload(b"HDR".to_vec(), 512, Padding::Random, Some(1)).unwrap();
+        assert_eq!(padded.len(), 512);
+        assert!(padded.starts_with(b"HDR"));
+        assert_eq!(
+            padded,
+            pad_payload(b"HDR".to_vec(), 512, Padding::Random, Some(1)).unwrap()
+        );
+        assert!(pad_payload(b"too long".to_vec(), 4, Padding::Zeros, None).is_err());
+
+        let mix = PayloadMix::new(vec![
+            PayloadClass::new("a", 1, b"a".to_vec()),
+            PayloadClass::new("b", 1, b"bb".to_vec()),
+        ])
+        .unwrap()
+        .with_padding(4, Padding::Zeros, None)
+        .unwrap();
+        assert!(mix.classes().iter().all(|c| c.data().len() == 4));
+    }
+
+    #[test]
+    fn parse_mix() {
+        assert_eq!(
+            "small=80".parse::<MixWeight>().unwrap(),
+            MixWeight {
+                name: "small".to_string(),
+                weight: 80
+            }
+        );
+        assert!("small".parse::<MixWeight>().is_err());
+        assert!("small=big".parse::<MixWeight>().is_err());
+        assert_eq!(
+            "large=@/tmp/large.bin".parse::<PayloadSpec>().unwrap(),
+            PayloadSpec {
+                name: "large".to_string(),
+                spec: "@/tmp/large.bin".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn from_specs() {
+        let weights = vec!["small=80".parse().unwrap(), "large=20".parse().unwrap()];
+        let payloads = vec!["small=a".parse().unwrap(), "large=aaaa".parse().unwrap()];
+        let mix = PayloadMix::from_specs(&weights, &payloads).unwrap();
+        assert_eq!(mix.classes().len(), 2);
+        assert_eq!(mix.classes()[1].data(), b"aaaa");
+
+        let payloads = vec!["small=a".parse().unwrap()];
+        assert!(PayloadMix::from_specs(&weights, &payloads).is_err());
+
+        let zero = vec!["small=0".parse().unwrap()];
+        assert!(PayloadMix::from_specs(&zero, &payloads).is_err());
+    }
+
+    #[test]
+    fn sequential() {
+        let classes = vec![
+            PayloadClass::new("header", 20, b"h".to_vec()),
+            PayloadClass::new("data", 40, b"d".to_vec()),
+        ];
+        let mix = PayloadMix::new(classes)
+            .unwrap()
+            .with_order(PayloadOrder::Sequential);
+        let sent: Vec<_> = (0..6).map(|_| mix.sample().name()).collect();
+        assert_eq!(sent, ["header", "data", "data", "header", "data", "data"]);
+    }
+
+    #[test]
+    fn from_files() {
+        let dir = std::env::temp_dir().join(format!("gn-payload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, second) = (dir.join("first"), dir.join("second"));
+        std::fs::write(&first, b"1").unwrap();
+        std::fs::write(&second, b"22").unwrap();
+
+        let mix = PayloadMix::from_files(&[&first, &second]).unwrap();
+        let sent: Vec<_> = (0..4).map(|_| mix.sample().data().to_vec()).collect();
+        assert_eq!(
+            sent,
+            [b"1".to_vec(), b"22".to_vec(), b"1".to_vec(), b"22".to_vec()]
+        );
+
+        assert!(PayloadMix::from_files(&[dir.join("missing")]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::HashSet,
+    hash::{BuildHasher, BuildHasherDefault, DefaultHasher},
+    net::IpAddr,
+};
+
+/// Number of distinct peers which are counted exactly, beyond which they are
+/// estimated with a [`HyperLogLog`] to bound the memory of long runs.
+const EXACT_PEERS: usize = 10_000;
+
+/// Bits of the hash which select a register, giving 4096 registers and a
+/// standard error of about 1.6%.
+const PRECISION: u32 = 12;
+
+/// Number of distinct peers which a server has received from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCount {
+    pub count: u64,
+    /// Whether the count is estimated, as there were too many peers to keep.
+    pub approximate: bool,
+}
+
+/// Counter of distinct IP addresses, which is exact until it holds
+/// [`EXACT_PEERS`] addresses and then switches to an estimate.
+pub(crate) enum PeerCounter {
+    Exact(HashSet<IpAddr>),
+    Estimated(HyperLogLog),
+}
+
+impl Default for PeerCounter {
+    fn default() -> Self {
+        Self::Exact(HashSet::new())
+    }
+}
+
+impl PeerCounter {
+    pub(crate) fn record(&mut self, ip: IpAddr) {
+        match self {
+            Self::Exact(peers) => {
+                peers.insert(ip);
+                if peers.len() > EXACT_PEERS {
+                    let mut estimate = HyperLogLog::new();
+                    peers.iter().for_each(|ip| estimate.insert(ip));
+                    *self = Self::Estimated(estimate);
+                }
+            }
+            Self::Estimated(estimate) => estimate.insert(&ip),
+        }
+    }
+
+    pub(crate) fn count(&self) -> PeerCount {
+        match self {
+            Self::Exact(peers) => PeerCount {
+                count: peers.len() as u64,
+                approximate: false,
+            },
+            Self::Estimated(estimate) => PeerCount {
+                count: estimate.estimate(),
+                approximate: true,
+            },
+        }
+    }
+}
+
+/// Estimator of the number of distinct values in fixed memory, from the
+/// longest run of leading zeros among the hashes of each register.
+pub(crate) struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << PRECISION],
+        }
+    }
+
+    fn insert(&mut self, ip: &IpAddr) {
+        // The default hasher uses fixed keys, so estimates are reproducible.
+        let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(ip);
+        let index = (hash >> (64 - PRECISION)) as usize;
+        // Set a bit below the rest of the hash, which bounds the run of
+        // zeros when the rest is all zeros.
+        let rest = (hash << PRECISION) | (1 << (PRECISION - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&r| 2f64.powi(-i32::from(r)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let empty = self.registers.iter().filter(|&&r| r == 0).count();
+        // Small cardinalities are more accurately estimated from the number
+        // of registers which are still empty.
+        if estimate <= 2.5 * m && empty > 0 {
+            return (m * (m / empty as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::{PeerCount, PeerCounter};
+
+    #[test]
+    fn exact() {
+        let mut peers = PeerCounter::default();
+        for _ in 0..3 {
+            peers.record(IpAddr::V4(Ipv4Addr::LOCALHOST));
+            peers.record("::1".parse().unwrap());
+        }
+        assert_eq!(
+            peers.count(),
+            PeerCount {
+                count: 2,
+                approximate: false
+            }
+        );
+    }
+
+    #[test]
+    fn estimated() {
+        let mut peers = PeerCounter::default();
+        let distinct = 100_000u32;
+        for n in 0..distinct {
+            peers.record(IpAddr::V4(Ipv4Addr::from(n)));
+            // Repeated peers do not change the estimate.
+            peers.record(IpAddr::V4(Ipv4Addr::from(n / 2)));
+        }
+        let count = peers.count();
+        assert!(count.approximate);
+        let error = count.count.abs_diff(u64::from(distinct)) as f64 / f64::from(distinct);
+        assert!(error < 0.05, "estimated {} peers", count.count);
+    }
+}
@@ -1,12 +1,44 @@
 use std::fmt::Display;
 
 use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
-#[derive(Default, Clone, ValueEnum)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Protocol {
     #[default]
     Tcp,
     Udp,
+    /// TCP with a TLS handshake before writing.
+    Tls,
+    /// Stream oriented Unix domain socket.
+    Unix,
+    /// Datagram oriented Unix domain socket.
+    UnixDatagram,
+    /// WebSocket, sending the input of each request as a single message.
+    Ws,
+    /// WebSocket over TLS.
+    Wss,
+}
+
+impl Protocol {
+    /// Whether the protocol addresses sockets by path rather than by IP
+    /// address and port.
+    pub fn is_unix(&self) -> bool {
+        matches!(self, Self::Unix | Self::UnixDatagram)
+    }
+
+    /// Whether the protocol writes a stream of bytes over a connection,
+    /// rather than individual datagrams.
+    pub fn is_stream(&self) -> bool {
+        matches!(self, Self::Tcp | Self::Tls | Self::Unix)
+    }
+
+    /// Whether the protocol opens a WebSocket, over which the input is sent
+    /// as messages rather than written to the connection directly.
+    pub fn is_websocket(&self) -> bool {
+        matches!(self, Self::Ws | Self::Wss)
+    }
 }
 
 impl From<&str> for Protocol {
@@ -14,6 +46,11 @@ impl From<&str> for Protocol {
         match value {
             "tcp" | "TCP" => Self::Tcp,
             "udp" | "UDP" => Self::Udp,
+            "tls" | "TLS" => Self::Tls,
+            "unix" => Self::Unix,
+            "unix-datagram" => Self::UnixDatagram,
+            "ws" => Self::Ws,
+            "wss" => Self::Wss,
             _ => panic!("unsupported protocol: {value}"),
         }
     }
@@ -24,6 +61,11 @@ impl Display for Protocol {
         match self {
             Self::Tcp => write!(f, "tcp"),
             Self::Udp => write!(f, "udp"),
+            Self::Tls => write!(f, "tls"),
+            Self::Unix => write!(f, "unix"),
+            Self::UnixDatagram => write!(f, "unix-datagram"),
+            Self::Ws => write!(f, "ws"),
+            Self::Wss => write!(f, "wss"),
         }
     }
 }
@@ -0,0 +1,328 @@
+//! Export of a writer's statistics to a Prometheus Pushgateway.
+//!
+//! Each push replaces the metrics of the gateway's grouping key, given by the
+//! path of its URL, such as `http://localhost:9091/metrics/job/gn`, with the
+//! latest [`Report`] rendered in the Prometheus text format, alongside the
+//! connections which are open at the time.
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::Write,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{metrics::write_metric, Report, WriteObserver};
+
+/// Grouping key which metrics are pushed to when the URL has no path.
+const DEFAULT_PATH: &str = "/metrics/job/gn";
+
+/// Pushgateway which metrics are pushed to over HTTP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pushgateway {
+    /// Host and port of the gateway.
+    authority: String,
+    path: String,
+}
+
+impl FromStr for Pushgateway {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(rest) = s.strip_prefix("http://") else {
+            return Err(format!("{s} must be an http:// URL"));
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        if authority.is_empty() {
+            return Err(format!("{s} has no host"));
+        }
+        let authority = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{authority}:80")
+        };
+        let path = match path.trim_end_matches('/') {
+            "" => DEFAULT_PATH.to_string(),
+            path => path.to_string(),
+        };
+        Ok(Self { authority, path })
+    }
+}
+
+/// Connections which are open, each held by a request in flight, overall
+/// and by the target they are open to.
+#[derive(Debug, Clone, Default)]
+struct Connections {
+    total: u64,
+    /// Targets which any connection was opened to, including those with
+    /// none open now, so their series does not disappear between pushes.
+    by_target: BTreeMap<SocketAddr, u64>,
+}
+
+impl Connections {
+    fn open(&mut self, addr: SocketAddr) {
+        self.total += 1;
+        *self.by_target.entry(addr).or_default() += 1;
+    }
+
+    fn close(&mut self, addr: SocketAddr) {
+        self.total = self.total.saturating_sub(1);
+        if let Some(open) = self.by_target.get_mut(&addr) {
+            *open = open.saturating_sub(1);
+        }
+    }
+}
+
+impl Pushgateway {
+    /// Replace the metrics of the grouping key with those of the report. As
+    /// this is outside of a run, no connections are reported as open.
+    pub async fn push(&self, report: &Report) -> crate::Result<()> {
+        self.push_with(report, &Connections::default()).await
+    }
+
+    async fn push_with(&self, report: &Report, connections: &Connections) -> crate::Result<()> {
+        let body = render(report, connections);
+        let request = format!(
+            "PUT {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.path,
+            self.authority,
+            body.len()
+        );
+        let mut stream = TcpStream::connect(&self.authority).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        let response = String::from_utf8_lossy(&response);
+        let status = response.lines().next().unwrap_or_default();
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(format!("pushgateway responded with {status:?}").into()),
+        }
+    }
+
+    /// Observer which pushes the progress of a run at most once per interval,
+    /// in the background, logging any failed push to stderr.
+    pub fn observer(self, interval: Duration) -> PushObserver {
+        PushObserver {
+            gateway: Arc::new(self),
+            interval,
+            last: Mutex::new(None),
+            connections: Mutex::default(),
+        }
+    }
+}
+
+/// [`WriteObserver`] which periodically pushes the progress of a run to a
+/// [`Pushgateway`], including gauges of the connections which are open.
+pub struct PushObserver {
+    gateway: Arc<Pushgateway>,
+    interval: Duration,
+    /// When metrics were last pushed.
+    last: Mutex<Option<Instant>>,
+    connections: Mutex<Connections>,
+}
+
+impl WriteObserver for PushObserver {
+    fn on_request_start(&self, addr: SocketAddr) {
+        self.connections.lock().unwrap().open(addr);
+    }
+
+    fn on_success(&self, addr: SocketAddr, _bytes: u64, _elapsed: Duration) {
+        self.connections.lock().unwrap().close(addr);
+    }
+
+    fn on_failure(&self, addr: SocketAddr, _error: &(dyn Error + 'static), _elapsed: Duration) {
+        self.connections.lock().unwrap().close(addr);
+    }
+
+    fn on_tick(&self, report: &Report) {
+        {
+            let mut last = self.last.lock().unwrap();
+            if last.is_some_and(|at| at.elapsed() < self.interval) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        let gateway = Arc::clone(&self.gateway);
+        let report = report.clone();
+        let connections = self.connections.lock().unwrap().clone();
+        tokio::spawn(async move {
+            if let Err(e) = gateway.push_with(&report, &connections).await {
+                eprintln!("Unable to push metrics: {e}");
+            }
+        });
+    }
+}
+
+/// Render the throughput, error rate and latency percentiles of the report,
+/// and the connections which are open.
+fn render(report: &Report, connections: &Connections) -> String {
+    let mut out = String::new();
+    let mut metric =
+        |name, kind, help, value: String| write_metric(&mut out, name, kind, help, &value);
+    metric(
+        "gn_write_bytes_total",
+        "counter",
+        "Bytes written.",
+        report.total_bytes.to_string(),
+    );
+    metric(
+        "gn_write_requests_total",
+        "counter",
+        "Requests which were attempted.",
+        report.requests.to_string(),
+    );
+    metric(
+        "gn_write_failed_requests_total",
+        "counter",
+        "Requests which failed.",
+        report.failed_requests.to_string(),
+    );
+    metric(
+        "gn_write_throughput_bytes_per_second",
+        "gauge",
+        "Bytes written per second.",
+        report.throughput.to_string(),
+    );
+    let error_ratio = match report.requests {
+        0 => 0.0,
+        requests => report.failed_requests as f64 / requests as f64,
+    };
+    metric(
+        "gn_write_error_ratio",
+        "gauge",
+        "Share of requests which failed.",
+        error_ratio.to_string(),
+    );
+    metric(
+        "gn_write_open_connections",
+        "gauge",
+        "Connections which are open, each held by a request in flight.",
+        connections.total.to_string(),
+    );
+    if !connections.by_target.is_empty() {
+        let name = "gn_write_target_open_connections";
+        let _ = write!(
+            out,
+            "# HELP {name} Connections which are open to each target.\n# TYPE {name} gauge\n"
+        );
+        for (target, open) in &connections.by_target {
+            let _ = writeln!(out, "{name}{{target=\"{target}\"}} {open}");
+        }
+    }
+
+    let quantiles = [
+        ("0.5", report.latency_p50_us),
+        ("0.9", report.latency_p90_us),
+        ("0.99", report.latency_p99_us),
+        ("0.999", report.latency_p999_us),
+    ];
+    if quantiles.iter().any(|(_, value)| value.is_some()) {
+        let name = "gn_write_latency_microseconds";
+        let _ = write!(
+            out,
+            "# HELP {name} Latency of successful requests.\n# TYPE {name} gauge\n"
+        );
+        for (quantile, value) in quantiles {
+            if let Some(value) = value {
+                let _ = writeln!(out, "{name}{{quantile=\"{quantile}\"}} {value}");
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{render, Pushgateway};
+    use crate::{statistics::Statistics, Report, WriteObserver};
+
+    #[test]
+    fn parse() {
+        let gateway: Pushgateway = "http://localhost:9091/metrics/job/load/instance/a"
+            .parse()
+            .unwrap();
+        assert_eq!(gateway.authority, "localhost:9091");
+        assert_eq!(gateway.path, "/metrics/job/load/instance/a");
+
+        let gateway: Pushgateway = "http://gateway".parse().unwrap();
+        assert_eq!(gateway.authority, "gateway:80");
+        assert_eq!(gateway.path, "/metrics/job/gn");
+
+        assert!("https://gateway".parse::<Pushgateway>().is_err());
+        assert!("http:///metrics".parse::<Pushgateway>().is_err());
+    }
+
+    #[tokio::test]
+    async fn push() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let gateway: Pushgateway = format!("http://{addr}/metrics/job/test").parse().unwrap();
+        let received = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = String::new();
+            let mut buf = vec![0; 4096];
+            // The body ends with the last latency quantile.
+            while !request.contains("quantile=\"0.999\"") || !request.ends_with('\n') {
+                let len = stream.read(&mut buf).await.unwrap();
+                request.push_str(std::str::from_utf8(&buf[..len]).unwrap());
+            }
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+            request
+        });
+
+        let stats = Statistics::new();
+        stats.increment_total(100);
+        stats.record_success();
+        stats.record_failure();
+        stats.record_latency(std::time::Duration::from_micros(250));
+        gateway.push(&Report::from(&stats)).await.unwrap();
+
+        let request = received.await.unwrap();
+        assert!(request.starts_with("PUT /metrics/job/test HTTP/1.1\r\n"));
+        assert!(request.contains("\ngn_write_bytes_total 100\n"));
+        assert!(request.contains("\ngn_write_error_ratio 0.5\n"));
+        assert!(request.contains("\ngn_write_latency_microseconds{quantile=\"0.5\"} 250"));
+        // Nothing is open once the run has ended.
+        assert!(request.contains("\ngn_write_open_connections 0\n"));
+    }
+
+    #[test]
+    fn render_connections() {
+        let observer = "http://127.0.0.1:9091"
+            .parse::<Pushgateway>()
+            .unwrap()
+            .observer(std::time::Duration::from_secs(10));
+        let (a, b) = (
+            "127.0.0.1:5000".parse().unwrap(),
+            "[::1]:5000".parse().unwrap(),
+        );
+        observer.on_request_start(a);
+        observer.on_request_start(a);
+        observer.on_request_start(b);
+        observer.on_success(b, 5, std::time::Duration::ZERO);
+
+        let body = render(
+            &Report::from(&Statistics::new()),
+            &observer.connections.lock().unwrap(),
+        );
+        assert!(body.contains("\ngn_write_open_connections 2\n"));
+        assert!(body.contains("\ngn_write_target_open_connections{target=\"127.0.0.1:5000\"} 2\n"));
+        assert!(body.contains("\ngn_write_target_open_connections{target=\"[::1]:5000\"} 0\n"));
+    }
+}
@@ -0,0 +1,121 @@
+//! Python bindings, allowing runs to be orchestrated from Python.
+//!
+//! ```python
+//! import gn
+//!
+//! opts = gn.WriteOptions(count=100, concurrency=5)
+//! report = gn.SocketManager("127.0.0.1:5000", b"hello", "udp", opts).write()
+//! print(report.throughput)
+//! ```
+use std::net::SocketAddr;
+
+use clap::ValueEnum;
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{statistics::Statistics, Protocol, Report};
+
+/// Python wrapper around [`crate::WriteOptions`].
+#[pyclass(name = "WriteOptions", frozen, from_py_object)]
+#[derive(Clone)]
+struct WriteOptions(crate::WriteOptions);
+
+#[pymethods]
+impl WriteOptions {
+    /// Build options in the same way as the `gn write` flags.
+    #[new]
+    #[pyo3(signature = (count=1, duration=None, concurrency=None, rate=None))]
+    fn new(
+        count: u64,
+        duration: Option<&str>,
+        concurrency: Option<u64>,
+        rate: Option<u64>,
+    ) -> PyResult<Self> {
+        let duration = duration
+            .map(|d| d.parse::<humantime::Duration>())
+            .transpose()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let opts = match rate {
+            Some(0) => return Err(PyValueError::new_err("the rate must be at least 1")),
+            Some(rate) => crate::WriteOptions::from_rate(rate, count, duration),
+            None => crate::WriteOptions::from_flags(count, duration, concurrency),
+        };
+        if opts.is_unlimited() {
+            return Err(PyValueError::new_err(
+                "an unlimited run cannot be stopped, provide a count or duration",
+            ));
+        }
+        Ok(Self(opts))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+/// Python wrapper around [`crate::SocketManager`].
+#[pyclass(name = "SocketManager", frozen)]
+struct SocketManager {
+    host: SocketAddr,
+    input: Vec<u8>,
+    protocol: Protocol,
+    write_options: WriteOptions,
+}
+
+#[pymethods]
+impl SocketManager {
+    #[new]
+    #[pyo3(signature = (host, input, protocol="tcp", write_options=None))]
+    fn new(
+        host: &str,
+        input: Vec<u8>,
+        protocol: &str,
+        write_options: Option<WriteOptions>,
+    ) -> PyResult<Self> {
+        let host = host
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("invalid host '{host}': {e}")))?;
+        let protocol = Protocol::from_str(protocol, true).map_err(PyValueError::new_err)?;
+        Ok(Self {
+            host,
+            input,
+            protocol,
+            write_options: write_options.unwrap_or(WriteOptions(crate::WriteOptions::Count(1))),
+        })
+    }
+
+    /// Perform the writes, blocking until they are complete, and return the
+    /// resulting [`Report`].
+    fn write(&self, py: Python<'_>) -> PyResult<Report> {
+        py.detach(|| {
+            let runtime = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+            runtime.block_on(async {
+                let manager = crate::SocketManager::new(
+                    self.host,
+                    &self.input,
+                    self.protocol.clone(),
+                    self.write_options.0.clone(),
+                    Statistics::new(),
+                );
+                manager.write().await.map_err(|e| e.to_string())?;
+                Ok(manager.report())
+            })
+        })
+        .map_err(|e: String| PyValueError::new_err(e))
+    }
+}
+
+#[pymodule]
+fn gn(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<WriteOptions>()?;
+    m.add_class::<SocketManager>()?;
+    m.add_class::<Report>()?;
+    m.add_class::<crate::StopReason>()?;
+    m.add_class::<crate::LatencyPercentiles>()?;
+    m.add_class::<crate::CircuitEvent>()?;
+    m.add_class::<crate::CircuitState>()?;
+    m.add_class::<crate::LatencyOutlier>()?;
+    m.add_class::<crate::ClockOffset>()?;
+    m.add_class::<crate::TimelinePoint>()?;
+    m.add_class::<crate::Annotation>()?;
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::{io, time::Duration};
+
+use tokio::{net::TcpStream, time::Instant};
+
+use crate::{Endpoint, Protocol};
+
+/// Time between attempts to connect to a target which is not yet accepting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Wait until the target accepts a connection, polling with connects which
+/// are closed straight away, for at most `timeout`.
+///
+/// This lets a run start alongside the system under test, such as in CI,
+/// without its start-up counting as failed requests. Host names are resolved
+/// on every attempt, as they may only be registered once the target is up.
+pub async fn wait_for_target(
+    endpoint: &Endpoint,
+    protocol: &Protocol,
+    timeout: Duration,
+) -> crate::Result<()> {
+    if !protocol.is_stream() && !protocol.is_websocket() {
+        return Err(format!(
+            "cannot wait for a {protocol} target, which does not accept connections"
+        )
+        .into());
+    }
+    let deadline = Instant::now() + timeout;
+    loop {
+        let error = match tokio::time::timeout_at(deadline, connect(endpoint)).await {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "timed out connecting".to_string(),
+        };
+        if Instant::now() + POLL_INTERVAL >= deadline {
+            return Err(format!(
+                "{endpoint} did not accept a connection within {}: {error}",
+                humantime::format_duration(timeout)
+            )
+            .into());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn connect(endpoint: &Endpoint) -> io::Result<()> {
+    match endpoint {
+        Endpoint::Inet(addr) => TcpStream::connect(addr).await.map(drop),
+        Endpoint::Host(host) => TcpStream::connect(host.as_str()).await.map(drop),
+        #[cfg(unix)]
+        Endpoint::Unix(path) => crate::unix::connect(path).await.map(drop),
+        #[cfg(not(unix))]
+        Endpoint::Unix(path) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} is a Unix socket", path.display()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, time::Duration};
+
+    use tokio::net::TcpListener;
+
+    use super::wait_for_target;
+    use crate::{Endpoint, Protocol};
+
+    #[tokio::test]
+    async fn wait_for_target_accepting() {
+        // Reserve a port, then only listen on it after a delay.
+        let addr: SocketAddr = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let listen = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let listener = TcpListener::bind(addr).await.unwrap();
+            listener.accept().await.unwrap();
+        });
+
+        wait_for_target(
+            &Endpoint::Inet(addr),
+            &Protocol::Tcp,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        listen.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_for_target_timeout() {
+        let addr: SocketAddr = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let err = wait_for_target(
+            &Endpoint::Inet(addr),
+            &Protocol::Tcp,
+            Duration::from_millis(250),
+        )
+        .await
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("did not accept a connection within 250ms"));
+
+        assert!(wait_for_target(
+            &Endpoint::Inet(addr),
+            &Protocol::Udp,
+            Duration::from_secs(1)
+        )
+        .await
+        .is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Reloading the `[serve]` table of a config file while a [`crate::Server`]
+//! is running, so a long capture does not have to be restarted to change how
+//! it responds or where it records to.
+//!
+//! The file is read again every second, as the modification time of files
+//! written in quick succession can be the same. Of the flags in the
+//! table, `respond-script`, `capture` and `delay` are applied as soon as they
+//! change, including being removed, while changes to any other flag are
+//! logged as needing a restart.
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+/// Interval at which the config file is read for modifications.
+const RELOAD_POLL: Duration = Duration::from_secs(1);
+
+/// Table of the config file holding the flags of `gn serve`.
+const SERVE_TABLE: &str = "serve";
+
+/// A change to the `[serve]` table of a config file.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Change {
+    /// Respond from the script at the path, or stop responding.
+    RespondScript(Option<PathBuf>),
+    /// Capture to a new file at the path, or stop capturing.
+    Capture(Option<PathBuf>),
+    /// Delay replies as the text says, e.g. `10ms..100ms`, or stop delaying.
+    Delay(Option<String>),
+    /// A flag whose change is not applied, and why.
+    Ignored { flag: String, reason: &'static str },
+}
+
+/// Watcher of a config file, producing the [`Change`]s made to it.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    /// Contents of the file when it was last read, or `None` when it could
+    /// not be, so an error is only returned once.
+    contents: Option<String>,
+    serve: toml::Table,
+    /// Interval the file is read at, which is kept between calls so that
+    /// waiting for changes can be cancelled without delaying the next read.
+    poll: Option<Interval>,
+    /// Flags which were given on the command line or through the environment,
+    /// so take precedence over the file.
+    pinned: Vec<String>,
+}
+
+impl ConfigWatcher {
+    /// Watch the config file at the path, whose current contents are what
+    /// the server was started with.
+    pub fn new(path: impl Into<PathBuf>) -> crate::Result<Self> {
+        let path = path.into();
+        let contents = std::fs::read_to_string(&path)?;
+        Ok(Self {
+            serve: parse_serve(&contents)?,
+            contents: Some(contents),
+            poll: None,
+            path,
+            pinned: Vec::new(),
+        })
+    }
+
+    /// Ignore changes to the flag, e.g. `capture`, as it was given elsewhere
+    /// and takes precedence over the file.
+    pub fn pin(mut self, flag: impl Into<String>) -> Self {
+        self.pinned.push(flag.into());
+        self
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Wait until the file is modified, returning how its `[serve]` table
+    /// changed. A file which cannot be read or parsed is an error, leaving
+    /// the previous contents in place.
+    pub(crate) async fn changed(&mut self) -> crate::Result<Vec<Change>> {
+        loop {
+            self.poll.get_or_insert_with(poll).tick().await;
+            let contents = match std::fs::read_to_string(&self.path) {
+                Ok(contents) if self.contents.as_ref() == Some(&contents) => continue,
+                Ok(contents) => contents,
+                Err(_) if self.contents.is_none() => continue,
+                Err(e) => {
+                    self.contents = None;
+                    return Err(e.into());
+                }
+            };
+            let serve = parse_serve(&contents);
+            self.contents = Some(contents);
+            let serve = serve?;
+            let changes = self.diff(&serve);
+            self.serve = serve;
+            if !changes.is_empty() {
+                return Ok(changes);
+            }
+        }
+    }
+
+    fn diff(&self, serve: &toml::Table) -> Vec<Change> {
+        let mut flags: Vec<&String> = self.serve.keys().chain(serve.keys()).collect();
+        flags.sort();
+        flags.dedup();
+
+        let mut changes = Vec::new();
+        for flag in flags {
+            let value = serve.get(flag);
+            if self.serve.get(flag) == value {
+                continue;
+            }
+            if self.pinned.contains(flag) {
+                changes.push(Change::Ignored {
+                    flag: flag.clone(),
+                    reason: "it was given on the command line or environment",
+                });
+                continue;
+            }
+            let text = value.and_then(toml::Value::as_str);
+            changes.push(match flag.as_str() {
+                "respond-script" => Change::RespondScript(text.map(PathBuf::from)),
+                "capture" => Change::Capture(text.map(PathBuf::from)),
+                "delay" => Change::Delay(text.map(String::from)),
+                _ => Change::Ignored {
+                    flag: flag.clone(),
+                    reason: "it only takes effect after a restart",
+                },
+            });
+        }
+        changes
+    }
+}
+
+/// Interval of reading the file, starting a poll from now.
+fn poll() -> Interval {
+    let mut poll = tokio::time::interval_at(Instant::now() + RELOAD_POLL, RELOAD_POLL);
+    poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    poll
+}
+
+fn parse_serve(contents: &str) -> crate::Result<toml::Table> {
+    let mut table: toml::Table = toml::from_str(contents)?;
+    match table.remove(SERVE_TABLE) {
+        Some(toml::Value::Table(serve)) => Ok(serve),
+        Some(_) => Err(format!("[{SERVE_TABLE}] is not a table").into()),
+        None => Ok(toml::Table::new()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{Change, ConfigWatcher};
+
+    #[test]
+    fn diff() {
+        let path = std::env::temp_dir().join(format!("gn-reload-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "pin-cores = \"0\"\n[serve]\ncapture = \"a.gncap\"\nbacklog = 10\n",
+        )
+        .unwrap();
+        let watcher = ConfigWatcher::new(&path).unwrap().pin("protocol");
+        std::fs::remove_file(&path).unwrap();
+
+        let serve = toml::from_str(
+            "respond-script = \"rules.toml\"\nbacklog = 20\nprotocol = \"udp\"\ndelay = \"5ms\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            watcher.diff(&serve),
+            [
+                Change::Ignored {
+                    flag: "backlog".into(),
+                    reason: "it only takes effect after a restart"
+                },
+                Change::Capture(None),
+                Change::Delay(Some("5ms".into())),
+                Change::Ignored {
+                    flag: "protocol".into(),
+                    reason: "it was given on the command line or environment"
+                },
+                Change::RespondScript(Some(PathBuf::from("rules.toml"))),
+            ]
+        );
+        assert!(watcher.diff(&watcher.serve).is_empty());
+    }
+}
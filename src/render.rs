@@ -0,0 +1,132 @@
+//! Rendering of the messages a [`crate::Server`] receives to its output.
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How each received message is written to the server's output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Render {
+    /// A line of UTF-8, where invalid sequences are replaced with U+FFFD.
+    #[default]
+    Utf8Lossy,
+    /// A line of lowercase hex, two digits per byte.
+    Hex,
+    /// A line of standard, padded, base64.
+    Base64,
+    /// The bytes exactly as they were received, without a trailing newline.
+    Raw,
+    /// Lines of the offset, up to 16 bytes in hex and those bytes as ASCII,
+    /// ending with the length of the message, as with `hexdump -C`.
+    Hexdump,
+}
+
+impl Render {
+    /// Write the message to the output in this form.
+    pub fn write(&self, out: &mut impl Write, message: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Utf8Lossy => writeln!(out, "{}", String::from_utf8_lossy(message)),
+            Self::Hex => {
+                let mut line = Vec::with_capacity(message.len() * 2 + 1);
+                for byte in message {
+                    line.push(HEX[usize::from(byte >> 4)]);
+                    line.push(HEX[usize::from(byte & 0xf)]);
+                }
+                line.push(b'\n');
+                out.write_all(&line)
+            }
+            Self::Base64 => {
+                let mut line = base64(message);
+                line.push(b'\n');
+                out.write_all(&line)
+            }
+            Self::Raw => out.write_all(message),
+            Self::Hexdump => out.write_all(hexdump(message).as_bytes()),
+        }
+    }
+}
+
+fn hexdump(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(16) * 78 + 9);
+    for (line, chunk) in data.chunks(16).enumerate() {
+        out.push_str(&format!("{:08x}  ", line * 16));
+        for i in 0..16 {
+            match chunk.get(i) {
+                Some(byte) => out.push_str(&format!("{byte:02x} ")),
+                None => out.push_str("   "),
+            }
+            // The two halves of the line are split by an extra space.
+            if i == 7 {
+                out.push(' ');
+            }
+        }
+        out.push_str(" |");
+        out.extend(chunk.iter().map(|&byte| match byte {
+            0x20..=0x7e => char::from(byte),
+            _ => '.',
+        }));
+        out.push_str("|\n");
+    }
+    out.push_str(&format!("{:08x}\n", data.len()));
+    out
+}
+
+fn base64(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len().div_ceil(3) * 4 + 1);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | u32::from(*byte) << (16 - 8 * i)
+        });
+        // A chunk of n bytes is n + 1 characters, padded to 4 with '='.
+        for i in 0..4 {
+            out.push(match i <= chunk.len() {
+                true => BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize],
+                false => b'=',
+            });
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::Render;
+
+    fn render(render: Render, message: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        render.write(&mut out, message).unwrap();
+        out
+    }
+
+    #[test]
+    fn write() {
+        let message = b"gn\xff\x00";
+        assert_eq!(
+            render(Render::Utf8Lossy, message),
+            "gn\u{fffd}\0\n".as_bytes()
+        );
+        assert_eq!(render(Render::Hex, message), b"676eff00\n");
+        assert_eq!(render(Render::Raw, message), message);
+        assert_eq!(
+            String::from_utf8(render(Render::Hexdump, b"GET / HTTP/1.1\r\n\xff\x00")).unwrap(),
+            "00000000  47 45 54 20 2f 20 48 54  54 50 2f 31 2e 31 0d 0a  |GET / HTTP/1.1..|\n\
+             00000010  ff 00                                             |..|\n\
+             00000012\n"
+        );
+        assert_eq!(render(Render::Hexdump, b""), b"00000000\n");
+
+        for (message, encoded) in [
+            (&b""[..], &b"\n"[..]),
+            (b"f", b"Zg==\n"),
+            (b"fo", b"Zm8=\n"),
+            (b"foo", b"Zm9v\n"),
+            (b"foob", b"Zm9vYg==\n"),
+            (message, b"Z27/AA==\n"),
+        ] {
+            assert_eq!(render(Render::Base64, message), encoded);
+        }
+    }
+}
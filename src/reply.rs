@@ -0,0 +1,263 @@
+use std::{
+    fmt::Display,
+    io::ErrorKind,
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    time::Instant,
+};
+
+use crate::{
+    size::ByteSize,
+    transport::{write_counted, Unacknowledged},
+};
+
+/// Size of each read while looking for the delimiter of a reply.
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Where the reply to a request ends, parsed from `eof`, a size such as `128`
+/// or `1KiB`, or `delimiter:TEXT`, where the text may contain `\n`, `\r`,
+/// `\t` and `\\` escapes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ReplyFraming {
+    /// The request is half-closed and the reply is read until the server
+    /// closes the connection.
+    #[default]
+    Eof,
+    /// The reply is this many bytes, leaving the connection open.
+    Bytes(usize),
+    /// The reply ends with the delimiter, leaving the connection open.
+    Delimiter(Vec<u8>),
+}
+
+impl FromStr for ReplyFraming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "eof" {
+            return Ok(Self::Eof);
+        }
+        if let Some(text) = s.strip_prefix("delimiter:") {
+            let delimiter = unescape(text);
+            if delimiter.is_empty() {
+                return Err("expected text after delimiter:".to_string());
+            }
+            return Ok(Self::Delimiter(delimiter));
+        }
+        let size = s.parse::<ByteSize>().map_err(|e| {
+            format!("invalid reply '{s}': expected eof, a size or delimiter:TEXT ({e})")
+        })?;
+        match usize::try_from(size.0) {
+            Ok(0) | Err(_) => Err(format!("invalid reply size '{s}'")),
+            Ok(len) => Ok(Self::Bytes(len)),
+        }
+    }
+}
+
+impl Display for ReplyFraming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Eof => write!(f, "eof"),
+            Self::Bytes(len) => write!(f, "{len}"),
+            Self::Delimiter(delimiter) => write!(
+                f,
+                "delimiter:{}",
+                String::from_utf8_lossy(delimiter).escape_default()
+            ),
+        }
+    }
+}
+
+fn unescape(text: &str) -> Vec<u8> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(next @ ('n' | 'r' | 't' | '\\'))) => {
+                chars.next();
+                unescaped.push(match next {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    _ => '\\',
+                });
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+    unescaped.into_bytes()
+}
+
+/// The reply to a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    /// Number of bytes of the request which were written.
+    pub written: u64,
+    pub data: Vec<u8>,
+    /// Time from starting to write the request until the whole reply was
+    /// received, which excludes establishing the connection.
+    pub round_trip: Duration,
+    /// Time from starting to write the request until the first byte of the
+    /// reply was received, which is the round trip when the whole reply
+    /// arrives at once or is empty.
+    pub first_byte: Duration,
+}
+
+/// Reader which notes when it first reads any data.
+struct FirstByte<'a, S> {
+    inner: &'a mut S,
+    at: Option<Instant>,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FirstByte<'_, S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let polled = Pin::new(&mut *self.inner).poll_read(cx, buf);
+        if self.at.is_none() && buf.filled().len() > filled {
+            self.at = Some(Instant::now());
+        }
+        polled
+    }
+}
+
+/// Write the input to a stream and read the reply, which ends as given by
+/// the framing.
+pub(crate) async fn exchange_stream<S>(
+    stream: &mut S,
+    input: &[u8],
+    framing: &ReplyFraming,
+) -> crate::Result<Reply>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let start = Instant::now();
+    let written = write_counted(stream, input).await?;
+    if *framing == ReplyFraming::Eof {
+        // Half-close the stream so the server knows the message is complete.
+        stream.shutdown().await.map_err(Unacknowledged::new)?;
+    }
+    let stream = &mut FirstByte {
+        inner: stream,
+        at: None,
+    };
+    // Once the request is written, failing to read the reply leaves it
+    // unacknowledged.
+    let read = async {
+        let data = match framing {
+            ReplyFraming::Eof => {
+                let mut reply = Vec::new();
+                stream.read_to_end(&mut reply).await?;
+                reply
+            }
+            ReplyFraming::Bytes(len) => {
+                let mut reply = vec![0; *len];
+                stream.read_exact(&mut reply).await?;
+                reply
+            }
+            ReplyFraming::Delimiter(delimiter) => {
+                let mut reply = Vec::new();
+                let mut chunk = [0; READ_CHUNK_SIZE];
+                loop {
+                    let n = stream.read(&mut chunk).await?;
+                    if n == 0 {
+                        return Err(std::io::Error::new(
+                            ErrorKind::UnexpectedEof,
+                            "connection closed before the reply delimiter",
+                        )
+                        .into());
+                    }
+                    // The delimiter may span the previous read.
+                    let from = reply.len().saturating_sub(delimiter.len() - 1);
+                    reply.extend_from_slice(&chunk[..n]);
+                    if let Some(at) = reply[from..]
+                        .windows(delimiter.len())
+                        .position(|w| w == delimiter.as_slice())
+                    {
+                        reply.truncate(from + at + delimiter.len());
+                        break reply;
+                    }
+                }
+            }
+        };
+        crate::Result::Ok(data)
+    };
+    let data = read.await.map_err(Unacknowledged::new)?;
+    let round_trip = start.elapsed();
+    Ok(Reply {
+        written,
+        data,
+        round_trip,
+        first_byte: stream.at.map_or(round_trip, |at| at - start),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{exchange_stream, ReplyFraming};
+
+    #[test]
+    fn parse() {
+        assert_eq!("eof".parse::<ReplyFraming>().unwrap(), ReplyFraming::Eof);
+        assert_eq!(
+            "1KiB".parse::<ReplyFraming>().unwrap(),
+            ReplyFraming::Bytes(1024)
+        );
+        assert_eq!(
+            "delimiter:\\r\\n".parse::<ReplyFraming>().unwrap(),
+            ReplyFraming::Delimiter(b"\r\n".to_vec())
+        );
+        assert_eq!(
+            "delimiter:END".parse::<ReplyFraming>().unwrap(),
+            ReplyFraming::Delimiter(b"END".to_vec())
+        );
+        assert!("0".parse::<ReplyFraming>().is_err());
+        assert!("delimiter:".parse::<ReplyFraming>().is_err());
+        assert!("lines".parse::<ReplyFraming>().is_err());
+    }
+
+    #[tokio::test]
+    async fn exchange() {
+        for (framing, expected) in [
+            (ReplyFraming::Bytes(3), b"PON".to_vec()),
+            (
+                ReplyFraming::Delimiter(b"\r\n".to_vec()),
+                b"PONG\r\n".to_vec(),
+            ),
+            (ReplyFraming::Eof, b"PONG\r\nextra".to_vec()),
+        ] {
+            let (mut client, mut server) = tokio::io::duplex(64);
+            let echo = tokio::spawn(async move {
+                let mut request = [0; 4];
+                server.read_exact(&mut request).await.unwrap();
+                // Reply in pieces, so the delimiter spans two reads.
+                server.write_all(b"PONG\r").await.unwrap();
+                tokio::task::yield_now().await;
+                server.write_all(b"\nextra").await.unwrap();
+            });
+            let reply = exchange_stream(&mut client, b"PING", &framing)
+                .await
+                .unwrap();
+            assert_eq!(reply.written, 4);
+            assert_eq!(reply.data, expected, "{framing}");
+            assert!(reply.first_byte <= reply.round_trip);
+            echo.await.unwrap();
+        }
+
+        // The server closing before the delimiter is an error.
+        let (mut client, server) = tokio::io::duplex(64);
+        drop(server);
+        let delimiter = ReplyFraming::Delimiter(b"\n".to_vec());
+        assert!(exchange_stream(&mut client, b"", &delimiter).await.is_err());
+    }
+}
@@ -0,0 +1,1056 @@
+use std::{
+    hash::{BuildHasher, BuildHasherDefault, DefaultHasher},
+    net::SocketAddr,
+    str::FromStr,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    resources::ResourceUsage,
+    statistics::{Statistics, StatisticsSnapshot},
+};
+
+/// Formats a [`Report`] can be rendered to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+    Markdown,
+    /// Percentile distribution in the HdrHistogram `.hgrm` text format.
+    Hgrm,
+    /// Self-contained page with charts of the time series, latency
+    /// percentiles and failures.
+    Html,
+}
+
+/// Why a write run stopped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(eq, eq_int, frozen, skip_from_py_object)
+)]
+pub enum StopReason {
+    /// The configured count or duration was reached.
+    #[default]
+    Completed,
+    /// The run was stopped early, such as through Ctrl-C.
+    Stopped,
+    /// The maximum number of failed requests was reached.
+    MaxFailures,
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Completed => write!(f, "completed"),
+            Self::Stopped => write!(f, "stopped"),
+            Self::MaxFailures => write!(f, "max_failures"),
+        }
+    }
+}
+
+/// How the latency percentiles of a report were calculated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(eq, eq_int, frozen, skip_from_py_object)
+)]
+pub enum LatencyPercentiles {
+    /// Approximated from a histogram, to 3 significant figures.
+    #[default]
+    Histogram,
+    /// Calculated from every recorded latency.
+    Exact,
+}
+
+impl std::fmt::Display for LatencyPercentiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Histogram => write!(f, "histogram"),
+            Self::Exact => write!(f, "exact"),
+        }
+    }
+}
+
+/// State a circuit breaker moved to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(eq, eq_int, frozen, skip_from_py_object)
+)]
+pub enum CircuitState {
+    /// Requests to the address were paused after consecutive failures.
+    Open,
+    /// Requests to the address succeeded again.
+    Closed,
+}
+
+impl std::fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Open => write!(f, "open"),
+            Self::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+/// A circuit breaker of an address opening or closing during a run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(get_all, frozen, skip_from_py_object)
+)]
+pub struct CircuitEvent {
+    pub addr: String,
+    pub state: CircuitState,
+    /// Time since the run started, in milliseconds.
+    pub at_ms: u128,
+}
+
+/// Offset of a server's clock from the writer's, estimated before the run
+/// from the calibration probe with the shortest round trip.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(get_all, frozen, skip_from_py_object)
+)]
+pub struct ClockOffset {
+    pub addr: String,
+    /// How far the server's clock is ahead of the writer's, which is
+    /// subtracted from every one-way delay to it.
+    pub offset_us: f64,
+    /// Round trip of the probe, excluding the time the server held it.
+    pub round_trip_us: f64,
+}
+
+/// Totals of a run at one point during it, recorded every second when a
+/// time series was requested.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(get_all, frozen, skip_from_py_object)
+)]
+pub struct TimelinePoint {
+    /// Time since the run started, in milliseconds.
+    pub elapsed_ms: u64,
+    pub total_bytes: u64,
+    pub requests: u64,
+    pub failed_requests: u64,
+    /// Latency percentiles of every request up to this point.
+    pub latency_p50_us: Option<f64>,
+    pub latency_p99_us: Option<f64>,
+    /// Messages of the annotations which were reached since the previous
+    /// point.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<String>,
+}
+
+impl From<&StatisticsSnapshot> for TimelinePoint {
+    fn from(snapshot: &StatisticsSnapshot) -> Self {
+        Self {
+            elapsed_ms: u64::try_from(snapshot.elapsed.as_millis()).unwrap_or(u64::MAX),
+            total_bytes: snapshot.bytes,
+            requests: snapshot.requests,
+            failed_requests: snapshot.failures,
+            latency_p50_us: snapshot.latency.map(|l| l.p50 as f64 / 1000.0),
+            latency_p99_us: snapshot.latency.map(|l| l.p99 as f64 / 1000.0),
+            annotations: Vec::new(),
+        }
+    }
+}
+
+/// Labelled marker at a point of a run, such as `60s=deployed v2`, so changes
+/// in its throughput can be correlated with events outside of it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(get_all, frozen, skip_from_py_object)
+)]
+pub struct Annotation {
+    /// Time since the run started, in milliseconds.
+    pub at_ms: u64,
+    pub message: String,
+}
+
+impl FromStr for Annotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (at, message) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected an annotation in the form TIME=MESSAGE, got '{s}'"))?;
+        let at = humantime::parse_duration(at.trim())
+            .map_err(|e| format!("invalid time of annotation '{s}': {e}"))?;
+        Ok(Self {
+            at_ms: u64::try_from(at.as_millis()).unwrap_or(u64::MAX),
+            message: message.to_string(),
+        })
+    }
+}
+
+/// Number of leading payload bytes kept by a [`LatencyOutlier`].
+const OUTLIER_PREFIX_LEN: usize = 16;
+
+/// A successful request which took longer than the outlier threshold, with a
+/// digest of its payload to correlate slowness with message contents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(get_all, frozen, skip_from_py_object)
+)]
+pub struct LatencyOutlier {
+    pub addr: String,
+    /// Time the request started, in nanoseconds since the UNIX epoch.
+    pub started_at_ns: u64,
+    pub latency_us: u64,
+    /// Length of the payload, in bytes.
+    pub payload_len: u64,
+    /// Hash of the whole payload, in hex.
+    pub payload_hash: String,
+    /// Leading bytes of the payload, with non-printable bytes escaped.
+    pub payload_prefix: String,
+}
+
+impl LatencyOutlier {
+    pub(crate) fn new(
+        addr: SocketAddr,
+        started_at_ns: u64,
+        latency: Duration,
+        payload: &[u8],
+    ) -> Self {
+        // The default hasher uses fixed keys, so digests match across runs.
+        let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(payload);
+        Self {
+            addr: addr.to_string(),
+            started_at_ns,
+            latency_us: u64::try_from(latency.as_micros()).unwrap_or(u64::MAX),
+            payload_len: payload.len() as u64,
+            payload_hash: format!("{hash:016x}"),
+            payload_prefix: payload[..payload.len().min(OUTLIER_PREFIX_LEN)]
+                .escape_ascii()
+                .to_string(),
+        }
+    }
+}
+
+/// Point in time summary of a write run, suitable for serialization.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(get_all, frozen, skip_from_py_object)
+)]
+pub struct Report {
+    /// Total number of bytes written.
+    pub total_bytes: u64,
+    /// Bytes written per second.
+    pub throughput: f64,
+    /// Number of requests which were attempted.
+    pub requests: u64,
+    pub successful_requests: u64,
+    pub failed_requests: u64,
+    /// Failed requests which had written part of their input, which still
+    /// counts towards the total bytes.
+    #[serde(default)]
+    pub partial_writes: u64,
+    /// Failed requests which were written, but whose response did not match
+    /// what was expected.
+    #[serde(default)]
+    pub mismatched_responses: u64,
+    /// Failed requests which the local network stack refused as the send
+    /// buffer was full (`EAGAIN`), so never left the host.
+    #[serde(default)]
+    pub would_block: u64,
+    /// Failed requests which the local network stack dropped as it had no
+    /// buffer space left (`ENOBUFS`), so never left the host.
+    #[serde(default)]
+    pub no_buffer_space: u64,
+    /// Failed requests which timed out while connecting or writing.
+    #[serde(default)]
+    pub timeouts: u64,
+    /// Failed requests which were written in full, but whose reply, or the
+    /// peer closing the connection, never arrived.
+    #[serde(default)]
+    pub unacknowledged_requests: u64,
+    /// Failed requests which could not be sent, whether connecting or
+    /// writing them failed.
+    #[serde(default)]
+    pub send_failures: u64,
+    /// Failed requests whose connection the peer reset or aborted.
+    #[serde(default)]
+    pub connection_resets: u64,
+    /// Failed requests whose connection the peer closed before the whole
+    /// reply was received.
+    #[serde(default)]
+    pub premature_closes: u64,
+    /// Attempts which were made again after a request failed, whether or
+    /// not the request eventually succeeded.
+    #[serde(default)]
+    pub retries: u64,
+    /// Retries which followed attempts that failed to connect, so none of
+    /// the request had been written.
+    #[serde(default)]
+    pub connect_retries: u64,
+    /// Retries which followed attempts that failed once connected, which may
+    /// have delivered the request more than once.
+    #[serde(default)]
+    pub write_retries: u64,
+    /// Attempts to resolve a host which failed, which are not counted as
+    /// requests.
+    #[serde(default)]
+    pub resolution_failures: u64,
+    /// Largest number of bytes sampled in a UDP send queue.
+    #[serde(default)]
+    pub send_queue_peak_bytes: u64,
+    /// Number of TCP connections which were opened, which is lower than the
+    /// number of requests when connections are kept alive.
+    #[serde(default)]
+    pub connections_opened: u64,
+    /// Kept alive connections which were closed and re-established once they
+    /// reached their lifetime.
+    #[serde(default)]
+    pub connections_recycled: u64,
+    /// Bytes which the server pushed down duplex connections, and how many
+    /// were read per second, alongside the bytes written.
+    #[serde(default)]
+    pub inbound_bytes: u64,
+    #[serde(default)]
+    pub inbound_throughput: f64,
+    /// Requests which waited for their payload from the generator threads,
+    /// and the milliseconds they waited for in total, when generating could
+    /// not keep up with sending.
+    #[serde(default)]
+    pub generator_waits: u64,
+    #[serde(default)]
+    pub generator_wait_ms: f64,
+    /// TLS handshakes which were completed in full or by resuming the
+    /// session of an earlier connection.
+    #[serde(default)]
+    pub tls_full_handshakes: u64,
+    #[serde(default)]
+    pub tls_resumed_handshakes: u64,
+    /// Duration of TLS handshakes in microseconds, when any completed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_handshake_p50_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_handshake_p99_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_handshake_max_us: Option<f64>,
+    pub success_percentage: f64,
+    /// Time elapsed since the [`Statistics`] were created, in milliseconds.
+    pub elapsed_ms: u128,
+    #[serde(default)]
+    pub stop_reason: StopReason,
+    /// Estimated one-way delays in microseconds, when timing was reflected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub one_way_delay_min_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub one_way_delay_mean_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub one_way_delay_max_us: Option<f64>,
+    /// Latencies of successful requests in microseconds, when any succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_min_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_mean_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_p50_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_p90_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_p99_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_p999_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_max_us: Option<f64>,
+    #[serde(default)]
+    pub latency_percentiles: LatencyPercentiles,
+    /// Total bytes received in replies, when replies were expected.
+    #[serde(default)]
+    pub reply_bytes: u64,
+    /// Round trips of replies in microseconds, from starting to write each
+    /// request until its whole reply was received.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_p50_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_p90_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_p99_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_max_us: Option<f64>,
+    /// Time to the first byte of replies in microseconds, from starting to
+    /// write each request until any of its reply was received.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttfb_p50_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttfb_p90_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttfb_p99_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttfb_max_us: Option<f64>,
+    /// Requests per second which were requested with a fixed rate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_rate: Option<u64>,
+    /// Requests per second which were actually sent at a fixed rate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub achieved_rate: Option<f64>,
+    /// How late requests were sent compared to their fixed-rate schedule,
+    /// in microseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_lag_p50_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_lag_p99_us: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_lag_max_us: Option<f64>,
+    /// CPU used by the writer per second, where 100% is one core, when
+    /// resources were sampled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_avg_percent: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_peak_percent: Option<f64>,
+    /// Highest CPU used by a single thread of the writer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_cpu_peak_percent: Option<f64>,
+    /// Resident memory of the writer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_avg_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_peak_bytes: Option<u64>,
+    /// Circuit breakers which opened or closed, in the order they did so.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub circuit_events: Vec<CircuitEvent>,
+    /// Requests which were slower than the outlier threshold, in the order
+    /// they completed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub latency_outliers: Vec<LatencyOutlier>,
+    /// Clock offset of each server, when clocks were calibrated.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clock_offsets: Vec<ClockOffset>,
+    /// Totals of the run every second, when a time series was recorded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<TimelinePoint>,
+    /// Annotations which the run reached, in the order they were reached.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+    /// Count of latencies in each bucket of the histogram, as pairs of the
+    /// highest latency of the bucket in nanoseconds and its count, from
+    /// which the distribution is rendered as `.hgrm`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub latency_histogram: Vec<(u64, u64)>,
+}
+
+impl From<&Statistics> for Report {
+    fn from(stats: &Statistics) -> Self {
+        let delay = stats.one_way_delay();
+        let latency = stats.latency();
+        let round_trip = stats.round_trip();
+        let first_byte = stats.first_byte();
+        let handshake = stats.tls_handshake();
+        let schedule = stats.schedule();
+        let micros = |nanos: u64| nanos as f64 / 1000.0;
+        Self {
+            total_bytes: stats.total_bytes(),
+            throughput: stats.throughput(),
+            requests: stats.request_count(),
+            successful_requests: stats.successful_requests(),
+            failed_requests: stats.failed_requests(),
+            partial_writes: stats.partial_writes(),
+            mismatched_responses: stats.mismatched_responses(),
+            would_block: stats.would_block(),
+            no_buffer_space: stats.no_buffer_space(),
+            timeouts: stats.timeouts(),
+            unacknowledged_requests: stats.unacknowledged(),
+            send_failures: stats.send_failures(),
+            connection_resets: stats.connection_resets(),
+            premature_closes: stats.premature_closes(),
+            retries: stats.retries(),
+            connect_retries: stats.connect_retries(),
+            write_retries: stats.retries() - stats.connect_retries(),
+            resolution_failures: stats.resolution_failures(),
+            send_queue_peak_bytes: stats.send_queue_peak(),
+            connections_opened: stats.connections_opened(),
+            connections_recycled: stats.connections_recycled(),
+            inbound_bytes: stats.inbound_bytes(),
+            inbound_throughput: stats.inbound_throughput(),
+            generator_waits: stats.generator_waits(),
+            generator_wait_ms: stats.generator_wait().as_secs_f64() * 1000.0,
+            tls_full_handshakes: stats.tls_full_handshakes(),
+            tls_resumed_handshakes: stats.tls_resumed_handshakes(),
+            tls_handshake_p50_us: handshake.map(|h| micros(h.p50)),
+            tls_handshake_p99_us: handshake.map(|h| micros(h.p99)),
+            tls_handshake_max_us: handshake.map(|h| micros(h.max)),
+            success_percentage: stats.success_percentage(),
+            elapsed_ms: stats.elapsed(),
+            stop_reason: StopReason::default(),
+            one_way_delay_min_us: delay.map(|d| d.min as f64 / 1000.0),
+            one_way_delay_mean_us: delay.map(|d| d.mean / 1000.0),
+            one_way_delay_max_us: delay.map(|d| d.max as f64 / 1000.0),
+            latency_min_us: latency.map(|l| micros(l.min)),
+            latency_mean_us: latency.map(|l| l.mean / 1000.0),
+            latency_p50_us: latency.map(|l| micros(l.p50)),
+            latency_p90_us: latency.map(|l| micros(l.p90)),
+            latency_p99_us: latency.map(|l| micros(l.p99)),
+            latency_p999_us: latency.map(|l| micros(l.p999)),
+            latency_max_us: latency.map(|l| micros(l.max)),
+            latency_percentiles: match latency {
+                Some(l) if l.exact => LatencyPercentiles::Exact,
+                _ => LatencyPercentiles::Histogram,
+            },
+            reply_bytes: stats.reply_bytes(),
+            rtt_p50_us: round_trip.map(|r| micros(r.p50)),
+            rtt_p90_us: round_trip.map(|r| micros(r.p90)),
+            rtt_p99_us: round_trip.map(|r| micros(r.p99)),
+            rtt_max_us: round_trip.map(|r| micros(r.max)),
+            ttfb_p50_us: first_byte.map(|f| micros(f.p50)),
+            ttfb_p90_us: first_byte.map(|f| micros(f.p90)),
+            ttfb_p99_us: first_byte.map(|f| micros(f.p99)),
+            ttfb_max_us: first_byte.map(|f| micros(f.max)),
+            requested_rate: None,
+            achieved_rate: schedule.and_then(|s| s.achieved_rate),
+            send_lag_p50_us: schedule.map(|s| micros(s.lag_p50)),
+            send_lag_p99_us: schedule.map(|s| micros(s.lag_p99)),
+            send_lag_max_us: schedule.map(|s| micros(s.lag_max)),
+            cpu_avg_percent: None,
+            cpu_peak_percent: None,
+            thread_cpu_peak_percent: None,
+            rss_avg_bytes: None,
+            rss_peak_bytes: None,
+            circuit_events: Vec::new(),
+            latency_outliers: Vec::new(),
+            clock_offsets: Vec::new(),
+            timeline: Vec::new(),
+            annotations: Vec::new(),
+            latency_histogram: stats.latency_buckets(),
+        }
+    }
+}
+
+impl Report {
+    /// Parse a report which was previously rendered as JSON.
+    pub fn from_json(json: &str) -> crate::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Include the resources which were used by the writer.
+    pub(crate) fn with_resources(self, usage: ResourceUsage) -> Self {
+        Self {
+            cpu_avg_percent: Some(usage.cpu_avg_percent),
+            cpu_peak_percent: Some(usage.cpu_peak_percent),
+            thread_cpu_peak_percent: Some(usage.thread_cpu_peak_percent),
+            rss_avg_bytes: Some(usage.rss_avg_bytes),
+            rss_peak_bytes: Some(usage.rss_peak_bytes),
+            ..self
+        }
+    }
+
+    /// Single line summarising the bytes, requests and throughput of the run.
+    pub fn summary(&self) -> String {
+        format!(
+            "Sent: {} bytes in {}ms, Requests: {}/{} ({:.2}%) successful, Throughput: {:.0} bytes/s",
+            self.total_bytes,
+            self.elapsed_ms,
+            self.successful_requests,
+            self.requests,
+            self.success_percentage,
+            self.throughput
+        )
+    }
+
+    /// Number of failed requests by their cause, including causes which no
+    /// request failed with. Failures with none of the recorded causes, such
+    /// as refused connections, are counted as `other`.
+    pub fn failure_causes(&self) -> [(&'static str, u64); 7] {
+        let causes = [
+            ("timeouts", self.timeouts),
+            ("connection_resets", self.connection_resets),
+            ("premature_closes", self.premature_closes),
+            ("mismatched_responses", self.mismatched_responses),
+            ("would_block", self.would_block),
+            ("no_buffer_space", self.no_buffer_space),
+        ];
+        let known: u64 = causes.iter().map(|(_, count)| count).sum();
+        let [a, b, c, d, e, f] = causes;
+        [
+            a,
+            b,
+            c,
+            d,
+            e,
+            f,
+            ("other", self.failed_requests.saturating_sub(known)),
+        ]
+    }
+
+    /// Render the report in the given format.
+    pub fn render(&self, format: ReportFormat) -> crate::Result<String> {
+        match format {
+            ReportFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+            ReportFormat::Csv => {
+                let (names, values): (Vec<_>, Vec<_>) = self
+                    .fields()
+                    .into_iter()
+                    .map(|(name, value)| (name, value.unwrap_or_default()))
+                    .unzip();
+                Ok(format!("{}\n{}\n", names.join(","), values.join(",")))
+            }
+            ReportFormat::Markdown => {
+                let mut out = String::from("| Metric | Value |\n|---|---|\n");
+                for (name, value) in self.fields() {
+                    if let Some(value) = value {
+                        out.push_str(&format!("| {name} | {value} |\n"));
+                    }
+                }
+                Ok(out)
+            }
+            ReportFormat::Hgrm => self.hgrm(),
+            ReportFormat::Html => Ok(crate::html::render(self)),
+        }
+    }
+
+    /// Latency distribution in the HdrHistogram `.hgrm` text format, with
+    /// latencies in milliseconds.
+    fn hgrm(&self) -> crate::Result<String> {
+        if self.latency_histogram.is_empty() {
+            return Err("report does not contain a latency distribution to render as hgrm".into());
+        }
+        let mut histogram =
+            hdrhistogram::Histogram::<u64>::new(3).expect("3 significant figures are supported");
+        for &(nanos, count) in &self.latency_histogram {
+            histogram.record_n(nanos, count)?;
+        }
+        let millis = |nanos: f64| nanos / 1_000_000.0;
+
+        let mut out = format!(
+            "{:>12} {:>14} {:>10} {:>14}\n\n",
+            "Value", "Percentile", "TotalCount", "1/(1-Percentile)"
+        );
+        let mut total = 0;
+        for v in histogram.iter_quantiles(5) {
+            total += v.count_since_last_iteration();
+            let value = millis(v.value_iterated_to() as f64);
+            let quantile = v.quantile_iterated_to();
+            if quantile < 1.0 {
+                out.push_str(&format!(
+                    "{value:12.3} {quantile:2.12} {total:10} {:14.2}\n",
+                    1.0 / (1.0 - quantile)
+                ));
+            } else {
+                out.push_str(&format!("{value:12.3} {quantile:2.12} {total:10}\n"));
+                break;
+            }
+        }
+        let sub_buckets = 2 * histogram.distinct_values() / (usize::from(histogram.buckets()) + 1);
+        out.push_str(&format!(
+            "#[Mean    = {:12.3}, StdDeviation   = {:12.3}]\n\
+             #[Max     = {:12.3}, Total count    = {:12}]\n\
+             #[Buckets = {:12}, SubBuckets     = {:12}]\n",
+            millis(histogram.mean()),
+            millis(histogram.stdev()),
+            millis(histogram.max() as f64),
+            histogram.len(),
+            histogram.buckets(),
+            sub_buckets,
+        ));
+        Ok(out)
+    }
+
+    /// Name and value of every field, in the order they are declared, where
+    /// the value is `None` if it was not recorded.
+    pub(crate) fn fields(&self) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            ("total_bytes", Some(self.total_bytes.to_string())),
+            ("throughput", Some(self.throughput.to_string())),
+            ("requests", Some(self.requests.to_string())),
+            (
+                "successful_requests",
+                Some(self.successful_requests.to_string()),
+            ),
+            ("failed_requests", Some(self.failed_requests.to_string())),
+            ("partial_writes", Some(self.partial_writes.to_string())),
+            (
+                "mismatched_responses",
+                Some(self.mismatched_responses.to_string()),
+            ),
+            ("would_block", Some(self.would_block.to_string())),
+            ("no_buffer_space", Some(self.no_buffer_space.to_string())),
+            ("timeouts", Some(self.timeouts.to_string())),
+            (
+                "unacknowledged_requests",
+                Some(self.unacknowledged_requests.to_string()),
+            ),
+            ("send_failures", Some(self.send_failures.to_string())),
+            (
+                "connection_resets",
+                Some(self.connection_resets.to_string()),
+            ),
+            ("premature_closes", Some(self.premature_closes.to_string())),
+            ("retries", Some(self.retries.to_string())),
+            ("connect_retries", Some(self.connect_retries.to_string())),
+            ("write_retries", Some(self.write_retries.to_string())),
+            (
+                "resolution_failures",
+                Some(self.resolution_failures.to_string()),
+            ),
+            (
+                "send_queue_peak_bytes",
+                Some(self.send_queue_peak_bytes.to_string()),
+            ),
+            (
+                "connections_opened",
+                Some(self.connections_opened.to_string()),
+            ),
+            (
+                "connections_recycled",
+                Some(self.connections_recycled.to_string()),
+            ),
+            ("inbound_bytes", Some(self.inbound_bytes.to_string())),
+            (
+                "inbound_throughput",
+                Some(self.inbound_throughput.to_string()),
+            ),
+            ("generator_waits", Some(self.generator_waits.to_string())),
+            (
+                "generator_wait_ms",
+                Some(self.generator_wait_ms.to_string()),
+            ),
+            (
+                "tls_full_handshakes",
+                Some(self.tls_full_handshakes.to_string()),
+            ),
+            (
+                "tls_resumed_handshakes",
+                Some(self.tls_resumed_handshakes.to_string()),
+            ),
+            (
+                "tls_handshake_p50_us",
+                self.tls_handshake_p50_us.map(|v| v.to_string()),
+            ),
+            (
+                "tls_handshake_p99_us",
+                self.tls_handshake_p99_us.map(|v| v.to_string()),
+            ),
+            (
+                "tls_handshake_max_us",
+                self.tls_handshake_max_us.map(|v| v.to_string()),
+            ),
+            (
+                "success_percentage",
+                Some(self.success_percentage.to_string()),
+            ),
+            ("elapsed_ms", Some(self.elapsed_ms.to_string())),
+            ("stop_reason", Some(self.stop_reason.to_string())),
+            (
+                "one_way_delay_min_us",
+                self.one_way_delay_min_us.map(|v| v.to_string()),
+            ),
+            (
+                "one_way_delay_mean_us",
+                self.one_way_delay_mean_us.map(|v| v.to_string()),
+            ),
+            (
+                "one_way_delay_max_us",
+                self.one_way_delay_max_us.map(|v| v.to_string()),
+            ),
+            ("latency_min_us", self.latency_min_us.map(|v| v.to_string())),
+            (
+                "latency_mean_us",
+                self.latency_mean_us.map(|v| v.to_string()),
+            ),
+            ("latency_p50_us", self.latency_p50_us.map(|v| v.to_string())),
+            ("latency_p90_us", self.latency_p90_us.map(|v| v.to_string())),
+            ("latency_p99_us", self.latency_p99_us.map(|v| v.to_string())),
+            (
+                "latency_p999_us",
+                self.latency_p999_us.map(|v| v.to_string()),
+            ),
+            ("latency_max_us", self.latency_max_us.map(|v| v.to_string())),
+            (
+                "latency_percentiles",
+                Some(self.latency_percentiles.to_string()),
+            ),
+            ("reply_bytes", Some(self.reply_bytes.to_string())),
+            ("rtt_p50_us", self.rtt_p50_us.map(|v| v.to_string())),
+            ("rtt_p90_us", self.rtt_p90_us.map(|v| v.to_string())),
+            ("rtt_p99_us", self.rtt_p99_us.map(|v| v.to_string())),
+            ("rtt_max_us", self.rtt_max_us.map(|v| v.to_string())),
+            ("ttfb_p50_us", self.ttfb_p50_us.map(|v| v.to_string())),
+            ("ttfb_p90_us", self.ttfb_p90_us.map(|v| v.to_string())),
+            ("ttfb_p99_us", self.ttfb_p99_us.map(|v| v.to_string())),
+            ("ttfb_max_us", self.ttfb_max_us.map(|v| v.to_string())),
+            ("requested_rate", self.requested_rate.map(|v| v.to_string())),
+            ("achieved_rate", self.achieved_rate.map(|v| v.to_string())),
+            (
+                "send_lag_p50_us",
+                self.send_lag_p50_us.map(|v| v.to_string()),
+            ),
+            (
+                "send_lag_p99_us",
+                self.send_lag_p99_us.map(|v| v.to_string()),
+            ),
+            (
+                "send_lag_max_us",
+                self.send_lag_max_us.map(|v| v.to_string()),
+            ),
+            (
+                "cpu_avg_percent",
+                self.cpu_avg_percent.map(|v| v.to_string()),
+            ),
+            (
+                "cpu_peak_percent",
+                self.cpu_peak_percent.map(|v| v.to_string()),
+            ),
+            (
+                "thread_cpu_peak_percent",
+                self.thread_cpu_peak_percent.map(|v| v.to_string()),
+            ),
+            ("rss_avg_bytes", self.rss_avg_bytes.map(|v| v.to_string())),
+            ("rss_peak_bytes", self.rss_peak_bytes.map(|v| v.to_string())),
+            (
+                "circuit_events",
+                (!self.circuit_events.is_empty()).then(|| {
+                    self.circuit_events
+                        .iter()
+                        .map(|e| format!("{} {} at {}ms", e.addr, e.state, e.at_ms))
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                }),
+            ),
+            (
+                "latency_outliers",
+                (!self.latency_outliers.is_empty()).then(|| {
+                    self.latency_outliers
+                        .iter()
+                        .map(|o| format!("{} {}us {}", o.addr, o.latency_us, o.payload_hash))
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                }),
+            ),
+            (
+                "clock_offsets",
+                (!self.clock_offsets.is_empty()).then(|| {
+                    self.clock_offsets
+                        .iter()
+                        .map(|o| format!("{} {:+}us", o.addr, o.offset_us))
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                }),
+            ),
+            (
+                "timeline",
+                (!self.timeline.is_empty()).then(|| {
+                    self.timeline
+                        .iter()
+                        .map(|p| format!("{}ms {}/{}", p.elapsed_ms, p.failed_requests, p.requests))
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                }),
+            ),
+            (
+                "annotations",
+                (!self.annotations.is_empty()).then(|| {
+                    self.annotations
+                        .iter()
+                        .map(|a| format!("{}ms {}", a.at_ms, a.message))
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                }),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{
+        Annotation, CircuitEvent, CircuitState, ClockOffset, LatencyOutlier, LatencyPercentiles,
+        Report, ReportFormat, StopReason, TimelinePoint,
+    };
+
+    #[test]
+    fn render() {
+        let report = Report {
+            total_bytes: 10,
+            throughput: 5.0,
+            requests: 1,
+            successful_requests: 1,
+            failed_requests: 0,
+            partial_writes: 0,
+            mismatched_responses: 0,
+            would_block: 0,
+            no_buffer_space: 0,
+            timeouts: 0,
+            unacknowledged_requests: 0,
+            send_failures: 0,
+            connection_resets: 0,
+            premature_closes: 0,
+            retries: 0,
+            connect_retries: 0,
+            write_retries: 0,
+            resolution_failures: 0,
+            send_queue_peak_bytes: 0,
+            connections_opened: 1,
+            connections_recycled: 0,
+            inbound_bytes: 0,
+            inbound_throughput: 0.0,
+            generator_waits: 0,
+            generator_wait_ms: 0.0,
+            tls_full_handshakes: 0,
+            tls_resumed_handshakes: 0,
+            tls_handshake_p50_us: None,
+            tls_handshake_p99_us: None,
+            tls_handshake_max_us: None,
+            success_percentage: 100.0,
+            elapsed_ms: 2000,
+            stop_reason: StopReason::Completed,
+            one_way_delay_min_us: None,
+            one_way_delay_mean_us: None,
+            one_way_delay_max_us: None,
+            latency_min_us: Some(80.0),
+            latency_mean_us: Some(120.5),
+            latency_p50_us: Some(110.0),
+            latency_p90_us: Some(180.0),
+            latency_p99_us: Some(250.0),
+            latency_p999_us: Some(290.0),
+            latency_max_us: Some(300.0),
+            latency_percentiles: LatencyPercentiles::Exact,
+            reply_bytes: 0,
+            rtt_p50_us: None,
+            rtt_p90_us: None,
+            rtt_p99_us: None,
+            rtt_max_us: None,
+            ttfb_p50_us: None,
+            ttfb_p90_us: None,
+            ttfb_p99_us: None,
+            ttfb_max_us: None,
+            requested_rate: None,
+            achieved_rate: None,
+            send_lag_p50_us: None,
+            send_lag_p99_us: None,
+            send_lag_max_us: None,
+            cpu_avg_percent: None,
+            cpu_peak_percent: None,
+            thread_cpu_peak_percent: None,
+            rss_avg_bytes: None,
+            rss_peak_bytes: None,
+            circuit_events: vec![CircuitEvent {
+                addr: "127.0.0.1:5000".to_string(),
+                state: CircuitState::Open,
+                at_ms: 1500,
+            }],
+            latency_outliers: vec![LatencyOutlier::new(
+                "127.0.0.1:5000".parse().unwrap(),
+                1_700_000_000_000_000_000,
+                Duration::from_millis(12),
+                b"slow\npayload",
+            )],
+            clock_offsets: vec![ClockOffset {
+                addr: "127.0.0.1:5000".to_string(),
+                offset_us: -12.5,
+                round_trip_us: 40.0,
+            }],
+            timeline: vec![TimelinePoint {
+                elapsed_ms: 1000,
+                total_bytes: 5,
+                requests: 1,
+                failed_requests: 0,
+                latency_p50_us: Some(110.0),
+                latency_p99_us: Some(250.0),
+                annotations: vec!["deployed v2".to_string()],
+            }],
+            annotations: vec!["1s=deployed v2".parse::<Annotation>().unwrap()],
+            latency_histogram: vec![(110_000, 3), (250_000, 6), (300_000, 1)],
+        };
+
+        let json = report.render(ReportFormat::Json).unwrap();
+        assert_eq!(Report::from_json(&json).unwrap(), report);
+
+        let csv = report.render(ReportFormat::Csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("total_bytes,throughput,requests,"));
+        assert!(lines[0]
+            .ends_with(",circuit_events,latency_outliers,clock_offsets,timeline,annotations"));
+        assert!(lines[1].starts_with("10,"));
+        let outlier = &report.latency_outliers[0];
+        assert_eq!(outlier.latency_us, 12000);
+        assert_eq!(outlier.payload_len, 12);
+        assert_eq!(outlier.payload_prefix, "slow\\npayload");
+        assert!(lines[1].ends_with(&format!(
+            ",,,80,120.5,110,180,250,290,300,exact,0,,,,,,,,,,,,,,,,,,,127.0.0.1:5000 open at 1500ms,127.0.0.1:5000 12000us {},127.0.0.1:5000 -12.5us,1000ms 0/1,1000ms deployed v2",
+            outlier.payload_hash
+        )));
+
+        let markdown = report.render(ReportFormat::Markdown).unwrap();
+        assert!(markdown.contains("| total_bytes | 10 |"));
+        assert!(!markdown.contains("one_way_delay"));
+        assert!(markdown.contains("| latency_p99_us | 250 |"));
+        assert!(markdown.contains("| circuit_events | 127.0.0.1:5000 open at 1500ms |"));
+
+        let hgrm = report.render(ReportFormat::Hgrm).unwrap();
+        let lines: Vec<_> = hgrm.lines().collect();
+        assert_eq!(
+            lines[0],
+            "       Value     Percentile TotalCount 1/(1-Percentile)"
+        );
+        assert_eq!(
+            lines[2],
+            "       0.110 0.000000000000          3           1.00"
+        );
+        assert!(lines.contains(&"       0.300 1.000000000000         10"));
+        assert!(hgrm.contains("#[Max     =        0.300, Total count    =           10]"));
+        assert!(Report {
+            latency_histogram: Vec::new(),
+            ..report.clone()
+        }
+        .render(ReportFormat::Hgrm)
+        .is_err());
+
+        let html = report.render(ReportFormat::Html).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        // Both the requests and failures per second are drawn over time.
+        assert_eq!(html.matches("<polyline").count(), 4);
+        assert!(html.contains("<td>latency_p99_us</td><td>250</td>"));
+        assert!(html.contains("<td>circuit_events</td><td>127.0.0.1:5000 open at 1500ms</td>"));
+        assert!(!html.contains("src="));
+        // Annotations are marked on both charts over time.
+        assert_eq!(html.matches("<title>deployed v2</title>").count(), 2);
+        assert!("deployed v2".parse::<Annotation>().is_err());
+        assert!("soon=deployed v2".parse::<Annotation>().is_err());
+
+        assert_eq!(
+            report.summary(),
+            "Sent: 10 bytes in 2000ms, Requests: 1/1 (100.00%) successful, Throughput: 5 bytes/s"
+        );
+    }
+
+    #[test]
+    fn failure_causes() {
+        let stats = crate::statistics::Statistics::new();
+        for _ in 0..4 {
+            stats.record_failure();
+        }
+        stats.record_timeout();
+        stats.record_would_block();
+        stats.record_connection_reset();
+        let causes = Report::from(&stats).failure_causes();
+        assert_eq!(
+            causes,
+            [
+                ("timeouts", 1),
+                ("connection_resets", 1),
+                ("premature_closes", 0),
+                ("mismatched_responses", 0),
+                ("would_block", 1),
+                ("no_buffer_space", 0),
+                ("other", 1)
+            ]
+        );
+    }
+}
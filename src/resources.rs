@@ -0,0 +1,234 @@
+//! Sampling of the CPU and memory which gn itself uses while writing, so a
+//! run which is limited by the writer rather than the target can be spotted.
+//!
+//! Usage is read from `/proc`, so is only sampled on Linux.
+#[cfg(not(target_os = "linux"))]
+use std::convert::Infallible;
+
+/// CPU and memory used by the process over a run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceUsage {
+    /// CPU time per second of the run, where 100% is one core.
+    pub cpu_avg_percent: f64,
+    /// Highest CPU usage of any sample interval.
+    pub cpu_peak_percent: f64,
+    /// Highest CPU usage of a single thread in any sample interval, which
+    /// approaches 100% when one thread is the bottleneck.
+    pub thread_cpu_peak_percent: f64,
+    /// Mean and highest resident memory, in bytes.
+    pub rss_avg_bytes: u64,
+    pub rss_peak_bytes: u64,
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::ResourceSampler;
+
+/// Sampler for platforms without `/proc`, which never samples anything.
+#[cfg(not(target_os = "linux"))]
+pub(crate) struct ResourceSampler(Infallible);
+
+#[cfg(not(target_os = "linux"))]
+impl ResourceSampler {
+    pub(crate) fn new() -> Option<Self> {
+        None
+    }
+
+    pub(crate) async fn run(&mut self) -> Infallible {
+        match self.0 {}
+    }
+
+    pub(crate) fn usage(&mut self) -> Option<ResourceUsage> {
+        match self.0 {}
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        time::{Duration, Instant},
+    };
+
+    use tokio::time::MissedTickBehavior;
+
+    use super::ResourceUsage;
+
+    /// Time between samples of the process' usage.
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// CPU ticks of the process and each of its threads at a point in time.
+    struct Snapshot {
+        at: Instant,
+        ticks: u64,
+        threads: HashMap<u32, u64>,
+    }
+
+    impl Snapshot {
+        fn take() -> Option<Self> {
+            let mut threads = HashMap::new();
+            for entry in std::fs::read_dir("/proc/self/task").ok()? {
+                let entry = entry.ok()?;
+                let Some(tid) = entry.file_name().to_str().and_then(|s| s.parse().ok()) else {
+                    continue;
+                };
+                // Threads may exit between listing and reading them.
+                if let Some(ticks) = cpu_ticks(&entry.path().join("stat")) {
+                    threads.insert(tid, ticks);
+                }
+            }
+            Some(Self {
+                at: Instant::now(),
+                ticks: cpu_ticks("/proc/self/stat".as_ref())?,
+                threads,
+            })
+        }
+    }
+
+    /// Sum of the user and system time of a `/proc` stat file, in clock
+    /// ticks.
+    fn cpu_ticks(path: &std::path::Path) -> Option<u64> {
+        let stat = std::fs::read_to_string(path).ok()?;
+        // The command name may contain spaces, so fields are counted from
+        // the closing parenthesis after it, where utime and stime are the
+        // 12th and 13th.
+        let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+        let utime: u64 = fields.next()?.parse().ok()?;
+        let stime: u64 = fields.next()?.parse().ok()?;
+        Some(utime + stime)
+    }
+
+    fn rss_bytes() -> Option<u64> {
+        let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+        let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+        // SAFETY: sysconf only reads a system setting.
+        let page_size = u64::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).ok()?;
+        Some(pages * page_size)
+    }
+
+    pub(crate) struct ResourceSampler {
+        ticks_per_second: f64,
+        first: Snapshot,
+        last: Snapshot,
+        cpu_peak_percent: f64,
+        thread_cpu_peak_percent: f64,
+        rss_samples: u64,
+        rss_total: u64,
+        rss_peak: u64,
+    }
+
+    impl ResourceSampler {
+        /// Start sampling, which is `None` when `/proc` cannot be read.
+        pub(crate) fn new() -> Option<Self> {
+            // SAFETY: sysconf only reads a system setting.
+            let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+            if ticks_per_second <= 0 {
+                return None;
+            }
+            let rss = rss_bytes()?;
+            Some(Self {
+                ticks_per_second: ticks_per_second as f64,
+                first: Snapshot::take()?,
+                last: Snapshot::take()?,
+                cpu_peak_percent: 0.0,
+                thread_cpu_peak_percent: 0.0,
+                rss_samples: 1,
+                rss_total: rss,
+                rss_peak: rss,
+            })
+        }
+
+        /// Sample at a fixed interval until the future is dropped.
+        pub(crate) async fn run(&mut self) -> Infallible {
+            let mut tick = tokio::time::interval(SAMPLE_INTERVAL);
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes immediately, when there is nothing new
+            // to sample.
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                self.sample();
+            }
+        }
+
+        fn sample(&mut self) {
+            let (Some(now), Some(rss)) = (Snapshot::take(), rss_bytes()) else {
+                return;
+            };
+            // Ticks are counted in hundredths of a second, so intervals much
+            // shorter than the sample interval give misleading peaks.
+            let secs = now.at.duration_since(self.last.at).as_secs_f64();
+            if secs < SAMPLE_INTERVAL.as_secs_f64() / 2.0 {
+                return;
+            }
+            let percent = |ticks: u64| ticks as f64 / self.ticks_per_second / secs * 100.0;
+            let cpu = percent(now.ticks.saturating_sub(self.last.ticks));
+            self.cpu_peak_percent = self.cpu_peak_percent.max(cpu);
+            for (tid, ticks) in &now.threads {
+                let before = self.last.threads.get(tid).copied().unwrap_or(0);
+                let thread = percent(ticks.saturating_sub(before));
+                self.thread_cpu_peak_percent = self.thread_cpu_peak_percent.max(thread);
+            }
+            self.rss_samples += 1;
+            self.rss_total += rss;
+            self.rss_peak = self.rss_peak.max(rss);
+            self.last = now;
+        }
+
+        /// Take a final sample and summarise the usage since sampling began.
+        pub(crate) fn usage(&mut self) -> Option<ResourceUsage> {
+            self.sample();
+            // The average covers the whole run, even when the final sample
+            // was too soon after the last to be kept.
+            let end = Snapshot::take();
+            let end = end.as_ref().unwrap_or(&self.last);
+            let secs = end.at.duration_since(self.first.at).as_secs_f64();
+            let ticks = end.ticks.saturating_sub(self.first.ticks);
+            Some(ResourceUsage {
+                cpu_avg_percent: if secs > 0.0 {
+                    ticks as f64 / self.ticks_per_second / secs * 100.0
+                } else {
+                    0.0
+                },
+                cpu_peak_percent: self.cpu_peak_percent,
+                thread_cpu_peak_percent: self.thread_cpu_peak_percent,
+                rss_avg_bytes: self.rss_total / self.rss_samples,
+                rss_peak_bytes: self.rss_peak,
+            })
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::ResourceSampler;
+
+    #[tokio::test]
+    async fn sample() {
+        let mut sampler = ResourceSampler::new().unwrap();
+        let busy = async {
+            // Keep a thread busy for long enough to cover several samples.
+            tokio::task::spawn_blocking(|| {
+                let start = Instant::now();
+                let mut n = 0u64;
+                while start.elapsed() < Duration::from_millis(800) {
+                    n = std::hint::black_box(n.wrapping_add(1));
+                }
+            })
+            .await
+            .unwrap();
+        };
+        tokio::select! {
+            _ = busy => {}
+            never = sampler.run() => match never {},
+        }
+        let usage = sampler.usage().unwrap();
+        assert!(usage.cpu_peak_percent > 50.0, "{usage:?}");
+        assert!(usage.thread_cpu_peak_percent > 50.0, "{usage:?}");
+        assert!(usage.cpu_avg_percent > 0.0);
+        assert!(usage.rss_peak_bytes >= usage.rss_avg_bytes);
+        assert!(usage.rss_avg_bytes > 0);
+    }
+}
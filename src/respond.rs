@@ -0,0 +1,143 @@
+//! Scripted responses for the server, allowing it to act as a simple stub of
+//! a protocol for closed-loop tests of clients.
+//!
+//! A script is a TOML file of rules which are tried in order, where the first
+//! rule matching a message decides the response:
+//!
+//! ```toml
+//! [[rule]]
+//! prefix = "PING"
+//! response = "PONG\n"
+//!
+//! [[rule]]
+//! regex = "^GET /slow"
+//! response = "HTTP/1.1 503 Service Unavailable\r\n\r\n"
+//! delay = "250ms"
+//! ```
+use std::{path::Path, time::Duration};
+
+use regex::bytes::Regex;
+use serde::Deserialize;
+
+use crate::MessageMatcher;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScriptFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    prefix: Option<String>,
+    regex: Option<String>,
+    response: String,
+    /// Delay in a human readable form, e.g. `250ms`.
+    delay: Option<String>,
+}
+
+/// A canned response to messages matching a prefix or regular expression.
+pub struct Rule {
+    matcher: MessageMatcher,
+    response: Vec<u8>,
+    delay: Duration,
+}
+
+impl Rule {
+    /// Data to reply with.
+    pub fn response(&self) -> &[u8] {
+        &self.response
+    }
+
+    /// Time to wait before replying.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
+/// Ordered [`Rule`]s which decide how the server responds to each message.
+pub struct ResponseScript {
+    rules: Vec<Rule>,
+}
+
+impl ResponseScript {
+    /// Read a script from a TOML file.
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse a script from TOML, where every rule requires either a `prefix`
+    /// or a `regex` alongside its `response`.
+    pub fn from_toml(s: &str) -> crate::Result<Self> {
+        let file: ScriptFile = toml::from_str(s)?;
+        let mut rules = Vec::with_capacity(file.rules.len());
+        for rule in file.rules {
+            let matcher = match (rule.prefix, rule.regex) {
+                (Some(prefix), None) => MessageMatcher::Prefix(prefix.into_bytes()),
+                (None, Some(regex)) => MessageMatcher::Regex(Regex::new(&regex)?),
+                _ => return Err("each rule requires exactly one of prefix or regex".into()),
+            };
+            let delay = match rule.delay {
+                Some(delay) => *delay.parse::<humantime::Duration>()?,
+                None => Duration::ZERO,
+            };
+            rules.push(Rule {
+                matcher,
+                response: rule.response.into_bytes(),
+                delay,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// The first rule which matches the message, if any.
+    pub fn matching(&self, message: &[u8]) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matcher.matches(message))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::ResponseScript;
+
+    #[test]
+    fn matching() {
+        let script = ResponseScript::from_toml(
+            r#"
+            [[rule]]
+            prefix = "PING"
+            response = "PONG\n"
+
+            [[rule]]
+            regex = "^GET /(slow|wait)"
+            response = "HTTP/1.1 503 Service Unavailable\r\n\r\n"
+            delay = "250ms"
+            "#,
+        )
+        .unwrap();
+
+        let rule = script.matching(b"PING 1").unwrap();
+        assert_eq!(rule.response(), b"PONG\n");
+        assert_eq!(rule.delay(), Duration::ZERO);
+
+        let rule = script.matching(b"GET /wait HTTP/1.1").unwrap();
+        assert_eq!(rule.response(), b"HTTP/1.1 503 Service Unavailable\r\n\r\n");
+        assert_eq!(rule.delay(), Duration::from_millis(250));
+
+        assert!(script.matching(b"POST /").is_none());
+    }
+
+    #[test]
+    fn invalid() {
+        let both = "[[rule]]\nprefix = \"a\"\nregex = \"b\"\nresponse = \"\"";
+        assert!(ResponseScript::from_toml(both).is_err());
+        let neither = "[[rule]]\nresponse = \"\"";
+        assert!(ResponseScript::from_toml(neither).is_err());
+        let regex = "[[rule]]\nregex = \"(\"\nresponse = \"\"";
+        assert!(ResponseScript::from_toml(regex).is_err());
+    }
+}
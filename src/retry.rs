@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// Longest wait between two attempts of a request, however many times it
+/// has been retried.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Retry failed requests up to a number of times, waiting longer before each
+/// attempt, so a target which restarts mid-run does not fail every request
+/// in flight.
+///
+/// Requests which fail to connect are always retried, as none of them was
+/// delivered. Those which fail once connected may have been partly or wholly
+/// delivered already, so they are only retried when `writes` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts which are made after the first fails.
+    pub retries: u32,
+    /// Wait before the first retry, which doubles for each one after it.
+    pub backoff: Duration,
+    /// Whether requests which failed after connecting are retried, when
+    /// they may be delivered more than once.
+    pub writes: bool,
+}
+
+impl RetryPolicy {
+    /// Whether a failed attempt may be retried, by the phase it failed in.
+    pub(crate) fn allows(&self, connecting: bool) -> bool {
+        connecting || self.writes
+    }
+
+    /// Wait before the given retry, counting from 1, capped at 30s.
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.backoff.saturating_mul(factor).min(MAX_BACKOFF)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(100),
+            writes: true,
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(40), Duration::from_secs(30));
+
+        let connect_only = RetryPolicy {
+            writes: false,
+            ..policy
+        };
+        assert!(connect_only.allows(true));
+        assert!(!connect_only.allows(false));
+        assert!(policy.allows(false));
+    }
+}
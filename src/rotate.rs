@@ -0,0 +1,194 @@
+//! File which the messages received by a [`crate::Server`] are written to,
+//! rotated once it grows too large or old so long captures stay manageable.
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// File which is moved aside to `<path>.1`, `<path>.2` and so on, numbered
+/// after any which already exist, and replaced by an empty one once it
+/// reaches its size or age limit. The oldest rotated files may be removed to
+/// keep only a number of them.
+///
+/// Rotation only happens between calls to [`Write::write`], each of which is
+/// written in full, so something written in a single call is never split
+/// across files.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    /// Bytes in the current file.
+    len: u64,
+    opened: Instant,
+    max_size: Option<u64>,
+    interval: Option<Duration>,
+    /// Rotated files which are kept, removing the oldest beyond them.
+    keep: Option<usize>,
+}
+
+impl RotatingFile {
+    /// Open the file at the path, appending to it if it already exists.
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = open(&path)?;
+        Ok(Self {
+            len: file.metadata()?.len(),
+            path,
+            file,
+            opened: Instant::now(),
+            max_size: None,
+            interval: None,
+            keep: None,
+        })
+    }
+
+    /// Rotate the file before a write would take it beyond this many bytes.
+    /// A single write which is larger still goes to a file of its own.
+    pub fn with_max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Rotate the file before writing once it has been open this long.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Keep only this many rotated files, removing the oldest once the file
+    /// is rotated, including any from earlier runs.
+    pub fn with_keep(mut self, files: usize) -> Self {
+        self.keep = Some(files);
+        self
+    }
+
+    fn is_due(&self, len: usize) -> bool {
+        if self.len == 0 {
+            return false;
+        }
+        let full = self.max_size.is_some_and(|max| self.len + len as u64 > max);
+        let old = self
+            .interval
+            .is_some_and(|interval| self.opened.elapsed() >= interval);
+        full || old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut rotated = self.rotated()?;
+        let next = rotated.last().map_or(1, |n| n + 1);
+        std::fs::rename(&self.path, self.suffixed(next))?;
+        rotated.push(next);
+        if let Some(keep) = self.keep {
+            for n in &rotated[..rotated.len().saturating_sub(keep)] {
+                std::fs::remove_file(self.suffixed(*n))?;
+            }
+        }
+        self.file = open(&self.path)?;
+        self.len = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+
+    fn suffixed(&self, n: u64) -> PathBuf {
+        PathBuf::from(format!("{}.{n}", self.path.display()))
+    }
+
+    /// Numbers of the files rotated from the path, oldest first.
+    fn rotated(&self) -> io::Result<Vec<u64>> {
+        let prefix = match self.path.file_name() {
+            Some(name) => format!("{}.", name.to_string_lossy()),
+            None => return Ok(Vec::new()),
+        };
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut rotated: Vec<u64> = std::fs::read_dir(dir)?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name();
+                name.to_str()?.strip_prefix(&prefix)?.parse().ok()
+            })
+            .collect();
+        rotated.sort_unstable();
+        Ok(rotated)
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.is_due(buf.len()) {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::RotatingFile;
+
+    #[test]
+    fn rotate_by_size() {
+        let dir = std::env::temp_dir().join(format!("gn-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("received.log");
+        // An earlier rotation is kept rather than replaced.
+        std::fs::write(dir.join("received.log.1"), "earlier\n").unwrap();
+
+        let mut file = RotatingFile::create(&path).unwrap().with_max_size(10);
+        for message in ["first\n", "second\n", "third\n", "4th\n"] {
+            file.write_all(message.as_bytes()).unwrap();
+        }
+        drop(file);
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("received.log.1"), "earlier\n");
+        assert_eq!(read("received.log.2"), "first\n");
+        assert_eq!(read("received.log.3"), "second\n");
+        assert_eq!(read("received.log"), "third\n4th\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotate_keep() {
+        let dir = std::env::temp_dir().join(format!("gn-rotate-keep-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("received.log");
+        std::fs::write(dir.join("received.log.1"), "earlier\n").unwrap();
+
+        let mut file = RotatingFile::create(&path)
+            .unwrap()
+            .with_max_size(10)
+            .with_keep(2);
+        for message in ["first\n", "second\n", "third\n", "4th\n", "fifth\n"] {
+            file.write_all(message.as_bytes()).unwrap();
+        }
+        drop(file);
+
+        // Only the two newest rotated files remain.
+        let mut names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["received.log", "received.log.3", "received.log.4"]);
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("received.log.3"), "second\n");
+        assert_eq!(read("received.log.4"), "third\n4th\n");
+        assert_eq!(read("received.log"), "fifth\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use std::net::SocketAddr;
+
+use crate::{statistics::Statistics, Protocol, Server, SocketManager, WriteOptions};
+
+/// A standard workload which is run against an in-process server.
+struct Preset {
+    name: &'static str,
+    protocol: Protocol,
+    payload_size: usize,
+    concurrency: u64,
+}
+
+const PRESETS: [Preset; 3] = [
+    Preset {
+        name: "tcp-connect",
+        protocol: Protocol::Tcp,
+        payload_size: 1,
+        concurrency: 4,
+    },
+    Preset {
+        name: "tcp-bulk",
+        protocol: Protocol::Tcp,
+        payload_size: 1024 * 1024,
+        concurrency: 4,
+    },
+    Preset {
+        name: "udp",
+        protocol: Protocol::Udp,
+        payload_size: 1024,
+        concurrency: 4,
+    },
+];
+
+/// Outcome of a single self-test preset.
+#[derive(Debug, Clone)]
+pub struct SelftestResult {
+    pub name: &'static str,
+    /// Requests sent per second, which for TCP is also the connection rate.
+    pub requests_per_second: f64,
+    /// Bytes sent per second by the writer.
+    pub bytes_per_second: f64,
+    /// Bytes per second which arrived at the server.
+    pub received_bytes_per_second: f64,
+    pub success_percentage: f64,
+}
+
+/// Run each standard preset for `duration` with a writer and a measure-only
+/// server paired over loopback. The results give a baseline of what the
+/// host, and gn itself, is capable of.
+pub async fn selftest(duration: humantime::Duration) -> crate::Result<Vec<SelftestResult>> {
+    let mut results = Vec::with_capacity(PRESETS.len());
+    for preset in PRESETS {
+        results.push(run_preset(preset, duration).await?);
+    }
+    Ok(results)
+}
+
+async fn run_preset(
+    preset: Preset,
+    duration: humantime::Duration,
+) -> crate::Result<SelftestResult> {
+    let loopback: SocketAddr = "127.0.0.1:0".parse()?;
+    let mut server = Server::new(loopback, preset.protocol.clone(), std::io::sink())
+        .measure_only()
+        .without_logs();
+    let received = server.statistics();
+    let mut bound = server.bound_addr();
+    let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+
+    let addr = bound
+        .wait_for(Option::is_some)
+        .await?
+        .expect("Server address is bound");
+
+    let input = vec![b'g'; preset.payload_size];
+    let manager = SocketManager::new(
+        addr,
+        &input,
+        preset.protocol,
+        WriteOptions::ConcurrencyWithDuration(preset.concurrency, duration),
+        Statistics::new(),
+    );
+    manager.write().await?;
+    handle.abort();
+
+    let secs = manager.elapsed() as f64 / 1000.0;
+    let report = manager.report();
+    Ok(SelftestResult {
+        name: preset.name,
+        requests_per_second: report.requests as f64 / secs,
+        bytes_per_second: report.throughput,
+        received_bytes_per_second: received.bytes() as f64 / secs,
+        success_percentage: report.success_percentage,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::selftest;
+
+    #[tokio::test]
+    async fn presets() {
+        let results = selftest(humantime::Duration::from_str("200ms").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        for result in results {
+            assert!(result.requests_per_second > 0.0, "{result:?}");
+            assert!(result.bytes_per_second > 0.0, "{result:?}");
+        }
+    }
+}
@@ -1,55 +1,2223 @@
-use std::{io::Write, net::SocketAddr};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Arguments,
+    fs::File,
+    future::Future,
+    io::{BufWriter, Write},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
+use clap::ValueEnum;
+use serde::Serialize;
+use socket2::{Domain, SockRef, Socket, Type};
 use tokio::{
-    io::AsyncReadExt,
-    net::{TcpListener, UdpSocket},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::watch,
+    task::JoinSet,
+    time::Instant,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{
+    cancel::CancellationToken,
+    chaos::{Chaos, Fault},
+    connections::ConnectionTable,
+    delay::Delay,
+    digest::Digest,
+    endpoint::{Endpoint, UNIX_PEER},
+    health::HealthEndpoint,
+    metrics::MetricsEndpoint,
+    multicast,
+    reload::{Change, ConfigWatcher},
+    render::Render,
+    statistics::ServerStatistics,
+    timing,
+    tls::TlsServerConfig,
+    transport, CaptureWriter, Protocol, ResponseScript,
+};
+#[cfg(unix)]
+use crate::{
+    control::{ControlSocket, ServeControl},
+    unix::{self, SocketFile},
 };
 
-use crate::Protocol;
+/// Interval at which received rates are reported in measure-only mode.
+const MEASURE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum size of a single read, large enough for any UDP datagram.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Connections which may wait to be accepted when no backlog is given, as
+/// with [`TcpListener::bind`].
+const DEFAULT_BACKLOG: u32 = 1024;
+
+/// Most dropped connections which are held open at once, beyond which the
+/// oldest is closed.
+const MAX_HELD_CONNECTIONS: usize = 1024;
+
+/// What happens to a connection which arrives once a [`Server`] has as many
+/// open as its limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ConnectionOverflow {
+    /// Accept and immediately close it, so the client sees the connection
+    /// closed before anything is read.
+    #[default]
+    Reject,
+    /// Stop accepting, leaving it in the listen backlog until one closes.
+    /// Once the backlog is full, the kernel refuses further connections.
+    Queue,
+}
 
 pub struct Server<W: Write> {
-    addr: SocketAddr,
+    endpoint: Endpoint,
     protocol: Protocol,
 
     /// Buffer for data to be written too. This buffer sink is for the actual
     /// data that is being sent and _not_ included with log lines.
     buffer: W,
+
+    /// How received messages are written to the buffer.
+    render: Render,
+    /// Discard received data, only reporting the rate at which it arrives.
+    measure_only: bool,
+    /// Reply to each message with the sender's address and timestamps.
+    reflect_timing: bool,
+    /// Reply to each message with its digest.
+    reply_digest: Option<Digest>,
+    /// Time waited before replying to each message.
+    delay: Option<Delay>,
+    /// Connections whose reply is delayed, which are replied to and closed in
+    /// the background.
+    delayed: JoinSet<()>,
+    /// Number of the delayed connections which are not closed yet, counted
+    /// towards the maximum number of connections.
+    closing: Arc<AtomicUsize>,
+    /// Faults injected into accepted connections and received datagrams.
+    chaos: Option<Chaos>,
+    /// Connections which were dropped, held open without being read from.
+    held: VecDeque<TcpStream>,
+    /// Rules deciding the reply to each message.
+    respond_script: Option<ResponseScript>,
+    /// Capture of every received message, alongside its peer and timestamp.
+    capture: Option<CaptureWriter<BufWriter<File>>>,
+    /// Certificate and key presented when serving TLS.
+    tls: Option<TlsServerConfig>,
+    /// Sinks for the messages received under each server name over TLS,
+    /// rather than the buffer.
+    sni_routes: HashMap<String, Box<dyn Write + Send + Sync>>,
+    /// Server name of the connection being handled, when it is routed.
+    sni: Option<String>,
+    /// Abort TCP connections with a reset once they are handled.
+    close_with_rst: bool,
+    /// Share the port with other sockets which also set `SO_REUSEPORT`.
+    reuseport: bool,
+    /// Size of the socket's receive buffer (`SO_RCVBUF`) to request.
+    recv_buffer_size: Option<usize>,
+    /// Connections which may wait to be accepted.
+    backlog: u32,
+    /// Most connections which are open at once, and what happens beyond it.
+    max_connections: Option<(usize, ConnectionOverflow)>,
+    /// Multicast group which received UDP datagrams are re-emitted to.
+    mirror_multicast: Option<SocketAddr>,
+    /// Multicast group which is joined to receive UDP datagrams sent to it.
+    multicast_group: Option<IpAddr>,
+    /// Network interface the multicast group is joined on.
+    multicast_interface: Option<String>,
+    /// Address which metrics are served on for Prometheus to scrape.
+    metrics_addr: Option<SocketAddr>,
+    /// Address which readiness and liveness probes are answered on.
+    health_addr: Option<SocketAddr>,
+    /// Path of the socket on which commands inspect the server.
+    control_socket: Option<PathBuf>,
+    /// Connections being handled, as listed on the control socket.
+    connections: Arc<ConnectionTable>,
+    /// Whether the listener is bound, so the server is ready.
+    ready: Arc<AtomicBool>,
+    /// Messages whose replies are held back and sent together.
+    ack_every: Option<usize>,
+    /// Longest a reply is held back before it is sent.
+    flush_interval: Option<Duration>,
+    stats: Arc<ServerStatistics>,
+
+    /// Token which stops the server once it is cancelled.
+    cancellation: Option<CancellationToken>,
+    /// Config file whose changes are applied while serving.
+    config_watcher: Option<ConfigWatcher>,
+
+    /// Whether log lines are printed to stderr.
+    log: bool,
+    /// Whether the address of every listener is announced on stdout.
+    announce: bool,
+    /// Address the server is bound to, once it is listening.
+    bound: watch::Sender<Option<SocketAddr>>,
 }
 
 impl<W: Write> Server<W> {
-    pub fn new(addr: SocketAddr, protocol: Protocol, buffer: W) -> Self {
+    /// Create a server listening on the endpoint, which is a path for
+    /// [`Protocol::Unix`] and [`Protocol::UnixDatagram`] and an IP address
+    /// otherwise.
+    pub fn new(endpoint: impl Into<Endpoint>, protocol: Protocol, buffer: W) -> Self {
         Self {
-            addr,
+            endpoint: endpoint.into(),
             protocol,
             buffer,
+            render: Render::default(),
+            measure_only: false,
+            reflect_timing: false,
+            reply_digest: None,
+            delay: None,
+            delayed: JoinSet::new(),
+            closing: Arc::default(),
+            chaos: None,
+            held: VecDeque::new(),
+            respond_script: None,
+            capture: None,
+            tls: None,
+            sni_routes: HashMap::new(),
+            sni: None,
+            close_with_rst: false,
+            reuseport: false,
+            recv_buffer_size: None,
+            backlog: DEFAULT_BACKLOG,
+            max_connections: None,
+            mirror_multicast: None,
+            multicast_group: None,
+            multicast_interface: None,
+            metrics_addr: None,
+            health_addr: None,
+            control_socket: None,
+            connections: Arc::default(),
+            ready: Arc::default(),
+            ack_every: None,
+            flush_interval: None,
+            stats: Arc::new(ServerStatistics::new()),
+            cancellation: None,
+            config_watcher: None,
+            log: true,
+            announce: false,
+            bound: watch::Sender::new(None),
+        }
+    }
+
+    /// Write each received message to the buffer in this form, rather than as
+    /// a line of lossy UTF-8.
+    pub fn render(mut self, render: Render) -> Self {
+        self.render = render;
+        self
+    }
+
+    /// Discard all received data and periodically report the byte and message
+    /// rates instead. Reads reuse a single buffer and skip any UTF-8 handling
+    /// so the server can act as a reference sink for the writer's throughput.
+    pub fn measure_only(mut self) -> Self {
+        self.measure_only = true;
+        self
+    }
+
+    /// Reply to each message with the address of the sender, the send
+    /// timestamp embedded by the writer and the time the message was received.
+    /// This allows writers to estimate the one-way delay, subject to how well
+    /// the clocks of both hosts are synchronised.
+    pub fn reflect_timing(mut self) -> Self {
+        self.reflect_timing = true;
+        self
+    }
+
+    /// Wait before replying to each message, or before closing a stream which
+    /// is not replied to, so the server behaves as a slow backend. Each
+    /// connection waits on its own while the ones after it are read, whereas
+    /// datagrams are handled one at a time, so this limits how many of them
+    /// are received.
+    pub fn delay(mut self, delay: Delay) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Inject faults into TCP and TLS connections as they are accepted, so
+    /// writers can be tested against an unreliable backend. A dropped
+    /// connection is held open without being read from, a reset one is
+    /// aborted with a reset (RST) and any other is closed without a reply
+    /// once the given number of bytes has been read. Over UDP, only dropping
+    /// is supported, which discards the datagram.
+    pub fn chaos(mut self, chaos: Chaos) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Reply to each message with its digest as a line of hex, which a writer
+    /// verifying digests compares with its own to detect corrupted messages.
+    /// Streams are replied to once they are read to the end, unless a
+    /// response script or reflected timing replies instead.
+    pub fn reply_digest(mut self, digest: Digest) -> Self {
+        self.reply_digest = Some(digest);
+        self
+    }
+
+    /// Reply to each message with the response of the first matching rule of
+    /// the script, after its delay. Messages matching no rule are not replied
+    /// to. For TCP, the message is read until the writer half-closes the
+    /// stream, so the server can act as a stub for request/response protocols.
+    pub fn respond_script(mut self, script: ResponseScript) -> Self {
+        self.respond_script = Some(script);
+        self
+    }
+
+    /// Record every received message to the capture, so it can be inspected
+    /// or replayed later. Messages are recorded as they were received, which
+    /// includes any embedded timestamp header.
+    pub fn capture(mut self, capture: CaptureWriter<BufWriter<File>>) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Present the certificate and key when serving [`Protocol::Tls`], which
+    /// terminates TLS and handles the decrypted messages as for TCP.
+    pub fn tls(mut self, config: TlsServerConfig) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Write the messages of TLS connections whose clients asked for the
+    /// server name, through server name indication (SNI), to the sink rather
+    /// than the buffer, so one server can capture several logical endpoints.
+    /// Names are matched ignoring case, and messages under any other name
+    /// are written to the buffer.
+    pub fn sni_route(mut self, name: &str, sink: impl Write + Send + Sync + 'static) -> Self {
+        self.sni_routes
+            .insert(name.to_ascii_lowercase(), Box::new(sink));
+        self
+    }
+
+    /// Abort TCP and TLS connections with a reset (RST) once they are handled,
+    /// rather than closing them gracefully, to test how clients handle resets.
+    /// Any reply which has not yet been sent when the connection is closed is
+    /// discarded.
+    pub fn close_with_rst(mut self) -> Self {
+        self.close_with_rst = true;
+        self
+    }
+
+    /// Bind TCP, TLS and UDP sockets with `SO_REUSEPORT`, so several servers
+    /// can listen on the same port with the kernel spreading connections or
+    /// datagrams across them. This is only supported on Unix.
+    pub fn reuseport(mut self) -> Self {
+        self.reuseport = true;
+        self
+    }
+
+    /// Request a receive buffer of this many bytes for TCP, TLS and UDP
+    /// sockets, to absorb bursts of datagrams without dropping them. The
+    /// kernel may adjust the size, so the size it uses is logged.
+    pub fn recv_buffer_size(mut self, bytes: usize) -> Self {
+        self.recv_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Allow this many TCP and TLS connections to wait to be accepted, which
+    /// defaults to 1024, to absorb floods of connections. The kernel caps
+    /// this, such as at `net.core.somaxconn` on Linux.
+    pub fn backlog(mut self, len: u32) -> Self {
+        self.backlog = len;
+        self
+    }
+
+    /// Keep at most `limit` TCP and TLS connections open, counting the one
+    /// being read from and those accepted while it is, which wait their turn.
+    /// Connections beyond the limit are handled as `overflow` says, to
+    /// simulate a backend which is at capacity.
+    pub fn max_connections(mut self, limit: usize, overflow: ConnectionOverflow) -> Self {
+        self.max_connections = Some((limit.max(1), overflow));
+        self
+    }
+
+    /// Re-emit every datagram received over UDP to the multicast group, so
+    /// several observers can consume the same stream live. Datagrams are
+    /// mirrored as they were received, before any reply is sent.
+    pub fn mirror_multicast(mut self, group: SocketAddr) -> Self {
+        self.mirror_multicast = Some(group);
+        self
+    }
+
+    /// Join the multicast group, so that UDP datagrams sent to it on the port
+    /// of the server are received, as with service discovery or market data
+    /// feeds. The server is usually bound to the unspecified address.
+    pub fn multicast_group(mut self, group: IpAddr) -> Self {
+        self.multicast_group = Some(group);
+        self
+    }
+
+    /// Join the multicast group on this network interface, such as `eth1`,
+    /// rather than the one chosen by the system.
+    pub fn multicast_interface(mut self, interface: impl Into<String>) -> Self {
+        self.multicast_interface = Some(interface.into());
+        self
+    }
+
+    /// Serve the bytes and messages received, their sizes, the active
+    /// connections, how long connections lasted and the receive rates over
+    /// HTTP on this address, in the Prometheus text format, while the server
+    /// is running.
+    pub fn metrics_addr(mut self, addr: SocketAddr) -> Self {
+        self.metrics_addr = Some(addr);
+        self
+    }
+
+    /// Answer readiness and liveness probes over HTTP on this address, at
+    /// `/ready` and `/live`, while the server is running. The server is only
+    /// ready once it is listening.
+    pub fn health_addr(mut self, addr: SocketAddr) -> Self {
+        self.health_addr = Some(addr);
+        self
+    }
+
+    /// Accept commands from `gn ctl` on the Unix socket at `path` while
+    /// serving, where `connections` lists the peer, age and bytes read so far
+    /// of each open connection.
+    pub fn control_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.control_socket = Some(path.into());
+        self
+    }
+
+    /// Hold back the replies to datagrams until this many messages have been
+    /// replied to, then send them together, to study how writers measure
+    /// latency against delayed acknowledgements. Without a flush interval,
+    /// replies are only sent once the batch is full.
+    pub fn ack_every(mut self, messages: usize) -> Self {
+        self.ack_every = Some(messages.max(1));
+        self
+    }
+
+    /// Send replies to datagrams which have been held back for this long,
+    /// even if their batch is not yet full. Without [`Server::ack_every`],
+    /// every reply within the interval is sent together.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = Some(interval);
+        self
+    }
+
+    /// Stop serving once the token is cancelled, returning from
+    /// [`Server::serve`] with what was received left in the statistics.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Announce the address of every listener on stdout once it is bound,
+    /// as a line of JSON such as
+    /// `{"service":"serve","protocol":"udp","address":"127.0.0.1:40123"}`,
+    /// so that scripts can discover ports chosen by the system when binding
+    /// to port 0. The metrics and health endpoints are announced as the
+    /// `metrics` and `health` services over `http`.
+    pub fn announce_bind(mut self) -> Self {
+        self.announce = true;
+        self
+    }
+
+    /// Apply changes to the `respond-script` and `capture` flags in the
+    /// `[serve]` table of the watched config file while serving, logging each
+    /// change, so a long capture does not need restarting. Measure-only
+    /// servers do not watch the file.
+    pub fn watch_config(mut self, watcher: ConfigWatcher) -> Self {
+        self.config_watcher = Some(watcher);
+        self
+    }
+
+    /// Do not print any log lines to stderr.
+    pub(crate) fn without_logs(mut self) -> Self {
+        self.log = false;
+        self
+    }
+
+    /// Counters of the data which has been received.
+    pub fn statistics(&self) -> Arc<ServerStatistics> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Receiver of the address the server is bound to, which is useful when
+    /// binding to port 0. This is `None` until the server is listening, and
+    /// remains so for Unix sockets.
+    pub fn bound_addr(&self) -> watch::Receiver<Option<SocketAddr>> {
+        self.bound.subscribe()
+    }
+
+    fn log(&self, args: Arguments) {
+        if self.log {
+            eprintln!("{args}");
+        }
+    }
+
+    /// Record a received message in the statistics and the capture.
+    fn record(&mut self, peer: SocketAddr, message: &[u8]) -> std::io::Result<()> {
+        self.stats.record_message(message.len() as u64);
+        if peer != UNIX_PEER {
+            self.stats.record_peer(peer.ip());
+        }
+        match &mut self.capture {
+            Some(capture) => capture.record(&self.protocol, peer, message),
+            None => Ok(()),
+        }
+    }
+
+    /// Apply the changes which were made to the watched config file.
+    fn apply(&mut self, changes: Result<Vec<Change>, String>) {
+        let path = match &self.config_watcher {
+            Some(watcher) => watcher.path().display().to_string(),
+            None => return,
+        };
+        let changes = match changes {
+            Ok(changes) => changes,
+            Err(e) => {
+                self.log(format_args!("Unable to reload {path}: {e}"));
+                return;
+            }
+        };
+        for change in changes {
+            match change {
+                Change::RespondScript(Some(script)) => match ResponseScript::load(&script) {
+                    Ok(loaded) => {
+                        self.respond_script = Some(loaded);
+                        self.log(format_args!(
+                            "Reloaded {path}: responding from {}",
+                            script.display()
+                        ));
+                    }
+                    Err(e) => self.log(format_args!(
+                        "Unable to reload {path}: invalid respond-script {}: {e}",
+                        script.display()
+                    )),
+                },
+                Change::RespondScript(None) => {
+                    self.respond_script = None;
+                    self.log(format_args!("Reloaded {path}: no longer responding"));
+                }
+                Change::Capture(Some(capture)) => match CaptureWriter::create(&capture) {
+                    Ok(writer) => {
+                        // The previous capture is flushed as it is dropped.
+                        self.capture = Some(writer);
+                        self.log(format_args!(
+                            "Reloaded {path}: capturing to {}",
+                            capture.display()
+                        ));
+                    }
+                    Err(e) => self.log(format_args!(
+                        "Unable to reload {path}: cannot capture to {}: {e}",
+                        capture.display()
+                    )),
+                },
+                Change::Capture(None) => {
+                    self.capture = None;
+                    self.log(format_args!("Reloaded {path}: no longer capturing"));
+                }
+                Change::Delay(Some(delay)) => match delay.parse::<Delay>() {
+                    Ok(delay) => {
+                        self.delay = Some(delay);
+                        self.log(format_args!("Reloaded {path}: delaying replies by {delay}"));
+                    }
+                    Err(e) => self.log(format_args!("Unable to reload {path}: {e}")),
+                },
+                Change::Delay(None) => {
+                    self.delay = None;
+                    self.log(format_args!("Reloaded {path}: no longer delaying replies"));
+                }
+                Change::Ignored { flag, reason } => self.log(format_args!(
+                    "Not applying the change to {flag} in {path}, as {reason}"
+                )),
+            }
+        }
+    }
+
+    /// Wait for the configured delay, if any, before replying.
+    async fn wait_to_reply(&self) {
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay.sample()).await;
+        }
+    }
+
+    /// Write the rendered message to the buffer, or the sink of the server
+    /// name it was received under, in a single write, so a buffer which
+    /// rotates files never splits it.
+    fn output(&mut self, message: &[u8]) -> std::io::Result<()> {
+        let mut rendered = Vec::with_capacity(message.len() * 2 + 1);
+        self.render.write(&mut rendered, message)?;
+        match self
+            .sni
+            .as_ref()
+            .and_then(|name| self.sni_routes.get_mut(name))
+        {
+            Some(sink) => sink.write_all(&rendered),
+            None => self.buffer.write_all(&rendered),
+        }
+    }
+
+    fn matching_rule(&self, message: &[u8]) -> Option<&crate::Rule> {
+        self.respond_script.as_ref()?.matching(message)
+    }
+
+    fn tls_acceptor(&self) -> crate::Result<TlsAcceptor> {
+        match &self.tls {
+            Some(config) => Ok(config.acceptor()),
+            None => Err("serving TLS requires a certificate and key".into()),
+        }
+    }
+
+    /// Prepare an accepted connection to be reset when it is dropped, if the
+    /// server closes connections with a reset.
+    fn accepted(&self, stream: &TcpStream) {
+        if self.close_with_rst {
+            if let Err(e) = stream.set_linger(Some(Duration::ZERO)) {
+                self.log(format_args!("Unable to close with a reset: {e}"));
+            }
+        }
+    }
+
+    /// Inject a fault into an accepted connection, if one is drawn, returning
+    /// the connection unless it was dropped or reset, alongside the bytes to
+    /// read from it before closing it early.
+    fn inject_fault(&mut self, stream: TcpStream) -> Option<(TcpStream, Option<u64>)> {
+        match self.chaos.as_ref().and_then(Chaos::draw) {
+            None => Some((stream, None)),
+            Some(Fault::CloseAfter(bytes)) => Some((stream, Some(bytes))),
+            Some(Fault::Drop) => {
+                self.stats.record_dropped();
+                if self.held.len() == MAX_HELD_CONNECTIONS {
+                    self.held.pop_front();
+                }
+                self.held.push_back(stream);
+                None
+            }
+            Some(Fault::Reset) => {
+                self.stats.record_reset();
+                if let Err(e) = stream.set_linger(Some(Duration::ZERO)) {
+                    self.log(format_args!("Unable to reset connection: {e}"));
+                }
+                None
+            }
+        }
+    }
+
+    /// Create a socket bound to the address with the socket options of the
+    /// server applied.
+    fn bind(&self, addr: SocketAddr, ty: Type) -> std::io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(addr), ty, None)?;
+        // As with `TcpListener::bind`, a restarted server does not have to
+        // wait for connections of the last one in TIME_WAIT.
+        #[cfg(not(windows))]
+        if ty == Type::STREAM {
+            socket.set_reuse_address(true)?;
+        }
+        if self.reuseport {
+            #[cfg(unix)]
+            socket.set_reuse_port(true)?;
+            #[cfg(not(unix))]
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "SO_REUSEPORT is not supported on this platform",
+            ));
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+            self.log(format_args!(
+                "Receive buffer is {} bytes",
+                socket.recv_buffer_size()?
+            ));
+        }
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        Ok(socket)
+    }
+
+    fn bind_tcp(&self, addr: SocketAddr) -> std::io::Result<TcpListener> {
+        let socket = self.bind(addr, Type::STREAM)?;
+        socket.listen(i32::try_from(self.backlog).unwrap_or(i32::MAX))?;
+        TcpListener::from_std(socket.into())
+    }
+
+    fn bind_udp(&self, addr: SocketAddr) -> crate::Result<UdpSocket> {
+        let socket = self.bind(addr, Type::DGRAM)?;
+        if let Some(group) = self.multicast_group {
+            if !group.is_multicast() {
+                return Err(format!("{group} is not a multicast group").into());
+            }
+            let interface = self.multicast_interface.as_deref();
+            multicast::join(SockRef::from(&socket), group, interface)
+                .map_err(|e| format!("unable to join {group}: {e}"))?;
+            self.log(format_args!("Joined multicast group {group}"));
+        }
+        Ok(UdpSocket::from_std(socket.into())?)
+    }
+
+    /// Create the socket which datagrams are mirrored from, if mirroring to
+    /// a multicast group.
+    async fn mirror_socket(&self) -> crate::Result<Option<(UdpSocket, SocketAddr)>> {
+        let Some(group) = self.mirror_multicast else {
+            return Ok(None);
+        };
+        if !group.ip().is_multicast() {
+            return Err(format!("{group} is not a multicast group").into());
+        }
+        let local: SocketAddr = match group {
+            SocketAddr::V4(_) => (std::net::Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (std::net::Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local).await?;
+        self.log(format_args!("Mirroring datagrams to {group}"));
+        Ok(Some((socket, group)))
+    }
+
+    /// Re-emit a received datagram to the multicast group, if mirroring.
+    async fn mirror(&self, mirror: &Option<(UdpSocket, SocketAddr)>, datagram: &[u8]) {
+        if let Some((socket, group)) = mirror {
+            if let Err(e) = socket.send_to(datagram, group).await {
+                self.log(format_args!("Unable to mirror datagram: {e}"));
+            }
+        }
+    }
+
+    /// Log that the server is listening, publishing the address it is bound
+    /// to when listening on an IP address.
+    fn listening(&self, bound: Option<SocketAddr>) {
+        match bound {
+            Some(addr) => self.log(format_args!("Listening on {}://{addr}", self.protocol)),
+            None => self.log(format_args!(
+                "Listening on {}://{}",
+                self.protocol, self.endpoint
+            )),
+        }
+        if let Some(addr) = bound {
+            self.announce_listener("serve", &self.protocol.to_string(), addr);
+        }
+        self.bound.send_replace(bound);
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    /// Print the announcement of a bound listener, if announcing them.
+    fn announce_listener(&self, service: &str, protocol: &str, addr: SocketAddr) {
+        if self.announce {
+            println!("{}", announcement(service, protocol, addr));
+        }
+    }
+
+    /// Start answering health checks, if an address was given for them. They
+    /// are answered until the returned endpoint is dropped.
+    async fn start_health(&self) -> crate::Result<Option<HealthEndpoint>> {
+        let Some(addr) = self.health_addr else {
+            return Ok(None);
+        };
+        let health = HealthEndpoint::start(addr, Arc::clone(&self.ready)).await?;
+        self.announce_listener("health", "http", health.addr());
+        self.log(format_args!(
+            "Answering health checks on http://{}/ready and /live",
+            health.addr()
+        ));
+        Ok(Some(health))
+    }
+
+    /// Start accepting commands on the control socket, if a path was given
+    /// for it. They are accepted until the returned socket is dropped.
+    #[cfg(unix)]
+    fn start_control(&self) -> crate::Result<Option<ControlSocket>> {
+        let Some(path) = &self.control_socket else {
+            return Ok(None);
+        };
+        let control = ServeControl(Arc::clone(&self.connections));
+        let socket = ControlSocket::start(path, control)
+            .map_err(|e| format!("cannot listen on the control socket: {e}"))?;
+        self.log(format_args!(
+            "Accepting commands on the control socket {}",
+            path.display()
+        ));
+        Ok(Some(socket))
+    }
+
+    #[cfg(not(unix))]
+    fn start_control(&self) -> crate::Result<Option<()>> {
+        match self.control_socket {
+            Some(_) => Err("a control socket is only supported on Unix".into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Start serving metrics, if an address was given for them. They are
+    /// served until the returned endpoint is dropped.
+    async fn start_metrics(&self) -> crate::Result<Option<MetricsEndpoint>> {
+        let Some(addr) = self.metrics_addr else {
+            return Ok(None);
+        };
+        let metrics = MetricsEndpoint::start(addr, self.statistics()).await?;
+        self.announce_listener("metrics", "http", metrics.addr());
+        self.log(format_args!(
+            "Serving metrics on http://{}/metrics",
+            metrics.addr()
+        ));
+        Ok(Some(metrics))
+    }
+
+    fn connection_queue(&self) -> ConnectionQueue {
+        ConnectionQueue {
+            limit: self.max_connections,
+            waiting: VecDeque::new(),
+            closing: Arc::clone(&self.closing),
+            stats: Arc::clone(&self.stats),
+        }
+    }
+
+    fn ack_batch<A: Clone>(&self) -> AckBatch<A> {
+        let every = match (self.ack_every, self.flush_interval) {
+            (Some(every), _) => every,
+            (None, Some(_)) => usize::MAX,
+            (None, None) => 1,
+        };
+        AckBatch::new(every, self.flush_interval)
+    }
+
+    /// Send replies to a UDP peer, logging any which fail.
+    async fn send_udp_replies(&self, bind: &UdpSocket, replies: Vec<Ack<SocketAddr>>) {
+        for (action, reply, addr) in replies {
+            if let Err(e) = bind.send_to(&reply, addr).await {
+                self.log(format_args!("Unable to {action}: {e}"));
+            }
+        }
+    }
+
+    /// Send replies to Unix datagram peers, logging any which fail.
+    #[cfg(unix)]
+    async fn send_unix_replies(
+        &self,
+        bind: &tokio::net::UnixDatagram,
+        replies: Vec<Ack<Option<std::path::PathBuf>>>,
+    ) {
+        for (action, reply, peer) in replies {
+            // Only peers which are bound to a path can be replied to.
+            let Some(peer) = peer else {
+                self.log(format_args!("Unable to {action}: peer socket is unnamed"));
+                continue;
+            };
+            if let Err(e) = bind.send_to(&reply, peer).await {
+                self.log(format_args!("Unable to {action}: {e}"));
+            }
         }
     }
 
     pub async fn serve(&mut self) -> crate::Result<()> {
-        match self.protocol {
-            Protocol::Tcp => {
-                let bind = TcpListener::bind(self.addr).await?;
-                eprintln!("Listening on tcp://{}", bind.local_addr()?);
+        if self.protocol.is_websocket() {
+            return Err(format!("serving {} is not supported", self.protocol).into());
+        }
+        if self.multicast_group.is_some() && self.protocol != Protocol::Udp {
+            return Err(format!(
+                "multicast groups can only be joined over udp, not {}",
+                self.protocol
+            )
+            .into());
+        }
+        if (self.ack_every.is_some() || self.flush_interval.is_some()) && self.protocol.is_stream()
+        {
+            return Err(format!(
+                "batching acknowledgements is not supported over {}",
+                self.protocol
+            )
+            .into());
+        }
+        if !self.sni_routes.is_empty() && (self.measure_only || self.protocol != Protocol::Tls) {
+            return Err("routing by server name is only supported when serving tls".into());
+        }
+        if self.max_connections.is_some()
+            && (self.measure_only || !matches!(self.protocol, Protocol::Tcp | Protocol::Tls))
+        {
+            return Err(match self.measure_only {
+                true => "limiting connections is not supported when only measuring".into(),
+                false => format!(
+                    "limiting connections is not supported over {}",
+                    self.protocol
+                )
+                .into(),
+            });
+        }
+        if let Some(chaos) = self.chaos {
+            if self.measure_only {
+                return Err("injecting faults is not supported when only measuring".into());
+            }
+            let supported = match self.protocol {
+                Protocol::Tcp | Protocol::Tls => true,
+                Protocol::Udp | Protocol::UnixDatagram => chaos.is_drop_only(),
+                _ => false,
+            };
+            if !supported {
+                return Err(
+                    format!("injecting {chaos} is not supported over {}", self.protocol).into(),
+                );
+            }
+            self.log(format_args!("Injecting faults: {chaos}"));
+        }
+        if self.control_socket.is_some() && (self.measure_only || !self.protocol.is_stream()) {
+            return Err(match self.measure_only {
+                true => "listing connections is not supported when only measuring".into(),
+                false => format!(
+                    "listing connections is not supported over {}",
+                    self.protocol
+                )
+                .into(),
+            });
+        }
+        let _metrics = self.start_metrics().await?;
+        let _health = self.start_health().await?;
+        let _control = self.start_control()?;
+        let Some(token) = self.cancellation.clone() else {
+            return self.listen().await;
+        };
+        // Any connection being handled is dropped with the listener.
+        tokio::select! {
+            served = self.listen() => served,
+            _ = token.cancelled() => {
+                self.delayed.abort_all();
+                Ok(())
+            }
+        }
+    }
+
+    /// Listen on the endpoint, handling everything received until the
+    /// listener fails.
+    async fn listen(&mut self) -> crate::Result<()> {
+        if self.measure_only {
+            return self.measure().await;
+        }
+        match (self.protocol.clone(), self.endpoint.resolved()?) {
+            (Protocol::Tcp, Endpoint::Inet(addr)) => {
+                let bind = self.bind_tcp(addr)?;
+                self.listening(Some(bind.local_addr()?));
 
-                while let Ok((mut stream, _addr)) = bind.accept().await {
-                    let mut s = String::new();
-                    match stream.read_to_string(&mut s).await {
-                        Ok(_) => writeln!(self.buffer, "{s}")?,
-                        Err(e) => eprintln!("Unable to read stream: {e}"),
+                let mut hangup = Hangup::new()?;
+                let mut queue = self.connection_queue();
+
+                loop {
+                    let (stream, addr) = match queue.pop() {
+                        Some(queued) => queued,
+                        None => tokio::select! {
+                            accepted = bind.accept() => match accepted {
+                                Ok(accepted) => accepted,
+                                Err(_) => break,
+                            },
+                            _ = hangup.recv() => {
+                                self.log_summary();
+                                continue;
+                            }
+                            changes = next_reload(&mut self.config_watcher) => {
+                                self.apply(changes);
+                                continue;
+                            }
+                        },
+                    };
+                    self.accepted(&stream);
+                    let Some((stream, close_after)) = self.inject_fault(stream) else {
+                        continue;
+                    };
+                    queue
+                        .accept_while(&bind, self.handle_stream(stream, addr, close_after, false))
+                        .await?;
+                }
+            }
+            #[cfg(unix)]
+            (Protocol::Unix, Endpoint::Unix(path)) => {
+                let _file = SocketFile::claim(&path)?;
+                let bind = unix::bind_listener(&path)?;
+                self.listening(None);
+
+                let mut hangup = Hangup::new()?;
+
+                loop {
+                    let stream = tokio::select! {
+                        accepted = bind.accept() => match accepted {
+                            Ok((stream, _)) => stream,
+                            Err(_) => break,
+                        },
+                        _ = hangup.recv() => {
+                            self.log_summary();
+                            continue;
+                        }
+                        changes = next_reload(&mut self.config_watcher) => {
+                            self.apply(changes);
+                            continue;
+                        }
+                    };
+                    self.handle_stream(stream, UNIX_PEER, None, false).await?;
+                }
+            }
+            (Protocol::Udp, Endpoint::Inet(addr)) => {
+                let bind = self.bind_udp(addr)?;
+                let mirror = self.mirror_socket().await?;
+                self.listening(Some(bind.local_addr()?));
+                let mut hangup = Hangup::new()?;
+                let mut acks = self.ack_batch();
+                let mut buf = vec![0; READ_BUFFER_SIZE];
+                loop {
+                    let (len, addr) = tokio::select! {
+                        received = bind.recv_from(&mut buf) => match received {
+                            Ok(received) => received,
+                            Err(_) => continue,
+                        },
+                        _ = hangup.recv() => {
+                            self.log_summary();
+                            continue;
+                        }
+                        changes = next_reload(&mut self.config_watcher) => {
+                            self.apply(changes);
+                            continue;
+                        }
+                        _ = acks.due() => {
+                            self.send_udp_replies(&bind, acks.take()).await;
+                            continue;
+                        }
+                    };
+                    self.mirror(&mirror, &buf[0..len]).await;
+                    let replies = self.handle_datagram(addr, &buf[0..len]).await?;
+                    acks.push(addr, replies);
+                    if acks.is_full() {
+                        self.send_udp_replies(&bind, acks.take()).await;
                     }
                 }
             }
-            Protocol::Udp => {
-                let bind = UdpSocket::bind(self.addr).await?;
-                eprintln!("Listening on udp://{}", bind.local_addr()?);
+            #[cfg(unix)]
+            (Protocol::UnixDatagram, Endpoint::Unix(path)) => {
+                let _file = SocketFile::claim(&path)?;
+                let bind = unix::bind_datagram(&path)?;
+                self.listening(None);
+                let mut hangup = Hangup::new()?;
+                let mut acks = self.ack_batch();
+                let mut buf = vec![0; READ_BUFFER_SIZE];
                 loop {
-                    let mut buf = [0; 1024];
-                    while let Ok((len, _addr)) = bind.recv_from(&mut buf).await {
-                        writeln!(self.buffer, "{}", String::from_utf8_lossy(&buf[0..len]))?;
+                    let (len, peer) = tokio::select! {
+                        received = bind.recv_from(&mut buf) => match received {
+                            Ok(received) => received,
+                            Err(_) => continue,
+                        },
+                        _ = hangup.recv() => {
+                            self.log_summary();
+                            continue;
+                        }
+                        changes = next_reload(&mut self.config_watcher) => {
+                            self.apply(changes);
+                            continue;
+                        }
+                        _ = acks.due() => {
+                            self.send_unix_replies(&bind, acks.take()).await;
+                            continue;
+                        }
+                    };
+                    let replies = self.handle_datagram(UNIX_PEER, &buf[0..len]).await?;
+                    let peer = peer.as_pathname().map(|path| path.to_path_buf());
+                    acks.push(peer, replies);
+                    if acks.is_full() {
+                        self.send_unix_replies(&bind, acks.take()).await;
                     }
                 }
             }
+            (Protocol::Tls, Endpoint::Inet(addr)) => {
+                let acceptor = self.tls_acceptor()?;
+                let bind = self.bind_tcp(addr)?;
+                self.listening(Some(bind.local_addr()?));
+
+                let mut hangup = Hangup::new()?;
+                let mut queue = self.connection_queue();
+
+                loop {
+                    let (stream, addr) = match queue.pop() {
+                        Some(queued) => queued,
+                        None => tokio::select! {
+                            accepted = bind.accept() => match accepted {
+                                Ok(accepted) => accepted,
+                                Err(_) => break,
+                            },
+                            _ = hangup.recv() => {
+                                self.log_summary();
+                                continue;
+                            }
+                            changes = next_reload(&mut self.config_watcher) => {
+                                self.apply(changes);
+                                continue;
+                            }
+                        },
+                    };
+                    self.accepted(&stream);
+                    let Some((stream, close_after)) = self.inject_fault(stream) else {
+                        continue;
+                    };
+                    queue
+                        .accept_while(&bind, self.handle_tls(&acceptor, stream, addr, close_after))
+                        .await?;
+                }
+            }
+            (protocol, endpoint) => {
+                return Err(transport::mismatched_endpoint(&protocol, &endpoint).into())
+            }
         }
         unreachable!("This is a blocking call");
     }
+
+    /// Complete the TLS handshake of a connection which was accepted from
+    /// `addr`, then handle it as a stream.
+    async fn handle_tls(
+        &mut self,
+        acceptor: &TlsAcceptor,
+        stream: TcpStream,
+        addr: SocketAddr,
+        close_after: Option<u64>,
+    ) -> crate::Result<()> {
+        let stream = match acceptor.accept(stream).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.log(format_args!("Unable to complete TLS handshake: {e}"));
+                return Ok(());
+            }
+        };
+        self.sni = stream
+            .get_ref()
+            .1
+            .server_name()
+            .map(str::to_ascii_lowercase)
+            .filter(|name| self.sni_routes.contains_key(name));
+        // Close with a close_notify, so a client waiting for the reply can
+        // tell that it is complete.
+        let close_notify = !self.close_with_rst;
+        let handled = self
+            .handle_stream(stream, addr, close_after, close_notify)
+            .await;
+        self.sni = None;
+        handled
+    }
+
+    /// Handle a stream which was accepted from `addr`, counting it as an
+    /// active connection until it is closed. When closing it early, at most
+    /// `close_after` bytes are read and nothing is replied. A reply which is
+    /// delayed is sent in the background, so that the connections which
+    /// follow are read meanwhile, and the stream is shut down after it when
+    /// `close_notify` is set. Once as many connections are being closed as
+    /// are allowed at once, this waits for one of them to close.
+    async fn handle_stream<S>(
+        &mut self,
+        stream: S,
+        addr: SocketAddr,
+        close_after: Option<u64>,
+        close_notify: bool,
+    ) -> crate::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        self.stats.record_connection_opened();
+        let opened = Instant::now();
+        let mut stream = self.connections.track(addr, stream);
+        let read = match close_after {
+            Some(limit) => self
+                .read_partial(&mut stream, addr, limit)
+                .await
+                .map(|()| Pending::default()),
+            None => self.read_stream(&mut stream, addr).await,
+        };
+        let pending = read.inspect_err(|_| {
+            self.stats.record_connection_closed(opened.elapsed());
+        })?;
+        let delayed = !pending.delay.is_zero();
+        let closing = pending.close(
+            stream,
+            close_notify,
+            Arc::clone(&self.stats),
+            opened,
+            self.log,
+        );
+        if delayed {
+            while self.delayed.try_join_next().is_some() {}
+            let count = Arc::clone(&self.closing);
+            count.fetch_add(1, Ordering::Relaxed);
+            self.delayed.spawn(async move {
+                closing.await;
+                count.fetch_sub(1, Ordering::Relaxed);
+            });
+            if let Some((limit, _)) = self.max_connections {
+                while self.closing.load(Ordering::Relaxed) >= limit {
+                    self.delayed.join_next().await;
+                }
+            }
+        } else {
+            closing.await;
+        }
+        Ok(())
+    }
+
+    /// Read at most `limit` bytes of a message from a stream which was
+    /// accepted from `addr`, to close it without a reply.
+    async fn read_partial<S>(
+        &mut self,
+        stream: &mut S,
+        addr: SocketAddr,
+        limit: u64,
+    ) -> crate::Result<()>
+    where
+        S: AsyncRead + Unpin,
+    {
+        self.stats.record_early_close();
+        let mut message = Vec::new();
+        match stream.take(limit).read_to_end(&mut message).await {
+            Ok(_) => {
+                self.record(addr, &message)?;
+                self.output(&message)?;
+            }
+            Err(e) => self.log(format_args!("Unable to read stream: {e}")),
+        }
+        Ok(())
+    }
+
+    /// Read a message from a stream which was accepted from `addr`, returning
+    /// the reply to it when responding from a script, reflecting timing or
+    /// replying with a digest, alongside how long to wait before sending it
+    /// or, without a reply, closing the stream.
+    async fn read_stream<S>(&mut self, stream: &mut S, addr: SocketAddr) -> crate::Result<Pending>
+    where
+        S: AsyncRead + Unpin,
+    {
+        let mut message = Vec::new();
+        if let Err(e) = stream.read_to_end(&mut message).await {
+            self.log(format_args!("Unable to read stream: {e}"));
+            return Ok(Pending::default());
+        }
+        let received = timing::now();
+        self.record(addr, &message)?;
+        let mut pending = Pending {
+            delay: self.delay.map(|delay| delay.sample()).unwrap_or_default(),
+            response: None,
+        };
+        if self.respond_script.is_some() {
+            if let Some(rule) = self.matching_rule(&message) {
+                pending.delay += rule.delay();
+                pending.response = Some(Response::Bytes("respond", rule.response().to_vec()));
+            }
+        } else if self.reflect_timing {
+            let (sent, body) = timing::split_timestamp(&message);
+            pending.response = Some(Response::Timing {
+                peer: addr,
+                sent,
+                received,
+            });
+            self.output(body)?;
+            return Ok(pending);
+        } else if let Some(digest) = self.reply_digest {
+            pending.response = Some(Response::Bytes(
+                "reply with the digest",
+                digest.reply(&message),
+            ));
+        }
+        self.output(&message)?;
+        Ok(pending)
+    }
+
+    /// Handle a datagram which was received from `addr`, returning the
+    /// replies to send back in order, alongside the action they are for.
+    async fn handle_datagram(
+        &mut self,
+        addr: SocketAddr,
+        mut message: &[u8],
+    ) -> crate::Result<Vec<(&'static str, Vec<u8>)>> {
+        let mut replies = Vec::new();
+        if let Some(Fault::Drop) = self.chaos.as_ref().and_then(Chaos::draw) {
+            self.stats.record_dropped();
+            return Ok(replies);
+        }
+        self.record(addr, message)?;
+        self.wait_to_reply().await;
+        if let Some(rule) = self.matching_rule(message) {
+            let response = rule.response().to_vec();
+            tokio::time::sleep(rule.delay()).await;
+            replies.push(("respond", response));
+        }
+        if let Some(digest) = self.reply_digest {
+            replies.push(("reply with the digest", digest.reply(message)));
+        }
+        if self.reflect_timing {
+            let (sent, body) = timing::split_timestamp(message);
+            let reply = timing::reply(addr, sent, timing::now());
+            replies.push(("reflect timing", reply.into_bytes()));
+            message = body;
+        }
+        self.output(message)?;
+        Ok(replies)
+    }
+
+    async fn measure(&self) -> crate::Result<()> {
+        let mut buf = vec![0; READ_BUFFER_SIZE];
+        let mut report = tokio::time::interval(MEASURE_INTERVAL);
+        let mut last = (Instant::now(), 0, 0);
+        let mut hangup = Hangup::new()?;
+
+        match (self.protocol.clone(), self.endpoint.resolved()?) {
+            (Protocol::Tcp, Endpoint::Inet(addr)) => {
+                let bind = self.bind_tcp(addr)?;
+                self.listening(Some(bind.local_addr()?));
+                loop {
+                    tokio::select! {
+                        accepted = bind.accept() => {
+                            let Ok((mut stream, addr)) = accepted else { continue };
+                            self.accepted(&stream);
+                            let len = self.read_len(&mut stream, &mut buf).await;
+                            self.stats.record_message(len);
+                            self.stats.record_peer(addr.ip());
+                        }
+                        _ = report.tick() => last = self.report_rates(last),
+                        _ = hangup.recv() => last = self.restart_interval(),
+                    }
+                }
+            }
+            #[cfg(unix)]
+            (Protocol::Unix, Endpoint::Unix(path)) => {
+                let _file = SocketFile::claim(&path)?;
+                let bind = unix::bind_listener(&path)?;
+                self.listening(None);
+                loop {
+                    tokio::select! {
+                        accepted = bind.accept() => {
+                            let Ok((mut stream, _addr)) = accepted else { continue };
+                            let len = self.read_len(&mut stream, &mut buf).await;
+                            self.stats.record_message(len);
+                        }
+                        _ = report.tick() => last = self.report_rates(last),
+                        _ = hangup.recv() => last = self.restart_interval(),
+                    }
+                }
+            }
+            (Protocol::Udp, Endpoint::Inet(addr)) => {
+                let bind = self.bind_udp(addr)?;
+                let mirror = self.mirror_socket().await?;
+                self.listening(Some(bind.local_addr()?));
+                loop {
+                    tokio::select! {
+                        received = bind.recv_from(&mut buf) => {
+                            if let Ok((len, addr)) = received {
+                                self.mirror(&mirror, &buf[..len]).await;
+                                self.stats.record_message(len as u64);
+                                self.stats.record_peer(addr.ip());
+                            }
+                        }
+                        _ = report.tick() => last = self.report_rates(last),
+                        _ = hangup.recv() => last = self.restart_interval(),
+                    }
+                }
+            }
+            #[cfg(unix)]
+            (Protocol::UnixDatagram, Endpoint::Unix(path)) => {
+                let _file = SocketFile::claim(&path)?;
+                let bind = unix::bind_datagram(&path)?;
+                self.listening(None);
+                loop {
+                    tokio::select! {
+                        received = bind.recv_from(&mut buf) => {
+                            if let Ok((len, _addr)) = received {
+                                self.stats.record_message(len as u64);
+                            }
+                        }
+                        _ = report.tick() => last = self.report_rates(last),
+                        _ = hangup.recv() => last = self.restart_interval(),
+                    }
+                }
+            }
+            (Protocol::Tls, Endpoint::Inet(addr)) => {
+                let acceptor = self.tls_acceptor()?;
+                let bind = self.bind_tcp(addr)?;
+                self.listening(Some(bind.local_addr()?));
+                loop {
+                    tokio::select! {
+                        accepted = bind.accept() => {
+                            let Ok((stream, addr)) = accepted else { continue };
+                            self.accepted(&stream);
+                            let Ok(mut stream) = acceptor.accept(stream).await else { continue };
+                            let len = self.read_len(&mut stream, &mut buf).await;
+                            self.stats.record_message(len);
+                            self.stats.record_peer(addr.ip());
+                        }
+                        _ = report.tick() => last = self.report_rates(last),
+                        _ = hangup.recv() => last = self.restart_interval(),
+                    }
+                }
+            }
+            (protocol, endpoint) => {
+                Err(transport::mismatched_endpoint(&protocol, &endpoint).into())
+            }
+        }
+    }
+
+    /// Read a stream to the end into a reused buffer, returning its length.
+    async fn read_len<S: AsyncRead + Unpin>(&self, stream: &mut S, buf: &mut [u8]) -> u64 {
+        self.stats.record_connection_opened();
+        let opened = Instant::now();
+        let mut len = 0;
+        loop {
+            match stream.read(buf).await {
+                Ok(0) => break,
+                Ok(n) => len += n as u64,
+                Err(e) => {
+                    self.log(format_args!("Unable to read stream: {e}"));
+                    break;
+                }
+            }
+        }
+        self.stats.record_connection_closed(opened.elapsed());
+        len
+    }
+
+    /// Log a summary on SIGHUP, returning the new baseline for the rates.
+    fn restart_interval(&self) -> (Instant, u64, u64) {
+        self.log_summary();
+        (Instant::now(), self.stats.messages(), self.stats.bytes())
+    }
+
+    /// Log the totals received so far, alongside what was received since
+    /// the last summary, which starts a new interval. This happens on SIGHUP
+    /// while serving.
+    pub fn log_summary(&self) {
+        let interval = self.stats.take_interval();
+        self.log(format_args!(
+            "Summary: {} messages, {} bytes in {:.1}s; {} messages, {} bytes in the last {:.1}s",
+            self.stats.messages(),
+            self.stats.bytes(),
+            self.stats.elapsed() as f64 / 1000.0,
+            interval.messages,
+            interval.bytes,
+            interval.elapsed.as_secs_f64()
+        ));
+        let peers = self.stats.distinct_peers();
+        if peers.count > 0 {
+            self.log(format_args!(
+                "Peers: {}{} distinct addresses",
+                if peers.approximate { "~" } else { "" },
+                peers.count
+            ));
+        }
+        if let Some((limit, _)) = self.max_connections {
+            self.log(format_args!(
+                "Connections: {} active, {} queued, {} rejected of at most {limit}",
+                self.stats.active_connections(),
+                self.stats.queued_connections(),
+                self.stats.rejected_connections()
+            ));
+        }
+        if self.chaos.is_some() {
+            self.log(format_args!(
+                "Chaos: {} dropped, {} reset, {} closed early",
+                self.stats.dropped(),
+                self.stats.resets(),
+                self.stats.early_closes()
+            ));
+        }
+        if let Some(sizes) = self.stats.message_sizes() {
+            self.log(format_args!(
+                "Message sizes: min {}, p50 {}, p90 {}, p99 {}, max {} bytes",
+                sizes.min, sizes.p50, sizes.p90, sizes.p99, sizes.max
+            ));
+        }
+    }
+
+    /// Print the rates since the last report, returning the new baseline.
+    fn report_rates(&self, (at, messages, bytes): (Instant, u64, u64)) -> (Instant, u64, u64) {
+        let now = (Instant::now(), self.stats.messages(), self.stats.bytes());
+        let secs = now.0.duration_since(at).as_secs_f64();
+        if now.1 > messages {
+            self.log(format_args!(
+                "Received: {:.0} messages/s, {:.0} bytes/s ({} messages, {} bytes total)",
+                (now.1 - messages) as f64 / secs,
+                (now.2 - bytes) as f64 / secs,
+                now.1,
+                now.2
+            ));
+        }
+        now
+    }
+}
+
+/// Line of JSON announcing that a listener of the service is bound to the
+/// address.
+pub(crate) fn announcement(service: &str, protocol: &str, addr: SocketAddr) -> String {
+    #[derive(Serialize)]
+    struct Announcement<'a> {
+        service: &'a str,
+        protocol: &'a str,
+        address: SocketAddr,
+    }
+    serde_json::to_string(&Announcement {
+        service,
+        protocol,
+        address: addr,
+    })
+    .expect("announcements serialize to JSON")
+}
+
+/// Reply to a datagram, alongside the action it is for and the peer it is
+/// sent to.
+type Ack<A> = (&'static str, Vec<u8>, A);
+
+/// Replies to datagrams which are held back to be sent together, once enough
+/// messages have been replied to or the oldest reply has waited long enough.
+struct AckBatch<A> {
+    every: usize,
+    flush_interval: Option<Duration>,
+    pending: Vec<Ack<A>>,
+    /// Messages with a pending reply.
+    messages: usize,
+    /// When the oldest pending reply was queued.
+    since: Option<Instant>,
+}
+
+impl<A: Clone> AckBatch<A> {
+    fn new(every: usize, flush_interval: Option<Duration>) -> Self {
+        Self {
+            every,
+            flush_interval,
+            pending: Vec::new(),
+            messages: 0,
+            since: None,
+        }
+    }
+
+    /// Queue the replies to a message from `peer`.
+    fn push(&mut self, peer: A, replies: Vec<(&'static str, Vec<u8>)>) {
+        if replies.is_empty() {
+            return;
+        }
+        self.messages += 1;
+        self.since.get_or_insert_with(Instant::now);
+        self.pending.extend(
+            replies
+                .into_iter()
+                .map(|(action, reply)| (action, reply, peer.clone())),
+        );
+    }
+
+    fn is_full(&self) -> bool {
+        self.messages >= self.every
+    }
+
+    /// Wait until the pending replies should be flushed, which is never when
+    /// there are none or there is no flush interval.
+    async fn due(&self) {
+        match (self.since, self.flush_interval) {
+            (Some(since), Some(interval)) => tokio::time::sleep_until(since + interval).await,
+            _ => std::future::pending().await,
+        }
+    }
+
+    /// Take the pending replies, in the order they were queued.
+    fn take(&mut self) -> Vec<Ack<A>> {
+        self.messages = 0;
+        self.since = None;
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// Connections which were accepted while another was being read from, and
+/// wait to be read from in the order they arrived, so that no more than the
+/// limit of a [`Server`] are open at once.
+struct ConnectionQueue {
+    limit: Option<(usize, ConnectionOverflow)>,
+    waiting: VecDeque<(TcpStream, SocketAddr)>,
+    /// Number of connections with a delayed reply which are not closed yet.
+    closing: Arc<AtomicUsize>,
+    stats: Arc<ServerStatistics>,
+}
+
+impl ConnectionQueue {
+    /// Take the connection which has waited longest.
+    fn pop(&mut self) -> Option<(TcpStream, SocketAddr)> {
+        let next = self.waiting.pop_front()?;
+        self.stats.record_connection_dequeued();
+        Some(next)
+    }
+
+    /// Whether another connection can wait, alongside the one being read and
+    /// those being closed after a delay.
+    fn has_room(&self, limit: usize) -> bool {
+        self.waiting.len() + self.closing.load(Ordering::Relaxed) + 1 < limit
+    }
+
+    /// Complete `handling` a connection, meanwhile accepting those which
+    /// arrive to wait or be rejected. Without a limit, nothing is accepted
+    /// until `handling` completes.
+    async fn accept_while<T>(
+        &mut self,
+        bind: &TcpListener,
+        handling: impl Future<Output = T>,
+    ) -> T {
+        tokio::pin!(handling);
+        loop {
+            let accepting = match self.limit {
+                Some((limit, ConnectionOverflow::Queue)) => self.has_room(limit),
+                Some((_, ConnectionOverflow::Reject)) => true,
+                None => false,
+            };
+            tokio::select! {
+                handled = &mut handling => return handled,
+                accepted = bind.accept(), if accepting => {
+                    let Ok((stream, addr)) = accepted else { continue };
+                    match self.limit {
+                        Some((limit, _)) if self.has_room(limit) => {
+                            self.waiting.push_back((stream, addr));
+                            self.stats.record_connection_queued();
+                        }
+                        // Dropping the stream closes it before it is read.
+                        _ => self.stats.record_connection_rejected(),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What is left to do with a connection once its message was read.
+#[derive(Default)]
+struct Pending {
+    /// Time waited before replying or, without a reply, closing it.
+    delay: Duration,
+    response: Option<Response>,
+}
+
+/// Reply to a message read from a connection.
+enum Response {
+    /// Bytes to reply with, alongside the action they are for.
+    Bytes(&'static str, Vec<u8>),
+    /// Timestamps reflected to the writer, where the time the reply was
+    /// transmitted is taken as it is sent.
+    Timing {
+        peer: SocketAddr,
+        sent: Option<u64>,
+        received: u64,
+    },
+}
+
+impl Pending {
+    /// Wait for the delay, send the reply and close the stream, recording how
+    /// long the connection was open. With `close_notify`, the stream is shut
+    /// down rather than only dropped.
+    async fn close<S: AsyncWrite + Unpin>(
+        self,
+        mut stream: S,
+        close_notify: bool,
+        stats: Arc<ServerStatistics>,
+        opened: Instant,
+        log: bool,
+    ) {
+        if !self.delay.is_zero() {
+            tokio::time::sleep(self.delay).await;
+        }
+        let reply = self.response.map(|response| match response {
+            Response::Bytes(action, reply) => (action, reply),
+            Response::Timing {
+                peer,
+                sent,
+                received,
+            } => (
+                "reflect timing",
+                timing::reply(peer, sent, received).into_bytes(),
+            ),
+        });
+        if let Some((action, reply)) = reply {
+            if let Err(e) = stream.write_all(&reply).await {
+                if log {
+                    eprintln!("Unable to {action}: {e}");
+                }
+            }
+        }
+        if close_notify {
+            let _ = stream.shutdown().await;
+        }
+        drop(stream);
+        stats.record_connection_closed(opened.elapsed());
+    }
+}
+
+/// Wait for the next changes to the watched config file, which never
+/// completes when no file is watched. The error is a message, as the
+/// listening future must remain `Send`.
+async fn next_reload(watcher: &mut Option<ConfigWatcher>) -> Result<Vec<Change>, String> {
+    match watcher {
+        Some(watcher) => watcher.changed().await.map_err(|e| e.to_string()),
+        None => std::future::pending().await,
+    }
+}
+
+/// Stream of SIGHUP signals, used to request a summary from a running server.
+/// This never yields on platforms without signals.
+struct Hangup {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl Hangup {
+    fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if self.signal.recv().await.is_some() {
+            return;
+        }
+        std::future::pending().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, time::Instant};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::Server;
+    use crate::{statistics::Statistics, Protocol, SocketManager, WriteOptions};
+
+    #[tokio::test]
+    async fn serve_message_sizes_large_datagram() {
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Udp,
+            std::io::sink(),
+        )
+        .without_logs();
+        let stats = server.statistics();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(&[0; 4096], addr).await.unwrap();
+        while stats.messages() == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let sizes = stats.message_sizes().unwrap();
+        assert_eq!((sizes.min, sizes.max), (4096, 4096));
+        assert_eq!(stats.bytes(), 4096);
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn serve_reply_digest_large_datagram() {
+        use crate::Digest;
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Udp,
+            std::io::sink(),
+        )
+        .reply_digest(Digest::Sha256)
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(addr).await.unwrap();
+        // Larger than a single KiB, so the whole datagram must be hashed.
+        let message: Vec<u8> = (0..2048).map(|i| i as u8).collect();
+        client.send(&message).await.unwrap();
+        let mut reply = [0; 128];
+        let len = tokio::time::timeout(std::time::Duration::from_secs(1), client.recv(&mut reply))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&reply[..len], Digest::Sha256.reply(&message));
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn serve_sni_route() {
+        use crate::{
+            events::SharedBuffer,
+            tls::{TestCertificate, TlsConfig, TlsServerConfig},
+        };
+
+        let certificate = TestCertificate::new();
+        let (out, routed) = (SharedBuffer::default(), SharedBuffer::default());
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tls,
+            out.clone(),
+        )
+        .tls(TlsServerConfig::load(&certificate.cert, &certificate.key).unwrap())
+        .sni_route("API.localhost", routed.clone())
+        .without_logs();
+        let received = server.statistics();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        for (name, input) in [("api.localhost", b"api"), ("localhost", b"web")] {
+            let s = SocketManager::new(
+                addr,
+                input,
+                Protocol::Tls,
+                WriteOptions::Count(1),
+                Statistics::new(),
+            )
+            .with_tls(
+                TlsConfig::new()
+                    .with_ca_file(&certificate.cert)
+                    .with_server_name(name),
+            );
+            s.write().await.unwrap();
+        }
+        while received.messages() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(routed.0.lock().unwrap().as_slice(), b"api\n");
+        assert_eq!(out.0.lock().unwrap().as_slice(), b"web\n");
+        handle.abort();
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .sni_route("api.localhost", std::io::sink());
+        let err = server.serve().await.unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "routing by server name is only supported when serving tls"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serve_control_socket() {
+        use crate::transport::TemporarySocketPath;
+
+        let path = TemporarySocketPath::new();
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .control_socket(path.0.clone())
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+        let command = ["connections".to_string()];
+        let connections = || crate::send_command(&path.0, &command);
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"hello").await.unwrap();
+        let peer = client.local_addr().unwrap().to_string();
+        let listed = loop {
+            let listed = connections().await.unwrap();
+            if listed.contains(&peer) && listed.trim_end().ends_with(" 5") {
+                break listed;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        };
+        let lines: Vec<_> = listed.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("PEER"));
+
+        // The connection is removed from the table once it closes.
+        drop(client);
+        while connections().await.unwrap() != "no open connections\n" {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        let err = crate::send_command(&path.0, &["top".to_string()])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown command 'top', expected connections"
+        );
+        handle.abort();
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Udp,
+            std::io::sink(),
+        )
+        .control_socket(path.0.clone());
+        let err = server.serve().await.unwrap_err().to_string();
+        assert_eq!(err, "listing connections is not supported over udp");
+    }
+
+    #[tokio::test]
+    async fn serve_multicast_group() {
+        use std::net::Ipv4Addr;
+
+        let group = Ipv4Addr::new(239, 4, 4, 4);
+        let mut server = Server::new(
+            "0.0.0.0:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Udp,
+            std::io::sink(),
+        )
+        .multicast_group(group.into())
+        .without_logs();
+        let stats = server.statistics();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let port = bound
+            .wait_for(Option::is_some)
+            .await
+            .unwrap()
+            .unwrap()
+            .port();
+
+        let s = SocketManager::new(
+            SocketAddr::from((group, port)),
+            b"group",
+            Protocol::Udp,
+            WriteOptions::Count(3),
+            Statistics::new(),
+        )
+        .with_multicast_ttl(2);
+        s.write().await.unwrap();
+        assert_eq!(s.successful_requests(), 3);
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while stats.messages() < 3 {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        handle.abort();
+
+        let mut tcp = Server::new(
+            "0.0.0.0:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .multicast_group(group.into())
+        .without_logs();
+        assert!(tcp.serve().await.is_err());
+    }
+
+    #[test]
+    fn serve_announcement() {
+        let addr = "127.0.0.1:40123".parse().unwrap();
+        assert_eq!(
+            crate::server::announcement("serve", "udp", addr),
+            r#"{"service":"serve","protocol":"udp","address":"127.0.0.1:40123"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn serve_health() {
+        let health = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let health_addr = health.local_addr().unwrap();
+        drop(health);
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .health_addr(health_addr)
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        bound.wait_for(Option::is_some).await.unwrap();
+
+        for path in ["/ready", "/live"] {
+            let mut stream = tokio::net::TcpStream::connect(health_addr).await.unwrap();
+            let request = format!("GET {path} HTTP/1.1\r\n\r\n");
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        }
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn serve_binary() {
+        use crate::{events::SharedBuffer, Render};
+
+        let out = SharedBuffer::default();
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            out.clone(),
+        )
+        .render(Render::Hex)
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"\xde\xad\xbe\xef").await.unwrap();
+        drop(stream);
+        while out.0.lock().unwrap().is_empty() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(out.0.lock().unwrap().as_slice(), b"deadbeef\n");
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn serve_delay() {
+        use crate::{Delay, Digest};
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .delay("50ms..60ms".parse::<Delay>().unwrap())
+        .reply_digest(Digest::Sha256)
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let start = tokio::time::Instant::now();
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"slow").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+        assert_eq!(reply, Digest::Sha256.reply(b"slow"));
+        handle.abort();
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .delay("300ms".parse::<Delay>().unwrap())
+        .reply_digest(Digest::Sha256)
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        // Each connection is delayed on its own, rather than after the one
+        // before it.
+        let start = tokio::time::Instant::now();
+        let clients = [b"one", b"two"].map(|input| {
+            tokio::spawn(async move {
+                let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+                stream.write_all(input).await.unwrap();
+                stream.shutdown().await.unwrap();
+                let mut reply = Vec::new();
+                stream.read_to_end(&mut reply).await.unwrap();
+                assert_eq!(reply, Digest::Sha256.reply(input));
+                start.elapsed()
+            })
+        });
+        for client in clients {
+            let elapsed = client.await.unwrap();
+            assert!(elapsed >= std::time::Duration::from_millis(300));
+            assert!(
+                elapsed < std::time::Duration::from_millis(550),
+                "{elapsed:?}"
+            );
+        }
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn serve_max_connections() {
+        use crate::{events::SharedBuffer, ConnectionOverflow};
+
+        async fn until(condition: impl Fn() -> bool) {
+            while !condition() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }
+
+        for overflow in [ConnectionOverflow::Reject, ConnectionOverflow::Queue] {
+            let out = SharedBuffer::default();
+            let mut server = Server::new(
+                "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+                Protocol::Tcp,
+                out.clone(),
+            )
+            .max_connections(2, overflow)
+            .without_logs();
+            let stats = server.statistics();
+            let mut bound = server.bound_addr();
+            let handle =
+                tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+            let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+            // The first connection is held open, so the second waits.
+            let mut held = tokio::net::TcpStream::connect(addr).await.unwrap();
+            held.write_all(b"a").await.unwrap();
+            until(|| stats.active_connections() == 1).await;
+            for message in [b"b", b"c"] {
+                let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+                // A rejected connection may already be closed.
+                let _ = stream.write_all(message).await;
+                let _ = stream.shutdown().await;
+                if message == b"c" && overflow == ConnectionOverflow::Reject {
+                    // The connection beyond the limit is closed unread.
+                    let mut buf = Vec::new();
+                    let _ = stream.read_to_end(&mut buf).await;
+                    assert_eq!(stats.rejected_connections(), 1);
+                }
+            }
+            until(|| stats.queued_connections() == 1).await;
+
+            drop(held);
+            let expected: &[u8] = match overflow {
+                ConnectionOverflow::Reject => b"a\nb\n",
+                // The connection beyond the limit is read once there is room.
+                ConnectionOverflow::Queue => b"a\nb\nc\n",
+            };
+            until(|| out.0.lock().unwrap().len() >= expected.len()).await;
+            assert_eq!(out.0.lock().unwrap().as_slice(), expected);
+            assert_eq!(stats.queued_connections(), 0);
+            handle.abort();
+        }
+
+        // Connections being closed after a delayed reply count towards the
+        // limit, so those beyond it are rejected until they are closed.
+        let out = SharedBuffer::default();
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            out.clone(),
+        )
+        .max_connections(2, ConnectionOverflow::Reject)
+        .delay("300ms".parse::<crate::Delay>().unwrap())
+        .without_logs();
+        let stats = server.statistics();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let mut closing = Vec::new();
+        for message in [b"a", b"b"] {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream.write_all(message).await.unwrap();
+            stream.shutdown().await.unwrap();
+            closing.push(stream);
+        }
+        until(|| out.0.lock().unwrap().len() == 4).await;
+        assert_eq!(stats.active_connections(), 2);
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let _ = stream.write_all(b"c").await;
+        let _ = stream.shutdown().await;
+        let mut buf = Vec::new();
+        let _ = stream.read_to_end(&mut buf).await;
+        assert_eq!(stats.rejected_connections(), 1);
+
+        // Once they are closed, there is room again.
+        for mut stream in closing {
+            stream.read_to_end(&mut buf).await.unwrap();
+        }
+        until(|| stats.active_connections() == 0).await;
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"d").await.unwrap();
+        stream.shutdown().await.unwrap();
+        until(|| out.0.lock().unwrap().len() == 6).await;
+        assert_eq!(out.0.lock().unwrap().as_slice(), b"a\nb\nd\n");
+        assert_eq!(stats.rejected_connections(), 1);
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn serve_reload_config() {
+        use crate::ConfigWatcher;
+
+        let dir = std::env::temp_dir().join(format!("gn-reload-serve-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("gn.toml");
+        let script = dir.join("rules.toml");
+        std::fs::write(&config, "[serve]\n").unwrap();
+        std::fs::write(
+            &script,
+            "[[rule]]\nprefix = \"PING\"\nresponse = \"PONG\"\n",
+        )
+        .unwrap();
+
+        let mut server = Server::new(
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+            Protocol::Tcp,
+            std::io::sink(),
+        )
+        .watch_config(ConfigWatcher::new(&config).unwrap())
+        .without_logs();
+        let mut bound = server.bound_addr();
+        let handle = tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+        let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+
+        let reply = || async {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream.write_all(b"PING").await.unwrap();
+            stream.shutdown().await.unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).await.unwrap();
+            reply
+        };
+        assert_eq!(reply().await, "");
+
+        let table = format!(
+            "[serve]\nrespond-script = {:?}\n",
+            script.display().to_string()
+        );
+        std::fs::write(&config, table).unwrap();
+        // The file is checked every second, after which the script is used.
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        while reply().await != "PONG" {
+            assert!(Instant::now() < deadline, "the script was not reloaded");
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        handle.abort();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn serve_ack_batching() {
+        use std::time::Duration;
+
+        let serve = |server: Server<std::io::Sink>| async move {
+            let mut server = server.reflect_timing().without_logs();
+            let mut bound = server.bound_addr();
+            let handle =
+                tokio::spawn(async move { server.serve().await.map_err(|e| e.to_string()) });
+            let addr = bound.wait_for(Option::is_some).await.unwrap().unwrap();
+            let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client.connect(addr).await.unwrap();
+            (client, handle)
+        };
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut buf = [0; 128];
+
+        let (client, handle) =
+            serve(Server::new(addr, Protocol::Udp, std::io::sink()).ack_every(3)).await;
+        client.send(b"one").await.unwrap();
+        client.send(b"two").await.unwrap();
+        let early = tokio::time::timeout(Duration::from_millis(100), client.recv(&mut buf)).await;
+        assert!(early.is_err(), "replies are held until the batch is full");
+        client.send(b"three").await.unwrap();
+        for _ in 0..3 {
+            tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        handle.abort();
+
+        let server = Server::new(addr, Protocol::Udp, std::io::sink())
+            .ack_every(10)
+            .flush_interval(Duration::from_millis(50));
+        let (client, handle) = serve(server).await;
+        let start = Instant::now();
+        client.send(b"flushed").await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        handle.abort();
+
+        let mut server = Server::new(addr, Protocol::Tcp, std::io::sink()).ack_every(2);
+        assert!(server.serve().await.is_err());
+    }
 }
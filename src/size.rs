@@ -0,0 +1,55 @@
+use std::{fmt::Display, str::FromStr};
+
+/// A number of bytes, parsed from a plain number or one with a decimal (`KB`,
+/// `MB`, `GB`) or binary (`KiB`, `MiB`, `GiB`) unit, such as `1GiB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+        let value: u64 = value
+            .parse()
+            .map_err(|e| format!("invalid size '{s}': {e}"))?;
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1000,
+            "mb" => 1000 * 1000,
+            "gb" => 1000 * 1000 * 1000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            unit => return Err(format!("unknown size unit '{unit}' in '{s}'")),
+        };
+        value
+            .checked_mul(multiplier)
+            .map(Self)
+            .ok_or_else(|| format!("size '{s}' is too large"))
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}B", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ByteSize;
+
+    #[test]
+    fn parse() {
+        assert_eq!("512".parse::<ByteSize>().unwrap(), ByteSize(512));
+        assert_eq!("10KB".parse::<ByteSize>().unwrap(), ByteSize(10_000));
+        assert_eq!("1GiB".parse::<ByteSize>().unwrap(), ByteSize(1 << 30));
+        assert_eq!("4 mib".parse::<ByteSize>().unwrap(), ByteSize(4 << 20));
+        assert!("GiB".parse::<ByteSize>().is_err());
+        assert!("1TB".parse::<ByteSize>().is_err());
+        assert!("99999999999999GiB".parse::<ByteSize>().is_err());
+    }
+}
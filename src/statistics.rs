@@ -1,15 +1,100 @@
-use std::sync::atomic::Ordering;
-use std::sync::Arc;
-use std::{sync::atomic::AtomicU64, time::Instant};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{
+    sync::atomic::AtomicU64,
+    time::{Duration, Instant},
+};
 
 use atomic_float::AtomicF64;
+use hdrhistogram::Histogram;
+
+use crate::peers::{PeerCount, PeerCounter};
 
 pub struct Statistics {
     start_time: Instant,
+    /// Nanoseconds since the start which are left out of the elapsed time.
+    excluded: Arc<AtomicU64>,
+    /// Whether requests are warming up, so what the transport observes of
+    /// their connections is not recorded.
+    warming_up: Arc<AtomicBool>,
     total_bytes: Arc<AtomicU64>,
     success_count: Arc<AtomicU64>,
     failure_count: Arc<AtomicU64>,
+    partial_writes: Arc<AtomicU64>,
+    mismatched_responses: Arc<AtomicU64>,
+    would_block: Arc<AtomicU64>,
+    no_buffer_space: Arc<AtomicU64>,
+    timeouts: Arc<AtomicU64>,
+    unacknowledged: Arc<AtomicU64>,
+    send_failures: Arc<AtomicU64>,
+    connection_resets: Arc<AtomicU64>,
+    premature_closes: Arc<AtomicU64>,
+    retries: Arc<AtomicU64>,
+    connect_retries: Arc<AtomicU64>,
+    resolution_failures: Arc<AtomicU64>,
+    send_queue_peak: Arc<AtomicU64>,
+    connections_opened: Arc<AtomicU64>,
+    connections_recycled: Arc<AtomicU64>,
     throughput: Arc<AtomicF64>,
+    inbound_bytes: Arc<AtomicU64>,
+    inbound_throughput: Arc<AtomicF64>,
+    one_way_delay: DelayRecorder,
+    latency: LatencyRecorder,
+    reply_bytes: Arc<AtomicU64>,
+    round_trip: LatencyRecorder,
+    first_byte: LatencyRecorder,
+    tls_full_handshakes: Arc<AtomicU64>,
+    tls_resumed_handshakes: Arc<AtomicU64>,
+    tls_handshake: LatencyRecorder,
+    /// Requests which waited for a payload from the generator threads, and
+    /// the nanoseconds they waited for in total.
+    generator_waits: Arc<AtomicU64>,
+    generator_wait_ns: Arc<AtomicU64>,
+    schedule: Mutex<SendSchedule>,
+    /// Start time and totals at the beginning of the current interval.
+    interval: Mutex<(Instant, WriteTotals)>,
+}
+
+/// Totals which an interval is the difference of.
+#[derive(Clone, Copy)]
+struct WriteTotals {
+    bytes: u64,
+    requests: u64,
+    failures: u64,
+    generator_waits: u64,
+}
+
+/// Requests written by a [`crate::SocketManager`] within an interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WriteInterval {
+    pub bytes: u64,
+    pub requests: u64,
+    pub failures: u64,
+    /// Requests which waited for a payload from the generator threads, so
+    /// the interval was limited by generating rather than sending.
+    pub generator_waits: u64,
+    pub elapsed: Duration,
+}
+
+impl WriteInterval {
+    /// Requests per second over the interval.
+    pub fn request_rate(&self) -> f64 {
+        self.requests as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Running totals of a [`crate::SocketManager::write`] at one point in the
+/// run, as published to its subscribers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatisticsSnapshot {
+    pub elapsed: Duration,
+    pub bytes: u64,
+    pub requests: u64,
+    pub failures: u64,
+    /// Bytes written per second since the start of the run.
+    pub throughput: f64,
+    pub latency: Option<LatencySummary>,
 }
 
 impl Default for Statistics {
@@ -22,82 +107,1074 @@ impl Statistics {
     pub fn new() -> Self {
         Self {
             start_time: Instant::now(),
+            excluded: Arc::new(AtomicU64::new(0)),
+            warming_up: Arc::new(AtomicBool::new(false)),
             total_bytes: Arc::new(AtomicU64::new(0)),
             success_count: Arc::new(AtomicU64::new(0)),
             failure_count: Arc::new(AtomicU64::new(0)),
+            partial_writes: Arc::new(AtomicU64::new(0)),
+            mismatched_responses: Arc::new(AtomicU64::new(0)),
+            would_block: Arc::new(AtomicU64::new(0)),
+            no_buffer_space: Arc::new(AtomicU64::new(0)),
+            timeouts: Arc::new(AtomicU64::new(0)),
+            unacknowledged: Arc::new(AtomicU64::new(0)),
+            send_failures: Arc::new(AtomicU64::new(0)),
+            connection_resets: Arc::new(AtomicU64::new(0)),
+            premature_closes: Arc::new(AtomicU64::new(0)),
+            retries: Arc::new(AtomicU64::new(0)),
+            connect_retries: Arc::new(AtomicU64::new(0)),
+            resolution_failures: Arc::new(AtomicU64::new(0)),
+            send_queue_peak: Arc::new(AtomicU64::new(0)),
+            connections_opened: Arc::new(AtomicU64::new(0)),
+            connections_recycled: Arc::new(AtomicU64::new(0)),
             throughput: Arc::new(AtomicF64::new(0.0)),
+            inbound_bytes: Arc::new(AtomicU64::new(0)),
+            inbound_throughput: Arc::new(AtomicF64::new(0.0)),
+            one_way_delay: DelayRecorder::new(),
+            latency: LatencyRecorder::new(),
+            reply_bytes: Arc::new(AtomicU64::new(0)),
+            round_trip: LatencyRecorder::new(),
+            first_byte: LatencyRecorder::new(),
+            tls_full_handshakes: Arc::new(AtomicU64::new(0)),
+            tls_resumed_handshakes: Arc::new(AtomicU64::new(0)),
+            tls_handshake: LatencyRecorder::new(),
+            generator_waits: Arc::new(AtomicU64::new(0)),
+            generator_wait_ns: Arc::new(AtomicU64::new(0)),
+            schedule: Mutex::new(SendSchedule::new()),
+            interval: Mutex::new((
+                Instant::now(),
+                WriteTotals {
+                    bytes: 0,
+                    requests: 0,
+                    failures: 0,
+                    generator_waits: 0,
+                },
+            )),
+        }
+    }
+
+    /// Leave a period in which nothing was recorded, such as a warm-up, out
+    /// of the elapsed time and throughput.
+    pub fn exclude(&self, period: Duration) {
+        let nanos = u64::try_from(period.as_nanos()).unwrap_or(u64::MAX);
+        self.excluded.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// Stop or resume recording the connections opened and recycled, their
+    /// TLS handshakes, send queues and inbound bytes, which transports record
+    /// for every request, while requests warm up before being measured.
+    pub(crate) fn set_warming_up(&self, warming_up: bool) {
+        self.warming_up.store(warming_up, Ordering::Relaxed);
+    }
+
+    fn is_warming_up(&self) -> bool {
+        self.warming_up.load(Ordering::Relaxed)
+    }
+
+    /// Time since the statistics were created, less any excluded periods.
+    fn measured(&self) -> Duration {
+        let excluded = Duration::from_nanos(self.excluded.load(Ordering::Relaxed));
+        self.start_time.elapsed().saturating_sub(excluded)
+    }
+
+    /// Summarise the requests written since the last call, starting a new
+    /// interval.
+    pub fn take_interval(&self) -> WriteInterval {
+        let mut interval = self.interval.lock().unwrap();
+        let totals = WriteTotals {
+            bytes: self.total_bytes(),
+            requests: self.request_count(),
+            failures: self.failed_requests(),
+            generator_waits: self.generator_waits(),
+        };
+        let now = Instant::now();
+        let (at, last) = std::mem::replace(&mut *interval, (now, totals));
+        WriteInterval {
+            bytes: totals.bytes - last.bytes,
+            requests: totals.requests - last.requests,
+            failures: totals.failures - last.failures,
+            generator_waits: totals.generator_waits - last.generator_waits,
+            elapsed: now.duration_since(at),
+        }
+    }
+
+    /// Totals of the run so far.
+    pub fn snapshot(&self) -> StatisticsSnapshot {
+        let elapsed = self.measured();
+        let bytes = self.total_bytes();
+        StatisticsSnapshot {
+            elapsed,
+            bytes,
+            requests: self.request_count(),
+            failures: self.failed_requests(),
+            throughput: bytes as f64 / elapsed.as_secs_f64(),
+            latency: self.latency(),
         }
     }
 
     /// Increment the total number of bytes written
     pub fn increment_total(&self, inc: u64) {
-        self.total_bytes.fetch_add(inc, Ordering::Release);
+        self.total_bytes.fetch_add(inc, Ordering::Relaxed);
     }
 
     /// Increment the number of successful requests
     pub fn record_success(&self) {
-        self.success_count.fetch_add(1, Ordering::Release);
+        self.success_count.fetch_add(1, Ordering::Relaxed);
     }
 
     /// Increment the number of failed requests
     pub fn record_failure(&self) {
-        self.failure_count.fetch_add(1, Ordering::Release);
+        self.failure_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a failed request which had written `written` bytes before the
+    /// error. The bytes still count towards the total which was written.
+    pub fn record_partial_write(&self, written: u64) {
+        self.increment_total(written);
+        self.partial_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of failed requests which had written part of their input.
+    pub fn partial_writes(&self) -> u64 {
+        self.partial_writes.load(Ordering::Relaxed)
+    }
+
+    /// Record a failed request which wrote `written` bytes, but whose
+    /// response did not match what was expected. The bytes still count
+    /// towards the total which was written.
+    pub fn record_mismatched_response(&self, written: u64) {
+        self.increment_total(written);
+        self.mismatched_responses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of failed requests with an unexpected response.
+    pub fn mismatched_responses(&self) -> u64 {
+        self.mismatched_responses.load(Ordering::Relaxed)
+    }
+
+    /// Record a failed request which the local network stack refused as the
+    /// socket's send buffer was full (`EAGAIN`).
+    pub fn record_would_block(&self) {
+        self.would_block.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of requests refused with `EAGAIN`.
+    pub fn would_block(&self) -> u64 {
+        self.would_block.load(Ordering::Relaxed)
+    }
+
+    /// Record a failed request which the local network stack dropped as it
+    /// had no buffer space left (`ENOBUFS`).
+    pub fn record_no_buffer_space(&self) {
+        self.no_buffer_space.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of requests dropped with `ENOBUFS`.
+    pub fn no_buffer_space(&self) -> u64 {
+        self.no_buffer_space.load(Ordering::Relaxed)
+    }
+
+    /// Record a failed request which timed out while connecting or writing.
+    pub fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of requests which timed out.
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Record a failed request which was written in full, but whose reply,
+    /// or the peer closing the connection, never arrived.
+    pub fn record_unacknowledged(&self) {
+        self.unacknowledged.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of requests which were written, but never
+    /// acknowledged.
+    pub fn unacknowledged(&self) -> u64 {
+        self.unacknowledged.load(Ordering::Relaxed)
+    }
+
+    /// Record a failed request which could not be sent, whether connecting
+    /// or writing it failed.
+    pub fn record_send_failure(&self) {
+        self.send_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of requests which could not be sent.
+    pub fn send_failures(&self) -> u64 {
+        self.send_failures.load(Ordering::Relaxed)
+    }
+
+    /// Record a failed request whose connection the peer reset or aborted,
+    /// including writes to a connection it had already closed.
+    pub fn record_connection_reset(&self) {
+        self.connection_resets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of requests whose connection was reset.
+    pub fn connection_resets(&self) -> u64 {
+        self.connection_resets.load(Ordering::Relaxed)
+    }
+
+    /// Record a failed request whose connection the peer closed before the
+    /// whole reply was received.
+    pub fn record_premature_close(&self) {
+        self.premature_closes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of requests whose connection was closed early.
+    pub fn premature_closes(&self) -> u64 {
+        self.premature_closes.load(Ordering::Relaxed)
+    }
+
+    /// Record a request which waited for its payload from the generator
+    /// threads, as none had been generated ahead of it.
+    pub fn record_generator_wait(&self, waited: Duration) {
+        let nanos = u64::try_from(waited.as_nanos()).unwrap_or(u64::MAX);
+        self.generator_waits.fetch_add(1, Ordering::Relaxed);
+        self.generator_wait_ns.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// Get the number of requests which waited for a generated payload.
+    pub fn generator_waits(&self) -> u64 {
+        self.generator_waits.load(Ordering::Relaxed)
+    }
+
+    /// Get the total time requests waited for generated payloads.
+    pub fn generator_wait(&self) -> Duration {
+        Duration::from_nanos(self.generator_wait_ns.load(Ordering::Relaxed))
+    }
+
+    /// Record the retries which were made for a single request, of which
+    /// `connect` followed attempts which failed to connect.
+    pub fn record_retries(&self, retries: u32, connect: u32) {
+        self.retries
+            .fetch_add(u64::from(retries), Ordering::Relaxed);
+        self.connect_retries
+            .fetch_add(u64::from(connect), Ordering::Relaxed);
+    }
+
+    /// Get the number of retries which were made across every request.
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of retries which followed attempts that failed to
+    /// connect, rather than once connected.
+    pub fn connect_retries(&self) -> u64 {
+        self.connect_retries.load(Ordering::Relaxed)
+    }
+
+    /// Increment the number of attempts to resolve a host which failed.
+    pub fn record_resolution_failure(&self) {
+        self.resolution_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of failed attempts to resolve a host, which are not
+    /// counted as requests.
+    pub fn resolution_failures(&self) -> u64 {
+        self.resolution_failures.load(Ordering::Relaxed)
+    }
+
+    /// Record a sample of the bytes queued in a socket's send buffer, which
+    /// have not yet left the host.
+    pub fn record_send_queue(&self, bytes: u64) {
+        if self.is_warming_up() {
+            return;
+        }
+        self.send_queue_peak.fetch_max(bytes, Ordering::Relaxed);
+    }
+
+    /// Get the largest number of bytes which were sampled in a send buffer.
+    pub fn send_queue_peak(&self) -> u64 {
+        self.send_queue_peak.load(Ordering::Relaxed)
+    }
+
+    /// Increment the number of connections which were opened.
+    pub fn record_connection(&self) {
+        if self.is_warming_up() {
+            return;
+        }
+        self.connections_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of connections which were opened, which is lower than
+    /// the number of requests when connections are reused.
+    pub fn connections_opened(&self) -> u64 {
+        self.connections_opened.load(Ordering::Relaxed)
+    }
+
+    /// Increment the number of kept alive connections which were closed once
+    /// they reached their lifetime, to be re-established by the next request.
+    pub fn record_connection_recycled(&self) {
+        if self.is_warming_up() {
+            return;
+        }
+        self.connections_recycled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connections_recycled(&self) -> u64 {
+        self.connections_recycled.load(Ordering::Relaxed)
     }
 
     pub fn successful_requests(&self) -> u64 {
         self.success_count.load(Ordering::Relaxed)
     }
 
+    /// Get the number of failed requests.
+    pub fn failed_requests(&self) -> u64 {
+        self.failure_count.load(Ordering::Relaxed)
+    }
+
     pub fn success_percentage(&self) -> f64 {
-        let success = self.success_count.load(Ordering::Acquire) as f64;
+        let success = self.success_count.load(Ordering::Relaxed) as f64;
         let failure = self.failure_count.load(Ordering::Relaxed) as f64;
+        if success + failure == 0.0 {
+            return 0.0;
+        }
 
         (success / (success + failure)) * 100.0
     }
 
     /// Get the total number of bytes written
     pub fn total_bytes(&self) -> u64 {
-        self.total_bytes.load(Ordering::Acquire)
+        self.total_bytes.load(Ordering::Relaxed)
     }
 
     /// Get the total number of sent requests.
     pub fn request_count(&self) -> u64 {
-        self.success_count.load(Ordering::Acquire) + self.failure_count.load(Ordering::Relaxed)
+        self.success_count.load(Ordering::Relaxed) + self.failure_count.load(Ordering::Relaxed)
     }
 
     /// Retrieve the perceived bytes per second throughput that was written to
     /// the sockets.
-    ///
-    /// NOTE: Owing to truncation from nanosecond precision to seconds, the
-    /// produced throughput may not be accurate for low write counts.
     pub fn record_throughput(&self) {
-        let throughput = self.total_bytes.load(Ordering::Acquire) as f64
-            / self.start_time.elapsed().as_secs() as f64;
+        let measured = self.measured().as_secs_f64();
+        let throughput = self.total_bytes.load(Ordering::Relaxed) as f64 / measured;
         self.throughput.store(throughput, Ordering::Relaxed);
+        let inbound = self.inbound_bytes.load(Ordering::Relaxed) as f64 / measured;
+        self.inbound_throughput.store(inbound, Ordering::Relaxed);
     }
 
     pub fn elapsed(&self) -> u128 {
-        self.start_time.elapsed().as_millis()
+        self.measured().as_millis()
     }
 
     /// Return the recorded throughput
     pub fn throughput(&self) -> f64 {
-        self.throughput.load(Ordering::Acquire)
+        self.throughput.load(Ordering::Relaxed)
+    }
+
+    /// Add bytes which the server pushed down a duplex connection.
+    pub fn record_inbound(&self, bytes: u64) {
+        if self.is_warming_up() {
+            return;
+        }
+        self.inbound_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Get the total number of bytes read from duplex connections.
+    pub fn inbound_bytes(&self) -> u64 {
+        self.inbound_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Return the recorded bytes per second read from duplex connections.
+    pub fn inbound_throughput(&self) -> f64 {
+        self.inbound_throughput.load(Ordering::Relaxed)
+    }
+
+    /// Record an estimated one-way delay, in nanoseconds, from timestamps
+    /// which were reflected by the server.
+    pub fn record_one_way_delay(&self, nanos: i64) {
+        self.one_way_delay.record(nanos);
+    }
+
+    /// Summary of the recorded one-way delays, if there are any.
+    pub fn one_way_delay(&self) -> Option<DelaySummary> {
+        self.one_way_delay.summary()
+    }
+
+    /// Also store every latency exactly, until more than `cap` are recorded,
+    /// so that short runs report exact rather than bucketed percentiles.
+    pub fn with_exact_latencies(mut self, cap: usize) -> Self {
+        self.latency.exact_cap = cap;
+        *self.latency.exact.get_mut().unwrap() = Some(Vec::new());
+        self
+    }
+
+    /// Record the time a successful request took.
+    pub fn record_latency(&self, latency: Duration) {
+        self.latency.record(latency);
+    }
+
+    /// Percentiles of the recorded latencies, if there are any.
+    pub fn latency(&self) -> Option<LatencySummary> {
+        self.latency.summary()
+    }
+
+    /// Count of latencies in each bucket of the histogram, as pairs of the
+    /// highest latency of the bucket, in nanoseconds, and its count.
+    pub fn latency_buckets(&self) -> Vec<(u64, u64)> {
+        self.latency.buckets()
+    }
+
+    /// Record a reply of `bytes` which arrived `round_trip` after its request
+    /// started being written.
+    pub fn record_reply(&self, bytes: u64, round_trip: Duration, first_byte: Duration) {
+        self.reply_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.round_trip.record(round_trip);
+        self.first_byte.record(first_byte);
+    }
+
+    /// Total bytes received in replies.
+    pub fn reply_bytes(&self) -> u64 {
+        self.reply_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Percentiles of the round trips of replies, if there are any.
+    pub fn round_trip(&self) -> Option<LatencySummary> {
+        self.round_trip.summary()
+    }
+
+    /// Percentiles of the time to the first byte of replies, if there are
+    /// any.
+    pub fn first_byte(&self) -> Option<LatencySummary> {
+        self.first_byte.summary()
+    }
+
+    /// Record a TLS handshake which took `duration`, and whether it resumed
+    /// the session of an earlier connection.
+    pub fn record_tls_handshake(&self, resumed: bool, duration: Duration) {
+        if self.is_warming_up() {
+            return;
+        }
+        let count = if resumed {
+            &self.tls_resumed_handshakes
+        } else {
+            &self.tls_full_handshakes
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+        self.tls_handshake.record(duration);
+    }
+
+    /// Get the number of TLS handshakes which were completed in full.
+    pub fn tls_full_handshakes(&self) -> u64 {
+        self.tls_full_handshakes.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of TLS handshakes which resumed an earlier session.
+    pub fn tls_resumed_handshakes(&self) -> u64 {
+        self.tls_resumed_handshakes.load(Ordering::Relaxed)
+    }
+
+    /// Percentiles of the duration of TLS handshakes, if any completed.
+    pub fn tls_handshake(&self) -> Option<LatencySummary> {
+        self.tls_handshake.summary()
+    }
+
+    /// Combine the statistics of runs which happened alongside each other,
+    /// such as by the writers of a [`crate::RunGroup`], as though they were
+    /// recorded by a single writer. The elapsed time starts from the earliest
+    /// of them, so throughput is over the span of every run.
+    pub fn merged<'a>(all: impl IntoIterator<Item = &'a Statistics>) -> Statistics {
+        // Latencies stay exact only if every run kept all of its own.
+        let mut merged = Statistics::new().with_exact_latencies(usize::MAX);
+        let mut start_time = merged.start_time;
+        let mut excluded = None::<u64>;
+        for stats in all {
+            start_time = start_time.min(stats.start_time);
+            // Only time which every run left out is excluded from the span.
+            let left_out = stats.excluded.load(Ordering::Relaxed);
+            excluded = Some(excluded.map_or(left_out, |e| e.min(left_out)));
+            for (into, from) in [
+                (&merged.total_bytes, &stats.total_bytes),
+                (&merged.success_count, &stats.success_count),
+                (&merged.failure_count, &stats.failure_count),
+                (&merged.partial_writes, &stats.partial_writes),
+                (&merged.mismatched_responses, &stats.mismatched_responses),
+                (&merged.would_block, &stats.would_block),
+                (&merged.no_buffer_space, &stats.no_buffer_space),
+                (&merged.timeouts, &stats.timeouts),
+                (&merged.unacknowledged, &stats.unacknowledged),
+                (&merged.send_failures, &stats.send_failures),
+                (&merged.connection_resets, &stats.connection_resets),
+                (&merged.premature_closes, &stats.premature_closes),
+                (&merged.retries, &stats.retries),
+                (&merged.connect_retries, &stats.connect_retries),
+                (&merged.resolution_failures, &stats.resolution_failures),
+                (&merged.connections_opened, &stats.connections_opened),
+                (&merged.connections_recycled, &stats.connections_recycled),
+                (&merged.reply_bytes, &stats.reply_bytes),
+                (&merged.inbound_bytes, &stats.inbound_bytes),
+                (&merged.tls_full_handshakes, &stats.tls_full_handshakes),
+                (
+                    &merged.tls_resumed_handshakes,
+                    &stats.tls_resumed_handshakes,
+                ),
+                (&merged.generator_waits, &stats.generator_waits),
+                (&merged.generator_wait_ns, &stats.generator_wait_ns),
+            ] {
+                into.fetch_add(from.load(Ordering::Relaxed), Ordering::Relaxed);
+            }
+            merged
+                .send_queue_peak
+                .fetch_max(stats.send_queue_peak(), Ordering::Relaxed);
+            merged.one_way_delay.merge(&stats.one_way_delay);
+            merged.latency.merge(&stats.latency);
+            merged.round_trip.merge(&stats.round_trip);
+            merged.first_byte.merge(&stats.first_byte);
+            merged.tls_handshake.merge(&stats.tls_handshake);
+            merged
+                .schedule
+                .lock()
+                .unwrap()
+                .merge(&stats.schedule.lock().unwrap());
+        }
+        Statistics {
+            start_time,
+            excluded: Arc::new(AtomicU64::new(excluded.unwrap_or_default())),
+            ..merged
+        }
+    }
+
+    /// Record a request which was scheduled to be sent at `intended`, but
+    /// was actually sent at `sent`.
+    pub fn record_send(&self, intended: Instant, sent: Instant) {
+        self.schedule.lock().unwrap().record(intended, sent);
+    }
+
+    /// How closely sends kept to their schedule, if any were recorded.
+    pub fn schedule(&self) -> Option<ScheduleSummary> {
+        self.schedule.lock().unwrap().summary()
+    }
+}
+
+/// How closely requests which were sent at a fixed rate kept to their
+/// schedule.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScheduleSummary {
+    /// Requests sent per second, from the first intended send time to the
+    /// last actual one, if more than one request was sent.
+    pub achieved_rate: Option<f64>,
+    /// Percentiles of how late requests were sent, in nanoseconds.
+    pub lag_p50: u64,
+    pub lag_p90: u64,
+    pub lag_p99: u64,
+    pub lag_max: u64,
+}
+
+/// Distribution of request latencies, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    /// Whether the values were calculated from every latency, rather than
+    /// approximated from a histogram.
+    pub exact: bool,
+    pub min: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+/// Minimum, mean and maximum of a set of delays, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelaySummary {
+    pub min: i64,
+    pub mean: f64,
+    pub max: i64,
+}
+
+/// Lock-free accumulator of delays which may be negative.
+struct DelayRecorder {
+    count: AtomicU64,
+    sum: AtomicI64,
+    min: AtomicI64,
+    max: AtomicI64,
+}
+
+impl DelayRecorder {
+    fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            sum: AtomicI64::new(0),
+            min: AtomicI64::new(i64::MAX),
+            max: AtomicI64::new(i64::MIN),
+        }
+    }
+
+    fn record(&self, nanos: i64) {
+        self.sum.fetch_add(nanos, Ordering::Relaxed);
+        self.min.fetch_min(nanos, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn merge(&self, other: &DelayRecorder) {
+        let count = other.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return;
+        }
+        self.sum
+            .fetch_add(other.sum.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.min
+            .fetch_min(other.min.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.max
+            .fetch_max(other.max.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.count.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn summary(&self) -> Option<DelaySummary> {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return None;
+        }
+        Some(DelaySummary {
+            min: self.min.load(Ordering::Relaxed),
+            mean: self.sum.load(Ordering::Relaxed) as f64 / count as f64,
+            max: self.max.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Histogram of latencies, which keeps 3 significant figures of each value,
+/// and optionally every latency up to a cap.
+struct LatencyRecorder {
+    histogram: Mutex<Histogram<u64>>,
+    exact_cap: usize,
+    /// Every recorded latency, which is `None` when they are not stored or
+    /// once there are more than the cap.
+    exact: Mutex<Option<Vec<u64>>>,
+}
+
+impl LatencyRecorder {
+    fn new() -> Self {
+        Self {
+            histogram: Mutex::new(Histogram::new(3).expect("3 significant figures are supported")),
+            exact_cap: 0,
+            exact: Mutex::new(None),
+        }
+    }
+
+    fn record(&self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        {
+            let mut exact = self.exact.lock().unwrap();
+            if let Some(samples) = exact.as_mut() {
+                if samples.len() < self.exact_cap {
+                    samples.push(nanos);
+                } else {
+                    // The percentiles fall back to the histogram, so free
+                    // the samples rather than report a truncated set.
+                    *exact = None;
+                }
+            }
+        }
+        let mut histogram = self.histogram.lock().unwrap();
+        // The histogram grows to fit the value, unless it is beyond the
+        // largest which can be tracked.
+        if histogram.record(nanos).is_err() {
+            histogram.saturating_record(nanos);
+        }
+    }
+
+    /// Add the latencies of another recorder, keeping every latency only
+    /// while both recorders have kept theirs and they fit within the cap.
+    fn merge(&mut self, other: &LatencyRecorder) {
+        let other_exact = other.exact.lock().unwrap();
+        let exact = self.exact.get_mut().unwrap();
+        match (exact.as_mut(), other_exact.as_ref()) {
+            (Some(samples), Some(other)) if samples.len() + other.len() <= self.exact_cap => {
+                samples.extend_from_slice(other)
+            }
+            _ => *exact = None,
+        }
+        self.histogram
+            .get_mut()
+            .unwrap()
+            .add(&*other.histogram.lock().unwrap())
+            .expect("histograms resize to fit the values");
+    }
+
+    fn buckets(&self) -> Vec<(u64, u64)> {
+        self.histogram
+            .lock()
+            .unwrap()
+            .iter_recorded()
+            .map(|v| (v.value_iterated_to(), v.count_at_value()))
+            .collect()
+    }
+
+    fn summary(&self) -> Option<LatencySummary> {
+        if let Some(summary) = self
+            .exact
+            .lock()
+            .unwrap()
+            .as_deref()
+            .and_then(exact_summary)
+        {
+            return Some(summary);
+        }
+        let histogram = self.histogram.lock().unwrap();
+        if histogram.is_empty() {
+            return None;
+        }
+        Some(LatencySummary {
+            exact: false,
+            min: histogram.min(),
+            mean: histogram.mean(),
+            p50: histogram.value_at_quantile(0.5),
+            p90: histogram.value_at_quantile(0.9),
+            p99: histogram.value_at_quantile(0.99),
+            p999: histogram.value_at_quantile(0.999),
+            max: histogram.max(),
+        })
+    }
+}
+
+/// Lag of each scheduled send, and the span of time they were sent over.
+struct SendSchedule {
+    lags: Histogram<u64>,
+    first_intended: Option<Instant>,
+    last_sent: Option<Instant>,
+}
+
+impl SendSchedule {
+    fn new() -> Self {
+        Self {
+            lags: Histogram::new(3).expect("3 significant figures are supported"),
+            first_intended: None,
+            last_sent: None,
+        }
+    }
+
+    fn record(&mut self, intended: Instant, sent: Instant) {
+        let lag =
+            u64::try_from(sent.saturating_duration_since(intended).as_nanos()).unwrap_or(u64::MAX);
+        if self.lags.record(lag).is_err() {
+            self.lags.saturating_record(lag);
+        }
+        self.first_intended = Some(self.first_intended.map_or(intended, |f| f.min(intended)));
+        self.last_sent = self.last_sent.max(Some(sent));
+    }
+
+    fn merge(&mut self, other: &SendSchedule) {
+        self.lags
+            .add(&other.lags)
+            .expect("histograms resize to fit the values");
+        self.first_intended = match (self.first_intended, other.first_intended) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.last_sent = self.last_sent.max(other.last_sent);
+    }
+
+    fn summary(&self) -> Option<ScheduleSummary> {
+        if self.lags.is_empty() {
+            return None;
+        }
+        let sends = self.lags.len();
+        let achieved_rate = match (self.first_intended, self.last_sent) {
+            (Some(first), Some(last)) if sends > 1 && last > first => {
+                Some((sends - 1) as f64 / last.duration_since(first).as_secs_f64())
+            }
+            _ => None,
+        };
+        Some(ScheduleSummary {
+            achieved_rate,
+            lag_p50: self.lags.value_at_quantile(0.5),
+            lag_p90: self.lags.value_at_quantile(0.9),
+            lag_p99: self.lags.value_at_quantile(0.99),
+            lag_max: self.lags.max(),
+        })
+    }
+}
+
+/// Summarise every latency, using the nearest-rank method for percentiles.
+fn exact_summary(samples: &[u64]) -> Option<LatencySummary> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_unstable();
+    let rank = |quantile: f64| {
+        let rank = (quantile * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    };
+    Some(LatencySummary {
+        exact: true,
+        min: *sorted.first()?,
+        mean: sorted.iter().map(|&n| n as f64).sum::<f64>() / sorted.len() as f64,
+        p50: rank(0.5),
+        p90: rank(0.9),
+        p99: rank(0.99),
+        p999: rank(0.999),
+        max: *sorted.last()?,
+    })
+}
+
+/// Counters for the data received by a [`crate::Server`].
+pub struct ServerStatistics {
+    start_time: Instant,
+    messages: AtomicU64,
+    bytes: AtomicU64,
+    active_connections: AtomicU64,
+    /// Connections which were accepted and are waiting to be read from.
+    queued_connections: AtomicU64,
+    /// Connections which were closed as too many were already open.
+    rejected_connections: AtomicU64,
+    /// Connections and datagrams which were dropped, reset or closed early
+    /// to inject faults.
+    dropped: AtomicU64,
+    resets: AtomicU64,
+    early_closes: AtomicU64,
+    /// Histogram of the length of each message, in bytes.
+    sizes: Mutex<Histogram<u64>>,
+    /// Histogram of how long each connection was read from, in microseconds.
+    connection_durations: Mutex<Histogram<u64>>,
+    peers: Mutex<PeerCounter>,
+    /// Start time and totals at the beginning of the current interval.
+    interval: Mutex<(Instant, u64, u64)>,
+}
+
+/// Distribution of the sizes of received messages, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeSummary {
+    pub min: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+    /// Number of messages whose size was recorded.
+    pub count: u64,
+}
+
+/// How long the connections to a [`crate::Server`] were open, counted for
+/// each of a set of upper bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DurationBuckets {
+    /// Connections which were open for at most each bound, in the order of
+    /// the bounds.
+    pub counts: Vec<u64>,
+    /// Total time the connections were open.
+    pub sum: Duration,
+    /// Number of connections which were closed.
+    pub count: u64,
+}
+
+/// Data received by a [`crate::Server`] within an interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntervalSummary {
+    pub messages: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Default for ServerStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerStatistics {
+    pub fn new() -> Self {
+        Self {
+            start_time: Instant::now(),
+            messages: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            active_connections: AtomicU64::new(0),
+            queued_connections: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            resets: AtomicU64::new(0),
+            early_closes: AtomicU64::new(0),
+            sizes: Mutex::new(Histogram::new(3).expect("3 significant figures are supported")),
+            connection_durations: Mutex::new(
+                Histogram::new(3).expect("3 significant figures are supported"),
+            ),
+            peers: Mutex::new(PeerCounter::default()),
+            interval: Mutex::new((Instant::now(), 0, 0)),
+        }
+    }
+
+    /// Summarise the data received since the last call, starting a new
+    /// interval.
+    pub fn take_interval(&self) -> IntervalSummary {
+        let now = (Instant::now(), self.messages(), self.bytes());
+        let (at, messages, bytes) = std::mem::replace(&mut *self.interval.lock().unwrap(), now);
+        IntervalSummary {
+            messages: now.1 - messages,
+            bytes: now.2 - bytes,
+            elapsed: now.0.duration_since(at),
+        }
+    }
+
+    /// Record a received message of `len` bytes.
+    pub fn record_message(&self, len: u64) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(len, Ordering::Relaxed);
+        let mut sizes = self.sizes.lock().unwrap();
+        if sizes.record(len).is_err() {
+            sizes.saturating_record(len);
+        }
+    }
+
+    /// Record a connection which is being read from.
+    pub fn record_connection_opened(&self) {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a connection which was read from being closed, after it was
+    /// open for `duration`.
+    pub fn record_connection_closed(&self, duration: Duration) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let mut durations = self.connection_durations.lock().unwrap();
+        if durations.record(micros).is_err() {
+            durations.saturating_record(micros);
+        }
+    }
+
+    /// Count the closed connections which were open for at most each of the
+    /// bounds, alongside how long they were open in total.
+    pub fn connection_durations(&self, bounds: &[Duration]) -> DurationBuckets {
+        let durations = self.connection_durations.lock().unwrap();
+        let micros = |d: &Duration| u64::try_from(d.as_micros()).unwrap_or(u64::MAX);
+        DurationBuckets {
+            counts: bounds
+                .iter()
+                .map(|bound| durations.count_between(0, micros(bound)))
+                .collect(),
+            sum: Duration::from_secs_f64(durations.mean() * durations.len() as f64 / 1e6),
+            count: durations.len(),
+        }
+    }
+
+    /// Get the number of connections which are being read from.
+    pub fn active_connections(&self) -> u64 {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    /// Record a connection which was accepted to wait for its turn to be
+    /// read from.
+    pub fn record_connection_queued(&self) {
+        self.queued_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a queued connection being taken to be read from.
+    pub fn record_connection_dequeued(&self) {
+        self.queued_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of connections which are waiting to be read from.
+    pub fn queued_connections(&self) -> u64 {
+        self.queued_connections.load(Ordering::Relaxed)
+    }
+
+    /// Record a connection which was closed as soon as it was accepted, as
+    /// the server was at its connection limit.
+    pub fn record_connection_rejected(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of connections which were rejected.
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected_connections.load(Ordering::Relaxed)
+    }
+
+    /// Record a connection which was held open unread, or a datagram which
+    /// was discarded, to inject a fault.
+    pub fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of connections and datagrams which were dropped.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Record a connection which was reset as soon as it was accepted, to
+    /// inject a fault.
+    pub fn record_reset(&self) {
+        self.resets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of connections which were reset.
+    pub fn resets(&self) -> u64 {
+        self.resets.load(Ordering::Relaxed)
+    }
+
+    /// Record a connection which was closed without a reply after reading
+    /// part of it, to inject a fault.
+    pub fn record_early_close(&self) {
+        self.early_closes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of connections which were closed early.
+    pub fn early_closes(&self) -> u64 {
+        self.early_closes.load(Ordering::Relaxed)
+    }
+
+    /// Record the IP address of a peer which a message was received from.
+    pub fn record_peer(&self, ip: IpAddr) {
+        self.peers.lock().unwrap().record(ip);
+    }
+
+    /// Get the number of distinct IP addresses which messages were received
+    /// from, so that hosts sharing an address, such as behind a NAT, count
+    /// once.
+    pub fn distinct_peers(&self) -> PeerCount {
+        self.peers.lock().unwrap().count()
+    }
+
+    /// Percentiles of the sizes of received messages, if there are any.
+    /// Sizes are the lowest of their histogram bucket, so a size which is
+    /// recorded exactly, such as any up to 2 KiB, is reported as it is.
+    pub fn message_sizes(&self) -> Option<SizeSummary> {
+        let sizes = self.sizes.lock().unwrap();
+        if sizes.is_empty() {
+            return None;
+        }
+        let size = |value| sizes.lowest_equivalent(value);
+        Some(SizeSummary {
+            min: size(sizes.min()),
+            mean: sizes.mean(),
+            p50: size(sizes.value_at_quantile(0.5)),
+            p90: size(sizes.value_at_quantile(0.9)),
+            p99: size(sizes.value_at_quantile(0.99)),
+            max: size(sizes.max()),
+            count: sizes.len(),
+        })
+    }
+
+    /// Get the total number of received messages.
+    pub fn messages(&self) -> u64 {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    /// Get the total number of received bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> u128 {
+        self.start_time.elapsed().as_millis()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::sync::atomic::Ordering;
+    use std::{sync::atomic::Ordering, time::Duration};
 
-    use super::Statistics;
+    use super::{ServerStatistics, Statistics};
 
     #[test]
     fn general() {
         let stats = Statistics::new();
         assert_eq!(stats.total_bytes(), 0);
         assert_eq!(stats.successful_requests(), 0);
-        assert_eq!(stats.failure_count.load(Ordering::Acquire), 0);
+        assert_eq!(stats.failure_count.load(Ordering::Relaxed), 0);
 
         stats.increment_total(10);
         assert_eq!(stats.total_bytes(), 10);
@@ -112,5 +1189,183 @@ mod test {
         assert_eq!(stats.failure_count.load(Ordering::Relaxed), 3);
         assert_eq!(stats.success_percentage(), 25.0);
         assert_eq!(stats.request_count(), 4);
+
+        stats.record_partial_write(3);
+        assert_eq!(stats.partial_writes(), 1);
+        assert_eq!(stats.total_bytes(), 13);
+    }
+
+    #[test]
+    fn write_interval() {
+        let stats = Statistics::new();
+        stats.increment_total(10);
+        stats.record_success();
+        stats.record_failure();
+        let interval = stats.take_interval();
+        assert_eq!(
+            (interval.bytes, interval.requests, interval.failures),
+            (10, 2, 1)
+        );
+
+        stats.increment_total(5);
+        stats.record_success();
+        let interval = stats.take_interval();
+        assert_eq!(
+            (interval.bytes, interval.requests, interval.failures),
+            (5, 1, 0)
+        );
+        assert_eq!(interval.generator_waits, 0);
+
+        stats.record_generator_wait(Duration::from_millis(2));
+        stats.record_generator_wait(Duration::from_millis(3));
+        assert_eq!(stats.take_interval().generator_waits, 2);
+        assert_eq!(stats.generator_waits(), 2);
+        assert_eq!(stats.generator_wait(), Duration::from_millis(5));
+    }
+
+    #[test]
+    fn one_way_delay() {
+        let stats = Statistics::new();
+        assert_eq!(stats.one_way_delay(), None);
+
+        stats.record_one_way_delay(-10);
+        stats.record_one_way_delay(40);
+        let delay = stats.one_way_delay().unwrap();
+        assert_eq!(delay.min, -10);
+        assert_eq!(delay.mean, 15.0);
+        assert_eq!(delay.max, 40);
+    }
+
+    #[test]
+    fn latency() {
+        let stats = Statistics::new();
+        assert_eq!(stats.latency(), None);
+
+        for micros in 1..=100 {
+            stats.record_latency(Duration::from_micros(micros));
+        }
+        let latency = stats.latency().unwrap();
+        assert_eq!(latency.min, 1000);
+        // Values are kept to 3 significant figures.
+        assert!(latency.max.abs_diff(100_000) <= 100);
+        assert!(latency.p50.abs_diff(50_000) <= 50);
+        assert!(latency.p99.abs_diff(99_000) <= 100);
+        assert!((latency.mean - 50_500.0).abs() < 100.0);
+        assert!(!latency.exact);
+    }
+
+    #[test]
+    fn exact_latency() {
+        let stats = Statistics::new().with_exact_latencies(1000);
+        for n in (1..=1000).rev() {
+            stats.record_latency(Duration::from_nanos(n * 1001));
+        }
+        let latency = stats.latency().unwrap();
+        assert!(latency.exact);
+        assert_eq!(latency.min, 1001);
+        assert_eq!(latency.p50, 500 * 1001);
+        assert_eq!(latency.p999, 999 * 1001);
+        assert_eq!(latency.max, 1000 * 1001);
+        assert_eq!(latency.mean, 500.5 * 1001.0);
+
+        // Beyond the cap, the percentiles come from the histogram.
+        stats.record_latency(Duration::from_nanos(1001));
+        assert!(!stats.latency().unwrap().exact);
+    }
+
+    #[test]
+    fn exclude() {
+        let stats = Statistics::new();
+        std::thread::sleep(Duration::from_millis(20));
+        stats.exclude(Duration::from_secs(60));
+        assert_eq!(stats.elapsed(), 0);
+
+        let stats = Statistics::new();
+        std::thread::sleep(Duration::from_millis(50));
+        stats.exclude(Duration::from_millis(40));
+        stats.increment_total(100);
+        stats.record_throughput();
+        assert!((10..50).contains(&stats.elapsed()));
+        assert!(stats.throughput() > 2000.0);
+    }
+
+    #[test]
+    fn round_trip() {
+        let stats = Statistics::new();
+        assert_eq!((stats.reply_bytes(), stats.round_trip()), (0, None));
+
+        stats.record_reply(4, Duration::from_micros(10), Duration::from_micros(2));
+        stats.record_reply(6, Duration::from_micros(30), Duration::from_micros(5));
+        assert_eq!(stats.reply_bytes(), 10);
+        let rtt = stats.round_trip().unwrap();
+        assert_eq!(rtt.min, 10_000);
+        assert!(rtt.max.abs_diff(30_000) <= 30);
+        let ttfb = stats.first_byte().unwrap();
+        assert_eq!(ttfb.min, 2_000);
+        assert!(ttfb.max.abs_diff(5_000) <= 5);
+        // Round trips are kept apart from the latencies of writes.
+        assert_eq!(stats.latency(), None);
+    }
+
+    #[test]
+    fn merged() {
+        let first = Statistics::new().with_exact_latencies(10);
+        first.increment_total(10);
+        first.record_success();
+        first.record_latency(Duration::from_micros(100));
+        let second = Statistics::new().with_exact_latencies(10);
+        second.record_failure();
+        second.record_timeout();
+        second.record_success();
+        second.record_latency(Duration::from_micros(300));
+
+        let merged = Statistics::merged([&first, &second]);
+        assert_eq!(merged.total_bytes(), 10);
+        assert_eq!(merged.request_count(), 3);
+        assert_eq!(merged.timeouts(), 1);
+        let latency = merged.latency().unwrap();
+        assert!(latency.exact);
+        assert_eq!((latency.min, latency.max), (100_000, 300_000));
+
+        let merged = Statistics::merged([&first, &Statistics::new()]);
+        assert!(!merged.latency().unwrap().exact);
+    }
+
+    #[test]
+    fn server() {
+        let stats = ServerStatistics::new();
+        assert_eq!(stats.messages(), 0);
+        assert_eq!(stats.bytes(), 0);
+
+        stats.record_message(5);
+        stats.record_message(0);
+        assert_eq!(stats.messages(), 2);
+        assert_eq!(stats.bytes(), 5);
+
+        let interval = stats.take_interval();
+        assert_eq!((interval.messages, interval.bytes), (2, 5));
+        stats.record_message(3);
+        let interval = stats.take_interval();
+        assert_eq!((interval.messages, interval.bytes), (1, 3));
+        assert_eq!(stats.messages(), 3);
+    }
+
+    #[test]
+    fn message_sizes() {
+        let stats = ServerStatistics::new();
+        assert_eq!(stats.message_sizes(), None);
+
+        for _ in 0..80 {
+            stats.record_message(64);
+        }
+        for _ in 0..20 {
+            stats.record_message(1500);
+        }
+        let sizes = stats.message_sizes().unwrap();
+        assert_eq!((sizes.min, sizes.p50), (64, 64));
+        assert_eq!(sizes.p90, sizes.max);
+        assert_eq!(sizes.max, 1500);
+        assert_eq!(sizes.count, 100);
+        assert!((sizes.mean - 351.2).abs() < 1.0);
     }
 }
@@ -0,0 +1,412 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::Display,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+use tokio::time::Instant;
+
+use crate::{
+    breaker::{Breaker, CircuitBreaker},
+    report::CircuitState,
+    statistics::Statistics,
+};
+
+/// Ratio of requests which are sent to IPv4 and IPv6 addresses respectively,
+/// parsed from the form `70:30`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FamilySplit {
+    pub ipv4: u32,
+    pub ipv6: u32,
+}
+
+impl FromStr for FamilySplit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ipv4, ipv6) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected a ratio in the form IPV4:IPV6, got '{s}'"))?;
+        let ipv4 = ipv4
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid IPv4 share '{ipv4}': {e}"))?;
+        let ipv6 = ipv6
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid IPv6 share '{ipv6}': {e}"))?;
+        if ipv4 == 0 && ipv6 == 0 {
+            return Err("at least one address family must receive requests".to_string());
+        }
+        Ok(Self { ipv4, ipv6 })
+    }
+}
+
+impl Display for FamilySplit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.ipv4, self.ipv6)
+    }
+}
+
+/// A set of addresses which receive a weighted share of requests, tracking
+/// their own [`Statistics`] alongside the overall run.
+pub(crate) struct TargetGroup {
+    name: String,
+    addrs: Vec<SocketAddr>,
+    /// Circuit breaker state of each address, by index.
+    breakers: Vec<Breaker>,
+    weight: u32,
+    cursor: AtomicUsize,
+    /// Weights of the addresses changed during the run, if any.
+    addr_weights: Option<Arc<AddressWeights>>,
+    /// Current weight of each address in the smooth weighted round robin.
+    current: Mutex<Vec<i64>>,
+    stats: Arc<Statistics>,
+}
+
+impl TargetGroup {
+    pub(crate) fn new(name: impl Into<String>, addrs: Vec<SocketAddr>, weight: u32) -> Self {
+        Self {
+            name: name.into(),
+            breakers: addrs.iter().map(|_| Breaker::default()).collect(),
+            current: Mutex::new(vec![0; addrs.len()]),
+            addrs,
+            weight,
+            cursor: AtomicUsize::new(0),
+            addr_weights: None,
+            stats: Arc::new(Statistics::new()),
+        }
+    }
+
+    /// Next address of the group, cycling through each of them in turn and
+    /// skipping those whose circuit is open.
+    fn next_addr(&self, now: Instant) -> Option<SocketAddr> {
+        if let Some(weights) = self.addr_weights.as_ref().filter(|w| w.is_changed()) {
+            return self.next_weighted(weights, now);
+        }
+        (0..self.addrs.len()).find_map(|_| {
+            let idx = self.cursor.fetch_add(1, Ordering::Relaxed) % self.addrs.len();
+            (!self.breakers[idx].is_open(now)).then_some(self.addrs[idx])
+        })
+    }
+
+    /// Next address of the group by smooth weighted round robin, which picks
+    /// each address in proportion to its weight while interleaving them.
+    /// Addresses whose weight is zero or whose circuit is open are skipped.
+    fn next_weighted(&self, addr_weights: &AddressWeights, now: Instant) -> Option<SocketAddr> {
+        let weights: Vec<i64> = self
+            .addrs
+            .iter()
+            .zip(&self.breakers)
+            .map(|(addr, breaker)| match breaker.is_open(now) {
+                true => 0,
+                false => i64::from(addr_weights.get(*addr)),
+            })
+            .collect();
+        let total: i64 = weights.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let mut current = self.current.lock().unwrap();
+        for (current, weight) in current.iter_mut().zip(&weights) {
+            *current += weight;
+        }
+        let idx = (0..weights.len())
+            .filter(|&i| weights[i] > 0)
+            .max_by_key(|&i| (current[i], Reverse(i)))?;
+        current[idx] -= total;
+        Some(self.addrs[idx])
+    }
+
+    fn breaker(&self, addr: SocketAddr) -> Option<&Breaker> {
+        let idx = self.addrs.iter().position(|a| *a == addr)?;
+        Some(&self.breakers[idx])
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn stats(&self) -> &Arc<Statistics> {
+        &self.stats
+    }
+}
+
+/// Weights of addresses set while writing, where an address which was never
+/// set has a weight of one. Until the first is set, the addresses of a group
+/// are cycled through in turn.
+#[derive(Debug, Default)]
+pub(crate) struct AddressWeights {
+    changed: AtomicBool,
+    weights: RwLock<HashMap<SocketAddr, u32>>,
+}
+
+impl AddressWeights {
+    pub(crate) fn set(&self, addr: SocketAddr, weight: u32) {
+        self.weights.write().unwrap().insert(addr, weight);
+        self.changed.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn get(&self, addr: SocketAddr) -> u32 {
+        self.weights
+            .read()
+            .unwrap()
+            .get(&addr)
+            .copied()
+            .unwrap_or(1)
+    }
+
+    fn is_changed(&self) -> bool {
+        self.changed.load(Ordering::Acquire)
+    }
+}
+
+/// Distributes requests across one or more [`TargetGroup`]s according to
+/// their weights.
+pub(crate) struct Targets {
+    groups: Vec<TargetGroup>,
+    /// Precomputed order of group indexes for a single cycle of weights.
+    schedule: Vec<usize>,
+    counter: AtomicUsize,
+    circuit_breaker: Option<CircuitBreaker>,
+}
+
+impl Targets {
+    /// Send every request to a single address.
+    pub(crate) fn single(addr: SocketAddr) -> Self {
+        Self::weighted(vec![TargetGroup::new(addr.to_string(), vec![addr], 1)])
+    }
+
+    /// Split requests between the IPv4 and IPv6 addresses which were resolved
+    /// according to the [`FamilySplit`].
+    pub(crate) fn family_split(addrs: &[SocketAddr], split: FamilySplit) -> crate::Result<Self> {
+        let (ipv4, ipv6): (Vec<_>, Vec<_>) = addrs.iter().partition(|a| a.is_ipv4());
+        let mut groups = Vec::new();
+        for (name, addrs, weight) in [("ipv4", ipv4, split.ipv4), ("ipv6", ipv6, split.ipv6)] {
+            if weight == 0 {
+                continue;
+            }
+            if addrs.is_empty() {
+                return Err(format!("family split {split} requires an {name} address").into());
+            }
+            groups.push(TargetGroup::new(name, addrs, weight));
+        }
+        Ok(Self::weighted(groups))
+    }
+
+    /// Cycle requests through each host in turn, where each host is a group
+    /// of its resolved addresses.
+    pub(crate) fn round_robin(hosts: Vec<(String, Vec<SocketAddr>)>) -> Self {
+        Self::weighted(
+            hosts
+                .into_iter()
+                .map(|(name, addrs)| TargetGroup::new(name, addrs, 1))
+                .collect(),
+        )
+    }
+
+    fn weighted(groups: Vec<TargetGroup>) -> Self {
+        let divisor = groups.iter().fold(0, |acc, g| gcd(acc, g.weight)).max(1);
+        let schedule = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(i, g)| std::iter::repeat_n(i, (g.weight / divisor) as usize))
+            .collect();
+        Self {
+            groups,
+            schedule,
+            counter: AtomicUsize::new(0),
+            circuit_breaker: None,
+        }
+    }
+
+    /// Pause requests to addresses which fail repeatedly, shifting their share
+    /// to the remaining addresses.
+    pub(crate) fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Weigh the addresses of each group by the weights, which can be changed
+    /// while writing.
+    pub(crate) fn with_address_weights(mut self, weights: &Arc<AddressWeights>) -> Self {
+        for group in &mut self.groups {
+            group.addr_weights = Some(Arc::clone(weights));
+        }
+        self
+    }
+
+    /// Pick the address for the next request and the group it belongs to.
+    /// Groups without an address whose circuit is closed and whose weight is
+    /// above zero are skipped, and `None` is returned when there are none.
+    pub(crate) fn next(&self) -> Option<(SocketAddr, &TargetGroup)> {
+        let now = Instant::now();
+        let idx = self.counter.fetch_add(1, Ordering::Relaxed);
+        (0..self.schedule.len()).find_map(|offset| {
+            let group = &self.groups[self.schedule[(idx + offset) % self.schedule.len()]];
+            group.next_addr(now).map(|addr| (addr, group))
+        })
+    }
+
+    /// Earliest time at which an open circuit allows requests again.
+    pub(crate) fn next_reopening(&self) -> Option<Instant> {
+        self.groups
+            .iter()
+            .flat_map(|g| &g.breakers)
+            .filter_map(Breaker::open_until)
+            .min()
+    }
+
+    /// Record the outcome of a request to `addr` in its circuit breaker, if
+    /// one is configured, returning the new state if the circuit opened or
+    /// closed.
+    pub(crate) fn record_outcome(
+        &self,
+        group: &TargetGroup,
+        addr: SocketAddr,
+        success: bool,
+    ) -> Option<CircuitState> {
+        let config = self.circuit_breaker.as_ref()?;
+        group.breaker(addr)?.record(config, success)
+    }
+
+    pub(crate) fn groups(&self) -> &[TargetGroup] {
+        &self.groups
+    }
+
+    /// Every address of every group.
+    pub(crate) fn addrs(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.groups
+            .iter()
+            .flat_map(|group| group.addrs.iter().copied())
+    }
+
+    /// The `idx`th of every address, in the order of [`Targets::addrs`],
+    /// and the group it belongs to.
+    pub(crate) fn nth(&self, idx: usize) -> Option<(SocketAddr, &TargetGroup)> {
+        self.groups
+            .iter()
+            .flat_map(|group| group.addrs.iter().map(move |addr| (*addr, group)))
+            .nth(idx)
+    }
+}
+
+pub(crate) fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use std::time::Duration;
+
+    use std::sync::Arc;
+
+    use super::{AddressWeights, FamilySplit, TargetGroup, Targets};
+    use crate::{report::CircuitState, CircuitBreaker};
+
+    #[test]
+    fn parse_family_split() {
+        assert_eq!(
+            "70:30".parse::<FamilySplit>().unwrap(),
+            FamilySplit { ipv4: 70, ipv6: 30 }
+        );
+        assert_eq!(
+            "0:1".parse::<FamilySplit>().unwrap(),
+            FamilySplit { ipv4: 0, ipv6: 1 }
+        );
+        assert!("70".parse::<FamilySplit>().is_err());
+        assert!("0:0".parse::<FamilySplit>().is_err());
+        assert!("a:b".parse::<FamilySplit>().is_err());
+    }
+
+    #[test]
+    fn family_split_distribution() {
+        let v4: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let v6: SocketAddr = "[::1]:5000".parse().unwrap();
+        let targets = Targets::family_split(&[v4, v6], FamilySplit { ipv4: 70, ipv6: 30 }).unwrap();
+
+        let sent_v4 = (0..100)
+            .filter(|_| targets.next().unwrap().0.is_ipv4())
+            .count();
+        assert_eq!(sent_v4, 70);
+
+        assert!(Targets::family_split(&[v4], FamilySplit { ipv4: 1, ipv6: 1 }).is_err());
+        assert!(Targets::family_split(&[v4], FamilySplit { ipv4: 1, ipv6: 0 }).is_ok());
+    }
+
+    #[test]
+    fn round_robin() {
+        let a: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        let c: SocketAddr = "[::1]:5001".parse().unwrap();
+        let targets = Targets::round_robin(vec![
+            ("a".to_string(), vec![a]),
+            ("b".to_string(), vec![b, c]),
+        ]);
+
+        let sent: Vec<_> = (0..4).map(|_| targets.next().unwrap()).collect();
+        let addrs: Vec<_> = sent.iter().map(|(addr, _)| *addr).collect();
+        assert_eq!(addrs, [a, b, a, c]);
+        assert_eq!(sent[1].1.name(), "b");
+    }
+
+    #[test]
+    fn address_weights() {
+        let a: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        let weights = Arc::new(AddressWeights::default());
+        let targets = Targets::weighted(vec![TargetGroup::new("all", vec![a, b], 1)])
+            .with_address_weights(&weights);
+
+        weights.set(a, 3);
+        let sent: Vec<_> = (0..8).map(|_| targets.next().unwrap().0).collect();
+        assert_eq!(sent, [a, a, b, a, a, a, b, a]);
+
+        weights.set(a, 0);
+        assert!((0..4).all(|_| targets.next().unwrap().0 == b));
+        weights.set(b, 0);
+        assert!(targets.next().is_none());
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker() {
+        let a: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        let targets = Targets::weighted(vec![TargetGroup::new("all", vec![a, b], 1)])
+            .with_circuit_breaker(CircuitBreaker {
+                failures: 1,
+                cooldown: Duration::from_millis(50),
+            });
+
+        let (addr, group) = targets.next().unwrap();
+        assert_eq!(addr, a);
+        assert_eq!(
+            targets.record_outcome(group, a, false),
+            Some(CircuitState::Open)
+        );
+        assert!((0..4).all(|_| targets.next().unwrap().0 == b));
+
+        targets.record_outcome(group, b, false);
+        assert!(targets.next().is_none());
+        assert!(targets.next_reopening().is_some());
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(targets.next().is_some());
+        assert_eq!(
+            targets.record_outcome(group, a, true),
+            Some(CircuitState::Closed)
+        );
+    }
+}
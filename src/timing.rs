@@ -0,0 +1,205 @@
+//! Wire format for reflecting timestamps between a writer and the server.
+//!
+//! When reflecting timing, the writer prefixes each message with a header
+//! holding its send time, `gn-ts:<nanos>\n`, where `nanos` are nanoseconds
+//! since the UNIX epoch. The server replies with a single line of the form
+//! `<peer> <sent> <received> <transmitted>\n`, where `sent` is the timestamp
+//! from the header (or `-` when absent), `received` is the time the server
+//! received the message and `transmitted` the time it replied.
+//!
+//! As both timestamps come from different clocks, the one-way delay derived
+//! from them is only as accurate as the synchronisation of those clocks. The
+//! offset between them can be estimated as NTP does, from probes which are
+//! timed by both the writer and the server.
+use std::{
+    net::SocketAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const HEADER_PREFIX: &[u8] = b"gn-ts:";
+
+/// Timestamps which were reflected by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Reflection {
+    pub(crate) sent: u64,
+    pub(crate) received: u64,
+    /// Time the server replied, which is the time it received the message
+    /// for servers which do not report it.
+    pub(crate) transmitted: u64,
+}
+
+impl Reflection {
+    /// Estimated one-way delay in nanoseconds, which can be negative when the
+    /// clocks of the writer and server are skewed.
+    pub(crate) fn one_way_delay(&self) -> i64 {
+        self.received as i64 - self.sent as i64
+    }
+}
+
+/// Reflection of a calibration probe, alongside when its reply was returned
+/// to the writer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Probe {
+    pub(crate) reflection: Reflection,
+    pub(crate) returned: u64,
+}
+
+impl Probe {
+    /// Estimated nanoseconds which the server's clock is ahead of the
+    /// writer's, assuming the request and reply took equally long.
+    pub(crate) fn offset(&self) -> i64 {
+        let Reflection {
+            sent,
+            received,
+            transmitted,
+        } = self.reflection;
+        (received as i64 - sent as i64 + transmitted as i64 - self.returned as i64) / 2
+    }
+
+    /// Nanoseconds the probe spent in flight, excluding the time it was held
+    /// by the server.
+    pub(crate) fn round_trip(&self) -> i64 {
+        let Reflection {
+            sent,
+            received,
+            transmitted,
+        } = self.reflection;
+        (self.returned as i64 - sent as i64) - (transmitted as i64 - received as i64)
+    }
+}
+
+/// Probe with the shortest round trip, whose offset is the least skewed by
+/// queueing on one leg of the trip but not the other.
+pub(crate) fn best_probe(probes: impl IntoIterator<Item = Probe>) -> Option<Probe> {
+    probes.into_iter().min_by_key(Probe::round_trip)
+}
+
+/// Nanoseconds since the UNIX epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is after the UNIX epoch")
+        .as_nanos() as u64
+}
+
+/// Prefix the input with a header containing the current time.
+pub(crate) fn with_timestamp(input: &[u8]) -> Vec<u8> {
+    let mut out = format!("gn-ts:{}\n", now()).into_bytes();
+    out.extend_from_slice(input);
+    out
+}
+
+/// Split the timestamp header from a message, if there is one, returning the
+/// timestamp and the remaining payload.
+pub(crate) fn split_timestamp(message: &[u8]) -> (Option<u64>, &[u8]) {
+    let Some(rest) = message.strip_prefix(HEADER_PREFIX) else {
+        return (None, message);
+    };
+    let Some(end) = rest.iter().position(|b| *b == b'\n') else {
+        return (None, message);
+    };
+    match std::str::from_utf8(&rest[..end])
+        .ok()
+        .and_then(|ts| ts.parse().ok())
+    {
+        Some(ts) => (Some(ts), &rest[end + 1..]),
+        None => (None, message),
+    }
+}
+
+/// Reply sent by the server for a message from `peer`, which is transmitted
+/// now.
+pub(crate) fn reply(peer: SocketAddr, sent: Option<u64>, received: u64) -> String {
+    let transmitted = now();
+    match sent {
+        Some(sent) => format!("{peer} {sent} {received} {transmitted}\n"),
+        None => format!("{peer} - {received} {transmitted}\n"),
+    }
+}
+
+/// Parse a reply from the server, which is `None` if it is malformed or the
+/// send timestamp was not reflected.
+pub(crate) fn parse_reply(reply: &[u8]) -> Option<Reflection> {
+    let reply = std::str::from_utf8(reply).ok()?;
+    let mut parts = reply.split_whitespace().skip(1);
+    let sent = parts.next()?.parse().ok()?;
+    let received = parts.next()?.parse().ok()?;
+    let transmitted = match parts.next() {
+        Some(transmitted) => transmitted.parse().ok()?,
+        None => received,
+    };
+    Some(Reflection {
+        sent,
+        received,
+        transmitted,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        best_probe, now, parse_reply, reply, split_timestamp, with_timestamp, Probe, Reflection,
+    };
+
+    #[test]
+    fn round_trip() {
+        let message = with_timestamp(b"hello");
+        let (sent, body) = split_timestamp(&message);
+        assert!(sent.is_some());
+        assert_eq!(body, b"hello");
+
+        let peer = "127.0.0.1:5000".parse().unwrap();
+        let line = reply(peer, sent, sent.unwrap() + 10);
+        let reflection = parse_reply(line.as_bytes()).unwrap();
+        assert_eq!(reflection.sent, sent.unwrap());
+        assert_eq!(reflection.received, sent.unwrap() + 10);
+        assert!(reflection.transmitted <= now());
+        assert_eq!(reflection.one_way_delay(), 10);
+
+        // Servers which do not report when they replied.
+        assert_eq!(
+            parse_reply(b"127.0.0.1:5000 5 15\n"),
+            Some(Reflection {
+                sent: 5,
+                received: 15,
+                transmitted: 15
+            })
+        );
+    }
+
+    #[test]
+    fn clock_offset() {
+        let probe = |sent, received, transmitted, returned| Probe {
+            reflection: Reflection {
+                sent,
+                received,
+                transmitted,
+            },
+            returned,
+        };
+        // The server is 1000ns ahead, with 100ns each way and 50ns to reply.
+        let even = probe(0, 1_100, 1_150, 250);
+        assert_eq!(even.offset(), 1_000);
+        assert_eq!(even.round_trip(), 200);
+        // A reply which was queued for 400ns skews the offset.
+        let queued = probe(0, 1_100, 1_150, 650);
+        assert_eq!(queued.offset(), 800);
+
+        assert_eq!(best_probe([queued, even]), Some(even));
+        assert_eq!(best_probe([]), None);
+    }
+
+    #[test]
+    fn without_header() {
+        assert_eq!(split_timestamp(b"hello"), (None, &b"hello"[..]));
+        assert_eq!(
+            split_timestamp(b"gn-ts:abc\nhi"),
+            (None, &b"gn-ts:abc\nhi"[..])
+        );
+        assert_eq!(split_timestamp(b"gn-ts:12"), (None, &b"gn-ts:12"[..]));
+
+        let peer = "127.0.0.1:5000".parse().unwrap();
+        assert_eq!(parse_reply(reply(peer, None, 1).as_bytes()), None);
+        assert_eq!(parse_reply(b"garbage"), None);
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use tokio_rustls::{
+    rustls::{
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        client::Resumption,
+        crypto::{self, CryptoProvider},
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+        ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme,
+    },
+    TlsAcceptor, TlsConnector,
+};
+
+/// Settings for writing over [`crate::Protocol::Tls`].
+///
+/// By default, the server's certificate is verified against the web PKI
+/// roots, using the IP address which is written to as the server name.
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    ca_file: Option<PathBuf>,
+    insecure: bool,
+    server_name: Option<String>,
+    no_session_resumption: bool,
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust the certificates of a PEM bundle rather than the web PKI roots.
+    pub fn with_ca_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_file = Some(path.into());
+        self
+    }
+
+    /// Accept any certificate the server presents. This leaves the connection
+    /// open to interception, so is only suitable for testing.
+    pub fn with_insecure(mut self) -> Self {
+        self.insecure = true;
+        self
+    }
+
+    /// Name which is sent through SNI and which the server's certificate is
+    /// verified against.
+    pub fn with_server_name(mut self, name: impl Into<String>) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    /// Perform a full handshake for every connection, rather than resuming
+    /// the session of an earlier connection to the same server, to measure
+    /// the full cost of the handshake.
+    pub fn without_session_resumption(mut self) -> Self {
+        self.no_session_resumption = true;
+        self
+    }
+
+    pub(crate) fn session_resumption(&self) -> bool {
+        !self.no_session_resumption
+    }
+
+    pub(crate) fn connector(&self) -> crate::Result<TlsConnector> {
+        let provider = Arc::new(crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()?;
+        let mut config = if self.insecure {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+                .with_no_client_auth()
+        } else {
+            let mut roots = RootCertStore::empty();
+            match &self.ca_file {
+                Some(path) => {
+                    for cert in CertificateDer::pem_file_iter(path)? {
+                        roots.add(cert?)?;
+                    }
+                }
+                None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+            }
+            builder.with_root_certificates(roots).with_no_client_auth()
+        };
+        if self.no_session_resumption {
+            config.resumption = Resumption::disabled();
+        }
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+
+    /// Name of the server when connecting to `addr`.
+    pub(crate) fn server_name(&self, addr: SocketAddr) -> crate::Result<ServerName<'static>> {
+        match &self.server_name {
+            Some(name) => Ok(ServerName::try_from(name.clone())?),
+            None => Ok(ServerName::IpAddress(addr.ip().into())),
+        }
+    }
+}
+
+/// Certificate chain and private key which a [`crate::Server`] presents when
+/// serving [`crate::Protocol::Tls`].
+#[derive(Clone)]
+pub struct TlsServerConfig {
+    config: Arc<ServerConfig>,
+}
+
+impl TlsServerConfig {
+    /// Read the certificate chain and private key from PEM files.
+    pub fn load(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> crate::Result<Self> {
+        let chain = CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+        let key = PrivateKeyDer::from_pem_file(key)?;
+        let config =
+            ServerConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()?
+                .with_no_client_auth()
+                .with_single_cert(chain, key)?;
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+
+    pub(crate) fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(Arc::clone(&self.config))
+    }
+}
+
+/// Sink for the data which a [`crate::Server`] serving TLS receives under a
+/// server name, parsed from `name=file`.
+///
+/// The name is matched against the server name indication (SNI) which each
+/// client sends in its handshake, ignoring case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SniRoute {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl FromStr for SniRoute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, path) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected a route in the form NAME=FILE, got '{s}'"))?;
+        let name = name.trim();
+        if name.is_empty() || path.is_empty() {
+            return Err(format!("expected a route in the form NAME=FILE, got '{s}'"));
+        }
+        Ok(Self {
+            name: name.to_ascii_lowercase(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// Verifier for `--insecure`, which only checks that the handshake was signed
+/// by the key of the certificate it was given.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Self-signed certificate for `localhost`, written to PEM files which are
+/// removed on drop.
+#[cfg(test)]
+pub(crate) struct TestCertificate {
+    pub(crate) cert: PathBuf,
+    pub(crate) key: PathBuf,
+}
+
+#[cfg(test)]
+impl TestCertificate {
+    pub(crate) fn new() -> Self {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir();
+        let cert = dir.join(format!("gn-{}-{id}-cert.pem", std::process::id()));
+        let key = dir.join(format!("gn-{}-{id}-key.pem", std::process::id()));
+
+        let names = vec!["localhost".to_string(), "api.localhost".to_string()];
+        let generated = rcgen::generate_simple_self_signed(names).unwrap();
+        std::fs::write(&cert, generated.cert.pem()).unwrap();
+        std::fs::write(&key, generated.signing_key.serialize_pem()).unwrap();
+        Self { cert, key }
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestCertificate {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.cert);
+        let _ = std::fs::remove_file(&self.key);
+    }
+}
@@ -0,0 +1,76 @@
+//! W3C trace context for requests, so that they can be found in the
+//! distributed traces of the target.
+//!
+//! Each request is given a random trace and span, which are sent ahead of its
+//! input as a line holding the `traceparent` header,
+//! `traceparent: 00-<trace-id>-<span-id>-01\n`, for the target to parse and
+//! continue the trace from.
+use std::fmt::Display;
+
+const HEADER_PREFIX: &[u8] = b"traceparent: ";
+
+/// Trace and span which a single request is part of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TraceContext {
+    trace_id: u128,
+    span_id: u64,
+}
+
+impl TraceContext {
+    /// Start a new, sampled, trace.
+    pub(crate) fn random() -> Self {
+        // Identifiers of all zeroes are invalid.
+        Self {
+            trace_id: rand::random::<u128>().max(1),
+            span_id: rand::random::<u64>().max(1),
+        }
+    }
+
+    pub(crate) fn trace_id(&self) -> String {
+        format!("{:032x}", self.trace_id)
+    }
+
+    pub(crate) fn span_id(&self) -> String {
+        format!("{:016x}", self.span_id)
+    }
+}
+
+impl Display for TraceContext {
+    /// The value of the `traceparent` header.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "00-{}-{}-01", self.trace_id(), self.span_id())
+    }
+}
+
+/// Prefix the input with the `traceparent` header of the trace.
+pub(crate) fn with_traceparent(trace: &TraceContext, input: &[u8]) -> Vec<u8> {
+    let mut out = HEADER_PREFIX.to_vec();
+    out.extend_from_slice(format!("{trace}\n").as_bytes());
+    out.extend_from_slice(input);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{with_traceparent, TraceContext};
+
+    #[test]
+    fn traceparent() {
+        let trace = TraceContext {
+            trace_id: 0x4bf92f3577b34da6a3ce929d0e0e4736,
+            span_id: 0x00f067aa0ba902b7,
+        };
+        assert_eq!(
+            trace.to_string(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        assert_eq!(
+            with_traceparent(&trace, b"hello"),
+            b"traceparent: 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01\nhello"
+        );
+
+        let random = TraceContext::random();
+        assert_ne!(random, TraceContext::random());
+        assert_eq!(random.to_string().len(), 55);
+    }
+}
@@ -0,0 +1,98 @@
+//! Transcript of a sample of the requests made by a writer and the replies
+//! to them, saved to a directory to debug a protocol offline.
+//!
+//! The `n`th request, counting from 0, is saved to `<n>.request` and its
+//! reply, when one was received, to `<n>.response`. Each is listed in
+//! `index.jsonl` as a line of JSON with the same start time, address,
+//! latency and outcome as its line in the event log, so the two can be
+//! joined.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    num::NonZeroU64,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use serde::Serialize;
+
+use crate::events::RequestEvent;
+
+/// Directory which a sample of requests and their replies are saved to, as
+/// described in the [module](self) documentation.
+pub struct Transcript {
+    dir: PathBuf,
+    every: u64,
+    index: Mutex<BufWriter<File>>,
+}
+
+/// Line of the index of a transcript.
+#[derive(Serialize)]
+struct Entry<'a> {
+    index: u64,
+    request: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<String>,
+    #[serde(flatten)]
+    event: &'a RequestEvent,
+}
+
+impl Transcript {
+    /// Save one in every `every` requests to the directory, which is created
+    /// if it does not exist.
+    pub fn create(dir: impl Into<PathBuf>, every: NonZeroU64) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        let index = File::create(dir.join("index.jsonl"))?;
+        Ok(Self {
+            dir,
+            every: every.get(),
+            index: Mutex::new(BufWriter::new(index)),
+        })
+    }
+
+    /// Whether the `index`th request is saved.
+    pub(crate) fn samples(&self, index: u64) -> bool {
+        index.is_multiple_of(self.every)
+    }
+
+    /// Save the `index`th request and its reply, logging to stderr if they
+    /// cannot be written.
+    pub(crate) fn record(
+        &self,
+        index: u64,
+        request: &[u8],
+        reply: Option<&[u8]>,
+        event: &RequestEvent,
+    ) {
+        if let Err(e) = self.write(index, request, reply, event) {
+            eprintln!("Unable to write to the transcript: {e}");
+        }
+    }
+
+    fn write(
+        &self,
+        index: u64,
+        request: &[u8],
+        reply: Option<&[u8]>,
+        event: &RequestEvent,
+    ) -> io::Result<()> {
+        let entry = Entry {
+            index,
+            request: format!("{index}.request"),
+            response: reply.map(|_| format!("{index}.response")),
+            event,
+        };
+        std::fs::write(self.dir.join(&entry.request), request)?;
+        if let (Some(name), Some(reply)) = (&entry.response, reply) {
+            std::fs::write(self.dir.join(name), reply)?;
+        }
+        let mut out = self.index.lock().unwrap();
+        serde_json::to_writer(&mut *out, &entry)?;
+        out.write_all(b"\n")
+    }
+
+    pub(crate) fn flush(&self) -> io::Result<()> {
+        self.index.lock().unwrap().flush()
+    }
+}
@@ -0,0 +1,1636 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    future::Future,
+    io::ErrorKind,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use socket2::SockRef;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream, UdpSocket,
+    },
+    sync::{mpsc, Semaphore},
+    task::JoinSet,
+    time::Instant,
+};
+use tokio_rustls::rustls::HandshakeKind;
+
+use crate::{
+    multicast,
+    reply::{exchange_stream, Reply, ReplyFraming},
+    statistics::Statistics,
+    tls::TlsConfig,
+    websocket::{WebSocketConfig, WebSocketTransport},
+    Endpoint, Protocol,
+};
+
+/// Largest reply which can be received in a single UDP datagram.
+const MAX_DATAGRAM_SIZE: usize = 64 * 1024;
+
+/// Size of each chunk of a generated payload which is streamed to a socket.
+const GENERATED_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Size of each read of the inbound stream of a duplex connection.
+const INBOUND_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The means by which a single request is written to an address.
+///
+/// Implementations are chosen from the [`Protocol`] by default, but a custom
+/// transport can be provided to a [`crate::SocketManager`], for example the
+/// [`MemoryTransport`] in tests.
+pub trait Transport: Send + Sync {
+    /// Write the input to the address, returning the number of bytes written.
+    fn write<'a>(&'a self, addr: SocketAddr, input: &'a [u8]) -> BoxFuture<'a, crate::Result<u64>>;
+
+    /// Write the input to the address and wait for the reply, returning the
+    /// number of bytes written alongside the reply.
+    ///
+    /// Transports do not support replies unless they implement this.
+    fn exchange<'a>(
+        &'a self,
+        _addr: SocketAddr,
+        _input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<(u64, Vec<u8>)>> {
+        Box::pin(async { Err("transport does not support replies".into()) })
+    }
+
+    /// Write the input to the address and read the reply until it ends as
+    /// given by the framing, timing the round trip.
+    ///
+    /// By default only replies which end at EOF are supported, through
+    /// [`Transport::exchange`], where the round trip includes connecting.
+    fn exchange_until<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+        framing: &'a ReplyFraming,
+    ) -> BoxFuture<'a, crate::Result<Reply>> {
+        Box::pin(async move {
+            if *framing != ReplyFraming::Eof {
+                return Err(
+                    format!("transport does not support replies framed by {framing}").into(),
+                );
+            }
+            timed_exchange(self, addr, input).await
+        })
+    }
+
+    /// Write `len` bytes of generated data to the address in chunks, without
+    /// holding the whole payload in memory, returning the number of bytes
+    /// written.
+    ///
+    /// Transports do not support streaming unless they implement this.
+    fn write_generated(&self, _addr: SocketAddr, _len: u64) -> BoxFuture<'_, crate::Result<u64>> {
+        Box::pin(async { Err("transport does not support streaming generated payloads".into()) })
+    }
+}
+
+/// Time a [`Transport::exchange`], for transports where the whole reply
+/// arrives at once regardless of framing.
+async fn timed_exchange<T: Transport + ?Sized>(
+    transport: &T,
+    addr: SocketAddr,
+    input: &[u8],
+) -> crate::Result<Reply> {
+    let start = Instant::now();
+    let (written, data) = transport.exchange(addr, input).await?;
+    let round_trip = start.elapsed();
+    Ok(Reply {
+        written,
+        data,
+        round_trip,
+        first_byte: round_trip,
+    })
+}
+
+/// A write which failed after some of the input was already written.
+#[derive(Debug)]
+pub struct PartialWrite {
+    /// Number of bytes which were written before the error.
+    pub written: u64,
+    pub source: std::io::Error,
+}
+
+impl Display for PartialWrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "write failed after {} bytes: {}",
+            self.written, self.source
+        )
+    }
+}
+
+impl std::error::Error for PartialWrite {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Write the whole input with explicit calls to `write`, rather than
+/// `write_all`, so the number of bytes which made it out before an error is
+/// known.
+pub(crate) async fn write_counted<W>(writer: &mut W, input: &[u8]) -> Result<u64, PartialWrite>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut written = 0;
+    while written < input.len() {
+        match writer.write(&input[written..]).await {
+            Ok(0) => {
+                return Err(PartialWrite {
+                    written: written as u64,
+                    source: ErrorKind::WriteZero.into(),
+                })
+            }
+            Ok(n) => written += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(source) => {
+                return Err(PartialWrite {
+                    written: written as u64,
+                    source,
+                })
+            }
+        }
+    }
+    Ok(written as u64)
+}
+
+/// Write `len` bytes of random data, repeating a single generated chunk, so
+/// that the payload never has to be held in memory.
+pub(crate) async fn write_generated_counted<W>(
+    writer: &mut W,
+    len: u64,
+) -> Result<u64, PartialWrite>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut chunk = vec![0; GENERATED_CHUNK_SIZE.min(len as usize)];
+    rand::fill(chunk.as_mut_slice());
+    let mut written = 0;
+    while written < len {
+        let remaining = (len - written).min(chunk.len() as u64) as usize;
+        match write_counted(writer, &chunk[..remaining]).await {
+            Ok(n) => written += n,
+            Err(e) => {
+                return Err(PartialWrite {
+                    written: written + e.written,
+                    source: e.source,
+                })
+            }
+        }
+    }
+    Ok(written)
+}
+
+/// Failure to establish the connection of a request, including its TLS
+/// handshake, before any of the request was written, so retrying it never
+/// delivers the request twice.
+#[derive(Debug)]
+pub struct ConnectError(Box<dyn std::error::Error>);
+
+impl ConnectError {
+    pub fn new(source: impl Into<Box<dyn std::error::Error>>) -> Self {
+        Self(source.into())
+    }
+
+    /// Whether a failed request failed to connect, rather than once it was
+    /// connected.
+    pub(crate) fn is_cause(err: &(dyn std::error::Error + 'static)) -> bool {
+        caused_by::<Self>(err)
+    }
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Connect errors already describe the step which failed.
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ConnectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+/// Failure of a request which was written in full, but whose reply, or the
+/// peer closing the connection when that is waited for, never arrived, as
+/// opposed to one which failed to be sent.
+#[derive(Debug)]
+pub struct Unacknowledged(Box<dyn std::error::Error>);
+
+impl Unacknowledged {
+    pub fn new(source: impl Into<Box<dyn std::error::Error>>) -> Self {
+        Self(source.into())
+    }
+
+    /// Whether a failed request was written, but never acknowledged.
+    pub(crate) fn is_cause(err: &(dyn std::error::Error + 'static)) -> bool {
+        caused_by::<Self>(err)
+    }
+}
+
+impl Display for Unacknowledged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Unacknowledged {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+/// Whether an error of the type is in the chain of sources of the error,
+/// starting with itself.
+fn caused_by<E: std::error::Error + 'static>(err: &(dyn std::error::Error + 'static)) -> bool {
+    std::iter::successors(Some(err), |e| e.source()).any(|e| e.is::<E>())
+}
+
+/// Half-close a stream whose request was written, so the server knows the
+/// message is complete, then read the reply until the server closes it.
+pub(crate) async fn read_to_close<S>(stream: &mut S) -> Result<Vec<u8>, Unacknowledged>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut reply = Vec::new();
+    let read = async {
+        stream.shutdown().await?;
+        stream.read_to_end(&mut reply).await
+    };
+    read.await.map_err(Unacknowledged::new)?;
+    Ok(reply)
+}
+
+/// Reason the local network stack refused to send a request, dropping it
+/// before it left the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SourceDrop {
+    /// The socket's send buffer was full (`EAGAIN`).
+    WouldBlock,
+    /// The stack had no buffer space left (`ENOBUFS`).
+    NoBufferSpace,
+}
+
+impl SourceDrop {
+    /// Classify the error of a failed request, if it was dropped at source.
+    pub(crate) fn from_error(err: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        io_errors(err).find_map(|err| {
+            if err.kind() == ErrorKind::WouldBlock {
+                return Some(Self::WouldBlock);
+            }
+            #[cfg(target_os = "linux")]
+            if err.raw_os_error() == Some(libc::ENOBUFS) {
+                return Some(Self::NoBufferSpace);
+            }
+            None
+        })
+    }
+}
+
+/// How the peer closed the connection of a failed request, when that is why
+/// it failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PeerClose {
+    /// The connection was reset or aborted, including by writing to or
+    /// shutting down one which the peer had already torn down (`EPIPE`,
+    /// `ENOTCONN`).
+    Reset,
+    /// The connection was closed before the whole reply was received.
+    Early,
+}
+
+impl PeerClose {
+    /// Classify the error of a failed request, if the peer closed it.
+    pub(crate) fn from_error(err: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        io_errors(err).find_map(|e| match e.kind() {
+            ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::NotConnected => Some(Self::Reset),
+            ErrorKind::UnexpectedEof => Some(Self::Early),
+            _ => None,
+        })
+    }
+}
+
+/// Whether a failed request timed out, either through a configured timeout
+/// or the operating system giving up (`ETIMEDOUT`).
+pub(crate) fn is_timeout(err: &(dyn std::error::Error + 'static)) -> bool {
+    io_errors(err).any(|e| e.kind() == ErrorKind::TimedOut)
+}
+
+/// I/O errors in the chain of sources of the error, starting with itself.
+fn io_errors<'a>(
+    err: &'a (dyn std::error::Error + 'static),
+) -> impl Iterator<Item = &'a std::io::Error> {
+    std::iter::successors(Some(err), |e| e.source()).filter_map(|e| e.downcast_ref())
+}
+
+/// Wait for a step of a request, failing with [`ErrorKind::TimedOut`] when
+/// it takes longer than the timeout, if there is one.
+async fn within<T, E>(
+    timeout: Option<Duration>,
+    step: &str,
+    future: impl Future<Output = Result<T, E>>,
+) -> crate::Result<T>
+where
+    E: Into<Box<dyn std::error::Error>>,
+{
+    let Some(timeout) = timeout else {
+        return future.await.map_err(Into::into);
+    };
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => Err(std::io::Error::new(
+            ErrorKind::TimedOut,
+            format!(
+                "{step} timed out after {}",
+                humantime::format_duration(timeout)
+            ),
+        )
+        .into()),
+    }
+}
+
+/// Settings applied to the transport chosen from the [`Protocol`].
+#[derive(Clone, Default)]
+pub(crate) struct TransportConfig {
+    /// Maximum number of connections which may be established at once.
+    pub(crate) connect_concurrency: Option<usize>,
+    /// Statistics to record samples of the UDP send queue and the number of
+    /// opened connections into.
+    pub(crate) stats: Option<Arc<Statistics>>,
+    pub(crate) tls: TlsConfig,
+    pub(crate) websocket: WebSocketConfig,
+    /// Reuse connections across requests rather than opening one for each.
+    pub(crate) keepalive: bool,
+    /// Read what the server pushes down kept alive connections while writing.
+    pub(crate) duplex: bool,
+    /// Age at which a kept alive connection is closed and re-established.
+    pub(crate) connection_lifetime: Option<Duration>,
+    /// Network interface which sockets are bound to.
+    pub(crate) interface: Option<String>,
+    /// Routers which datagrams to a multicast group may cross.
+    pub(crate) multicast_ttl: Option<u32>,
+    /// Time allowed to establish a connection, including any TLS handshake.
+    pub(crate) connect_timeout: Option<Duration>,
+    /// Time allowed to write the input of a request once connected.
+    pub(crate) write_timeout: Option<Duration>,
+    /// Half-close connections once written and wait for the peer to close
+    /// them before a write succeeds.
+    pub(crate) wait_peer_close: bool,
+    /// Time allowed for the peer to close the connection.
+    pub(crate) close_timeout: Option<Duration>,
+}
+
+/// Transport for the given [`Protocol`] writing to the endpoint, which must
+/// be a path for Unix sockets and an IP address otherwise.
+///
+/// Transports for Unix sockets write to their path whatever address they are
+/// given, so requests to them are made with [`crate::endpoint::UNIX_PEER`].
+pub(crate) fn for_endpoint(
+    protocol: &Protocol,
+    endpoint: &Endpoint,
+    config: &TransportConfig,
+) -> crate::Result<Arc<dyn Transport>> {
+    if config.keepalive && *protocol != Protocol::Tcp {
+        return Err(format!("keepalive is only supported over tcp, not {protocol}").into());
+    }
+    if config.connection_lifetime.is_some() && !config.keepalive {
+        return Err("a connection lifetime requires keepalive".into());
+    }
+    if config.duplex && !config.keepalive {
+        return Err("duplex requires keepalive".into());
+    }
+    if config.interface.is_some() && protocol.is_unix() {
+        return Err(format!("an interface cannot be used with {protocol}").into());
+    }
+    if config.multicast_ttl.is_some() && *protocol != Protocol::Udp {
+        return Err(format!("a multicast TTL is only supported over udp, not {protocol}").into());
+    }
+    let timeouts = config.connect_timeout.is_some() || config.write_timeout.is_some();
+    if timeouts && !matches!(protocol, Protocol::Tcp | Protocol::Tls) {
+        return Err(format!("timeouts are only supported over tcp and tls, not {protocol}").into());
+    }
+    if config.wait_peer_close && !matches!(protocol, Protocol::Tcp | Protocol::Tls) {
+        return Err(format!(
+            "waiting for the peer to close is only supported over tcp and tls, not {protocol}"
+        )
+        .into());
+    }
+    if config.wait_peer_close && config.keepalive {
+        return Err("waiting for the peer to close cannot be used with keepalive".into());
+    }
+    match (protocol, endpoint) {
+        (Protocol::Tcp, Endpoint::Inet(_) | Endpoint::Host(_)) => {
+            let mut transport = TcpTransport::new();
+            if let Some(limit) = config.connect_concurrency {
+                transport = transport.with_connect_concurrency(limit);
+            }
+            if let Some(stats) = &config.stats {
+                transport = transport.with_connection_stats(Arc::clone(stats));
+            }
+            if config.keepalive {
+                transport = transport.with_keepalive();
+            }
+            if config.duplex {
+                transport = transport.with_duplex();
+            }
+            if let Some(lifetime) = config.connection_lifetime {
+                transport = transport.with_connection_lifetime(lifetime);
+            }
+            if let Some(interface) = &config.interface {
+                transport = transport.with_interface(interface);
+            }
+            Ok(Arc::new(transport.with_timeouts(config)))
+        }
+        (Protocol::Tls, Endpoint::Inet(_) | Endpoint::Host(_)) => {
+            let mut tcp = TcpTransport::new();
+            if let Some(limit) = config.connect_concurrency {
+                tcp = tcp.with_connect_concurrency(limit);
+            }
+            if let Some(interface) = &config.interface {
+                tcp = tcp.with_interface(interface);
+            }
+            let mut transport = TlsTransport::new(tcp.with_timeouts(config), &config.tls)?;
+            if let Some(stats) = &config.stats {
+                transport = transport.with_handshake_stats(Arc::clone(stats));
+            }
+            Ok(Arc::new(transport))
+        }
+        (Protocol::Ws | Protocol::Wss, Endpoint::Inet(_) | Endpoint::Host(_)) => {
+            let mut tcp = TcpTransport::new();
+            if let Some(limit) = config.connect_concurrency {
+                tcp = tcp.with_connect_concurrency(limit);
+            }
+            if let Some(stats) = &config.stats {
+                tcp = tcp.with_connection_stats(Arc::clone(stats));
+            }
+            if let Some(interface) = &config.interface {
+                tcp = tcp.with_interface(interface);
+            }
+            let transport = WebSocketTransport::new(tcp, config.websocket.clone());
+            match protocol {
+                Protocol::Wss => Ok(Arc::new(transport.with_tls(&config.tls)?)),
+                _ => Ok(Arc::new(transport)),
+            }
+        }
+        (Protocol::Udp, Endpoint::Inet(_) | Endpoint::Host(_)) => {
+            let mut transport = UdpTransport::new();
+            if let Some(stats) = &config.stats {
+                transport = transport.with_send_queue_sampling(Arc::clone(stats));
+            }
+            if let Some(interface) = &config.interface {
+                transport = transport.with_interface(interface);
+            }
+            if let Some(ttl) = config.multicast_ttl {
+                transport = transport.with_multicast_ttl(ttl);
+            }
+            Ok(Arc::new(transport))
+        }
+        #[cfg(unix)]
+        (Protocol::Unix, Endpoint::Unix(path)) => Ok(Arc::new(UnixTransport::new(path))),
+        #[cfg(unix)]
+        (Protocol::UnixDatagram, Endpoint::Unix(path)) => {
+            Ok(Arc::new(UnixDatagramTransport::new(path)))
+        }
+        (protocol, endpoint) => Err(mismatched_endpoint(protocol, endpoint).into()),
+    }
+}
+
+/// Reason a [`Protocol`] cannot be used with the endpoint.
+pub(crate) fn mismatched_endpoint(protocol: &Protocol, endpoint: &Endpoint) -> String {
+    match endpoint {
+        _ if protocol.is_unix() && cfg!(not(unix)) => {
+            "Unix sockets are not supported on this platform".to_string()
+        }
+        Endpoint::Inet(_) | Endpoint::Host(_) => {
+            format!("{protocol} requires a socket path, got {endpoint}")
+        }
+        Endpoint::Unix(path) => {
+            format!("{protocol} requires an IP address, got {}", path.display())
+        }
+    }
+}
+
+/// Opens a new [`TcpStream`] for every write, unless connections are kept
+/// alive.
+#[derive(Default)]
+pub struct TcpTransport {
+    connect_permits: Option<Arc<Semaphore>>,
+    connections: Option<Arc<Statistics>>,
+    idle: Option<ConnectionPool<(TcpStream, Instant)>>,
+    /// Write halves of kept alive connections whose read halves are read
+    /// by the tasks of `inbound`, which are aborted with the transport.
+    duplex: Option<ConnectionPool<(OwnedWriteHalf, Instant)>>,
+    inbound: Mutex<JoinSet<()>>,
+    connection_lifetime: Option<Duration>,
+    interface: Option<String>,
+    connect_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    wait_peer_close: bool,
+    close_timeout: Option<Duration>,
+}
+
+impl TcpTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of connections which may be in the process of being
+    /// established at once, independent of how many writes are in flight.
+    pub fn with_connect_concurrency(mut self, limit: usize) -> Self {
+        self.connect_permits = Some(Arc::new(Semaphore::new(limit)));
+        self
+    }
+
+    /// Count every connection which is opened in the statistics.
+    pub fn with_connection_stats(mut self, stats: Arc<Statistics>) -> Self {
+        self.connections = Some(stats);
+        self
+    }
+
+    /// Keep connections open once a write completes, reusing them for later
+    /// writes to the same address. Each concurrent write holds a connection
+    /// of its own, and a connection which fails is closed rather than reused.
+    ///
+    /// As a reply is read until the server closes the connection,
+    /// [`Transport::exchange`] still opens a connection for every request.
+    pub fn with_keepalive(mut self) -> Self {
+        self.idle = Some(ConnectionPool::default());
+        self
+    }
+
+    /// Keep connections open as with [`TcpTransport::with_keepalive`], while
+    /// reading whatever the server pushes down each of them in the
+    /// background, independently of the writes, as with chat and streaming
+    /// protocols. The bytes read are counted as inbound in the connection
+    /// statistics.
+    pub fn with_duplex(mut self) -> Self {
+        self.idle = None;
+        self.duplex = Some(ConnectionPool::default());
+        self
+    }
+
+    /// Close kept alive connections once they have been open for the
+    /// lifetime, so the next write re-establishes them, as with clients behind
+    /// load balancers which recycle connections. A connection is only closed
+    /// between writes, and is counted in the connection statistics.
+    pub fn with_connection_lifetime(mut self, lifetime: Duration) -> Self {
+        self.connection_lifetime = Some(lifetime);
+        self
+    }
+
+    /// Connect through a network interface or VRF, such as `eth1`, so
+    /// traffic only leaves through it. This is only supported on Linux,
+    /// through `SO_BINDTODEVICE`, which usually requires `CAP_NET_RAW`.
+    pub fn with_interface(mut self, interface: impl Into<String>) -> Self {
+        self.interface = Some(interface.into());
+        self
+    }
+
+    /// Fail connections which are not established within the timeout, so a
+    /// target which never answers does not stall a request indefinitely.
+    /// Over TLS, this includes the handshake.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail requests whose input is not written within the timeout once
+    /// connected, such as when the target stops reading.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Once the input is written, half-close the connection and wait for the
+    /// peer to close it too before the write succeeds, failing if it does not
+    /// within the timeout or resets the connection instead. This catches
+    /// servers which accept the input but crash while processing it.
+    pub fn with_wait_peer_close(mut self, timeout: Option<Duration>) -> Self {
+        self.wait_peer_close = true;
+        self.close_timeout = timeout;
+        self
+    }
+
+    fn with_timeouts(mut self, config: &TransportConfig) -> Self {
+        self.connect_timeout = config.connect_timeout;
+        self.write_timeout = config.write_timeout;
+        self.wait_peer_close = config.wait_peer_close;
+        self.close_timeout = config.close_timeout;
+        self
+    }
+
+    /// Half-close the written stream and wait for the peer to close it, when
+    /// waiting for the peer to close. Anything it sends meanwhile is
+    /// discarded.
+    async fn await_peer_close<S>(&self, stream: &mut S) -> crate::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        if !self.wait_peer_close {
+            return Ok(());
+        }
+        stream.shutdown().await?;
+        within(
+            self.close_timeout,
+            "waiting for the peer to close",
+            tokio::io::copy(stream, &mut tokio::io::sink()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn connect(&self, addr: SocketAddr) -> crate::Result<TcpStream> {
+        let _permit = match &self.connect_permits {
+            Some(permits) => Some(permits.acquire().await?),
+            None => None,
+        };
+        let stream = within(self.connect_timeout, "connect", async {
+            match &self.interface {
+                Some(interface) => connect_through(addr, interface).await,
+                None => TcpStream::connect(addr).await,
+            }
+        })
+        .await
+        .map_err(ConnectError::new)?;
+        if let Some(stats) = &self.connections {
+            stats.record_connection();
+        }
+        Ok(stream)
+    }
+
+    /// An idle connection to the address when connections are kept alive,
+    /// otherwise a new one, alongside the time it was opened.
+    async fn checkout(&self, addr: SocketAddr) -> crate::Result<(TcpStream, Instant)> {
+        match self.idle.as_ref().and_then(|idle| idle.take(addr)) {
+            Some(idle) => Ok(idle),
+            None => Ok((self.connect(addr).await?, Instant::now())),
+        }
+    }
+
+    /// Write the whole input within the write timeout.
+    async fn write_within<W>(&self, stream: &mut W, input: &[u8]) -> crate::Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        within(self.write_timeout, "write", write_counted(stream, input)).await
+    }
+
+    /// Write `len` bytes of generated data within the write timeout.
+    async fn write_generated_within<W>(&self, stream: &mut W, len: u64) -> crate::Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        within(
+            self.write_timeout,
+            "write",
+            write_generated_counted(stream, len),
+        )
+        .await
+    }
+
+    /// Return a connection whose write succeeded, to be reused when
+    /// connections are kept alive. Connections which the peer has closed, or
+    /// which have reached their lifetime, are never reused.
+    fn checkin(&self, addr: SocketAddr, stream: TcpStream, opened: Instant) {
+        let (Some(idle), false) = (&self.idle, self.wait_peer_close) else {
+            return;
+        };
+        self.keep(idle, addr, stream, opened);
+    }
+
+    /// Write half of an idle duplex connection to the address, otherwise of a
+    /// new one whose read half is read until the server closes it.
+    async fn checkout_duplex(
+        &self,
+        duplex: &ConnectionPool<(OwnedWriteHalf, Instant)>,
+        addr: SocketAddr,
+    ) -> crate::Result<(OwnedWriteHalf, Instant)> {
+        if let Some(idle) = duplex.take(addr) {
+            return Ok(idle);
+        }
+        let (read, write) = self.connect(addr).await?.into_split();
+        self.inbound
+            .lock()
+            .unwrap()
+            .spawn(read_inbound(read, self.connections.clone()));
+        Ok((write, Instant::now()))
+    }
+
+    /// Put a connection back into the pool, unless it has reached its
+    /// lifetime.
+    fn keep<S>(
+        &self,
+        pool: &ConnectionPool<(S, Instant)>,
+        addr: SocketAddr,
+        stream: S,
+        opened: Instant,
+    ) {
+        match self.connection_lifetime {
+            Some(lifetime) if opened.elapsed() >= lifetime => {
+                if let Some(stats) = &self.connections {
+                    stats.record_connection_recycled();
+                }
+            }
+            _ => pool.put(addr, (stream, opened)),
+        }
+    }
+}
+
+/// Read the inbound stream of a duplex connection until the server closes
+/// it, counting the bytes which arrive.
+async fn read_inbound(mut read: OwnedReadHalf, stats: Option<Arc<Statistics>>) {
+    let mut chunk = vec![0; INBOUND_CHUNK_SIZE];
+    while let Ok(n @ 1..) = read.read(&mut chunk).await {
+        if let Some(stats) = &stats {
+            stats.record_inbound(n as u64);
+        }
+    }
+}
+
+/// Idle connections which are reused by later writes to the same address.
+pub(crate) struct ConnectionPool<S> {
+    idle: Mutex<HashMap<SocketAddr, Vec<S>>>,
+}
+
+impl<S> Default for ConnectionPool<S> {
+    fn default() -> Self {
+        Self {
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<S> ConnectionPool<S> {
+    pub(crate) fn take(&self, addr: SocketAddr) -> Option<S> {
+        self.idle.lock().unwrap().get_mut(&addr)?.pop()
+    }
+
+    pub(crate) fn put(&self, addr: SocketAddr, stream: S) {
+        self.idle
+            .lock()
+            .unwrap()
+            .entry(addr)
+            .or_default()
+            .push(stream);
+    }
+}
+
+impl Transport for TcpTransport {
+    fn write<'a>(&'a self, addr: SocketAddr, input: &'a [u8]) -> BoxFuture<'a, crate::Result<u64>> {
+        Box::pin(async move {
+            if let Some(duplex) = &self.duplex {
+                let (mut stream, opened) = self.checkout_duplex(duplex, addr).await?;
+                let written = self.write_within(&mut stream, input).await?;
+                self.keep(duplex, addr, stream, opened);
+                return Ok(written);
+            }
+            let (mut stream, opened) = self.checkout(addr).await?;
+            let written = self.write_within(&mut stream, input).await?;
+            self.await_peer_close(&mut stream)
+                .await
+                .map_err(Unacknowledged::new)?;
+            self.checkin(addr, stream, opened);
+            Ok(written)
+        })
+    }
+
+    fn exchange<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<(u64, Vec<u8>)>> {
+        Box::pin(async move {
+            let mut stream = self.connect(addr).await?;
+            let written = self.write_within(&mut stream, input).await?;
+            let reply = read_to_close(&mut stream).await?;
+            Ok((written, reply))
+        })
+    }
+
+    fn exchange_until<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+        framing: &'a ReplyFraming,
+    ) -> BoxFuture<'a, crate::Result<Reply>> {
+        Box::pin(async move {
+            let mut stream = self.connect(addr).await?;
+            exchange_stream(&mut stream, input, framing).await
+        })
+    }
+
+    fn write_generated(&self, addr: SocketAddr, len: u64) -> BoxFuture<'_, crate::Result<u64>> {
+        Box::pin(async move {
+            if let Some(duplex) = &self.duplex {
+                let (mut stream, opened) = self.checkout_duplex(duplex, addr).await?;
+                let written = self.write_generated_within(&mut stream, len).await?;
+                self.keep(duplex, addr, stream, opened);
+                return Ok(written);
+            }
+            let (mut stream, opened) = self.checkout(addr).await?;
+            let written = self.write_generated_within(&mut stream, len).await?;
+            self.await_peer_close(&mut stream)
+                .await
+                .map_err(Unacknowledged::new)?;
+            self.checkin(addr, stream, opened);
+            Ok(written)
+        })
+    }
+}
+
+/// Opens a new TCP connection for every write, as with [`TcpTransport`], and
+/// performs a TLS handshake before writing.
+///
+/// The connection is closed with a TLS `close_notify` once the input is
+/// written, so servers can tell a complete message from a truncated one.
+/// Unless session resumption is disabled, the server is then given the
+/// chance to close the connection, so its session tickets are received.
+pub struct TlsTransport {
+    tcp: TcpTransport,
+    connector: tokio_rustls::TlsConnector,
+    config: TlsConfig,
+    handshakes: Option<Arc<Statistics>>,
+}
+
+impl TlsTransport {
+    pub fn new(tcp: TcpTransport, config: &TlsConfig) -> crate::Result<Self> {
+        Ok(Self {
+            tcp,
+            connector: config.connector()?,
+            config: config.clone(),
+            handshakes: None,
+        })
+    }
+
+    /// Count every handshake which completes in the statistics, by whether
+    /// it resumed an earlier session, alongside how long it took.
+    pub fn with_handshake_stats(mut self, stats: Arc<Statistics>) -> Self {
+        self.handshakes = Some(stats);
+        self
+    }
+
+    pub(crate) async fn connect(
+        &self,
+        addr: SocketAddr,
+    ) -> crate::Result<tokio_rustls::client::TlsStream<TcpStream>> {
+        let start = Instant::now();
+        let stream = self.tcp.connect(addr).await?;
+        let name = self.config.server_name(addr)?;
+        // The handshake has whatever remains of the connect timeout.
+        let remaining = self
+            .tcp
+            .connect_timeout
+            .map(|timeout| timeout.saturating_sub(start.elapsed()));
+        let handshake = Instant::now();
+        let stream = within(
+            remaining,
+            "TLS handshake",
+            self.connector.connect(name, stream),
+        )
+        .await
+        .map_err(ConnectError::new)?;
+        if let Some(stats) = &self.handshakes {
+            let resumed = stream.get_ref().1.handshake_kind() == Some(HandshakeKind::Resumed);
+            stats.record_tls_handshake(resumed, handshake.elapsed());
+        }
+        Ok(stream)
+    }
+}
+
+impl TlsTransport {
+    /// Read until the server closes the connection, when sessions may be
+    /// resumed or waiting for the peer to close. TLS 1.3 servers send their
+    /// session tickets after the handshake, so they are only stored for later
+    /// connections once read.
+    async fn await_close(
+        &self,
+        stream: &mut tokio_rustls::client::TlsStream<TcpStream>,
+    ) -> crate::Result<()> {
+        if self.tcp.wait_peer_close {
+            // The close_notify has already been sent.
+            within(
+                self.tcp.close_timeout,
+                "waiting for the peer to close",
+                tokio::io::copy(stream, &mut tokio::io::sink()),
+            )
+            .await?;
+        } else if self.config.session_resumption() {
+            // The server may close without a close_notify, which is not a
+            // failure of a request which was already written.
+            let _ = tokio::io::copy(stream, &mut tokio::io::sink()).await;
+        }
+        Ok(())
+    }
+}
+
+impl Transport for TlsTransport {
+    fn write<'a>(&'a self, addr: SocketAddr, input: &'a [u8]) -> BoxFuture<'a, crate::Result<u64>> {
+        Box::pin(async move {
+            let mut stream = self.connect(addr).await?;
+            let written = self.tcp.write_within(&mut stream, input).await?;
+            stream.shutdown().await?;
+            self.await_close(&mut stream)
+                .await
+                .map_err(Unacknowledged::new)?;
+            Ok(written)
+        })
+    }
+
+    fn write_generated(&self, addr: SocketAddr, len: u64) -> BoxFuture<'_, crate::Result<u64>> {
+        Box::pin(async move {
+            let mut stream = self.connect(addr).await?;
+            let written = self.tcp.write_generated_within(&mut stream, len).await?;
+            stream.shutdown().await?;
+            self.await_close(&mut stream)
+                .await
+                .map_err(Unacknowledged::new)?;
+            Ok(written)
+        })
+    }
+
+    fn exchange<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<(u64, Vec<u8>)>> {
+        Box::pin(async move {
+            let mut stream = self.connect(addr).await?;
+            let written = self.tcp.write_within(&mut stream, input).await?;
+            let reply = read_to_close(&mut stream).await?;
+            Ok((written, reply))
+        })
+    }
+
+    fn exchange_until<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+        framing: &'a ReplyFraming,
+    ) -> BoxFuture<'a, crate::Result<Reply>> {
+        Box::pin(async move {
+            let mut stream = self.connect(addr).await?;
+            exchange_stream(&mut stream, input, framing).await
+        })
+    }
+}
+
+/// Sends a single datagram from a new [`UdpSocket`] for every write.
+///
+/// A datagram which the local network stack refuses is not retried, so it
+/// fails with the underlying `EAGAIN` or `ENOBUFS` error rather than being
+/// counted as sent.
+#[derive(Default)]
+pub struct UdpTransport {
+    send_queue: Option<Arc<Statistics>>,
+    interface: Option<String>,
+    multicast_ttl: Option<u32>,
+}
+
+impl UdpTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sample the bytes still queued in the socket's send buffer after each
+    /// datagram, recording them into the statistics. This is only supported
+    /// on Linux, through `SIOCOUTQ`.
+    pub fn with_send_queue_sampling(mut self, stats: Arc<Statistics>) -> Self {
+        self.send_queue = Some(stats);
+        self
+    }
+
+    /// Send through a network interface or VRF, as with
+    /// [`TcpTransport::with_interface`]. This is also the interface which
+    /// datagrams to a multicast group leave from.
+    pub fn with_interface(mut self, interface: impl Into<String>) -> Self {
+        self.interface = Some(interface.into());
+        self
+    }
+
+    /// Let datagrams to a multicast group cross this many routers, rather
+    /// than staying on the local network.
+    pub fn with_multicast_ttl(mut self, ttl: u32) -> Self {
+        self.multicast_ttl = Some(ttl);
+        self
+    }
+
+    /// Bind a socket for sending to `addr`, setting the multicast TTL when it
+    /// is a multicast group.
+    async fn socket(&self, addr: SocketAddr) -> std::io::Result<UdpSocket> {
+        let socket = unspecified_socket(addr, self.interface.as_deref()).await?;
+        if let (true, Some(ttl)) = (addr.ip().is_multicast(), self.multicast_ttl) {
+            multicast::set_ttl(SockRef::from(&socket), addr.ip(), ttl)?;
+        }
+        Ok(socket)
+    }
+}
+
+impl Transport for UdpTransport {
+    fn write<'a>(&'a self, addr: SocketAddr, input: &'a [u8]) -> BoxFuture<'a, crate::Result<u64>> {
+        Box::pin(async move {
+            let stream = self.socket(addr).await?;
+            stream.writable().await?;
+            // Unlike `send_to`, this surfaces `EAGAIN` instead of waiting.
+            let written = stream.try_send_to(input, addr)? as u64;
+            if let Some(stats) = &self.send_queue {
+                if let Some(queued) = send_queue_len(&stream) {
+                    stats.record_send_queue(queued);
+                }
+            }
+            Ok(written)
+        })
+    }
+
+    fn exchange<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<(u64, Vec<u8>)>> {
+        Box::pin(async move {
+            let stream = self.socket(addr).await?;
+            stream.connect(addr).await?;
+            let written = stream.send(input).await? as u64;
+            let mut reply = vec![0; MAX_DATAGRAM_SIZE];
+            let len = stream.recv(&mut reply).await.map_err(Unacknowledged::new)?;
+            reply.truncate(len);
+            Ok((written, reply))
+        })
+    }
+
+    fn exchange_until<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+        _framing: &'a ReplyFraming,
+    ) -> BoxFuture<'a, crate::Result<Reply>> {
+        // A datagram is the whole reply, however it is framed.
+        Box::pin(timed_exchange(self, addr, input))
+    }
+}
+
+/// Bind a [`UdpSocket`] for writing to `addr`.
+///
+/// Binding to 0 mimics the functionality of an unspecified socket.
+/// It simply assigns a random port for the UDP socket to begin writing.
+/// Ref: https://man7.org/linux/man-pages/man7/udp.7.html
+async fn unspecified_socket(
+    addr: SocketAddr,
+    interface: Option<&str>,
+) -> std::io::Result<UdpSocket> {
+    let local: SocketAddr = if addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local).await?;
+    if let Some(interface) = interface {
+        #[cfg(target_os = "linux")]
+        socket
+            .bind_device(Some(interface.as_bytes()))
+            .map_err(|e| interface_error(interface, e))?;
+        #[cfg(not(target_os = "linux"))]
+        return Err(interface_error(interface, ErrorKind::Unsupported.into()));
+    }
+    Ok(socket)
+}
+
+/// Open a [`TcpStream`] which is bound to a network interface before it
+/// connects.
+#[cfg(target_os = "linux")]
+async fn connect_through(addr: SocketAddr, interface: &str) -> std::io::Result<TcpStream> {
+    let socket = if addr.is_ipv4() {
+        tokio::net::TcpSocket::new_v4()?
+    } else {
+        tokio::net::TcpSocket::new_v6()?
+    };
+    socket
+        .bind_device(Some(interface.as_bytes()))
+        .map_err(|e| interface_error(interface, e))?;
+    socket.connect(addr).await
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn connect_through(_addr: SocketAddr, interface: &str) -> std::io::Result<TcpStream> {
+    Err(interface_error(interface, ErrorKind::Unsupported.into()))
+}
+
+fn interface_error(interface: &str, e: std::io::Error) -> std::io::Error {
+    std::io::Error::new(
+        e.kind(),
+        format!("cannot bind to interface {interface}: {e}"),
+    )
+}
+
+/// Bytes in the socket's send queue which have not yet left the host.
+#[cfg(target_os = "linux")]
+fn send_queue_len(socket: &UdpSocket) -> Option<u64> {
+    use std::os::fd::AsRawFd;
+
+    let mut queued: libc::c_int = 0;
+    // SIOCOUTQ shares its value with TIOCOUTQ, which is what libc exposes.
+    // SAFETY: the descriptor is owned by the socket and `queued` outlives the call.
+    let res = unsafe { libc::ioctl(socket.as_raw_fd(), libc::TIOCOUTQ, &mut queued) };
+    (res == 0).then_some(queued as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_queue_len(_socket: &UdpSocket) -> Option<u64> {
+    None
+}
+
+/// Opens a new [`tokio::net::UnixStream`] to the socket at a path for every
+/// write, ignoring the address it is given.
+#[cfg(unix)]
+pub struct UnixTransport {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixTransport {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixTransport {
+    fn write<'a>(
+        &'a self,
+        _addr: SocketAddr,
+        input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<u64>> {
+        Box::pin(async move {
+            let mut stream = crate::unix::connect(&self.path)
+                .await
+                .map_err(ConnectError::new)?;
+            Ok(write_counted(&mut stream, input).await?)
+        })
+    }
+
+    fn exchange<'a>(
+        &'a self,
+        _addr: SocketAddr,
+        input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<(u64, Vec<u8>)>> {
+        Box::pin(async move {
+            let mut stream = crate::unix::connect(&self.path)
+                .await
+                .map_err(ConnectError::new)?;
+            let written = write_counted(&mut stream, input).await?;
+            let reply = read_to_close(&mut stream).await?;
+            Ok((written, reply))
+        })
+    }
+
+    fn exchange_until<'a>(
+        &'a self,
+        _addr: SocketAddr,
+        input: &'a [u8],
+        framing: &'a ReplyFraming,
+    ) -> BoxFuture<'a, crate::Result<Reply>> {
+        Box::pin(async move {
+            let mut stream = crate::unix::connect(&self.path)
+                .await
+                .map_err(ConnectError::new)?;
+            exchange_stream(&mut stream, input, framing).await
+        })
+    }
+
+    fn write_generated(&self, _addr: SocketAddr, len: u64) -> BoxFuture<'_, crate::Result<u64>> {
+        Box::pin(async move {
+            let mut stream = crate::unix::connect(&self.path)
+                .await
+                .map_err(ConnectError::new)?;
+            Ok(write_generated_counted(&mut stream, len).await?)
+        })
+    }
+}
+
+/// Sends a single datagram to the socket at a path for every write, ignoring
+/// the address it is given.
+///
+/// Writes are sent from an unbound socket. Waiting for a reply requires the
+/// socket to have a path of its own, so a temporary one is bound for the
+/// duration of each exchange.
+#[cfg(unix)]
+pub struct UnixDatagramTransport {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixDatagramTransport {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixDatagramTransport {
+    fn write<'a>(
+        &'a self,
+        _addr: SocketAddr,
+        input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<u64>> {
+        Box::pin(async move {
+            let socket = crate::unix::connect_datagram(None, &self.path)?;
+            Ok(socket.send(input).await? as u64)
+        })
+    }
+
+    fn exchange<'a>(
+        &'a self,
+        _addr: SocketAddr,
+        input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<(u64, Vec<u8>)>> {
+        Box::pin(async move {
+            let local = TemporarySocketPath::new();
+            let socket = crate::unix::connect_datagram(Some(&local.0), &self.path)?;
+            let written = socket.send(input).await? as u64;
+            let mut reply = vec![0; MAX_DATAGRAM_SIZE];
+            let len = socket.recv(&mut reply).await.map_err(Unacknowledged::new)?;
+            reply.truncate(len);
+            Ok((written, reply))
+        })
+    }
+
+    fn exchange_until<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+        _framing: &'a ReplyFraming,
+    ) -> BoxFuture<'a, crate::Result<Reply>> {
+        // A datagram is the whole reply, however it is framed.
+        Box::pin(timed_exchange(self, addr, input))
+    }
+}
+
+/// Unique path in the temporary directory, which is removed on drop.
+#[cfg(unix)]
+pub(crate) struct TemporarySocketPath(pub(crate) PathBuf);
+
+#[cfg(unix)]
+impl TemporarySocketPath {
+    pub(crate) fn new() -> Self {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("gn-{}-{id}.sock", std::process::id());
+        Self(std::env::temp_dir().join(name))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for TemporarySocketPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// In-memory transport where every write creates a [`DuplexStream`] pair,
+/// handing the receiving half to the paired [`MemoryListener`].
+///
+/// This allows the behaviour of a [`crate::SocketManager`] to be exercised
+/// without real sockets or ports. Writes fail once the listener is dropped,
+/// mirroring a refused connection.
+pub struct MemoryTransport {
+    streams: mpsc::UnboundedSender<(SocketAddr, DuplexStream)>,
+}
+
+/// Receiving side of a [`MemoryTransport`].
+pub struct MemoryListener {
+    streams: mpsc::UnboundedReceiver<(SocketAddr, DuplexStream)>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> (Self, MemoryListener) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { streams: tx }, MemoryListener { streams: rx })
+    }
+}
+
+impl MemoryListener {
+    /// Accept the next stream which was written, along with the address it
+    /// was written to. Returns `None` once the transport is dropped and every
+    /// stream has been accepted.
+    pub async fn accept(&mut self) -> Option<(SocketAddr, DuplexStream)> {
+        self.streams.recv().await
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn write<'a>(&'a self, addr: SocketAddr, input: &'a [u8]) -> BoxFuture<'a, crate::Result<u64>> {
+        Box::pin(async move {
+            // Size the buffer to the input so the write completes without
+            // the listener having to read it first.
+            let (mut client, server) = tokio::io::duplex(input.len().max(1));
+            self.streams
+                .send((addr, server))
+                .map_err(|_| "memory listener was dropped")?;
+            Ok(write_counted(&mut client, input).await?)
+        })
+    }
+
+    fn exchange<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<(u64, Vec<u8>)>> {
+        Box::pin(async move {
+            let (mut client, server) = tokio::io::duplex(input.len().max(1));
+            self.streams
+                .send((addr, server))
+                .map_err(|_| "memory listener was dropped")?;
+            let written = write_counted(&mut client, input).await?;
+            let reply = read_to_close(&mut client).await?;
+            Ok((written, reply))
+        })
+    }
+
+    fn exchange_until<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+        framing: &'a ReplyFraming,
+    ) -> BoxFuture<'a, crate::Result<Reply>> {
+        Box::pin(async move {
+            let (mut client, server) = tokio::io::duplex(input.len().max(1));
+            self.streams
+                .send((addr, server))
+                .map_err(|_| "memory listener was dropped")?;
+            exchange_stream(&mut client, input, framing).await
+        })
+    }
+
+    fn write_generated(&self, addr: SocketAddr, len: u64) -> BoxFuture<'_, crate::Result<u64>> {
+        Box::pin(async move {
+            // The payload is only written as fast as the listener reads it.
+            let (mut client, server) = tokio::io::duplex(GENERATED_CHUNK_SIZE);
+            self.streams
+                .send((addr, server))
+                .map_err(|_| "memory listener was dropped")?;
+            Ok(write_generated_counted(&mut client, len).await?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use std::{io::ErrorKind, sync::Arc, time::Duration};
+
+    use super::{
+        is_timeout, write_counted, write_generated_counted, MemoryTransport, SourceDrop,
+        TcpTransport, Transport, UdpTransport,
+    };
+    use crate::statistics::Statistics;
+
+    #[tokio::test]
+    async fn memory() {
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        let (transport, mut listener) = MemoryTransport::new();
+        assert_eq!(transport.write(addr, b"hello").await.unwrap(), 5);
+
+        let (to, mut stream) = listener.accept().await.unwrap();
+        assert_eq!(to, addr);
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        drop(listener);
+        assert!(transport.write(addr, b"hello").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn partial_write() {
+        let (mut client, mut server) = tokio::io::duplex(4);
+        tokio::spawn(async move {
+            let mut buf = [0; 4];
+            server.read_exact(&mut buf).await.unwrap();
+        });
+        let err = write_counted(&mut client, b"0123456789").await.unwrap_err();
+        assert_eq!(err.written, 4);
+
+        let (mut client, _server) = tokio::io::duplex(16);
+        assert_eq!(write_counted(&mut client, b"0123456789").await.unwrap(), 10);
+    }
+
+    #[tokio::test]
+    async fn generated() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let read = tokio::spawn(async move {
+            let mut received = Vec::new();
+            server.read_to_end(&mut received).await.unwrap();
+            received.len()
+        });
+        let len = 3 * 64 * 1024 + 10;
+        assert_eq!(
+            write_generated_counted(&mut client, len).await.unwrap(),
+            len
+        );
+        drop(client);
+        assert_eq!(read.await.unwrap() as u64, len);
+
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let mut buf = [0; 100];
+            server.read_exact(&mut buf).await.unwrap();
+        });
+        let err = write_generated_counted(&mut client, 10_000)
+            .await
+            .unwrap_err();
+        assert!((100..=1124).contains(&err.written));
+
+        let transport = UdpTransport::new();
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        assert!(transport.write_generated(addr, 10).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn tcp_connect_concurrency() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let transport = TcpTransport::new().with_connect_concurrency(1);
+
+        let writes = (0..5).map(|_| transport.write(addr, b"hello"));
+        for written in futures::future::join_all(writes).await {
+            assert_eq!(written.unwrap(), 5);
+        }
+        let permits = transport.connect_permits.as_ref().unwrap();
+        assert_eq!(permits.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn write_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Accept the connection, but never read from it.
+        let accepted = tokio::spawn(async move { listener.accept().await.unwrap() });
+        let transport = TcpTransport::new().with_write_timeout(Duration::from_millis(100));
+
+        let input = vec![0; 64 * 1024 * 1024];
+        let err = transport.write(addr, &input).await.unwrap_err();
+        assert!(is_timeout(err.as_ref()), "{err}");
+        drop(accepted.await.unwrap());
+
+        let refused = std::io::Error::from(ErrorKind::ConnectionRefused);
+        assert!(!is_timeout(&refused));
+    }
+
+    #[tokio::test]
+    async fn memory_exchange() {
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        let (transport, mut listener) = MemoryTransport::new();
+        tokio::spawn(async move {
+            let (_, mut stream) = listener.accept().await.unwrap();
+            let mut message = Vec::new();
+            stream.read_to_end(&mut message).await.unwrap();
+            stream.write_all(&message.repeat(2)).await.unwrap();
+        });
+        let (written, reply) = transport.exchange(addr, b"echo").await.unwrap();
+        assert_eq!(written, 4);
+        assert_eq!(reply, b"echoecho");
+    }
+
+    #[tokio::test]
+    async fn udp_send_queue() {
+        let receiver = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = receiver.local_addr().unwrap();
+        let stats = Arc::new(Statistics::new());
+        let transport = UdpTransport::new().with_send_queue_sampling(Arc::clone(&stats));
+        assert_eq!(transport.write(addr, b"hello").await.unwrap(), 5);
+
+        let mut buf = [0; 5];
+        receiver.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn unknown_interface() {
+        let receiver = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = receiver.local_addr().unwrap();
+        let err = UdpTransport::new()
+            .with_interface("gn-missing0")
+            .write(addr, b"hello")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("interface gn-missing0"), "{err}");
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let transport = TcpTransport::new().with_interface("gn-missing0");
+        assert!(transport.write(addr, b"hello").await.is_err());
+    }
+
+    #[test]
+    fn source_drop() {
+        let would_block = std::io::Error::from(ErrorKind::WouldBlock);
+        assert_eq!(
+            SourceDrop::from_error(&would_block),
+            Some(SourceDrop::WouldBlock)
+        );
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            SourceDrop::from_error(&std::io::Error::from_raw_os_error(libc::ENOBUFS)),
+            Some(SourceDrop::NoBufferSpace)
+        );
+        let refused = std::io::Error::from(ErrorKind::ConnectionRefused);
+        assert_eq!(SourceDrop::from_error(&refused), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_exchange() {
+        use super::{TemporarySocketPath, UnixDatagramTransport, UnixTransport};
+        use crate::endpoint::UNIX_PEER;
+
+        let path = TemporarySocketPath::new();
+        let listener = tokio::net::UnixListener::bind(&path.0).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut message = Vec::new();
+            stream.read_to_end(&mut message).await.unwrap();
+            stream.write_all(&message.repeat(2)).await.unwrap();
+        });
+        let transport = UnixTransport::new(&path.0);
+        let (written, reply) = transport.exchange(UNIX_PEER, b"echo").await.unwrap();
+        assert_eq!(written, 4);
+        assert_eq!(reply, b"echoecho");
+
+        let path = TemporarySocketPath::new();
+        let socket = tokio::net::UnixDatagram::bind(&path.0).unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0; 16];
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let peer = peer.as_pathname().unwrap();
+            socket.send_to(&buf[..len].repeat(2), peer).await.unwrap();
+        });
+        let transport = UnixDatagramTransport::new(&path.0);
+        let (written, reply) = transport.exchange(UNIX_PEER, b"echo").await.unwrap();
+        assert_eq!(written, 4);
+        assert_eq!(reply, b"echoecho");
+    }
+
+    #[tokio::test]
+    async fn tls() {
+        use super::TlsTransport;
+        use crate::tls::{TestCertificate, TlsConfig, TlsServerConfig};
+
+        let certificate = TestCertificate::new();
+        let ca_file = &certificate.cert;
+        let acceptor = TlsServerConfig::load(&certificate.cert, &certificate.key)
+            .unwrap()
+            .acceptor();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    continue;
+                };
+                let mut message = Vec::new();
+                stream.read_to_end(&mut message).await.unwrap();
+                stream.write_all(&message.repeat(2)).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+
+        let trusted = TlsConfig::new()
+            .with_ca_file(ca_file)
+            .with_server_name("localhost");
+        let transport = TlsTransport::new(TcpTransport::new(), &trusted).unwrap();
+        let (written, reply) = transport.exchange(addr, b"echo").await.unwrap();
+        assert_eq!(written, 4);
+        assert_eq!(reply, b"echoecho");
+
+        // The self-signed certificate is not trusted by the web PKI roots, nor
+        // is it valid for the IP address.
+        let untrusted = TlsConfig::new().with_server_name("localhost");
+        let transport = TlsTransport::new(TcpTransport::new(), &untrusted).unwrap();
+        assert!(transport.write(addr, b"echo").await.is_err());
+        let wrong_name = TlsConfig::new().with_ca_file(ca_file);
+        let transport = TlsTransport::new(TcpTransport::new(), &wrong_name).unwrap();
+        assert!(transport.write(addr, b"echo").await.is_err());
+
+        let insecure = TlsConfig::new().with_insecure();
+        let transport = TlsTransport::new(TcpTransport::new(), &insecure).unwrap();
+        assert_eq!(transport.write(addr, b"echo").await.unwrap(), 4);
+    }
+}
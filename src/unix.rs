@@ -0,0 +1,162 @@
+//! Unix sockets, addressed by the path of their file or, on Linux, by a
+//! name in the abstract namespace given as a path starting with `@`, such as
+//! `@gn`. Abstract sockets have no file, so are never left behind.
+use std::{
+    io,
+    os::unix::{ffi::OsStrExt, net},
+    path::{Path, PathBuf},
+};
+
+/// Address of the socket at `path`.
+pub(crate) fn socket_addr(path: &Path) -> io::Result<net::SocketAddr> {
+    match abstract_name(path) {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+
+            net::SocketAddr::from_abstract_name(name)
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "abstract Unix sockets are only supported on Linux",
+        )),
+        None => net::SocketAddr::from_pathname(path),
+    }
+}
+
+/// Name of the socket in the abstract namespace, when it is in it.
+fn abstract_name(path: &Path) -> Option<&[u8]> {
+    path.as_os_str().as_bytes().strip_prefix(b"@")
+}
+
+/// Listen on the socket at `path`.
+pub(crate) fn bind_listener(path: &Path) -> io::Result<tokio::net::UnixListener> {
+    let listener = net::UnixListener::bind_addr(&socket_addr(path)?)?;
+    listener.set_nonblocking(true)?;
+    tokio::net::UnixListener::from_std(listener)
+}
+
+/// Receive datagrams on the socket at `path`.
+pub(crate) fn bind_datagram(path: &Path) -> io::Result<tokio::net::UnixDatagram> {
+    let socket = net::UnixDatagram::bind_addr(&socket_addr(path)?)?;
+    socket.set_nonblocking(true)?;
+    tokio::net::UnixDatagram::from_std(socket)
+}
+
+/// Connect a stream to the socket at `path`.
+pub(crate) async fn connect(path: &Path) -> io::Result<tokio::net::UnixStream> {
+    if abstract_name(path).is_none() {
+        return tokio::net::UnixStream::connect(path).await;
+    }
+    // Connecting to a Unix socket completes immediately unless its backlog is
+    // full, so is not worth handing off to a blocking thread.
+    let stream = net::UnixStream::connect_addr(&socket_addr(path)?)?;
+    stream.set_nonblocking(true)?;
+    tokio::net::UnixStream::from_std(stream)
+}
+
+/// Connect a datagram socket, which is bound to `local` when given so that
+/// it can be replied to, to the socket at `path`.
+pub(crate) fn connect_datagram(
+    local: Option<&Path>,
+    path: &Path,
+) -> io::Result<tokio::net::UnixDatagram> {
+    let socket = match local {
+        Some(local) => net::UnixDatagram::bind(local)?,
+        None => net::UnixDatagram::unbound()?,
+    };
+    socket.connect_addr(&socket_addr(path)?)?;
+    socket.set_nonblocking(true)?;
+    tokio::net::UnixDatagram::from_std(socket)
+}
+
+/// File of a socket which a server is about to bind, which is removed when
+/// this is dropped so that it is not left behind once the server stops.
+pub(crate) struct SocketFile(Option<PathBuf>);
+
+impl SocketFile {
+    /// Remove a socket left behind at `path` by a previous server, as binding
+    /// fails while it exists. Any other kind of file is left in place.
+    pub(crate) fn claim(path: &Path) -> io::Result<Self> {
+        if abstract_name(path).is_some() {
+            return Ok(Self(None));
+        }
+        remove_socket(path)?;
+        Ok(Self(Some(path.to_path_buf())))
+    }
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0 {
+            let _ = remove_socket(path);
+        }
+    }
+}
+
+/// Remove the file at `path` if it is a socket.
+fn remove_socket(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::SocketFile;
+    use crate::transport::TemporarySocketPath;
+
+    #[tokio::test]
+    async fn socket_file() {
+        let path = TemporarySocketPath::new();
+        let stale = std::os::unix::net::UnixListener::bind(&path.0).unwrap();
+        drop(stale);
+        assert!(path.0.exists());
+
+        let file = SocketFile::claim(&path.0).unwrap();
+        assert!(!path.0.exists());
+        let _bound = super::bind_listener(&path.0).unwrap();
+        assert!(path.0.exists());
+        drop(file);
+        assert!(!path.0.exists());
+
+        // Other files are never removed.
+        let other = std::env::temp_dir().join(format!("gn-{}-not-a-socket", std::process::id()));
+        std::fs::write(&other, b"").unwrap();
+        drop(SocketFile::claim(&other).unwrap());
+        assert!(other.exists());
+        std::fs::remove_file(other).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn abstract_namespace() {
+        let name = format!("@gn-test-{}", std::process::id());
+        let path = Path::new(&name);
+        let listener = super::bind_listener(path).unwrap();
+        drop(SocketFile::claim(path).unwrap());
+
+        let mut stream = super::connect(path).await.unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        drop(stream);
+        let (mut accepted, _) = listener.accept().await.unwrap();
+        let mut received = Vec::new();
+        accepted.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"hello");
+
+        let socket = super::bind_datagram(Path::new(&format!("{name}-datagram"))).unwrap();
+        let sender = super::connect_datagram(None, Path::new(&format!("{name}-datagram"))).unwrap();
+        sender.send(b"datagram").await.unwrap();
+        let mut buf = [0; 16];
+        let len = socket.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"datagram");
+    }
+}
@@ -0,0 +1,242 @@
+//! Writing over WebSockets, where every request opens a connection, performs
+//! the opening handshake and sends its input as a single message.
+use std::net::SocketAddr;
+
+use clap::ValueEnum;
+use futures::{future::BoxFuture, SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::{client_async, tungstenite::Message, WebSocketStream};
+
+use crate::{
+    reply::{Reply, ReplyFraming},
+    tls::TlsConfig,
+    transport::{TcpTransport, TlsTransport, Transport, Unacknowledged},
+};
+
+/// Kind of message which the input is sent as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum WebSocketMessage {
+    #[default]
+    Binary,
+    /// UTF-8 text, which fails requests whose input is not valid UTF-8.
+    Text,
+}
+
+/// Settings for writing over [`crate::Protocol::Ws`] and
+/// [`crate::Protocol::Wss`].
+#[derive(Debug, Clone)]
+pub struct WebSocketConfig {
+    path: String,
+    message: WebSocketMessage,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            path: "/".to_string(),
+            message: WebSocketMessage::default(),
+        }
+    }
+}
+
+impl WebSocketConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Path, and any query, which is requested in the opening handshake, such
+    /// as `/chat?room=1`. Defaults to `/`.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Send the input as the given kind of message, rather than binary.
+    pub fn with_message(mut self, message: WebSocketMessage) -> Self {
+        self.message = message;
+        self
+    }
+}
+
+/// Connection which a WebSocket is opened over, with or without TLS.
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+enum Connector {
+    Plain(TcpTransport),
+    Tls(TlsTransport),
+}
+
+/// Opens a WebSocket for every write, sending the input as a single message
+/// and closing the WebSocket once it is sent or, when waiting for a reply,
+/// once the first message is received back.
+pub struct WebSocketTransport {
+    connector: Connector,
+    config: WebSocketConfig,
+}
+
+impl WebSocketTransport {
+    pub fn new(tcp: TcpTransport, config: WebSocketConfig) -> Self {
+        Self {
+            connector: Connector::Plain(tcp),
+            config,
+        }
+    }
+
+    /// Open WebSockets over TLS, as with [`crate::Protocol::Wss`].
+    pub fn with_tls(mut self, tls: &TlsConfig) -> crate::Result<Self> {
+        if let Connector::Plain(tcp) = self.connector {
+            self.connector = Connector::Tls(TlsTransport::new(tcp, tls)?);
+        }
+        Ok(self)
+    }
+
+    /// Connect and perform the opening handshake.
+    async fn open(&self, addr: SocketAddr) -> crate::Result<WebSocketStream<Box<dyn Connection>>> {
+        let (scheme, stream): (_, Box<dyn Connection>) = match &self.connector {
+            Connector::Plain(tcp) => ("ws", Box::new(tcp.connect(addr).await?)),
+            Connector::Tls(tls) => ("wss", Box::new(tls.connect(addr).await?)),
+        };
+        let url = format!("{scheme}://{addr}{}", self.config.path);
+        let (socket, _) = client_async(url, stream).await?;
+        Ok(socket)
+    }
+
+    fn message(&self, input: &[u8]) -> crate::Result<Message> {
+        match self.config.message {
+            WebSocketMessage::Binary => Ok(Message::binary(input.to_vec())),
+            WebSocketMessage::Text => match std::str::from_utf8(input) {
+                Ok(text) => Ok(Message::text(text)),
+                Err(_) => Err("input is not valid UTF-8, so cannot be sent as text".into()),
+            },
+        }
+    }
+
+    /// Send the input as a message, then read the first message which is
+    /// received back when `reply` is set, before closing the WebSocket.
+    async fn send(
+        &self,
+        addr: SocketAddr,
+        input: &[u8],
+        reply: bool,
+    ) -> crate::Result<Option<Reply>> {
+        let message = self.message(input)?;
+        let mut socket = self.open(addr).await?;
+        let start = tokio::time::Instant::now();
+        socket.send(message).await?;
+        let reply = match reply {
+            true => {
+                let data = read_message(&mut socket)
+                    .await
+                    .map_err(Unacknowledged::new)?;
+                // The reply arrives as a single message.
+                let round_trip = start.elapsed();
+                Some(Reply {
+                    written: input.len() as u64,
+                    data,
+                    round_trip,
+                    first_byte: round_trip,
+                })
+            }
+            false => None,
+        };
+        socket.close(None).await?;
+        // Wait for the server to acknowledge the close, so the message is
+        // known to have been received in full.
+        while let Some(Ok(_)) = socket.next().await {}
+        Ok(reply)
+    }
+}
+
+/// Read the next data message, skipping control messages.
+async fn read_message(socket: &mut WebSocketStream<Box<dyn Connection>>) -> crate::Result<Vec<u8>> {
+    while let Some(message) = socket.next().await {
+        match message? {
+            Message::Text(text) => return Ok(text.as_bytes().to_vec()),
+            Message::Binary(data) => return Ok(data.to_vec()),
+            Message::Close(_) => break,
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+        }
+    }
+    Err("WebSocket was closed before a reply was received".into())
+}
+
+impl Transport for WebSocketTransport {
+    fn write<'a>(&'a self, addr: SocketAddr, input: &'a [u8]) -> BoxFuture<'a, crate::Result<u64>> {
+        Box::pin(async move {
+            self.send(addr, input, false).await?;
+            Ok(input.len() as u64)
+        })
+    }
+
+    fn exchange<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+    ) -> BoxFuture<'a, crate::Result<(u64, Vec<u8>)>> {
+        Box::pin(async move {
+            let reply = self.send(addr, input, true).await?.expect("reply was read");
+            Ok((reply.written, reply.data))
+        })
+    }
+
+    fn exchange_until<'a>(
+        &'a self,
+        addr: SocketAddr,
+        input: &'a [u8],
+        _framing: &'a ReplyFraming,
+    ) -> BoxFuture<'a, crate::Result<Reply>> {
+        // A message is the whole reply, however it is framed.
+        Box::pin(async move { Ok(self.send(addr, input, true).await?.expect("reply was read")) })
+    }
+}
+
+/// Listen for WebSockets, echoing every message back with the kind it was
+/// received as, where text is prefixed with `text:`.
+#[cfg(test)]
+pub(crate) async fn echo_server() -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                while let Some(Ok(message)) = socket.next().await {
+                    let echo = match message {
+                        Message::Text(text) => Message::text(format!("text:{text}")),
+                        Message::Binary(data) => Message::binary(data),
+                        _ => continue,
+                    };
+                    let _ = socket.send(echo).await;
+                }
+            });
+        }
+    });
+    addr
+}
+
+#[cfg(test)]
+mod test {
+    use super::{echo_server, WebSocketConfig, WebSocketMessage, WebSocketTransport};
+    use crate::transport::{TcpTransport, Transport};
+
+    #[tokio::test]
+    async fn exchange() {
+        let addr = echo_server().await;
+        let binary = WebSocketTransport::new(TcpTransport::new(), WebSocketConfig::new());
+        assert_eq!(binary.write(addr, b"\xffbinary").await.unwrap(), 7);
+        let (written, reply) = binary.exchange(addr, b"\xffbinary").await.unwrap();
+        assert_eq!((written, reply.as_slice()), (7, b"\xffbinary".as_slice()));
+
+        let text = WebSocketTransport::new(
+            TcpTransport::new(),
+            WebSocketConfig::new()
+                .with_path("/chat?room=1")
+                .with_message(WebSocketMessage::Text),
+        );
+        let (_, reply) = text.exchange(addr, b"hello").await.unwrap();
+        assert_eq!(reply, b"text:hello");
+        assert!(text.write(addr, b"\xff").await.is_err());
+    }
+}